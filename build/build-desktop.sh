@@ -112,6 +112,8 @@ install_local() {
     cp "$PROJECT_ROOT/target/release/spinner-shell" "$install_dir/bin/" 2>/dev/null || log_warn "spinner-shell not found"
     cp "$PROJECT_ROOT/target/release/spinner-settings" "$install_dir/bin/" 2>/dev/null || log_warn "spinner-settings not found"
     cp "$PROJECT_ROOT/target/release/spinner-store" "$install_dir/bin/" 2>/dev/null || log_warn "spinner-store not found"
+    cp "$PROJECT_ROOT/target/release/spinner-portal" "$install_dir/bin/" 2>/dev/null || log_warn "spinner-portal not found"
+    cp "$PROJECT_ROOT/build/rootfs/spinner-screencast" "$install_dir/bin/"
     
     cp -r "$PROJECT_ROOT/assets/"* "$install_dir/share/spinneros/" 2>/dev/null || true
     
@@ -137,6 +139,8 @@ install_system() {
     cp "$PROJECT_ROOT/target/release/spinner-shell" /usr/local/bin/
     cp "$PROJECT_ROOT/target/release/spinner-settings" /usr/local/bin/
     cp "$PROJECT_ROOT/target/release/spinner-store" /usr/local/bin/
+    cp "$PROJECT_ROOT/target/release/spinner-portal" /usr/local/bin/
+    cp "$PROJECT_ROOT/build/rootfs/spinner-screencast" /usr/local/bin/
     
     chmod +x /usr/local/bin/spinner-*
     
@@ -145,6 +149,13 @@ install_system() {
     cp -r "$PROJECT_ROOT/spinner-shell/src/theme/"*.css /usr/share/spinneros/
     
     cp "$PROJECT_ROOT/build/rootfs/spinner-wm.desktop" /usr/share/wayland-sessions/ 2>/dev/null || true
+    cp "$PROJECT_ROOT/build/rootfs/spinner-lock.pam" /etc/pam.d/spinner-lock
+    
+    mkdir -p /usr/share/xdg-desktop-portal/portals /usr/share/dbus-1/services /usr/lib/systemd/user
+    cp "$PROJECT_ROOT/build/rootfs/spinneros.portal" /usr/share/xdg-desktop-portal/portals/
+    cp "$PROJECT_ROOT/build/rootfs/spinneros-portals.conf" /usr/share/xdg-desktop-portal/
+    cp "$PROJECT_ROOT/build/rootfs/org.freedesktop.impl.portal.desktop.spinneros.service" /usr/share/dbus-1/services/
+    cp "$PROJECT_ROOT/build/rootfs/spinner-portal.service" /usr/lib/systemd/user/
     
     log_success "Components installed system-wide"
 }
@@ -142,7 +142,7 @@ build_rust_components() {
     
     log_info "Sprawdzanie zbudowanych plików..."
     
-    local binaries=("spinner-wm" "spinner-shell" "spinner-settings" "spinner-store")
+    local binaries=("spinner-wm" "spinner-shell" "spinner-settings" "spinner-store" "spinner-portal")
     for bin in "${binaries[@]}"; do
         if [[ -f "$PROJECT_ROOT/target/release/$bin" ]]; then
             log_success "✓ $bin zbudowany"
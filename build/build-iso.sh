@@ -192,6 +192,8 @@ cp target/release/spinner-wm /usr/local/bin/
 cp target/release/spinner-shell /usr/local/bin/
 cp target/release/spinner-settings /usr/local/bin/
 cp target/release/spinner-store /usr/local/bin/
+cp target/release/spinner-portal /usr/local/bin/
+cp build/rootfs/spinner-screencast /usr/local/bin/
 
 chmod +x /usr/local/bin/spinner-*
 
@@ -0,0 +1,351 @@
+//! SpinnerBluez - Bluetooth through BlueZ for SpinnerOS
+//!
+//! Shared by the shell's panel indicator and quick settings, and the
+//! settings app's Bluetooth page, which also renames the adapter and makes
+//! it discoverable. Adapters and devices are read from `org.bluez`'s object
+//! manager on the system bus and re-read whenever it signals a change.
+//! Pairing relies on BlueZ's fallback when no agent is registered, which is
+//! enough for headsets, mice and speakers; devices that show a passkey are
+//! left to `bluetoothctl`.
+
+use anyhow::{bail, Context, Result};
+use std::collections::HashMap;
+use std::sync::{mpsc, Arc};
+use std::time::Duration;
+use tracing::{debug, info, warn};
+use zbus::blocking::{Connection, MessageIterator};
+use zbus::zvariant::{OwnedObjectPath, OwnedValue, Value};
+use zbus::Message;
+
+const BLUEZ: &str = "org.bluez";
+const ADAPTER: &str = "org.bluez.Adapter1";
+const DEVICE: &str = "org.bluez.Device1";
+const BATTERY: &str = "org.bluez.Battery1";
+const OBJECT_MANAGER: &str = "org.freedesktop.DBus.ObjectManager";
+const PROPERTIES: &str = "org.freedesktop.DBus.Properties";
+
+const RESTART_DELAY: Duration = Duration::from_secs(5);
+/// Discovery reports every advertisement; they are coalesced.
+const SETTLE_DELAY: Duration = Duration::from_millis(300);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Device {
+    pub address: String,
+    pub name: String,
+    /// A freedesktop icon name such as `audio-headset`.
+    pub icon: Option<String>,
+    pub paired: bool,
+    pub connected: bool,
+    /// Percent, for devices that report it.
+    pub battery: Option<u8>,
+    path: OwnedObjectPath,
+}
+
+impl Device {
+    pub fn icon_name(&self) -> String {
+        match &self.icon {
+            Some(icon) => format!("{}-symbolic", icon),
+            None => "bluetooth-symbolic".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BluetoothState {
+    /// An adapter is present.
+    pub available: bool,
+    /// The name other devices see.
+    pub name: String,
+    pub powered: bool,
+    /// Visible to other devices looking for one to pair with.
+    pub discoverable: bool,
+    pub discovering: bool,
+    /// Connected devices first, then paired ones, then the rest by name.
+    pub devices: Vec<Device>,
+    adapter: Option<OwnedObjectPath>,
+}
+
+impl BluetoothState {
+    pub fn connected(&self) -> impl Iterator<Item = &Device> {
+        self.devices.iter().filter(|d| d.connected)
+    }
+
+    pub fn icon_name(&self) -> &'static str {
+        if !self.powered {
+            "bluetooth-disabled-symbolic"
+        } else if self.connected().next().is_some() {
+            "bluetooth-active-symbolic"
+        } else {
+            "bluetooth-symbolic"
+        }
+    }
+
+    pub fn description(&self) -> String {
+        if !self.powered {
+            return "Bluetooth off".to_string();
+        }
+        let connected: Vec<&str> = self.connected().map(|d| d.name.as_str()).collect();
+        match connected.as_slice() {
+            [] => "Bluetooth on".to_string(),
+            [name] => format!("Connected to {}", name),
+            names => format!("{} devices connected", names.len()),
+        }
+    }
+}
+
+/// Streams the Bluetooth state, refreshed on every change BlueZ reports.
+/// The worker stops once the receiver is dropped.
+pub fn subscribe() -> async_channel::Receiver<BluetoothState> {
+    let (sender, receiver) = async_channel::unbounded();
+
+    std::thread::spawn(move || {
+        let mut last = None;
+        loop {
+            match watch(&sender, &mut last) {
+                Ok(()) => return,
+                Err(e) => debug!("BlueZ unavailable: {:#}", e),
+            }
+            // Shown as unavailable until BlueZ (re)appears.
+            if last.is_none() {
+                if sender.send_blocking(BluetoothState::default()).is_err() {
+                    return;
+                }
+                last = Some(BluetoothState::default());
+            }
+            std::thread::sleep(RESTART_DELAY);
+        }
+    });
+
+    receiver
+}
+
+/// Returns `Ok` only when nobody is listening any more.
+fn watch(sender: &async_channel::Sender<BluetoothState>, last: &mut Option<BluetoothState>) -> Result<()> {
+    let connection = Connection::system().context("Failed to connect to the system bus")?;
+    let rule = format!("type='signal',sender='{}'", BLUEZ);
+    let signals = signal_channel(MessageIterator::for_match_rule(rule.as_str(), &connection, None)?);
+
+    let mut send = |state: BluetoothState| {
+        if last.as_ref() == Some(&state) {
+            return true;
+        }
+        let sent = sender.send_blocking(state.clone()).is_ok();
+        *last = Some(state);
+        sent
+    };
+
+    if !send(query(&connection)?) {
+        return Ok(());
+    }
+    while let Ok(signal) = signals.recv() {
+        signal?;
+        std::thread::sleep(SETTLE_DELAY);
+        while let Ok(signal) = signals.try_recv() {
+            signal?;
+        }
+        match query(&connection) {
+            Ok(state) => {
+                if !send(state) {
+                    return Ok(());
+                }
+            }
+            Err(e) => warn!("Failed to read Bluetooth state: {:#}", e),
+        }
+    }
+    bail!("Signal stream ended")
+}
+
+/// Moves the signal stream onto a thread of its own, so that a burst of
+/// signals can be drained after the first and answered with one query.
+fn signal_channel(signals: MessageIterator) -> mpsc::Receiver<zbus::Result<Arc<Message>>> {
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        for signal in signals {
+            if sender.send(signal).is_err() {
+                return;
+            }
+        }
+    });
+    receiver
+}
+
+type Properties = HashMap<String, OwnedValue>;
+type ManagedObjects = HashMap<OwnedObjectPath, HashMap<String, Properties>>;
+
+fn property<T>(properties: &Properties, key: &str) -> Option<T>
+where
+    T: TryFrom<OwnedValue>,
+{
+    properties.get(key).and_then(|v| T::try_from(v.clone()).ok())
+}
+
+fn query(connection: &Connection) -> Result<BluetoothState> {
+    let objects: ManagedObjects = connection
+        .call_method(Some(BLUEZ), "/", Some(OBJECT_MANAGER), "GetManagedObjects", &())?
+        .body()?;
+
+    let mut state = BluetoothState::default();
+
+    // The first adapter, by path; hci0 on most machines.
+    let mut adapters: Vec<(&OwnedObjectPath, &Properties)> = objects
+        .iter()
+        .filter_map(|(path, interfaces)| Some((path, interfaces.get(ADAPTER)?)))
+        .collect();
+    adapters.sort_by(|a, b| a.0.as_str().cmp(b.0.as_str()));
+    let Some((adapter, properties)) = adapters.first() else {
+        return Ok(state);
+    };
+    state.available = true;
+    state.name = property(properties, "Alias").unwrap_or_default();
+    state.powered = property(properties, "Powered").unwrap_or(false);
+    state.discoverable = property(properties, "Discoverable").unwrap_or(false);
+    state.discovering = property(properties, "Discovering").unwrap_or(false);
+    state.adapter = Some((*adapter).clone());
+
+    for (path, interfaces) in &objects {
+        let Some(properties) = interfaces.get(DEVICE) else {
+            continue;
+        };
+        if property::<OwnedObjectPath>(properties, "Adapter").as_ref() != Some(*adapter) {
+            continue;
+        }
+        let paired: bool = property(properties, "Paired").unwrap_or(false);
+        // Nearby devices that only advertise an address aren't worth listing.
+        let name = property::<String>(properties, "Alias").filter(|_| properties.contains_key("Name") || paired);
+        let Some(name) = name else {
+            continue;
+        };
+
+        state.devices.push(Device {
+            address: property(properties, "Address").unwrap_or_default(),
+            name,
+            icon: property(properties, "Icon"),
+            paired,
+            connected: property(properties, "Connected").unwrap_or(false),
+            battery: interfaces.get(BATTERY).and_then(|b| property(b, "Percentage")),
+            path: path.clone(),
+        });
+    }
+
+    state.devices.sort_by(|a, b| {
+        b.connected
+            .cmp(&a.connected)
+            .then(b.paired.cmp(&a.paired))
+            .then_with(|| a.name.to_lowercase().cmp(&b.name.to_lowercase()))
+    });
+    Ok(state)
+}
+
+/// Runs a blocking request on its own thread; the result comes back on
+/// the returned channel. Failures are logged as well.
+fn spawn<F>(what: &'static str, request: F) -> async_channel::Receiver<Result<()>>
+where
+    F: FnOnce(&Connection) -> Result<()> + Send + 'static,
+{
+    let (sender, receiver) = async_channel::bounded(1);
+    std::thread::spawn(move || {
+        let result = Connection::system()
+            .context("Failed to connect to the system bus")
+            .and_then(|connection| request(&connection));
+        if let Err(e) = &result {
+            warn!("Failed to {}: {:#}", what, e);
+        }
+        let _ = sender.send_blocking(result);
+    });
+    receiver
+}
+
+fn set_property(connection: &Connection, path: &str, interface: &str, name: &str, value: Value) -> Result<()> {
+    connection.call_method(Some(BLUEZ), path, Some(PROPERTIES), "Set", &(interface, name, value))?;
+    Ok(())
+}
+
+pub fn set_powered(state: &BluetoothState, powered: bool) -> async_channel::Receiver<Result<()>> {
+    let adapter = state.adapter.clone();
+    spawn("toggle Bluetooth", move |connection| {
+        let adapter = adapter.context("No Bluetooth adapter")?;
+        set_property(connection, adapter.as_str(), ADAPTER, "Powered", Value::from(powered))
+    })
+}
+
+pub fn set_discoverable(state: &BluetoothState, discoverable: bool) -> async_channel::Receiver<Result<()>> {
+    let adapter = state.adapter.clone();
+    spawn("change visibility", move |connection| {
+        let adapter = adapter.context("No Bluetooth adapter")?;
+        set_property(connection, adapter.as_str(), ADAPTER, "Discoverable", Value::from(discoverable))
+    })
+}
+
+pub fn set_name(state: &BluetoothState, name: &str) -> async_channel::Receiver<Result<()>> {
+    let adapter = state.adapter.clone();
+    let name = name.to_string();
+    spawn("rename the adapter", move |connection| {
+        let adapter = adapter.context("No Bluetooth adapter")?;
+        set_property(connection, adapter.as_str(), ADAPTER, "Alias", Value::from(name))
+    })
+}
+
+/// Pairs and trusts the device first if needed, then connects.
+pub fn connect(device: &Device) -> async_channel::Receiver<Result<()>> {
+    let device = device.clone();
+    spawn("connect the device", move |connection| {
+        let path = device.path.as_str();
+        if !device.paired {
+            info!("Pairing with {} ({})", device.name, device.address);
+            connection.call_method(Some(BLUEZ), path, Some(DEVICE), "Pair", &())?;
+            set_property(connection, path, DEVICE, "Trusted", Value::from(true))?;
+        }
+        connection.call_method(Some(BLUEZ), path, Some(DEVICE), "Connect", &())?;
+        Ok(())
+    })
+}
+
+pub fn disconnect(device: &Device) -> async_channel::Receiver<Result<()>> {
+    let path = device.path.clone();
+    spawn("disconnect the device", move |connection| {
+        connection.call_method(Some(BLUEZ), path.as_str(), Some(DEVICE), "Disconnect", &())?;
+        Ok(())
+    })
+}
+
+/// Unpairs the device.
+pub fn forget(state: &BluetoothState, device: &Device) -> async_channel::Receiver<Result<()>> {
+    let adapter = state.adapter.clone();
+    let path = device.path.clone();
+    spawn("forget the device", move |connection| {
+        let adapter = adapter.context("No Bluetooth adapter")?;
+        connection.call_method(Some(BLUEZ), adapter.as_str(), Some(ADAPTER), "RemoveDevice", &path)?;
+        Ok(())
+    })
+}
+
+/// Looks for nearby devices until dropped. BlueZ stops a discovery once
+/// the connection that started it closes, so it is held on its own thread.
+pub struct Discovery {
+    _stop: mpsc::Sender<()>,
+}
+
+impl Discovery {
+    pub fn start(state: &BluetoothState) -> Self {
+        let (stop, stopped) = mpsc::channel::<()>();
+        let adapter = state.adapter.clone();
+
+        std::thread::spawn(move || {
+            let Some(adapter) = adapter else {
+                return;
+            };
+            let result = Connection::system().map_err(anyhow::Error::from).and_then(|connection| {
+                connection.call_method(Some(BLUEZ), adapter.as_str(), Some(ADAPTER), "StartDiscovery", &())?;
+                // Returns once the sender is dropped.
+                let _ = stopped.recv();
+                connection.call_method(Some(BLUEZ), adapter.as_str(), Some(ADAPTER), "StopDiscovery", &())?;
+                Ok(())
+            });
+            if let Err(e) = result {
+                debug!("Bluetooth discovery: {:#}", e);
+            }
+        });
+
+        Self { _stop: stop }
+    }
+}
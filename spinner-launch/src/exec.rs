@@ -0,0 +1,383 @@
+//! Command lines: plain ones such as keybindings, and the `Exec` key of
+//! desktop entries with its field codes.
+
+use anyhow::{bail, Result};
+use std::path::{Path, PathBuf};
+
+/// Splits a command line into words the way a shell would, without
+/// expanding anything. Single and double quotes group words, and a
+/// backslash escapes the next character outside single quotes.
+pub fn split(command: &str) -> Result<Vec<String>> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = command.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {
+                words.extend(word.take());
+            }
+            '\'' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => bail!("Unterminated ' in {:?}", command),
+                    }
+                }
+            }
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        // Inside double quotes only these are escaped.
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\' | '$' | '`')) => word.push(c),
+                            Some('\n') => {}
+                            Some(c) => {
+                                word.push('\\');
+                                word.push(c);
+                            }
+                            None => bail!("Unterminated \" in {:?}", command),
+                        },
+                        Some(c) => word.push(c),
+                        None => bail!("Unterminated \" in {:?}", command),
+                    }
+                }
+            }
+            '\\' => match chars.next() {
+                Some('\n') => {}
+                Some(c) => word.get_or_insert_with(String::new).push(c),
+                None => bail!("Trailing \\ in {:?}", command),
+            },
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+/// What the field codes of an `Exec` line expand to.
+#[derive(Debug, Default)]
+pub struct ExecContext<'a> {
+    /// `%c`, the app's name in the current locale.
+    pub name: &'a str,
+    /// `%i`, dropped when empty.
+    pub icon: &'a str,
+    /// `%k`, the desktop file.
+    pub location: Option<&'a Path>,
+    /// Paths or URIs to open, for `%f`, `%F`, `%u` and `%U`.
+    pub files: &'a [String],
+}
+
+/// A parsed `Exec` line, as in the Desktop Entry Specification.
+#[derive(Debug, Clone)]
+pub struct Exec {
+    args: Vec<String>,
+}
+
+impl Exec {
+    /// Splits the line and checks its field codes. The value is expected
+    /// to be unescaped already, as a desktop entry parser returns it.
+    pub fn parse(exec: &str) -> Result<Self> {
+        let args = split(exec)?;
+        if args.is_empty() {
+            bail!("Empty Exec line");
+        }
+
+        for arg in &args {
+            for code in field_codes(arg) {
+                match code {
+                    Some('F' | 'U' | 'i') if arg.len() != 2 => {
+                        bail!("{:?} in {:?} must be an argument of its own", arg, exec)
+                    }
+                    Some('f' | 'F' | 'u' | 'U' | 'i' | 'c' | 'k' | '%') => {}
+                    // Deprecated, and expanded to nothing.
+                    Some('d' | 'D' | 'n' | 'N' | 'v' | 'm') => {}
+                    Some(code) => bail!("Unknown field code %{} in {:?}", code, exec),
+                    None => bail!("Trailing % in {:?}", exec),
+                }
+            }
+        }
+
+        Ok(Self { args })
+    }
+
+    /// The commands to run. An app taking one file at a time with `%f`
+    /// or `%u` is started once per file, any other just once.
+    pub fn commands(&self, context: &ExecContext) -> Vec<Vec<String>> {
+        let single = self
+            .args
+            .iter()
+            .flat_map(|arg| field_codes(arg))
+            .any(|code| matches!(code, Some('f' | 'u')));
+        if single && context.files.len() > 1 {
+            return context
+                .files
+                .iter()
+                .map(|file| self.expand(context, std::slice::from_ref(file)))
+                .collect();
+        }
+        vec![self.expand(context, context.files)]
+    }
+
+    fn expand(&self, context: &ExecContext, files: &[String]) -> Vec<String> {
+        let mut command = Vec::new();
+
+        for arg in &self.args {
+            match arg.as_str() {
+                "%F" => command.extend(files.iter().filter_map(|file| to_path(file))),
+                "%U" => command.extend(files.iter().map(|file| to_uri(file))),
+                "%i" => {
+                    if !context.icon.is_empty() {
+                        command.push("--icon".to_string());
+                        command.push(context.icon.to_string());
+                    }
+                }
+                _ => {
+                    // Field codes expanding to nothing leave no argument.
+                    let expanded = expand_codes(arg, context, files.first());
+                    if !expanded.is_empty() || field_codes(arg).next().is_none() {
+                        command.push(expanded);
+                    }
+                }
+            }
+        }
+
+        command
+    }
+}
+
+/// The field codes in `arg`, `None` for a trailing `%`.
+fn field_codes(arg: &str) -> impl Iterator<Item = Option<char>> + '_ {
+    let mut chars = arg.chars();
+    std::iter::from_fn(move || {
+        while let Some(c) = chars.next() {
+            if c == '%' {
+                return Some(chars.next());
+            }
+        }
+        None
+    })
+}
+
+fn expand_codes(arg: &str, context: &ExecContext, file: Option<&String>) -> String {
+    let mut result = String::with_capacity(arg.len());
+    let mut chars = arg.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('%') => result.push('%'),
+            Some('f') => result.extend(file.and_then(|file| to_path(file))),
+            Some('u') => result.extend(file.map(|file| to_uri(file))),
+            Some('c') => result.push_str(context.name),
+            Some('k') => {
+                if let Some(location) = context.location {
+                    result.push_str(&location.to_string_lossy());
+                }
+            }
+            _ => {}
+        }
+    }
+    result
+}
+
+/// A local path for a path or `file://` URI. Other URIs can't be opened
+/// as files, so they're dropped.
+fn to_path(file: &str) -> Option<String> {
+    if !file.contains("://") {
+        return Some(file.to_string());
+    }
+    let path = file.strip_prefix("file://")?;
+    // Only the local host, written as nothing or `localhost`.
+    let path = path.strip_prefix("localhost").unwrap_or(path);
+    path.starts_with('/').then(|| percent_decode(path))
+}
+
+/// A URI for a path or URI, relative paths resolved against the working
+/// directory.
+fn to_uri(file: &str) -> String {
+    if file.contains("://") {
+        return file.to_string();
+    }
+    let path = Path::new(file);
+    let path = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        std::env::current_dir()
+            .map(|dir| dir.join(path))
+            .unwrap_or_else(|_| PathBuf::from(file))
+    };
+    format!("file://{}", percent_encode(&path.to_string_lossy()))
+}
+
+fn percent_encode(path: &str) -> String {
+    let mut result = String::with_capacity(path.len());
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' => {
+                result.push(byte as char)
+            }
+            _ => result.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    result
+}
+
+fn percent_decode(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut result = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                result.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                result.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&result).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(command: &str) -> Vec<String> {
+        split(command).unwrap()
+    }
+
+    fn run(exec: &str, files: &[&str]) -> Vec<Vec<String>> {
+        let files: Vec<String> = files.iter().map(|file| file.to_string()).collect();
+        let context = ExecContext {
+            name: "Text Editor",
+            icon: "accessories-text-editor",
+            location: Some(Path::new("/usr/share/applications/editor.desktop")),
+            files: &files,
+        };
+        Exec::parse(exec).unwrap().commands(&context)
+    }
+
+    #[test]
+    fn split_on_whitespace() {
+        assert_eq!(
+            words("  firefox   --new-window\tx "),
+            ["firefox", "--new-window", "x"]
+        );
+        assert!(words("   ").is_empty());
+    }
+
+    #[test]
+    fn split_quotes() {
+        assert_eq!(
+            words("sh -c 'echo \"$HOME\" \\n'"),
+            ["sh", "-c", "echo \"$HOME\" \\n"]
+        );
+        assert_eq!(words("echo \"a 'b' c\""), ["echo", "a 'b' c"]);
+        assert_eq!(words("a'b c'\"d\"e"), ["ab cde"]);
+        assert_eq!(words("echo '' \"\""), ["echo", "", ""]);
+    }
+
+    #[test]
+    fn split_escapes() {
+        assert_eq!(words("a\\ b c\\\\d \\'"), ["a b", "c\\d", "'"]);
+        assert_eq!(words("\"\\\" \\\\ \\$ \\` \\n\""), ["\" \\ $ ` \\n"]);
+        assert_eq!(words("a\\\nb"), ["ab"]);
+    }
+
+    #[test]
+    fn split_errors() {
+        assert!(split("echo 'open").is_err());
+        assert!(split("echo \"open").is_err());
+        assert!(split("echo \"open\\").is_err());
+        assert!(split("echo \\").is_err());
+    }
+
+    #[test]
+    fn single_file_codes_run_once_per_file() {
+        assert_eq!(
+            run("editor %f", &["/a", "/b"]),
+            [vec!["editor", "/a"], vec!["editor", "/b"]]
+        );
+        assert_eq!(
+            run("viewer %u", &["/a", "https://example.com/"]),
+            [
+                vec!["viewer", "file:///a"],
+                vec!["viewer", "https://example.com/"]
+            ]
+        );
+        assert_eq!(run("editor %f", &[]), [vec!["editor"]]);
+    }
+
+    #[test]
+    fn file_lists() {
+        assert_eq!(
+            run(
+                "editor %F",
+                &[
+                    "/a b",
+                    "file:///c%20d",
+                    "file://localhost/e",
+                    "https://example.com/"
+                ]
+            ),
+            [vec!["editor", "/a b", "/c d", "/e"]]
+        );
+        assert_eq!(
+            run("viewer %U", &["/a b", "https://example.com/"]),
+            [vec!["viewer", "file:///a%20b", "https://example.com/"]]
+        );
+        assert_eq!(run("editor --files %F", &[]), [vec!["editor", "--files"]]);
+    }
+
+    #[test]
+    fn other_codes() {
+        assert_eq!(
+            run("editor %i", &[]),
+            [vec!["editor", "--icon", "accessories-text-editor"]]
+        );
+        assert_eq!(
+            run("editor --name=%c", &[]),
+            [vec!["editor", "--name=Text Editor"]]
+        );
+        assert_eq!(
+            run("editor --from %k", &[]),
+            [vec![
+                "editor",
+                "--from",
+                "/usr/share/applications/editor.desktop"
+            ]]
+        );
+        assert_eq!(run("printf 100%% %d", &[]), [vec!["printf", "100%"]]);
+
+        let context = ExecContext {
+            name: "Editor",
+            ..Default::default()
+        };
+        assert_eq!(
+            Exec::parse("editor %i").unwrap().commands(&context),
+            [vec!["editor"]]
+        );
+    }
+
+    #[test]
+    fn invalid_field_codes() {
+        assert!(Exec::parse("").is_err());
+        assert!(Exec::parse("editor --files=%F").is_err());
+        assert!(Exec::parse("editor %x").is_err());
+        assert!(Exec::parse("editor 100%").is_err());
+    }
+}
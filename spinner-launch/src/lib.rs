@@ -0,0 +1,129 @@
+//! SpinnerLaunch - starting applications for SpinnerOS
+//!
+//! Shared by the compositor, for keybindings and autostart, and the shell,
+//! for desktop entries. Commands are split with shell quoting, `Exec` lines
+//! expand their field codes, and every launch runs in a systemd user scope
+//! of its own when a user manager is running.
+
+pub mod dbus;
+mod exec;
+mod scope;
+mod terminal;
+
+pub use exec::{split, Exec, ExecContext};
+
+use anyhow::{Context, Result};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use tracing::{debug, warn};
+
+/// A program to start, detached from the caller.
+#[derive(Debug, Clone)]
+pub struct Launch {
+    args: Vec<String>,
+    app_id: Option<String>,
+    working_dir: Option<PathBuf>,
+    terminal: bool,
+    activation_token: Option<String>,
+}
+
+impl Launch {
+    pub fn new(args: Vec<String>) -> Self {
+        Self {
+            args,
+            app_id: None,
+            working_dir: None,
+            terminal: false,
+            activation_token: None,
+        }
+    }
+
+    /// A plain command line, split with [`split`].
+    pub fn command(command: &str) -> Result<Self> {
+        Ok(Self::new(split(command)?))
+    }
+
+    /// Names the app's scope; the program's name is used otherwise.
+    pub fn app_id(mut self, app_id: impl Into<String>) -> Self {
+        self.app_id = Some(app_id.into());
+        self
+    }
+
+    pub fn working_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.working_dir = Some(dir.into());
+        self
+    }
+
+    /// Runs the program in a terminal emulator.
+    pub fn terminal(mut self, terminal: bool) -> Self {
+        self.terminal = terminal;
+        self
+    }
+
+    /// An xdg-activation token, passed on in `XDG_ACTIVATION_TOKEN` and
+    /// `DESKTOP_STARTUP_ID` so the app's window may take focus.
+    pub fn activation_token(mut self, token: impl Into<String>) -> Self {
+        self.activation_token = Some(token.into());
+        self
+    }
+
+    /// Starts the program. It is waited for on a thread of its own, so
+    /// nothing is left behind when it exits.
+    pub fn spawn(self) -> Result<()> {
+        let program = self.args.first().context("No command to run")?.clone();
+        let app_id = self
+            .app_id
+            .unwrap_or_else(|| program.rsplit('/').next().unwrap_or(&program).to_string());
+
+        let mut args = self.args;
+        if self.terminal {
+            args = terminal::wrap(args)?;
+        }
+        if let Some(scope) = scope::command(&app_id) {
+            args = scope.into_iter().chain(args).collect();
+        }
+
+        let mut command = Command::new(&args[0]);
+        command.args(&args[1..]).stdin(Stdio::null());
+        if let Some(dir) = self.working_dir {
+            if dir.is_dir() {
+                command.current_dir(dir);
+            } else {
+                warn!("Working directory {:?} of {} does not exist", dir, app_id);
+            }
+        }
+        // A token the caller was started with is not meant for the app.
+        match &self.activation_token {
+            Some(token) => command
+                .env("XDG_ACTIVATION_TOKEN", token)
+                .env("DESKTOP_STARTUP_ID", token),
+            None => command
+                .env_remove("XDG_ACTIVATION_TOKEN")
+                .env_remove("DESKTOP_STARTUP_ID"),
+        };
+
+        let mut child = command
+            .spawn()
+            .with_context(|| format!("Failed to start {}", program))?;
+        debug!("Started {} as {}", app_id, child.id());
+        std::thread::spawn(move || {
+            let _ = child.wait();
+        });
+        Ok(())
+    }
+}
+
+/// Whether `program` is an executable path, or one on `$PATH`.
+pub fn is_installed(program: &str) -> bool {
+    let is_executable = |path: &Path| {
+        path.metadata()
+            .is_ok_and(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+    };
+    if program.contains('/') {
+        return is_executable(Path::new(program));
+    }
+    std::env::var_os("PATH").is_some_and(|path| {
+        std::env::split_paths(&path).any(|dir| is_executable(&dir.join(program)))
+    })
+}
@@ -0,0 +1,64 @@
+//! systemd user scopes, one per launched app
+//!
+//! Units are named `app-spinner-<id>-<random>.scope` in `app.slice`, as in
+//! systemd's desktop environment conventions, so resource control and
+//! `systemctl --user` see each app on its own.
+
+use crate::is_installed;
+
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+static COUNTER: AtomicU32 = AtomicU32::new(0);
+
+/// The `systemd-run` prefix that starts a command in a new scope, or
+/// `None` without a user manager to ask.
+pub fn command(app_id: &str) -> Option<Vec<String>> {
+    let private =
+        std::env::var_os("XDG_RUNTIME_DIR").map(|dir| PathBuf::from(dir).join("systemd/private"));
+    if !private.is_some_and(|socket| socket.exists()) || !is_installed("systemd-run") {
+        return None;
+    }
+
+    Some(vec![
+        "systemd-run".to_string(),
+        "--user".to_string(),
+        "--scope".to_string(),
+        "--quiet".to_string(),
+        "--collect".to_string(),
+        "--slice=app.slice".to_string(),
+        format!("--unit={}", unit_name(app_id)),
+        "--".to_string(),
+    ])
+}
+
+fn unit_name(app_id: &str) -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.subsec_nanos());
+    let serial = COUNTER.fetch_add(1, Ordering::Relaxed);
+    format!(
+        "app-spinner-{}-{:x}{:x}{:x}.scope",
+        escape(app_id.trim_end_matches(".desktop")),
+        std::process::id(),
+        serial,
+        nanos
+    )
+}
+
+/// Escapes like `systemd-escape`: `-` separates the parts of the name, so
+/// it and anything else outside `[A-Za-z0-9:_.]` becomes `\xNN`.
+fn escape(id: &str) -> String {
+    let mut result = String::with_capacity(id.len());
+    for (i, byte) in id.bytes().enumerate() {
+        match byte {
+            b'.' if i == 0 => result.push_str("\\x2e"),
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b':' | b'_' | b'.' => {
+                result.push(byte as char)
+            }
+            _ => result.push_str(&format!("\\x{:02x}", byte)),
+        }
+    }
+    result
+}
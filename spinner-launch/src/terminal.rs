@@ -0,0 +1,56 @@
+//! Running `Terminal=true` apps in a terminal emulator
+
+use crate::exec::split;
+use crate::is_installed;
+
+use anyhow::{bail, Result};
+
+/// Terminals tried in order, with the option that runs a command in them.
+/// `None` takes the command right after the terminal's own arguments.
+const TERMINALS: &[(&str, Option<&str>)] = &[
+    ("gnome-terminal", Some("--")),
+    ("xfce4-terminal", Some("-x")),
+    ("foot", None),
+    ("alacritty", Some("-e")),
+    ("kitty", None),
+    ("konsole", Some("-e")),
+    ("xterm", Some("-e")),
+];
+
+/// `args` with the terminal to run them in in front. `$TERMINAL` takes
+/// precedence over the terminals found on `$PATH`.
+pub fn wrap(args: Vec<String>) -> Result<Vec<String>> {
+    let mut command = match std::env::var("TERMINAL")
+        .ok()
+        .filter(|t| !t.trim().is_empty())
+    {
+        Some(terminal) => {
+            let mut command = split(&terminal)?;
+            let name = command
+                .first()
+                .and_then(|program| program.rsplit('/').next())
+                .unwrap_or_default();
+            // Most terminals take `-e`, so unknown ones get it too.
+            let option = TERMINALS
+                .iter()
+                .find(|(known, _)| *known == name)
+                .map_or(Some("-e"), |(_, option)| *option);
+            command.extend(option.map(str::to_string));
+            command
+        }
+        None => {
+            let Some((terminal, option)) = TERMINALS
+                .iter()
+                .find(|(terminal, _)| is_installed(terminal))
+            else {
+                bail!("No terminal emulator installed");
+            };
+            std::iter::once(*terminal)
+                .chain(*option)
+                .map(str::to_string)
+                .collect()
+        }
+    };
+    command.extend(args);
+    Ok(command)
+}
@@ -0,0 +1,151 @@
+//! Configuration for the SpinnerOS portal backend
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use xdg::BaseDirectories;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PortalConfig {
+    pub screenshot: ScreenshotConfig,
+    pub screencast: ScreenCastConfig,
+    pub file_chooser: FileChooserConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScreenshotConfig {
+    /// Command that captures the full screen; the output path is appended.
+    pub command: String,
+    /// Command that prints a `x,y wxh` geometry for interactive captures.
+    pub select_command: String,
+    pub directory: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScreenCastConfig {
+    /// Helper that publishes a PipeWire video node for the requested
+    /// output and prints `<node_id> <width> <height>` on its first line.
+    pub helper: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FileChooserConfig {
+    pub command: String,
+}
+
+impl Default for ScreenshotConfig {
+    fn default() -> Self {
+        Self {
+            command: "grim".to_string(),
+            select_command: "slurp".to_string(),
+            directory: None,
+        }
+    }
+}
+
+impl Default for ScreenCastConfig {
+    fn default() -> Self {
+        Self {
+            helper: Some("spinner-screencast".to_string()),
+        }
+    }
+}
+
+impl Default for FileChooserConfig {
+    fn default() -> Self {
+        Self {
+            command: "zenity".to_string(),
+        }
+    }
+}
+
+impl PortalConfig {
+    pub fn load() -> Result<Self> {
+        let path = config_dir()?.join("spinner-portal.toml");
+
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let contents = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read config from {:?}", path))?;
+
+        toml::from_str(&contents).with_context(|| "Failed to parse portal config")
+    }
+}
+
+/// The `[theme]` section of spinner-shell.toml, which is where the shell's
+/// `ThemeManager` takes dark mode and accent colour from.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ThemeConfig {
+    pub variant: String,
+    pub accent_color: String,
+}
+
+impl Default for ThemeConfig {
+    fn default() -> Self {
+        Self {
+            variant: "dark".to_string(),
+            accent_color: "#88c0d0".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ShellConfigFile {
+    #[serde(default)]
+    theme: ThemeConfig,
+}
+
+impl ThemeConfig {
+    pub fn load() -> Result<Self> {
+        let path = Self::path()?;
+
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let contents = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read config from {:?}", path))?;
+
+        let file: ShellConfigFile = toml::from_str(&contents)
+            .with_context(|| "Failed to parse spinner-shell.toml")?;
+
+        Ok(file.theme)
+    }
+
+    pub fn path() -> Result<PathBuf> {
+        Ok(config_dir()?.join("spinner-shell.toml"))
+    }
+
+    pub fn is_dark(&self) -> bool {
+        self.variant != "light"
+    }
+
+    /// Accent colour as RGB components in the 0.0..=1.0 range.
+    pub fn accent_rgb(&self) -> Option<(f64, f64, f64)> {
+        let hex = self.accent_color.strip_prefix('#')?;
+        if hex.len() != 6 {
+            return None;
+        }
+
+        let channel = |i: usize| {
+            u8::from_str_radix(&hex[i..i + 2], 16)
+                .ok()
+                .map(|v| f64::from(v) / 255.0)
+        };
+
+        Some((channel(0)?, channel(2)?, channel(4)?))
+    }
+}
+
+fn config_dir() -> Result<PathBuf> {
+    let xdg = BaseDirectories::with_prefix("spinneros")?;
+    Ok(xdg.get_config_home())
+}
@@ -0,0 +1,279 @@
+//! `org.freedesktop.impl.portal.FileChooser` backend
+//!
+//! Dialogs are shown with zenity so the portal does not need a GTK main
+//! loop of its own.
+
+use crate::config::FileChooserConfig;
+use crate::request::{file_uri, PendingRequest, Results, RESPONSE_CANCELLED, RESPONSE_OTHER, RESPONSE_SUCCESS};
+
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tokio::process::Command;
+use tracing::{info, warn};
+use zbus::zvariant::{OwnedObjectPath, OwnedValue, Value};
+use zbus::{dbus_interface, Connection};
+
+const FILTER_GLOB: u32 = 0;
+
+type Filter = (String, Vec<(u32, String)>);
+
+#[derive(Debug, Default)]
+struct DialogOptions {
+    title: String,
+    multiple: bool,
+    directory: bool,
+    save: bool,
+    filename: Option<PathBuf>,
+    filters: Vec<Filter>,
+}
+
+fn option_bool(options: &HashMap<String, OwnedValue>, key: &str) -> bool {
+    options
+        .get(key)
+        .and_then(|v| bool::try_from(v.clone()).ok())
+        .unwrap_or(false)
+}
+
+fn option_string(options: &HashMap<String, OwnedValue>, key: &str) -> Option<String> {
+    options.get(key).and_then(|v| String::try_from(v.clone()).ok())
+}
+
+/// Portal paths are NUL-terminated byte strings.
+fn option_path(options: &HashMap<String, OwnedValue>, key: &str) -> Option<PathBuf> {
+    use std::os::unix::ffi::OsStringExt;
+
+    let mut bytes = options
+        .get(key)
+        .and_then(|v| Vec::<u8>::try_from(v.clone()).ok())?;
+    if bytes.last() == Some(&0) {
+        bytes.pop();
+    }
+    if bytes.is_empty() {
+        return None;
+    }
+    Some(PathBuf::from(std::ffi::OsString::from_vec(bytes)))
+}
+
+fn option_filters(options: &HashMap<String, OwnedValue>) -> Vec<Filter> {
+    options
+        .get("filters")
+        .and_then(|v| Vec::<Filter>::try_from(v.clone()).ok())
+        .unwrap_or_default()
+}
+
+pub struct FileChooser {
+    config: FileChooserConfig,
+}
+
+impl FileChooser {
+    pub fn new(config: FileChooserConfig) -> Self {
+        Self { config }
+    }
+
+    fn command(&self, dialog: &DialogOptions) -> Result<Command> {
+        let mut parts = self.config.command.split_whitespace();
+        let program = parts.next().context("Empty file chooser command")?;
+
+        let mut cmd = Command::new(program);
+        cmd.args(parts)
+            .arg("--file-selection")
+            .arg(format!("--title={}", dialog.title))
+            .arg("--separator=\n")
+            .kill_on_drop(true);
+
+        if dialog.multiple {
+            cmd.arg("--multiple");
+        }
+        if dialog.directory {
+            cmd.arg("--directory");
+        }
+        if dialog.save {
+            cmd.arg("--save").arg("--confirm-overwrite");
+        }
+        if let Some(filename) = &dialog.filename {
+            cmd.arg(format!("--filename={}", filename.display()));
+        }
+
+        for (name, patterns) in &dialog.filters {
+            // zenity can only filter on globs; MIME type patterns are dropped.
+            let globs: Vec<&str> = patterns
+                .iter()
+                .filter(|(kind, _)| *kind == FILTER_GLOB)
+                .map(|(_, pattern)| pattern.as_str())
+                .collect();
+            if !globs.is_empty() {
+                cmd.arg(format!("--file-filter={} | {}", name, globs.join(" ")));
+            }
+        }
+
+        Ok(cmd)
+    }
+
+    async fn run(&self, dialog: &DialogOptions) -> Result<Option<Vec<String>>> {
+        let output = self
+            .command(dialog)?
+            .output()
+            .await
+            .context("Failed to run file chooser")?;
+
+        // zenity exits with 1 when the dialog is cancelled.
+        if !output.status.success() {
+            return Ok(None);
+        }
+
+        let paths = String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter(|line| !line.is_empty())
+            .map(str::to_string)
+            .collect();
+        Ok(Some(paths))
+    }
+
+    async fn respond(
+        &self,
+        connection: &Connection,
+        handle: OwnedObjectPath,
+        dialog: DialogOptions,
+        map_paths: impl FnOnce(Vec<String>) -> Vec<String>,
+    ) -> (u32, Results) {
+        let mut request = match PendingRequest::export(connection, &handle).await {
+            Ok(request) => request,
+            Err(e) => {
+                warn!("Failed to export request {}: {}", handle.as_str(), e);
+                return (RESPONSE_OTHER, Results::new());
+            }
+        };
+
+        let outcome = tokio::select! {
+            result = self.run(&dialog) => Some(result),
+            _ = request.cancelled() => None,
+        };
+        request.finish().await;
+
+        match outcome {
+            Some(Ok(Some(paths))) if !paths.is_empty() => {
+                let uris = map_paths(paths)
+                    .iter()
+                    .map(|path| file_uri(Path::new(path)))
+                    .collect::<Result<Vec<String>>>();
+                match uris {
+                    Ok(uris) => {
+                        let mut results = Results::new();
+                        results.insert("uris".to_string(), Value::from(uris).into());
+                        (RESPONSE_SUCCESS, results)
+                    }
+                    Err(e) => {
+                        warn!("File chooser failed: {:#}", e);
+                        (RESPONSE_OTHER, Results::new())
+                    }
+                }
+            }
+            Some(Ok(_)) | None => (RESPONSE_CANCELLED, Results::new()),
+            Some(Err(e)) => {
+                warn!("File chooser failed: {:#}", e);
+                (RESPONSE_OTHER, Results::new())
+            }
+        }
+    }
+}
+
+#[dbus_interface(name = "org.freedesktop.impl.portal.FileChooser")]
+impl FileChooser {
+    async fn open_file(
+        &self,
+        #[zbus(connection)] connection: &Connection,
+        handle: OwnedObjectPath,
+        app_id: String,
+        _parent_window: String,
+        title: String,
+        options: HashMap<String, OwnedValue>,
+    ) -> (u32, Results) {
+        info!("Open file dialog requested by {:?}", app_id);
+
+        let dialog = DialogOptions {
+            title,
+            multiple: option_bool(&options, "multiple"),
+            directory: option_bool(&options, "directory"),
+            save: false,
+            filename: option_path(&options, "current_folder").map(|dir| dir.join("")),
+            filters: option_filters(&options),
+        };
+
+        self.respond(connection, handle, dialog, |paths| paths).await
+    }
+
+    async fn save_file(
+        &self,
+        #[zbus(connection)] connection: &Connection,
+        handle: OwnedObjectPath,
+        app_id: String,
+        _parent_window: String,
+        title: String,
+        options: HashMap<String, OwnedValue>,
+    ) -> (u32, Results) {
+        info!("Save file dialog requested by {:?}", app_id);
+
+        let filename = option_path(&options, "current_file").or_else(|| {
+            let name = option_string(&options, "current_name")?;
+            let dir = option_path(&options, "current_folder").unwrap_or_default();
+            Some(dir.join(name))
+        });
+
+        let dialog = DialogOptions {
+            title,
+            save: true,
+            filename,
+            filters: option_filters(&options),
+            ..Default::default()
+        };
+
+        self.respond(connection, handle, dialog, |paths| paths).await
+    }
+
+    async fn save_files(
+        &self,
+        #[zbus(connection)] connection: &Connection,
+        handle: OwnedObjectPath,
+        app_id: String,
+        _parent_window: String,
+        title: String,
+        options: HashMap<String, OwnedValue>,
+    ) -> (u32, Results) {
+        info!("Save files dialog requested by {:?}", app_id);
+
+        let files: Vec<String> = options
+            .get("files")
+            .and_then(|v| Vec::<Vec<u8>>::try_from(v.clone()).ok())
+            .unwrap_or_default()
+            .into_iter()
+            .map(|mut name| {
+                if name.last() == Some(&0) {
+                    name.pop();
+                }
+                String::from_utf8_lossy(&name).into_owned()
+            })
+            .collect();
+
+        let dialog = DialogOptions {
+            title,
+            directory: true,
+            filename: option_path(&options, "current_folder").map(|dir| dir.join("")),
+            ..Default::default()
+        };
+
+        self.respond(connection, handle, dialog, move |dirs| {
+            let dir = PathBuf::from(&dirs[0]);
+            files
+                .iter()
+                .map(|name| dir.join(name).display().to_string())
+                .collect()
+        })
+        .await
+    }
+
+    #[dbus_interface(property, name = "version")]
+    fn version(&self) -> u32 {
+        4
+    }
+}
@@ -0,0 +1,78 @@
+//! `org.freedesktop.impl.portal.Request` objects
+//!
+//! The frontend exports a handle per call and may ask the backend to close
+//! it while a dialog is still open; closing cancels the pending call.
+
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use std::path::Path;
+use tokio::sync::watch;
+use url::Url;
+use zbus::zvariant::{ObjectPath, OwnedObjectPath, OwnedValue};
+use zbus::{dbus_interface, Connection};
+
+pub const RESPONSE_SUCCESS: u32 = 0;
+pub const RESPONSE_CANCELLED: u32 = 1;
+pub const RESPONSE_OTHER: u32 = 2;
+
+pub type Results = HashMap<String, OwnedValue>;
+
+/// The `file://` URI of an absolute path, with spaces and other reserved
+/// characters percent-encoded.
+pub fn file_uri(path: &Path) -> Result<String> {
+    Url::from_file_path(path)
+        .map(String::from)
+        .map_err(|_| anyhow!("{:?} is not an absolute path", path))
+}
+
+pub struct Request {
+    cancel: watch::Sender<bool>,
+}
+
+#[dbus_interface(name = "org.freedesktop.impl.portal.Request")]
+impl Request {
+    async fn close(&self) {
+        let _ = self.cancel.send(true);
+    }
+}
+
+/// A request exported on the bus for the duration of a portal call.
+pub struct PendingRequest {
+    connection: Connection,
+    handle: OwnedObjectPath,
+    cancelled: watch::Receiver<bool>,
+}
+
+impl PendingRequest {
+    pub async fn export(connection: &Connection, handle: &ObjectPath<'_>) -> zbus::Result<Self> {
+        let (cancel, cancelled) = watch::channel(false);
+
+        connection
+            .object_server()
+            .at(handle, Request { cancel })
+            .await?;
+
+        Ok(Self {
+            connection: connection.clone(),
+            handle: handle.to_owned().into(),
+            cancelled,
+        })
+    }
+
+    /// Resolves once the frontend closes the request.
+    pub async fn cancelled(&mut self) {
+        while !*self.cancelled.borrow() {
+            if self.cancelled.changed().await.is_err() {
+                std::future::pending::<()>().await;
+            }
+        }
+    }
+
+    pub async fn finish(self) {
+        let _ = self
+            .connection
+            .object_server()
+            .remove::<Request, _>(&self.handle)
+            .await;
+    }
+}
@@ -0,0 +1,285 @@
+//! `org.freedesktop.impl.portal.ScreenCast` backend
+//!
+//! SpinnerWM does not encode frames itself. Each started session spawns the
+//! configured capture helper, which publishes a PipeWire video node and
+//! reports its id; the helper lives exactly as long as the portal session.
+
+use crate::config::ScreenCastConfig;
+use crate::request::{PendingRequest, Results, RESPONSE_CANCELLED, RESPONSE_OTHER, RESPONSE_SUCCESS};
+
+use anyhow::{bail, Context, Result};
+use std::collections::HashMap;
+use std::future::Future;
+use std::process::Stdio;
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::process::{Child, Command};
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+use zbus::zvariant::{OwnedObjectPath, OwnedValue, Value};
+use zbus::{dbus_interface, Connection, SignalContext};
+
+const SOURCE_MONITOR: u32 = 1;
+const CURSOR_HIDDEN: u32 = 1;
+const CURSOR_EMBEDDED: u32 = 2;
+
+#[derive(Default)]
+struct CastSession {
+    app_id: String,
+    cursor_mode: u32,
+    helper: Option<Child>,
+}
+
+type Sessions = Arc<Mutex<HashMap<OwnedObjectPath, CastSession>>>;
+
+pub struct ScreenCast {
+    config: ScreenCastConfig,
+    sessions: Sessions,
+}
+
+impl ScreenCast {
+    pub fn new(config: ScreenCastConfig) -> Self {
+        Self {
+            config,
+            sessions: Arc::default(),
+        }
+    }
+
+    async fn start_helper(&self, cursor_mode: u32) -> Result<(Child, u32, (i32, i32))> {
+        let Some(helper) = &self.config.helper else {
+            bail!("No screencast helper configured in spinner-portal.toml");
+        };
+
+        let mut parts = helper.split_whitespace();
+        let program = parts.next().context("Empty screencast helper")?;
+
+        let mut child = Command::new(program)
+            .args(parts)
+            .arg("--cursor")
+            .arg(if cursor_mode == CURSOR_EMBEDDED { "embedded" } else { "hidden" })
+            .stdout(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .with_context(|| format!("Failed to start {}", program))?;
+
+        let stdout = child.stdout.take().context("Helper has no stdout")?;
+        let mut line = String::new();
+        BufReader::new(stdout)
+            .read_line(&mut line)
+            .await
+            .context("Failed to read helper output")?;
+
+        let mut fields = line.split_whitespace().map(str::parse::<u32>);
+        let (Some(Ok(node_id)), Some(Ok(width)), Some(Ok(height))) =
+            (fields.next(), fields.next(), fields.next())
+        else {
+            bail!("Unexpected helper output: {:?}", line.trim());
+        };
+
+        Ok((child, node_id, (width as i32, height as i32)))
+    }
+
+    /// Runs `call` with its request exported, answering `RESPONSE_CANCELLED`
+    /// if the frontend closes the request first.
+    async fn respond(
+        &self,
+        connection: &Connection,
+        handle: OwnedObjectPath,
+        call: impl Future<Output = (u32, Results)>,
+    ) -> (u32, Results) {
+        let mut request = match PendingRequest::export(connection, &handle).await {
+            Ok(request) => request,
+            Err(e) => {
+                warn!("Failed to export request {}: {}", handle.as_str(), e);
+                return (RESPONSE_OTHER, Results::new());
+            }
+        };
+
+        let response = tokio::select! {
+            response = call => response,
+            _ = request.cancelled() => (RESPONSE_CANCELLED, Results::new()),
+        };
+        request.finish().await;
+
+        response
+    }
+
+    async fn create(
+        &self,
+        connection: &Connection,
+        session_handle: OwnedObjectPath,
+        app_id: String,
+    ) -> (u32, Results) {
+        info!("Screencast session {} created for {:?}", session_handle.as_str(), app_id);
+
+        let session = Session {
+            path: session_handle.clone(),
+            sessions: self.sessions.clone(),
+        };
+
+        if let Err(e) = connection.object_server().at(&session_handle, session).await {
+            warn!("Failed to export session {}: {}", session_handle.as_str(), e);
+            return (RESPONSE_OTHER, Results::new());
+        }
+
+        self.sessions.lock().await.insert(
+            session_handle,
+            CastSession {
+                app_id,
+                cursor_mode: CURSOR_HIDDEN,
+                helper: None,
+            },
+        );
+
+        (RESPONSE_SUCCESS, Results::new())
+    }
+
+    async fn select(
+        &self,
+        session_handle: OwnedObjectPath,
+        options: HashMap<String, OwnedValue>,
+    ) -> (u32, Results) {
+        let mut sessions = self.sessions.lock().await;
+        let Some(session) = sessions.get_mut(&session_handle) else {
+            return (RESPONSE_OTHER, Results::new());
+        };
+
+        if let Some(mode) = options
+            .get("cursor_mode")
+            .and_then(|v| u32::try_from(v.clone()).ok())
+        {
+            session.cursor_mode = mode;
+        }
+
+        (RESPONSE_SUCCESS, Results::new())
+    }
+
+    async fn stream(&self, session_handle: OwnedObjectPath) -> (u32, Results) {
+        let cursor_mode = match self.sessions.lock().await.get(&session_handle) {
+            Some(session) => session.cursor_mode,
+            None => return (RESPONSE_OTHER, Results::new()),
+        };
+
+        let (child, node_id, size) = match self.start_helper(cursor_mode).await {
+            Ok(started) => started,
+            Err(e) => {
+                warn!("Failed to start screencast: {:#}", e);
+                return (RESPONSE_OTHER, Results::new());
+            }
+        };
+
+        let mut sessions = self.sessions.lock().await;
+        let Some(session) = sessions.get_mut(&session_handle) else {
+            // Closed while the helper was starting; dropping the child kills it.
+            return (RESPONSE_OTHER, Results::new());
+        };
+        session.helper = Some(child);
+        info!("Streaming PipeWire node {} to {:?}", node_id, session.app_id);
+
+        let mut properties: HashMap<String, OwnedValue> = HashMap::new();
+        properties.insert("position".to_string(), Value::from((0i32, 0i32)).into());
+        properties.insert("size".to_string(), Value::from(size).into());
+        properties.insert("source_type".to_string(), Value::from(SOURCE_MONITOR).into());
+
+        let streams = vec![(node_id, properties)];
+        let mut results = Results::new();
+        results.insert("streams".to_string(), Value::from(streams).into());
+
+        (RESPONSE_SUCCESS, results)
+    }
+}
+
+#[dbus_interface(name = "org.freedesktop.impl.portal.ScreenCast")]
+impl ScreenCast {
+    async fn create_session(
+        &self,
+        #[zbus(connection)] connection: &Connection,
+        handle: OwnedObjectPath,
+        session_handle: OwnedObjectPath,
+        app_id: String,
+        _options: HashMap<String, OwnedValue>,
+    ) -> (u32, Results) {
+        let call = self.create(connection, session_handle, app_id);
+        self.respond(connection, handle, call).await
+    }
+
+    async fn select_sources(
+        &self,
+        #[zbus(connection)] connection: &Connection,
+        handle: OwnedObjectPath,
+        session_handle: OwnedObjectPath,
+        _app_id: String,
+        options: HashMap<String, OwnedValue>,
+    ) -> (u32, Results) {
+        let call = self.select(session_handle, options);
+        self.respond(connection, handle, call).await
+    }
+
+    async fn start(
+        &self,
+        #[zbus(connection)] connection: &Connection,
+        handle: OwnedObjectPath,
+        session_handle: OwnedObjectPath,
+        _app_id: String,
+        _parent_window: String,
+        _options: HashMap<String, OwnedValue>,
+    ) -> (u32, Results) {
+        // Closing the request drops a helper that is still starting.
+        let call = self.stream(session_handle);
+        self.respond(connection, handle, call).await
+    }
+
+    #[dbus_interface(property)]
+    fn available_source_types(&self) -> u32 {
+        SOURCE_MONITOR
+    }
+
+    #[dbus_interface(property)]
+    fn available_cursor_modes(&self) -> u32 {
+        CURSOR_HIDDEN | CURSOR_EMBEDDED
+    }
+
+    #[dbus_interface(property, name = "version")]
+    fn version(&self) -> u32 {
+        4
+    }
+}
+
+/// `org.freedesktop.impl.portal.Session` for a screencast session.
+pub struct Session {
+    path: OwnedObjectPath,
+    sessions: Sessions,
+}
+
+#[dbus_interface(name = "org.freedesktop.impl.portal.Session")]
+impl Session {
+    async fn close(
+        &self,
+        #[zbus(connection)] connection: &Connection,
+        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+    ) {
+        if let Some(session) = self.sessions.lock().await.remove(&self.path) {
+            info!("Screencast session for {:?} closed", session.app_id);
+        }
+
+        let _ = Self::closed(&ctxt).await;
+
+        // Unexport outside of our own method call.
+        let connection = connection.clone();
+        let path = self.path.clone();
+        tokio::spawn(async move {
+            let _ = connection
+                .object_server()
+                .remove::<Session, _>(&path)
+                .await;
+        });
+    }
+
+    #[dbus_interface(signal)]
+    async fn closed(ctxt: &SignalContext<'_>) -> zbus::Result<()>;
+
+    #[dbus_interface(property, name = "version")]
+    fn version(&self) -> u32 {
+        1
+    }
+}
@@ -0,0 +1,228 @@
+//! `org.freedesktop.impl.portal.Screenshot` backend
+
+use crate::config::ScreenshotConfig;
+use crate::request::{file_uri, PendingRequest, Results, RESPONSE_CANCELLED, RESPONSE_OTHER, RESPONSE_SUCCESS};
+
+use anyhow::{bail, Context, Result};
+use chrono::Local;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use tokio::process::Command;
+use tracing::{info, warn};
+use zbus::zvariant::{OwnedObjectPath, OwnedValue, Value};
+use zbus::{dbus_interface, Connection};
+
+pub struct Screenshot {
+    config: ScreenshotConfig,
+}
+
+impl Screenshot {
+    pub fn new(config: ScreenshotConfig) -> Self {
+        Self { config }
+    }
+
+    fn output_dir(&self) -> PathBuf {
+        if let Some(dir) = &self.config.directory {
+            return dir.clone();
+        }
+
+        let pictures = std::env::var_os("XDG_PICTURES_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| {
+                let home = std::env::var_os("HOME").unwrap_or_default();
+                PathBuf::from(home).join("Pictures")
+            });
+        pictures.join("Screenshots")
+    }
+
+    async fn select_region(&self, point: bool) -> Result<Option<String>> {
+        let mut parts = self.config.select_command.split_whitespace();
+        let program = parts.next().context("Empty select command")?;
+
+        let mut cmd = Command::new(program);
+        cmd.args(parts).kill_on_drop(true);
+        if point {
+            cmd.arg("-p");
+        }
+
+        let output = cmd
+            .output()
+            .await
+            .with_context(|| format!("Failed to run {}", program))?;
+
+        // slurp exits non-zero when the selection is aborted with Escape.
+        if !output.status.success() {
+            return Ok(None);
+        }
+
+        let geometry = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        Ok(Some(geometry))
+    }
+
+    fn capture_command(&self, geometry: Option<&str>) -> Result<Command> {
+        let mut parts = self.config.command.split_whitespace();
+        let program = parts.next().context("Empty screenshot command")?;
+
+        let mut cmd = Command::new(program);
+        cmd.args(parts).kill_on_drop(true);
+        if let Some(geometry) = geometry {
+            cmd.arg("-g").arg(geometry);
+        }
+        Ok(cmd)
+    }
+
+    /// Takes a screenshot and returns its URI, or `None` if the selection
+    /// was aborted.
+    async fn take(&self, interactive: bool) -> Result<Option<String>> {
+        let geometry = if interactive {
+            match self.select_region(false).await? {
+                Some(geometry) => Some(geometry),
+                None => return Ok(None),
+            }
+        } else {
+            None
+        };
+
+        let dir = self.output_dir();
+        tokio::fs::create_dir_all(&dir)
+            .await
+            .with_context(|| format!("Failed to create {:?}", dir))?;
+
+        let path = dir.join(format!(
+            "Screenshot from {}.png",
+            Local::now().format("%Y-%m-%d %H-%M-%S")
+        ));
+
+        let status = self
+            .capture_command(geometry.as_deref())?
+            .arg(&path)
+            .status()
+            .await
+            .context("Failed to run screenshot command")?;
+
+        if !status.success() {
+            bail!("Screenshot command exited with {}", status);
+        }
+
+        file_uri(&path).map(Some)
+    }
+
+    async fn pick(&self) -> Result<Option<(f64, f64, f64)>> {
+        let Some(point) = self.select_region(true).await? else {
+            return Ok(None);
+        };
+
+        let output = self
+            .capture_command(Some(&point))?
+            .args(["-t", "ppm", "-"])
+            .output()
+            .await
+            .context("Failed to run screenshot command")?;
+
+        if !output.status.success() {
+            bail!("Screenshot command exited with {}", output.status);
+        }
+
+        // A 1x1 binary PPM ends with the three bytes of the only pixel.
+        let data = output.stdout;
+        if data.len() < 3 {
+            bail!("Screenshot command returned no pixel data");
+        }
+        let pixel = &data[data.len() - 3..];
+
+        Ok(Some((
+            f64::from(pixel[0]) / 255.0,
+            f64::from(pixel[1]) / 255.0,
+            f64::from(pixel[2]) / 255.0,
+        )))
+    }
+}
+
+#[dbus_interface(name = "org.freedesktop.impl.portal.Screenshot")]
+impl Screenshot {
+    async fn screenshot(
+        &self,
+        #[zbus(connection)] connection: &Connection,
+        handle: OwnedObjectPath,
+        app_id: String,
+        _parent_window: String,
+        options: HashMap<String, OwnedValue>,
+    ) -> (u32, Results) {
+        let interactive = options
+            .get("interactive")
+            .and_then(|v| bool::try_from(v.clone()).ok())
+            .unwrap_or(false);
+
+        info!("Screenshot requested by {:?} (interactive: {})", app_id, interactive);
+
+        let mut request = match PendingRequest::export(connection, &handle).await {
+            Ok(request) => request,
+            Err(e) => {
+                warn!("Failed to export request {}: {}", handle.as_str(), e);
+                return (RESPONSE_OTHER, Results::new());
+            }
+        };
+
+        let outcome = tokio::select! {
+            result = self.take(interactive) => Some(result),
+            _ = request.cancelled() => None,
+        };
+        request.finish().await;
+
+        match outcome {
+            Some(Ok(Some(uri))) => {
+                let mut results = Results::new();
+                results.insert("uri".to_string(), Value::from(uri).into());
+                (RESPONSE_SUCCESS, results)
+            }
+            Some(Ok(None)) | None => (RESPONSE_CANCELLED, Results::new()),
+            Some(Err(e)) => {
+                warn!("Screenshot failed: {:#}", e);
+                (RESPONSE_OTHER, Results::new())
+            }
+        }
+    }
+
+    async fn pick_color(
+        &self,
+        #[zbus(connection)] connection: &Connection,
+        handle: OwnedObjectPath,
+        app_id: String,
+        _parent_window: String,
+        _options: HashMap<String, OwnedValue>,
+    ) -> (u32, Results) {
+        info!("Color pick requested by {:?}", app_id);
+
+        let mut request = match PendingRequest::export(connection, &handle).await {
+            Ok(request) => request,
+            Err(e) => {
+                warn!("Failed to export request {}: {}", handle.as_str(), e);
+                return (RESPONSE_OTHER, Results::new());
+            }
+        };
+
+        let outcome = tokio::select! {
+            result = self.pick() => Some(result),
+            _ = request.cancelled() => None,
+        };
+        request.finish().await;
+
+        match outcome {
+            Some(Ok(Some(color))) => {
+                let mut results = Results::new();
+                results.insert("color".to_string(), Value::from(color).into());
+                (RESPONSE_SUCCESS, results)
+            }
+            Some(Ok(None)) | None => (RESPONSE_CANCELLED, Results::new()),
+            Some(Err(e)) => {
+                warn!("Color pick failed: {:#}", e);
+                (RESPONSE_OTHER, Results::new())
+            }
+        }
+    }
+
+    #[dbus_interface(property, name = "version")]
+    fn version(&self) -> u32 {
+        2
+    }
+}
@@ -0,0 +1,165 @@
+//! `org.freedesktop.impl.portal.Settings` backend
+//!
+//! Exposes the shell theme (dark mode and accent colour) under the
+//! `org.freedesktop.appearance` namespace so sandboxed apps follow it.
+
+use crate::config::ThemeConfig;
+
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
+use tracing::{debug, info, warn};
+use zbus::zvariant::{OwnedValue, Value};
+use zbus::{dbus_interface, Connection, DBusError, SignalContext};
+
+const APPEARANCE_NAMESPACE: &str = "org.freedesktop.appearance";
+const COLOR_SCHEME_KEY: &str = "color-scheme";
+const ACCENT_COLOR_KEY: &str = "accent-color";
+
+const COLOR_SCHEME_DARK: u32 = 1;
+const COLOR_SCHEME_LIGHT: u32 = 2;
+
+#[derive(Debug, DBusError)]
+#[dbus_error(prefix = "org.freedesktop.portal.Error")]
+pub enum PortalError {
+    #[dbus_error(zbus_error)]
+    ZBus(zbus::Error),
+    NotFound(String),
+}
+
+pub struct Settings {
+    theme: ThemeConfig,
+}
+
+impl Settings {
+    pub fn new(theme: ThemeConfig) -> Self {
+        Self { theme }
+    }
+
+    fn appearance(&self) -> HashMap<String, OwnedValue> {
+        let mut values = HashMap::new();
+
+        let scheme = if self.theme.is_dark() {
+            COLOR_SCHEME_DARK
+        } else {
+            COLOR_SCHEME_LIGHT
+        };
+        values.insert(COLOR_SCHEME_KEY.to_string(), Value::from(scheme).into());
+
+        if let Some(rgb) = self.theme.accent_rgb() {
+            values.insert(ACCENT_COLOR_KEY.to_string(), Value::from(rgb).into());
+        }
+
+        values
+    }
+}
+
+fn namespace_matches(pattern: &str, namespace: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => namespace.starts_with(prefix),
+        None => pattern == namespace,
+    }
+}
+
+#[dbus_interface(name = "org.freedesktop.impl.portal.Settings")]
+impl Settings {
+    async fn read_all(&self, namespaces: Vec<String>) -> HashMap<String, HashMap<String, OwnedValue>> {
+        let mut all = HashMap::new();
+
+        let wanted = namespaces.is_empty()
+            || namespaces
+                .iter()
+                .any(|pattern| pattern.is_empty() || namespace_matches(pattern, APPEARANCE_NAMESPACE));
+
+        if wanted {
+            all.insert(APPEARANCE_NAMESPACE.to_string(), self.appearance());
+        }
+
+        all
+    }
+
+    async fn read(&self, namespace: String, key: String) -> Result<OwnedValue, PortalError> {
+        if namespace == APPEARANCE_NAMESPACE {
+            if let Some(value) = self.appearance().remove(&key) {
+                return Ok(value);
+            }
+        }
+
+        Err(PortalError::NotFound(format!(
+            "Requested setting {}.{} not found",
+            namespace, key
+        )))
+    }
+
+    #[dbus_interface(signal)]
+    async fn setting_changed(
+        ctxt: &SignalContext<'_>,
+        namespace: &str,
+        key: &str,
+        value: Value<'_>,
+    ) -> zbus::Result<()>;
+
+    #[dbus_interface(property, name = "version")]
+    fn version(&self) -> u32 {
+        2
+    }
+}
+
+fn modified(path: &std::path::Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Polls spinner-shell.toml and emits `SettingChanged` when the theme
+/// section changes, so running apps switch without a restart.
+pub async fn watch_theme(connection: Connection, path: &str) -> zbus::Result<()> {
+    let iface = connection
+        .object_server()
+        .interface::<_, Settings>(path)
+        .await?;
+
+    let Ok(config_path) = ThemeConfig::path() else {
+        warn!("No config directory, theme changes will not be tracked");
+        return Ok(());
+    };
+
+    let mut last_modified = modified(&config_path);
+    let mut interval = tokio::time::interval(Duration::from_secs(2));
+
+    loop {
+        interval.tick().await;
+
+        let current = modified(&config_path);
+        if current == last_modified {
+            continue;
+        }
+        last_modified = current;
+
+        let theme = match ThemeConfig::load() {
+            Ok(theme) => theme,
+            Err(e) => {
+                warn!("Failed to reload theme: {:#}", e);
+                continue;
+            }
+        };
+
+        let mut settings = iface.get_mut().await;
+        if settings.theme == theme {
+            debug!("Config changed but theme is unchanged");
+            continue;
+        }
+
+        let old = settings.appearance();
+        settings.theme = theme;
+        let new = settings.appearance();
+        drop(settings);
+
+        info!("Theme changed, notifying portal clients");
+
+        let ctxt = iface.signal_context();
+        for (key, value) in &new {
+            if old.get(key) != Some(value) {
+                Settings::setting_changed(ctxt, APPEARANCE_NAMESPACE, key, Value::from(value.clone()))
+                    .await?;
+            }
+        }
+    }
+}
@@ -0,0 +1,319 @@
+//! Runs the portal against a private dbus-daemon and calls it the way
+//! xdg-desktop-portal does. Skipped when dbus-daemon is not installed.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::time::Duration;
+use tokio::time::{sleep, timeout};
+use zbus::fdo::DBusProxy;
+use zbus::names::BusName;
+use zbus::zvariant::{DynamicType, ObjectPath, OwnedValue, Type, Value};
+use zbus::{Connection, ConnectionBuilder};
+
+const BUS_NAME: &str = "org.freedesktop.impl.portal.desktop.spinneros";
+const OBJECT_PATH: &str = "/org/freedesktop/portal/desktop";
+const REQUEST_PATH: &str = "/org/freedesktop/portal/desktop/request/1_1/test";
+const SESSION_PATH: &str = "/org/freedesktop/portal/desktop/session/1_1/test";
+
+/// A portal that doesn't answer fails the test instead of hanging it.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+type Results = HashMap<String, OwnedValue>;
+
+fn path(path: &str) -> ObjectPath<'_> {
+    ObjectPath::try_from(path).unwrap()
+}
+
+/// A dbus-daemon and the portal on it, with a configuration of its own.
+struct Portal {
+    dir: PathBuf,
+    bus: Child,
+    portal: Child,
+    connection: Connection,
+}
+
+impl Portal {
+    async fn start(name: &str) -> Option<Self> {
+        let dir =
+            std::env::temp_dir().join(format!("spinner-portal-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let config = dir.join("config/spinneros");
+        fs::create_dir_all(&config).unwrap();
+        // A screencast helper that never reports a node keeps `Start`
+        // pending until the request is closed.
+        let helper = dir.join("screencast-helper");
+        fs::write(&helper, "#!/bin/sh\nexec sleep 60\n").unwrap();
+        fs::set_permissions(&helper, fs::Permissions::from_mode(0o755)).unwrap();
+        // `touch` stands in for grim; the directory name needs escaping in
+        // the URI.
+        fs::write(
+            config.join("spinner-portal.toml"),
+            format!(
+                "[screenshot]\ncommand = \"touch\"\ndirectory = {:?}\n\n[screencast]\nhelper = {:?}\n",
+                dir.join("Screen shots"),
+                helper
+            ),
+        )
+        .unwrap();
+        fs::write(
+            config.join("spinner-shell.toml"),
+            "[theme]\nvariant = \"light\"\naccent_color = \"#ff0000\"\n",
+        )
+        .unwrap();
+
+        let mut bus = match Command::new("dbus-daemon")
+            .args(["--session", "--nofork", "--print-address"])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+        {
+            Ok(bus) => bus,
+            Err(e) => {
+                eprintln!("Skipping, dbus-daemon is not available: {}", e);
+                return None;
+            }
+        };
+        let mut address = String::new();
+        BufReader::new(bus.stdout.take().unwrap())
+            .read_line(&mut address)
+            .unwrap();
+        let address = address.trim().to_string();
+
+        let portal = Command::new(env!("CARGO_BIN_EXE_spinner-portal"))
+            .env("DBUS_SESSION_BUS_ADDRESS", &address)
+            .env("XDG_CONFIG_HOME", dir.join("config"))
+            .stdout(Stdio::null())
+            .spawn()
+            .unwrap();
+
+        let connection = ConnectionBuilder::address(address.as_str())
+            .unwrap()
+            .build()
+            .await
+            .unwrap();
+        let portal = Self {
+            dir,
+            bus,
+            portal,
+            connection,
+        };
+        portal.wait_for_name().await;
+        Some(portal)
+    }
+
+    async fn wait_for_name(&self) {
+        let dbus = DBusProxy::new(&self.connection).await.unwrap();
+        let name = BusName::try_from(BUS_NAME).unwrap();
+        let owned = async {
+            while !dbus.name_has_owner(name.clone()).await.unwrap() {
+                sleep(Duration::from_millis(50)).await;
+            }
+        };
+        timeout(TIMEOUT, owned)
+            .await
+            .expect("The portal did not take its bus name");
+    }
+
+    async fn call<B, R>(
+        &self,
+        path: &str,
+        interface: &str,
+        method: &str,
+        body: &B,
+    ) -> zbus::Result<R>
+    where
+        B: serde::Serialize + DynamicType,
+        R: for<'d> serde::Deserialize<'d> + Type,
+    {
+        let reply =
+            self.connection
+                .call_method(Some(BUS_NAME), path, Some(interface), method, body);
+        timeout(TIMEOUT, reply)
+            .await
+            .unwrap_or_else(|_| panic!("No reply to {}.{}", interface, method))?
+            .body()
+    }
+
+    fn dir(&self) -> &Path {
+        &self.dir
+    }
+}
+
+impl Drop for Portal {
+    fn drop(&mut self) {
+        let _ = self.portal.kill();
+        let _ = self.portal.wait();
+        let _ = self.bus.kill();
+        let _ = self.bus.wait();
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+#[tokio::test]
+async fn settings_follow_the_shell_theme() {
+    let Some(portal) = Portal::start("settings").await else {
+        return;
+    };
+
+    let scheme: OwnedValue = portal
+        .call(
+            OBJECT_PATH,
+            "org.freedesktop.impl.portal.Settings",
+            "Read",
+            &("org.freedesktop.appearance", "color-scheme"),
+        )
+        .await
+        .unwrap();
+    // 2 is prefer-light.
+    assert_eq!(u32::try_from(scheme).unwrap(), 2);
+
+    let accent: OwnedValue = portal
+        .call(
+            OBJECT_PATH,
+            "org.freedesktop.impl.portal.Settings",
+            "Read",
+            &("org.freedesktop.appearance", "accent-color"),
+        )
+        .await
+        .unwrap();
+    let accent = <(f64, f64, f64)>::try_from(accent).unwrap();
+    assert_eq!(accent, (1.0, 0.0, 0.0));
+}
+
+#[tokio::test]
+async fn screenshot_returns_an_escaped_uri() {
+    let Some(portal) = Portal::start("screenshot").await else {
+        return;
+    };
+
+    let options: HashMap<&str, Value> = HashMap::new();
+    let (response, results): (u32, Results) = portal
+        .call(
+            OBJECT_PATH,
+            "org.freedesktop.impl.portal.Screenshot",
+            "Screenshot",
+            &(path(REQUEST_PATH), "org.example.App", "", options),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response, 0);
+
+    let uri = String::try_from(results["uri"].clone()).unwrap();
+    let prefix = format!(
+        "file://{}/Screen%20shots/Screenshot%20from%20",
+        portal.dir().display()
+    );
+    assert!(uri.starts_with(&prefix), "{}", uri);
+    assert!(!uri.contains(' '), "{}", uri);
+
+    let shots: Vec<_> = fs::read_dir(portal.dir().join("Screen shots"))
+        .unwrap()
+        .collect();
+    assert_eq!(shots.len(), 1);
+}
+
+#[tokio::test]
+async fn screencast_session_closes() {
+    let Some(portal) = Portal::start("screencast").await else {
+        return;
+    };
+
+    let options: HashMap<&str, Value> = HashMap::new();
+    let (response, _): (u32, Results) = portal
+        .call(
+            OBJECT_PATH,
+            "org.freedesktop.impl.portal.ScreenCast",
+            "CreateSession",
+            &(
+                path(REQUEST_PATH),
+                path(SESSION_PATH),
+                "org.example.App",
+                options,
+            ),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response, 0);
+
+    let close = || {
+        portal.call::<_, ()>(
+            SESSION_PATH,
+            "org.freedesktop.impl.portal.Session",
+            "Close",
+            &(),
+        )
+    };
+    close().await.unwrap();
+
+    // The session is unexported after the reply.
+    let unexported = async {
+        while close().await.is_ok() {
+            sleep(Duration::from_millis(50)).await;
+        }
+    };
+    timeout(TIMEOUT, unexported)
+        .await
+        .expect("The session was not unexported");
+}
+
+#[tokio::test]
+async fn screencast_start_can_be_closed() {
+    let Some(portal) = Portal::start("screencast-close").await else {
+        return;
+    };
+
+    let options: HashMap<&str, Value> = HashMap::new();
+    let (response, _): (u32, Results) = portal
+        .call(
+            OBJECT_PATH,
+            "org.freedesktop.impl.portal.ScreenCast",
+            "CreateSession",
+            &(
+                path(REQUEST_PATH),
+                path(SESSION_PATH),
+                "org.example.App",
+                options.clone(),
+            ),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response, 0);
+
+    let body = (
+        path(REQUEST_PATH),
+        path(SESSION_PATH),
+        "org.example.App",
+        "",
+        options,
+    );
+    let start = portal.call::<_, (u32, Results)>(
+        OBJECT_PATH,
+        "org.freedesktop.impl.portal.ScreenCast",
+        "Start",
+        &body,
+    );
+    // The request is exported once the portal has started the helper.
+    let close = async {
+        while portal
+            .call::<_, ()>(
+                REQUEST_PATH,
+                "org.freedesktop.impl.portal.Request",
+                "Close",
+                &(),
+            )
+            .await
+            .is_err()
+        {
+            sleep(Duration::from_millis(50)).await;
+        }
+    };
+
+    let ((response, results), ()) = tokio::join!(async { start.await.unwrap() }, close);
+    // 1 is cancelled.
+    assert_eq!(response, 1);
+    assert!(results.is_empty());
+}
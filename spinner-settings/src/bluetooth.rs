@@ -0,0 +1,275 @@
+//! Bluetooth page - Adapter power, name and visibility, paired devices and
+//! pairing new ones
+
+use anyhow::Result;
+use gtk4::prelude::*;
+use gtk4::{self, glib, Align, Button, Image, Label};
+use libadwaita as adw;
+use libadwaita::prelude::*;
+use spinner_bluez::{self as bluez, BluetoothState, Device, Discovery};
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+struct BluetoothPage {
+    adapter_group: adw::PreferencesGroup,
+    power: adw::SwitchRow,
+    name: adw::EntryRow,
+    discoverable: adw::SwitchRow,
+    devices_group: adw::PreferencesGroup,
+    nearby_group: adw::PreferencesGroup,
+    scan: Button,
+    error: Label,
+    rows: RefCell<Vec<(adw::PreferencesGroup, adw::ActionRow)>>,
+    state: RefCell<BluetoothState>,
+    discovery: RefCell<Option<Discovery>>,
+    /// Set while widgets are updated from BlueZ, so their handlers don't
+    /// echo the change back.
+    updating: Cell<bool>,
+}
+
+pub fn build() -> adw::PreferencesPage {
+    let page = adw::PreferencesPage::builder()
+        .title("Bluetooth")
+        .icon_name("bluetooth-symbolic")
+        .build();
+
+    let adapter_group = adw::PreferencesGroup::builder()
+        .title("Bluetooth")
+        .build();
+    let power = adw::SwitchRow::builder().title("Bluetooth").build();
+    adapter_group.add(&power);
+    let name = adw::EntryRow::builder()
+        .title("Device Name")
+        .show_apply_button(true)
+        .build();
+    adapter_group.add(&name);
+    let discoverable = adw::SwitchRow::builder()
+        .title("Visible to Other Devices")
+        .subtitle("Let nearby devices find this computer to pair with it")
+        .build();
+    adapter_group.add(&discoverable);
+    page.add(&adapter_group);
+
+    let error = Label::builder()
+        .xalign(0.0)
+        .wrap(true)
+        .visible(false)
+        .build();
+    error.add_css_class("error");
+    adapter_group.add(&error);
+
+    let devices_group = adw::PreferencesGroup::builder()
+        .title("My Devices")
+        .build();
+    page.add(&devices_group);
+
+    let scan = Button::builder()
+        .icon_name("view-refresh-symbolic")
+        .tooltip_text("Search for devices")
+        .valign(Align::Center)
+        .build();
+    scan.add_css_class("flat");
+    let nearby_group = adw::PreferencesGroup::builder()
+        .title("Nearby Devices")
+        .description("Put the device in pairing mode to see it here")
+        .header_suffix(&scan)
+        .build();
+    page.add(&nearby_group);
+
+    let bluetooth = Rc::new(BluetoothPage {
+        adapter_group,
+        power,
+        name,
+        discoverable,
+        devices_group,
+        nearby_group,
+        scan,
+        error,
+        rows: RefCell::new(Vec::new()),
+        state: RefCell::new(BluetoothState::default()),
+        discovery: RefCell::new(None),
+        updating: Cell::new(false),
+    });
+    bluetooth.connect_signals();
+
+    // Discovery runs while the page is shown.
+    let weak = Rc::downgrade(&bluetooth);
+    page.connect_map(move |_| {
+        if let Some(bluetooth) = weak.upgrade() {
+            bluetooth.start_discovery();
+        }
+    });
+    let weak = Rc::downgrade(&bluetooth);
+    page.connect_unmap(move |_| {
+        if let Some(bluetooth) = weak.upgrade() {
+            bluetooth.discovery.borrow_mut().take();
+        }
+    });
+
+    // The task owns the page's state for as long as the app runs.
+    let receiver = bluez::subscribe();
+    glib::MainContext::default().spawn_local(async move {
+        while let Ok(state) = receiver.recv().await {
+            bluetooth.update(state);
+        }
+    });
+
+    page
+}
+
+impl BluetoothPage {
+    fn connect_signals(self: &Rc<Self>) {
+        let weak = Rc::downgrade(self);
+        self.power.connect_active_notify(move |row| {
+            if let Some(page) = weak.upgrade().filter(|p| !p.updating.get()) {
+                let result = bluez::set_powered(&page.state.borrow(), row.is_active());
+                page.report(result);
+            }
+        });
+
+        let weak = Rc::downgrade(self);
+        self.discoverable.connect_active_notify(move |row| {
+            if let Some(page) = weak.upgrade().filter(|p| !p.updating.get()) {
+                let result = bluez::set_discoverable(&page.state.borrow(), row.is_active());
+                page.report(result);
+            }
+        });
+
+        let weak = Rc::downgrade(self);
+        self.name.connect_apply(move |row| {
+            if let Some(page) = weak.upgrade() {
+                let result = bluez::set_name(&page.state.borrow(), row.text().trim());
+                page.report(result);
+            }
+        });
+
+        let weak = Rc::downgrade(self);
+        self.scan.connect_clicked(move |_| {
+            if let Some(page) = weak.upgrade() {
+                page.start_discovery();
+            }
+        });
+    }
+
+    fn start_discovery(&self) {
+        let state = self.state.borrow();
+        if state.powered {
+            *self.discovery.borrow_mut() = Some(Discovery::start(&state));
+        }
+    }
+
+    fn update(self: &Rc<Self>, state: BluetoothState) {
+        self.updating.set(true);
+
+        let powered_on = state.powered && !self.state.borrow().powered;
+        self.adapter_group.set_description(Some(&if state.available {
+            state.description()
+        } else {
+            "No Bluetooth adapter found".to_string()
+        }));
+        self.power.set_sensitive(state.available);
+        self.power.set_active(state.powered);
+        self.name.set_visible(state.available);
+        if !self.name.has_focus() {
+            self.name.set_text(&state.name);
+        }
+        self.discoverable.set_visible(state.powered);
+        self.discoverable.set_active(state.discoverable);
+        self.scan.set_sensitive(!state.discovering);
+
+        for (group, row) in self.rows.borrow_mut().drain(..) {
+            group.remove(&row);
+        }
+        let (known, nearby): (Vec<&Device>, Vec<&Device>) = state.devices.iter().partition(|d| d.paired);
+        self.devices_group.set_visible(state.powered && !known.is_empty());
+        self.nearby_group.set_visible(state.powered);
+        for device in known {
+            self.add_row(&self.devices_group, device);
+        }
+        for device in nearby {
+            self.add_row(&self.nearby_group, device);
+        }
+
+        *self.state.borrow_mut() = state;
+        self.updating.set(false);
+
+        // Switched on while the page is shown: look around right away.
+        if powered_on && self.power.is_mapped() {
+            self.start_discovery();
+        }
+    }
+
+    fn add_row(self: &Rc<Self>, group: &adw::PreferencesGroup, device: &Device) {
+        let subtitle = match (device.connected, device.battery) {
+            (true, Some(battery)) => format!("Connected · {}% battery", battery),
+            (true, None) => "Connected".to_string(),
+            (false, _) if device.paired => "Not connected".to_string(),
+            (false, _) => device.address.clone(),
+        };
+        let row = adw::ActionRow::builder()
+            .title(&device.name)
+            .subtitle(subtitle)
+            .build();
+        row.add_prefix(&Image::from_icon_name(&device.icon_name()));
+
+        let label = match (device.connected, device.paired) {
+            (true, _) => "Disconnect",
+            (false, true) => "Connect",
+            (false, false) => "Pair",
+        };
+        let action = Button::builder()
+            .label(label)
+            .valign(Align::Center)
+            .build();
+        let target = device.clone();
+        let weak = Rc::downgrade(self);
+        action.connect_clicked(move |button| {
+            let Some(page) = weak.upgrade() else {
+                return;
+            };
+            button.set_sensitive(false);
+            if target.connected {
+                page.report(bluez::disconnect(&target));
+            } else {
+                page.report(bluez::connect(&target));
+            }
+        });
+        row.add_suffix(&action);
+
+        if device.paired {
+            let forget = Button::builder()
+                .icon_name("user-trash-symbolic")
+                .tooltip_text("Forget")
+                .valign(Align::Center)
+                .build();
+            forget.add_css_class("flat");
+            let target = device.clone();
+            let weak = Rc::downgrade(self);
+            forget.connect_clicked(move |_| {
+                if let Some(page) = weak.upgrade() {
+                    let result = bluez::forget(&page.state.borrow(), &target);
+                    page.report(result);
+                }
+            });
+            row.add_suffix(&forget);
+        }
+
+        group.add(&row);
+        self.rows.borrow_mut().push((group.clone(), row));
+    }
+
+    /// Shows the failure of a request on the page, once it finishes.
+    fn report(self: &Rc<Self>, result: async_channel::Receiver<Result<()>>) {
+        self.error.set_visible(false);
+        let weak = Rc::downgrade(self);
+        glib::MainContext::default().spawn_local(async move {
+            let Ok(Err(e)) = result.recv().await else {
+                return;
+            };
+            if let Some(page) = weak.upgrade() {
+                page.error.set_label(&format!("{:#}", e));
+                page.error.set_visible(true);
+            }
+        });
+    }
+}
@@ -1,7 +1,9 @@
 //! SpinnerOS Settings Application
 
+mod bluetooth;
+
 use gtk4::prelude::*;
-use gtk4::{self, gio, glib, Align, Box as GtkBox, Label, Orientation};
+use gtk4::{self, gio, glib, Box as GtkBox, Orientation};
 use libadwaita as adw;
 use tracing::info;
 use tracing_subscriber::{fmt, prelude::*, EnvFilter};
@@ -33,21 +35,32 @@ fn build_ui(app: &adw::Application) {
         .default_height(600)
         .build();
     
+    let stack = adw::ViewStack::new();
+    stack.add_titled_with_icon(&general_page(), Some("general"), "General", "preferences-system-symbolic");
+    stack.add_titled_with_icon(&bluetooth::build(), Some("bluetooth"), "Bluetooth", "bluetooth-symbolic");
+    
+    let header = adw::HeaderBar::builder()
+        .title_widget(
+            &adw::ViewSwitcher::builder()
+                .stack(&stack)
+                .policy(adw::ViewSwitcherPolicy::Wide)
+                .build(),
+        )
+        .build();
+    
     let main_box = GtkBox::builder()
         .orientation(Orientation::Vertical)
-        .spacing(24)
-        .margin_top(24)
-        .margin_bottom(24)
-        .margin_start(24)
-        .margin_end(24)
         .build();
+    main_box.append(&header);
+    stack.set_vexpand(true);
+    main_box.append(&stack);
     
-    let title = Label::builder()
-        .label("SpinnerOS Settings")
-        .halign(Align::Start)
-        .build();
-    title.add_css_class("title-1");
-    main_box.append(&title);
+    window.set_content(Some(&main_box));
+    window.present();
+}
+
+fn general_page() -> adw::PreferencesPage {
+    let page = adw::PreferencesPage::new();
     
     let appearance_group = adw::PreferencesGroup::builder()
         .title("Appearance")
@@ -60,7 +73,7 @@ fn build_ui(app: &adw::Application) {
     dark_mode.set_active(true);
     appearance_group.add(&dark_mode);
     
-    main_box.append(&appearance_group);
+    page.add(&appearance_group);
     
     let about_group = adw::PreferencesGroup::builder()
         .title("About")
@@ -72,8 +85,6 @@ fn build_ui(app: &adw::Application) {
         .build();
     about_group.add(&version_row);
     
-    main_box.append(&about_group);
-    
-    window.set_content(Some(&main_box));
-    window.present();
+    page.add(&about_group);
+    page
 }
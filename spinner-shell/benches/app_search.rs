@@ -0,0 +1,105 @@
+//! Search latency per keystroke over a few thousand apps
+//!
+//! Run with `cargo bench -p spinner-shell --bench app_search`. The menu
+//! searches on the main thread as the user types, so a query should stay
+//! well within a frame.
+
+// Only the GTK-free part of the app menu is built here.
+#![allow(dead_code)]
+
+#[path = "../src/app_menu"]
+mod app_menu {
+    pub mod entry;
+    pub mod history;
+    pub mod search;
+
+    pub use entry::AppEntry;
+}
+
+use app_menu::history::LaunchHistory;
+use app_menu::search::SearchEngine;
+use app_menu::AppEntry;
+use chrono::Local;
+use std::hint::black_box;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+const APPS: usize = 3000;
+const ROUNDS: u32 = 20;
+/// Typed a letter at a time, as in the menu.
+const QUERIES: &[&str] = &["firefox", "text editor", "terminal", "xyzzy"];
+
+const WORDS: &[&str] = &[
+    "Files", "Web", "Text", "Image", "Video", "Music", "Office", "Terminal", "Mail", "Chat",
+    "Code", "System", "Disk", "Network", "Photo", "Paint", "Calendar", "Notes", "Game", "Archive",
+];
+const KINDS: &[&str] = &[
+    "Editor", "Viewer", "Manager", "Player", "Browser", "Client", "Monitor", "Tool", "Studio",
+    "Center",
+];
+
+fn apps() -> Vec<AppEntry> {
+    (0..APPS)
+        .map(|i| {
+            let word = WORDS[i % WORDS.len()];
+            let kind = KINDS[i / WORDS.len() % KINDS.len()];
+            AppEntry {
+                id: format!("org.example.{}{}{}.desktop", word, kind, i),
+                name: format!("{} {} {}", word, kind, i),
+                generic_name: format!("{} {}", word, kind),
+                exec: format!("{}-{} %U", word.to_lowercase(), i),
+                icon: String::new(),
+                description: format!(
+                    "A {} {} for everyday use, number {}",
+                    word.to_lowercase(),
+                    kind.to_lowercase(),
+                    i
+                ),
+                keywords: vec![
+                    word.to_lowercase(),
+                    kind.to_lowercase(),
+                    "example".to_string(),
+                ],
+                categories: vec!["Utility".to_string()],
+                actions: Vec::new(),
+                location: PathBuf::new(),
+                terminal: false,
+                dbus_activatable: false,
+                working_dir: None,
+            }
+        })
+        .collect()
+}
+
+fn main() {
+    let apps = apps();
+    let mut history = LaunchHistory::default();
+    let now = Local::now();
+    for app in apps.iter().step_by(7) {
+        history.record(&app.id, now);
+    }
+    let engine = SearchEngine::new(apps, history);
+
+    println!("{} apps, {} rounds", APPS, ROUNDS);
+    for query in QUERIES {
+        let mut total = Duration::ZERO;
+        let mut worst = Duration::ZERO;
+        let mut runs = 0;
+        for _ in 0..ROUNDS {
+            for end in 1..=query.len() {
+                let start = Instant::now();
+                black_box(engine.search(black_box(&query[..end])));
+                let elapsed = start.elapsed();
+                total += elapsed;
+                worst = worst.max(elapsed);
+                runs += 1;
+            }
+        }
+        println!(
+            "{:<14} mean {:>10.3?} per keystroke, worst {:>10.3?}",
+            format!("{:?}", query),
+            total / runs,
+            worst
+        );
+    }
+}
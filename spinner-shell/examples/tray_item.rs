@@ -0,0 +1,201 @@
+//! A StatusNotifierItem with a pixmap icon, a tooltip and a small menu,
+//! for trying the panel's tray against a private bus:
+//!
+//! ```sh
+//! dbus-run-session -- sh -c 'spinner-shell & cargo run --example tray_item'
+//! ```
+//!
+//! Clicks, scrolls and menu choices are printed. "Attention" toggles the
+//! attention state, "Quit" removes the item.
+
+use std::collections::HashMap;
+use std::sync::mpsc;
+use zbus::blocking::{Connection, ConnectionBuilder};
+use zbus::zvariant::{ObjectPath, OwnedValue, Value};
+use zbus::{dbus_interface, SignalContext};
+
+const ITEM_PATH: &str = "/StatusNotifierItem";
+const MENU_PATH: &str = "/MenuBar";
+
+const ATTENTION: i32 = 1;
+const QUIT: i32 = 2;
+
+/// Width, height and ARGB32 pixels.
+type Pixmaps = Vec<(i32, i32, Vec<u8>)>;
+
+struct Item {
+    attention: bool,
+}
+
+/// A 32x32 ARGB square: blue, or red when asking for attention.
+fn pixmap(attention: bool) -> Pixmaps {
+    let pixel: [u8; 4] = if attention { [255, 220, 50, 50] } else { [255, 50, 120, 220] };
+    vec![(32, 32, pixel.repeat(32 * 32))]
+}
+
+#[dbus_interface(name = "org.kde.StatusNotifierItem")]
+impl Item {
+    fn activate(&self, x: i32, y: i32) {
+        println!("Activate at {},{}", x, y);
+    }
+
+    fn secondary_activate(&self, x: i32, y: i32) {
+        println!("SecondaryActivate at {},{}", x, y);
+    }
+
+    fn scroll(&self, delta: i32, orientation: String) {
+        println!("Scroll {} {}", delta, orientation);
+    }
+
+    #[dbus_interface(property)]
+    fn category(&self) -> &str {
+        "ApplicationStatus"
+    }
+
+    #[dbus_interface(property)]
+    fn id(&self) -> &str {
+        "tray-item-example"
+    }
+
+    #[dbus_interface(property)]
+    fn title(&self) -> &str {
+        "Tray item example"
+    }
+
+    #[dbus_interface(property)]
+    fn status(&self) -> &str {
+        if self.attention {
+            "NeedsAttention"
+        } else {
+            "Active"
+        }
+    }
+
+    #[dbus_interface(property)]
+    fn icon_name(&self) -> &str {
+        ""
+    }
+
+    #[dbus_interface(property)]
+    fn icon_pixmap(&self) -> Pixmaps {
+        pixmap(false)
+    }
+
+    #[dbus_interface(property)]
+    fn attention_icon_pixmap(&self) -> Pixmaps {
+        pixmap(true)
+    }
+
+    #[dbus_interface(property)]
+    fn tool_tip(&self) -> (String, Pixmaps, String, String) {
+        let description = if self.attention { "Needs <i>attention</i>" } else { "All quiet" };
+        (String::new(), Vec::new(), "Tray item example".to_string(), description.to_string())
+    }
+
+    #[dbus_interface(property)]
+    fn item_is_menu(&self) -> bool {
+        false
+    }
+
+    #[dbus_interface(property)]
+    fn menu(&self) -> ObjectPath<'_> {
+        ObjectPath::from_static_str_unchecked(MENU_PATH)
+    }
+
+    #[dbus_interface(signal)]
+    async fn new_status(ctxt: &SignalContext<'_>, status: &str) -> zbus::Result<()>;
+}
+
+struct Menu {
+    attention: bool,
+    chosen: mpsc::Sender<i32>,
+}
+
+type Node = (i32, HashMap<String, OwnedValue>, Vec<OwnedValue>);
+
+fn node(id: i32, properties: &[(&str, Value)]) -> Node {
+    let properties = properties
+        .iter()
+        .map(|(key, value)| (key.to_string(), OwnedValue::from(value.clone())))
+        .collect();
+    (id, properties, Vec::new())
+}
+
+#[dbus_interface(name = "com.canonical.dbusmenu")]
+impl Menu {
+    fn get_layout(&self, _parent: i32, _depth: i32, _properties: Vec<String>) -> (u32, Node) {
+        let children = [
+            node(
+                ATTENTION,
+                &[
+                    ("label", Value::from("_Attention")),
+                    ("toggle-type", Value::from("checkmark")),
+                    ("toggle-state", Value::from(self.attention as i32)),
+                ],
+            ),
+            node(3, &[("type", Value::from("separator"))]),
+            node(QUIT, &[("label", Value::from("_Quit"))]),
+        ];
+        let children = children
+            .into_iter()
+            .map(|child| OwnedValue::from(Value::from(child)))
+            .collect();
+        let root = (0, HashMap::new(), children);
+        (1, root)
+    }
+
+    fn about_to_show(&self, _id: i32) -> bool {
+        false
+    }
+
+    fn event(&self, id: i32, event_id: String, _data: Value<'_>, _timestamp: u32) {
+        println!("Menu item {} {}", id, event_id);
+        if event_id == "clicked" {
+            let _ = self.chosen.send(id);
+        }
+    }
+
+    #[dbus_interface(property)]
+    fn version(&self) -> u32 {
+        3
+    }
+}
+
+fn main() -> zbus::Result<()> {
+    let (chosen, choices) = mpsc::channel();
+    let connection = ConnectionBuilder::session()?
+        .serve_at(ITEM_PATH, Item { attention: false })?
+        .serve_at(MENU_PATH, Menu { attention: false, chosen })?
+        .build()?;
+    register(&connection)?;
+    println!("Serving a tray item as {}", connection.unique_name().unwrap());
+
+    for id in choices {
+        match id {
+            ATTENTION => {
+                let item = connection.object_server().interface::<_, Item>(ITEM_PATH)?;
+                let menu = connection.object_server().interface::<_, Menu>(MENU_PATH)?;
+                let attention = !item.get().attention;
+                item.get_mut().attention = attention;
+                menu.get_mut().attention = attention;
+                let status = item.get().status().to_string();
+                zbus::block_on(Item::new_status(item.signal_context(), &status))?;
+            }
+            QUIT => break,
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn register(connection: &Connection) -> zbus::Result<()> {
+    let name = connection.unique_name().unwrap().to_string();
+    connection.call_method(
+        Some("org.kde.StatusNotifierWatcher"),
+        "/StatusNotifierWatcher",
+        Some("org.kde.StatusNotifierWatcher"),
+        "RegisterStatusNotifierItem",
+        &(name.as_str(),),
+    )?;
+    Ok(())
+}
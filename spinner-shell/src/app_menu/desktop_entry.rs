@@ -0,0 +1,193 @@
+//! Desktop Entry files, as in the freedesktop Desktop Entry Specification
+//!
+//! Only parsing lives here: groups, the string escapes, `;`-separated lists
+//! and localized keys such as `Name[de]`. Which entries become menu items
+//! is up to the launcher.
+
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::path::Path;
+
+pub const MAIN_GROUP: &str = "Desktop Entry";
+
+/// The group for the desktop action `id`, e.g. `new-window`.
+pub fn action_group(id: &str) -> String {
+    format!("Desktop Action {}", id)
+}
+
+type Group = HashMap<String, String>;
+
+pub struct DesktopEntry {
+    groups: HashMap<String, Group>,
+}
+
+impl DesktopEntry {
+    pub fn read(path: &Path) -> Result<Self> {
+        let text =
+            std::fs::read_to_string(path).with_context(|| format!("Failed to read {:?}", path))?;
+        Ok(Self::parse(&text))
+    }
+
+    /// Parses leniently: lines that are neither groups nor keys are
+    /// skipped, and a repeated group or key keeps its first value.
+    pub fn parse(text: &str) -> Self {
+        let mut groups: HashMap<String, Group> = HashMap::new();
+        let mut current: Option<String> = None;
+
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                current = (!groups.contains_key(name)).then(|| name.to_string());
+                if let Some(name) = &current {
+                    groups.insert(name.clone(), Group::new());
+                }
+                continue;
+            }
+            let (Some(group), Some((key, value))) = (&current, line.split_once('=')) else {
+                continue;
+            };
+            if let Some(group) = groups.get_mut(group) {
+                group
+                    .entry(key.trim_end().to_string())
+                    .or_insert_with(|| value.trim_start().to_string());
+            }
+        }
+
+        Self { groups }
+    }
+
+    pub fn has_group(&self, group: &str) -> bool {
+        self.groups.contains_key(group)
+    }
+
+    fn raw(&self, group: &str, key: &str) -> Option<&str> {
+        self.groups.get(group)?.get(key).map(String::as_str)
+    }
+
+    pub fn string(&self, group: &str, key: &str) -> Option<String> {
+        self.raw(group, key).map(unescape)
+    }
+
+    /// The value for the best matching locale in `locales`, most specific
+    /// first as from `locales()`, falling back to the plain key.
+    pub fn localized(&self, group: &str, key: &str, locales: &[String]) -> Option<String> {
+        locales
+            .iter()
+            .find_map(|locale| self.raw(group, &format!("{}[{}]", key, locale)))
+            .or_else(|| self.raw(group, key))
+            .map(unescape)
+    }
+
+    pub fn boolean(&self, group: &str, key: &str) -> bool {
+        self.raw(group, key) == Some("true")
+    }
+
+    pub fn list(&self, group: &str, key: &str) -> Vec<String> {
+        self.raw(group, key).map(split_list).unwrap_or_default()
+    }
+
+    pub fn localized_list(&self, group: &str, key: &str, locales: &[String]) -> Vec<String> {
+        locales
+            .iter()
+            .find_map(|locale| self.raw(group, &format!("{}[{}]", key, locale)))
+            .or_else(|| self.raw(group, key))
+            .map(split_list)
+            .unwrap_or_default()
+    }
+}
+
+/// Resolves `\s`, `\n`, `\t`, `\r` and `\\`. Anything else after a
+/// backslash is kept as is.
+fn unescape(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('s') => result.push(' '),
+            Some('n') => result.push('\n'),
+            Some('t') => result.push('\t'),
+            Some('r') => result.push('\r'),
+            Some('\\') => result.push('\\'),
+            Some(other) => {
+                result.push('\\');
+                result.push(other);
+            }
+            None => result.push('\\'),
+        }
+    }
+    result
+}
+
+/// Splits at `;` but not `\;`, dropping empty items such as the one
+/// after the trailing separator.
+fn split_list(value: &str) -> Vec<String> {
+    let mut items = Vec::new();
+    let mut item = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(';') => item.push(';'),
+                Some(other) => {
+                    item.push('\\');
+                    item.push(other);
+                }
+                None => item.push('\\'),
+            },
+            ';' => items.push(std::mem::take(&mut item)),
+            c => item.push(c),
+        }
+    }
+    items.push(item);
+    items
+        .into_iter()
+        .map(|item| unescape(&item))
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+/// The message locale's variants to look up, most specific first: for
+/// `sr_YU.UTF-8@Latn` that is `sr_YU@Latn`, `sr_YU`, `sr@Latn` and `sr`.
+pub fn locales() -> Vec<String> {
+    let locale = ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|value| !value.is_empty())
+        .unwrap_or_default();
+    locale_variants(&locale)
+}
+
+fn locale_variants(locale: &str) -> Vec<String> {
+    if locale.is_empty() || locale == "C" || locale == "POSIX" {
+        return Vec::new();
+    }
+    let (rest, modifier) = match locale.split_once('@') {
+        Some((rest, modifier)) => (rest, Some(modifier)),
+        None => (locale, None),
+    };
+    let rest = rest.split_once('.').map_or(rest, |(rest, _encoding)| rest);
+    let (lang, country) = match rest.split_once('_') {
+        Some((lang, country)) => (lang, Some(country)),
+        None => (rest, None),
+    };
+
+    let mut variants = Vec::new();
+    if let (Some(country), Some(modifier)) = (country, modifier) {
+        variants.push(format!("{}_{}@{}", lang, country, modifier));
+    }
+    if let Some(country) = country {
+        variants.push(format!("{}_{}", lang, country));
+    }
+    if let Some(modifier) = modifier {
+        variants.push(format!("{}@{}", lang, modifier));
+    }
+    variants.push(lang.to_string());
+    variants
+}
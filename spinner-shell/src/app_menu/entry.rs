@@ -0,0 +1,65 @@
+//! Applications as listed in the menu
+
+use std::path::PathBuf;
+
+/// Sidebar sections and the freedesktop main categories they gather.
+pub const SECTIONS: &[(&str, &[&str])] = &[
+    ("Accessories", &["Utility"]),
+    ("Development", &["Development"]),
+    ("Education", &["Education", "Science"]),
+    ("Games", &["Game"]),
+    ("Graphics", &["Graphics"]),
+    ("Internet", &["Network"]),
+    ("Multimedia", &["AudioVideo", "Audio", "Video"]),
+    ("Office", &["Office"]),
+    ("Settings", &["Settings"]),
+    ("System", &["System"]),
+];
+
+/// The section for apps in none of the others.
+pub const OTHER_SECTION: &str = "Other";
+
+#[derive(Debug, Clone)]
+pub struct AppEntry {
+    /// The desktop file ID, e.g. `org.gnome.Nautilus.desktop`.
+    pub id: String,
+    pub name: String,
+    /// Empty when the entry has none.
+    pub generic_name: String,
+    pub exec: String,
+    /// A themed icon name, or an absolute path.
+    pub icon: String,
+    pub description: String,
+    pub keywords: Vec<String>,
+    pub categories: Vec<String>,
+    pub actions: Vec<AppAction>,
+    /// The desktop file, for `%k`.
+    pub location: PathBuf,
+    pub terminal: bool,
+    /// Started over D-Bus rather than with `exec`.
+    pub dbus_activatable: bool,
+    pub working_dir: Option<PathBuf>,
+}
+
+/// A desktop action, such as a browser's "New Private Window".
+#[derive(Debug, Clone)]
+pub struct AppAction {
+    pub id: String,
+    pub name: String,
+    pub exec: String,
+    pub icon: String,
+}
+
+impl AppEntry {
+    /// The sidebar section the app is listed under.
+    pub fn section(&self) -> &'static str {
+        SECTIONS
+            .iter()
+            .find(|(_, categories)| {
+                self.categories
+                    .iter()
+                    .any(|c| categories.contains(&c.as_str()))
+            })
+            .map_or(OTHER_SECTION, |(name, _)| name)
+    }
+}
@@ -0,0 +1,85 @@
+//! Launch history on disk
+//!
+//! Kept in `$XDG_STATE_HOME/spinneros/launch-history.json` by desktop file
+//! ID. Each app has a frecency score: every launch adds one, and the score
+//! halves every `HALF_LIFE_DAYS`, so apps used often and lately rank
+//! higher in the search.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use xdg::BaseDirectories;
+
+const FILE_NAME: &str = "launch-history.json";
+const HALF_LIFE_DAYS: f64 = 14.0;
+/// Scores below this are as good as forgotten and dropped.
+const MIN_SCORE: f64 = 0.01;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct Entry {
+    score: f64,
+    /// When `score` was last brought up to date.
+    updated: DateTime<Local>,
+}
+
+impl Entry {
+    fn score_at(&self, now: DateTime<Local>) -> f64 {
+        let days = (now - self.updated).num_seconds().max(0) as f64 / 86_400.0;
+        self.score * 0.5f64.powf(days / HALF_LIFE_DAYS)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LaunchHistory {
+    apps: HashMap<String, Entry>,
+}
+
+impl LaunchHistory {
+    /// Reads the stored history. A missing file is an empty history.
+    pub fn load() -> Result<Self> {
+        let xdg = BaseDirectories::with_prefix("spinneros")?;
+        let path = xdg.get_state_home().join(FILE_NAME);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let contents =
+            fs::read_to_string(&path).with_context(|| format!("Failed to read {:?}", path))?;
+        serde_json::from_str(&contents).with_context(|| format!("Failed to parse {:?}", path))
+    }
+
+    /// Writes the history through a temporary file, so a crash mid-write
+    /// leaves the previous one intact.
+    pub fn save(&self) -> Result<()> {
+        let xdg = BaseDirectories::with_prefix("spinneros")?;
+        let path = xdg.place_state_file(FILE_NAME)?;
+        let temp = path.with_extension("json.tmp");
+
+        fs::write(&temp, serde_json::to_vec(self)?)
+            .with_context(|| format!("Failed to write {:?}", temp))?;
+        fs::rename(&temp, &path).with_context(|| format!("Failed to replace {:?}", path))?;
+        Ok(())
+    }
+
+    pub fn record(&mut self, app_id: &str, now: DateTime<Local>) {
+        let score = self.score(app_id, now) + 1.0;
+        self.apps.insert(
+            app_id.to_string(),
+            Entry {
+                score,
+                updated: now,
+            },
+        );
+        self.apps
+            .retain(|_, entry| entry.score_at(now) >= MIN_SCORE);
+    }
+
+    /// The app's frecency, 0 for apps never launched.
+    pub fn score(&self, app_id: &str, now: DateTime<Local>) -> f64 {
+        self.apps
+            .get(app_id)
+            .map_or(0.0, |entry| entry.score_at(now))
+    }
+}
@@ -1,6 +1,24 @@
 //! Application launcher
+//!
+//! Apps come from the `.desktop` files in the `applications` directories
+//! of `$XDG_DATA_HOME` and `$XDG_DATA_DIRS`. A file shadows those with the
+//! same desktop file ID further down the list, even when it hides the app.
 
-use super::AppEntry;
+use super::desktop_entry::{self, action_group, DesktopEntry, MAIN_GROUP};
+use super::{AppAction, AppEntry};
+use crate::wm;
+
+use anyhow::{bail, Context, Result};
+use spinner_launch::{is_installed, Exec, ExecContext, Launch};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use tracing::{debug, warn};
+use xdg::BaseDirectories;
+
+/// Matched against `OnlyShowIn` and `NotShowIn`, besides the desktops in
+/// `$XDG_CURRENT_DESKTOP`.
+const DESKTOP_NAME: &str = "SpinnerOS";
+const DEFAULT_ICON: &str = "application-x-executable";
 
 pub struct AppLauncher {
     apps: Vec<AppEntry>,
@@ -9,25 +27,10 @@ pub struct AppLauncher {
 impl AppLauncher {
     pub fn new() -> Self {
         Self {
-            apps: vec![
-                AppEntry {
-                    name: "Files".to_string(),
-                    exec: "nautilus".to_string(),
-                    icon: "system-file-manager".to_string(),
-                    description: "Browse files".to_string(),
-                    keywords: vec!["file".to_string()],
-                },
-                AppEntry {
-                    name: "Firefox".to_string(),
-                    exec: "firefox".to_string(),
-                    icon: "firefox".to_string(),
-                    description: "Web Browser".to_string(),
-                    keywords: vec!["web".to_string()],
-                },
-            ],
-        }
-    }
-    
+            apps: scan(&application_dirs()),
+        }
+    }
+
     pub fn get_apps(&self) -> &[AppEntry] {
         &self.apps
     }
@@ -38,3 +41,213 @@ impl Default for AppLauncher {
         Self::new()
     }
 }
+
+/// Where `.desktop` files are looked up, highest precedence first.
+pub fn application_dirs() -> Vec<PathBuf> {
+    match BaseDirectories::new() {
+        Ok(xdg) => std::iter::once(xdg.get_data_home())
+            .chain(xdg.get_data_dirs())
+            .map(|dir| dir.join("applications"))
+            .collect(),
+        Err(e) => {
+            warn!("Failed to find the XDG data directories: {}", e);
+            vec![PathBuf::from("/usr/share/applications")]
+        }
+    }
+}
+
+fn scan(dirs: &[PathBuf]) -> Vec<AppEntry> {
+    let locales = desktop_entry::locales();
+    let desktops = current_desktops();
+    let mut seen = HashSet::new();
+    let mut apps = Vec::new();
+
+    for dir in dirs {
+        let mut files = Vec::new();
+        find_desktop_files(dir, "", &mut files);
+        for (id, path) in files {
+            if !seen.insert(id.clone()) {
+                continue;
+            }
+            match load(id, &path, &locales, &desktops) {
+                Ok(Some(app)) => apps.push(app),
+                Ok(None) => {}
+                Err(e) => debug!("Skipping {:?}: {:#}", path, e),
+            }
+        }
+    }
+
+    debug!("Found {} applications", apps.len());
+    apps
+}
+
+/// Collects `.desktop` files with their desktop file IDs, the path below
+/// `applications` with `/` replaced by `-`.
+fn find_desktop_files(dir: &Path, prefix: &str, files: &mut Vec<(String, PathBuf)>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .collect();
+    paths.sort();
+
+    for path in paths {
+        let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        if path.is_dir() {
+            find_desktop_files(&path, &format!("{}{}-", prefix, name), files);
+        } else if name.ends_with(".desktop") {
+            files.push((format!("{}{}", prefix, name), path));
+        }
+    }
+}
+
+/// The desktops this session counts as for `OnlyShowIn` and `NotShowIn`.
+fn current_desktops() -> Vec<String> {
+    let mut desktops: Vec<String> = std::env::var("XDG_CURRENT_DESKTOP")
+        .unwrap_or_default()
+        .split(':')
+        .filter(|desktop| !desktop.is_empty())
+        .map(str::to_string)
+        .collect();
+    if !desktops.iter().any(|desktop| desktop == DESKTOP_NAME) {
+        desktops.push(DESKTOP_NAME.to_string());
+    }
+    desktops
+}
+
+/// Reads an entry; `None` when it isn't an app to list here.
+fn load(
+    id: String,
+    path: &Path,
+    locales: &[String],
+    desktops: &[String],
+) -> Result<Option<AppEntry>> {
+    let entry = DesktopEntry::read(path)?;
+    let group = MAIN_GROUP;
+    if !entry.has_group(group) {
+        bail!("No [{}] group", group);
+    }
+
+    if entry.string(group, "Type").as_deref() != Some("Application")
+        || entry.boolean(group, "Hidden")
+        || entry.boolean(group, "NoDisplay")
+    {
+        return Ok(None);
+    }
+    let only_show_in = entry.list(group, "OnlyShowIn");
+    if !only_show_in.is_empty()
+        && !only_show_in
+            .iter()
+            .any(|desktop| desktops.contains(desktop))
+    {
+        return Ok(None);
+    }
+    if entry
+        .list(group, "NotShowIn")
+        .iter()
+        .any(|desktop| desktops.contains(desktop))
+    {
+        return Ok(None);
+    }
+    if let Some(try_exec) = entry.string(group, "TryExec") {
+        if !is_installed(&try_exec) {
+            return Ok(None);
+        }
+    }
+
+    let name = entry.localized(group, "Name", locales).context("No Name")?;
+    let exec = entry.string(group, "Exec").context("No Exec")?;
+    Exec::parse(&exec)?;
+
+    let actions = entry
+        .list(group, "Actions")
+        .into_iter()
+        .filter_map(|action| {
+            let action_group = action_group(&action);
+            let exec = entry.string(&action_group, "Exec")?;
+            Exec::parse(&exec).ok()?;
+            Some(AppAction {
+                name: entry.localized(&action_group, "Name", locales)?,
+                exec,
+                icon: entry.string(&action_group, "Icon").unwrap_or_default(),
+                id: action,
+            })
+        })
+        .collect();
+
+    Ok(Some(AppEntry {
+        id,
+        name,
+        generic_name: entry
+            .localized(group, "GenericName", locales)
+            .unwrap_or_default(),
+        exec,
+        icon: entry
+            .string(group, "Icon")
+            .unwrap_or_else(|| DEFAULT_ICON.to_string()),
+        description: entry
+            .localized(group, "Comment", locales)
+            .unwrap_or_default(),
+        keywords: entry.localized_list(group, "Keywords", locales),
+        categories: entry.list(group, "Categories"),
+        actions,
+        location: path.to_path_buf(),
+        terminal: entry.boolean(group, "Terminal"),
+        dbus_activatable: entry.boolean(group, "DBusActivatable"),
+        working_dir: entry
+            .string(group, "Path")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from),
+    }))
+}
+
+/// Starts the app detached from the shell, in a scope of its own. Waits
+/// on SpinnerWM and D-Bus, so it's best called off the main thread.
+pub fn launch(app: &AppEntry) -> Result<()> {
+    start(app, None)
+}
+
+pub fn launch_action(app: &AppEntry, action: &AppAction) -> Result<()> {
+    start(app, Some(action))
+}
+
+fn start(app: &AppEntry, action: Option<&AppAction>) -> Result<()> {
+    // Without a token the app still starts, it just may not get focus.
+    let token = wm::activation_token(&app.id)
+        .map_err(|e| debug!("No activation token for {}: {:#}", app.id, e))
+        .ok();
+
+    if app.dbus_activatable {
+        let action_id = action.map(|action| action.id.as_str());
+        match spinner_launch::dbus::activate(&app.id, &[], action_id, token.as_deref()) {
+            Ok(()) => return Ok(()),
+            Err(e) => warn!(
+                "D-Bus activation of {} failed, running its Exec instead: {:#}",
+                app.id, e
+            ),
+        }
+    }
+
+    let exec = Exec::parse(action.map_or(&app.exec, |action| &action.exec))?;
+    let context = ExecContext {
+        name: &app.name,
+        icon: &app.icon,
+        location: Some(&app.location),
+        files: &[],
+    };
+    for args in exec.commands(&context) {
+        let mut launch = Launch::new(args).app_id(&app.id).terminal(app.terminal);
+        if let Some(dir) = &app.working_dir {
+            launch = launch.working_dir(dir);
+        }
+        if let Some(token) = &token {
+            launch = launch.activation_token(token);
+        }
+        launch.spawn()?;
+    }
+    Ok(())
+}
@@ -0,0 +1,560 @@
+//! Application menu overlay - search, category sidebar and app grid
+//!
+//! Toggled from the `app-menu` application action, so the panel's menu
+//! button and `spinner-shell --menu` (bound to `Super_L` and `Super + D` in
+//! spinner-wm.toml) open it. Typing anywhere searches; the arrow keys move
+//! through the grid, Enter launches and Escape closes. Right clicking an app
+//! lists its desktop actions.
+//!
+//! The application directories are watched, so apps show up and go away
+//! as they are installed and removed.
+
+use super::history::LaunchHistory;
+use super::launcher::{self, AppLauncher};
+use super::search::SearchEngine;
+use super::{AppAction, AppEntry, OTHER_SECTION, SECTIONS};
+use crate::config::AppMenuConfig;
+
+use gtk4::prelude::*;
+use gtk4::{
+    self, gdk, gio, glib, pango, Align, Box as GtkBox, Button, FlowBox, FlowBoxChild, GestureClick,
+    Image, Label, Orientation, PopoverMenu, ScrolledWindow, SearchEntry, SelectionMode,
+};
+use gtk4_layer_shell::{KeyboardMode, Layer, LayerShell};
+use libadwaita as adw;
+use std::cell::{Cell, RefCell};
+use std::path::Path;
+use std::rc::Rc;
+use std::time::Duration;
+use tracing::{debug, error, info, warn};
+
+const SIDEBAR_WIDTH: i32 = 180;
+const HEIGHT: i32 = 560;
+/// Room around an icon in its tile.
+const TILE_PADDING: i32 = 56;
+/// Installs touch several files, so rescanning waits for them to settle.
+const RELOAD_DELAY: Duration = Duration::from_millis(500);
+
+pub struct AppMenu {
+    window: gtk4::ApplicationWindow,
+    search: SearchEntry,
+    grid: FlowBox,
+    empty: Label,
+    sidebar: GtkBox,
+    engine: RefCell<SearchEngine>,
+    apps: RefCell<Vec<AppEntry>>,
+    columns: u32,
+    icon_size: i32,
+    /// What the grid shows, in order.
+    shown: RefCell<Vec<AppEntry>>,
+    /// `None` for all apps.
+    section: Cell<Option<&'static str>>,
+    section_buttons: RefCell<Vec<(Option<&'static str>, Button)>>,
+    monitors: RefCell<Vec<gio::FileMonitor>>,
+    reload_serial: Cell<u32>,
+}
+
+impl AppMenu {
+    pub fn new(app: &adw::Application, config: &AppMenuConfig) -> Rc<Self> {
+        let columns = config.columns.max(1);
+        let icon_size = config.icon_size.max(16);
+        let grid_width = columns as i32 * (icon_size + TILE_PADDING + 8) + 32;
+        let width = grid_width
+            + if config.show_categories {
+                SIDEBAR_WIDTH
+            } else {
+                0
+            };
+
+        let window = gtk4::ApplicationWindow::builder()
+            .application(app)
+            .decorated(false)
+            .resizable(false)
+            .default_width(width)
+            .default_height(HEIGHT)
+            .build();
+        window.add_css_class("app-menu-window");
+
+        window.init_layer_shell();
+        window.set_layer(Layer::Overlay);
+        window.set_namespace("spinner-app-menu");
+        window.set_keyboard_mode(KeyboardMode::OnDemand);
+
+        let content = GtkBox::builder().orientation(Orientation::Vertical).build();
+        content.add_css_class("app-menu-container");
+
+        let header = GtkBox::builder()
+            .orientation(Orientation::Horizontal)
+            .spacing(12)
+            .margin_top(16)
+            .margin_bottom(16)
+            .margin_start(16)
+            .margin_end(16)
+            .build();
+        header.add_css_class("app-menu-header");
+        let search = SearchEntry::builder()
+            .placeholder_text("Search applications…")
+            .hexpand(true)
+            .visible(config.show_search)
+            .build();
+        search.add_css_class("app-search-entry");
+        search.set_key_capture_widget(Some(&window));
+        header.append(&search);
+        let close = Button::builder()
+            .icon_name("window-close-symbolic")
+            .tooltip_text("Close")
+            .halign(Align::End)
+            .hexpand(!config.show_search)
+            .build();
+        close.add_css_class("app-menu-close");
+        header.append(&close);
+        content.append(&header);
+
+        let body = GtkBox::builder()
+            .orientation(Orientation::Horizontal)
+            .vexpand(true)
+            .build();
+        let sidebar = GtkBox::builder()
+            .orientation(Orientation::Vertical)
+            .spacing(2)
+            .width_request(SIDEBAR_WIDTH)
+            .visible(config.show_categories)
+            .build();
+        sidebar.add_css_class("app-menu-sidebar");
+        body.append(&sidebar);
+
+        let grid = FlowBox::builder()
+            .selection_mode(SelectionMode::Single)
+            .min_children_per_line(columns)
+            .max_children_per_line(columns)
+            .homogeneous(true)
+            .activate_on_single_click(true)
+            .valign(Align::Start)
+            .build();
+        grid.add_css_class("app-grid");
+        let empty = Label::builder()
+            .label("No applications found")
+            .vexpand(true)
+            .visible(false)
+            .build();
+        empty.add_css_class("app-menu-empty");
+        let grid_box = GtkBox::builder().orientation(Orientation::Vertical).build();
+        grid_box.append(&grid);
+        grid_box.append(&empty);
+        let scroll = ScrolledWindow::builder()
+            .hscrollbar_policy(gtk4::PolicyType::Never)
+            .hexpand(true)
+            .child(&grid_box)
+            .build();
+        scroll.add_css_class("app-grid-scroll");
+        body.append(&scroll);
+        content.append(&body);
+        window.set_child(Some(&content));
+
+        let apps = AppLauncher::new().get_apps().to_vec();
+        let history = LaunchHistory::load().unwrap_or_else(|e| {
+            error!("Failed to load launch history: {:#}", e);
+            LaunchHistory::default()
+        });
+        let menu = Rc::new(Self {
+            window,
+            search,
+            grid,
+            empty,
+            sidebar,
+            engine: RefCell::new(SearchEngine::new(apps.clone(), history)),
+            apps: RefCell::new(apps),
+            columns,
+            icon_size,
+            shown: RefCell::new(Vec::new()),
+            section: Cell::new(None),
+            section_buttons: RefCell::new(Vec::new()),
+            monitors: RefCell::new(Vec::new()),
+            reload_serial: Cell::new(0),
+        });
+        menu.build_sidebar();
+        menu.connect_signals(&close);
+        menu.watch();
+        menu
+    }
+
+    fn build_sidebar(self: &Rc<Self>) {
+        for (_, button) in self.section_buttons.take() {
+            self.sidebar.remove(&button);
+        }
+
+        let apps = self.apps.borrow();
+        let mut sections: Vec<Option<&'static str>> = vec![None];
+        sections.extend(
+            SECTIONS
+                .iter()
+                .map(|(name, _)| *name)
+                .chain([OTHER_SECTION])
+                .filter(|name| apps.iter().any(|app| app.section() == *name))
+                .map(Some),
+        );
+
+        for section in sections {
+            let button = Button::builder()
+                .label(section.unwrap_or("All Applications"))
+                .build();
+            button.add_css_class("category-button");
+            if let Some(label) = button.child().and_downcast::<Label>() {
+                label.set_xalign(0.0);
+            }
+            let weak = Rc::downgrade(self);
+            button.connect_clicked(move |_| {
+                if let Some(menu) = weak.upgrade() {
+                    menu.select_section(section);
+                }
+            });
+            self.sidebar.append(&button);
+            self.section_buttons.borrow_mut().push((section, button));
+        }
+        drop(apps);
+        self.update_section_buttons();
+    }
+
+    /// Watches the application directories and the directories in them.
+    /// Missing ones are watched too, for when they get created.
+    fn watch(self: &Rc<Self>) {
+        let mut monitors = Vec::new();
+        let mut dirs = launcher::application_dirs();
+        while let Some(dir) = dirs.pop() {
+            if let Ok(entries) = std::fs::read_dir(&dir) {
+                dirs.extend(
+                    entries
+                        .filter_map(|entry| entry.ok())
+                        .map(|entry| entry.path())
+                        .filter(|path| path.is_dir()),
+                );
+            }
+            let monitor = match gio::File::for_path(&dir)
+                .monitor_directory(gio::FileMonitorFlags::NONE, gio::Cancellable::NONE)
+            {
+                Ok(monitor) => monitor,
+                Err(e) => {
+                    debug!("Not watching {:?}: {}", dir, e);
+                    continue;
+                }
+            };
+            let weak = Rc::downgrade(self);
+            monitor.connect_changed(move |_, _, _, _| {
+                if let Some(menu) = weak.upgrade() {
+                    menu.schedule_reload();
+                }
+            });
+            monitors.push(monitor);
+        }
+        self.monitors.replace(monitors);
+    }
+
+    fn schedule_reload(self: &Rc<Self>) {
+        let serial = self.reload_serial.get().wrapping_add(1);
+        self.reload_serial.set(serial);
+        let weak = Rc::downgrade(self);
+        glib::timeout_add_local_once(RELOAD_DELAY, move || {
+            if let Some(menu) = weak.upgrade() {
+                if menu.reload_serial.get() == serial {
+                    menu.reload();
+                }
+            }
+        });
+    }
+
+    /// Rescans the application directories after a change in them.
+    fn reload(self: &Rc<Self>) {
+        let apps = AppLauncher::new().get_apps().to_vec();
+        info!("Reloaded {} applications", apps.len());
+        if let Some(section) = self.section.get() {
+            if !apps.iter().any(|app| app.section() == section) {
+                self.section.set(None);
+            }
+        }
+        self.engine.borrow_mut().set_apps(apps.clone());
+        self.apps.replace(apps);
+        self.build_sidebar();
+        // New subdirectories need watching as well.
+        self.watch();
+        if self.window.is_visible() {
+            self.refresh();
+        }
+    }
+
+    fn connect_signals(self: &Rc<Self>, close: &Button) {
+        let weak = Rc::downgrade(self);
+        close.connect_clicked(move |_| {
+            if let Some(menu) = weak.upgrade() {
+                menu.hide();
+            }
+        });
+
+        let weak = Rc::downgrade(self);
+        self.search.connect_search_changed(move |search| {
+            let Some(menu) = weak.upgrade() else {
+                return;
+            };
+            // A search covers every section.
+            if !search.text().is_empty() && menu.section.get().is_some() {
+                menu.section.set(None);
+                menu.update_section_buttons();
+            }
+            menu.refresh();
+        });
+
+        let weak = Rc::downgrade(self);
+        self.search.connect_activate(move |_| {
+            if let Some(menu) = weak.upgrade() {
+                let selected = menu.grid.selected_children().into_iter().next();
+                if let Some(child) = selected.or_else(|| menu.grid.child_at_index(0)) {
+                    menu.launch(child.index());
+                }
+            }
+        });
+
+        let weak = Rc::downgrade(self);
+        self.search.connect_stop_search(move |_| {
+            if let Some(menu) = weak.upgrade() {
+                menu.hide();
+            }
+        });
+
+        let weak = Rc::downgrade(self);
+        self.grid.connect_child_activated(move |_, child| {
+            if let Some(menu) = weak.upgrade() {
+                menu.launch(child.index());
+            }
+        });
+
+        // Down from the search field enters the grid, up from its first
+        // row goes back.
+        let keys = gtk4::EventControllerKey::new();
+        let weak = Rc::downgrade(self);
+        keys.connect_key_pressed(move |_, key, _, _| {
+            let Some(menu) = weak.upgrade() else {
+                return glib::Propagation::Proceed;
+            };
+            if key == gdk::Key::Escape {
+                menu.hide();
+                return glib::Propagation::Stop;
+            }
+            // The search field's focus is on the text inside it.
+            let focus = menu.window.focus();
+            let in_search = focus.as_ref().is_some_and(|w| w.is_ancestor(&menu.search));
+            let row_index = focus
+                .and_downcast::<FlowBoxChild>()
+                .map(|child| child.index());
+            match key {
+                gdk::Key::Down if in_search => {
+                    let child = menu.grid.selected_children().into_iter().next();
+                    if let Some(child) = child.or_else(|| menu.grid.child_at_index(0)) {
+                        child.grab_focus();
+                    }
+                    glib::Propagation::Stop
+                }
+                gdk::Key::Up if row_index.is_some_and(|i| i < menu.columns as i32) => {
+                    menu.search.grab_focus();
+                    glib::Propagation::Stop
+                }
+                _ => glib::Propagation::Proceed,
+            }
+        });
+        keys.set_propagation_phase(gtk4::PropagationPhase::Capture);
+        self.window.add_controller(keys);
+
+        // Clicking elsewhere closes the menu.
+        let weak = Rc::downgrade(self);
+        self.window.connect_is_active_notify(move |window| {
+            if window.is_active() {
+                return;
+            }
+            if let Some(menu) = weak.upgrade() {
+                menu.hide();
+            }
+        });
+    }
+
+    pub fn toggle(self: &Rc<Self>) {
+        if self.window.is_visible() {
+            self.hide();
+        } else {
+            self.show();
+        }
+    }
+
+    pub fn show(self: &Rc<Self>) {
+        info!("Showing app menu");
+        self.section.set(None);
+        self.update_section_buttons();
+        self.search.set_text("");
+        self.refresh();
+        self.window.present();
+        self.search.grab_focus();
+    }
+
+    pub fn hide(&self) {
+        if self.window.is_visible() {
+            info!("Hiding app menu");
+            self.window.set_visible(false);
+        }
+    }
+
+    fn select_section(self: &Rc<Self>, section: Option<&'static str>) {
+        self.section.set(section);
+        self.update_section_buttons();
+        // Leaving the search also leaves its results.
+        if self.search.text().is_empty() {
+            self.refresh();
+        } else {
+            self.search.set_text("");
+        }
+    }
+
+    fn update_section_buttons(&self) {
+        let current = self.section.get();
+        for (section, button) in self.section_buttons.borrow().iter() {
+            if *section == current {
+                button.add_css_class("active");
+            } else {
+                button.remove_css_class("active");
+            }
+        }
+    }
+
+    /// Fills the grid with the search results, or the current section
+    /// alphabetically.
+    fn refresh(self: &Rc<Self>) {
+        let query = self.search.text();
+        let section = self.section.get();
+        let mut apps: Vec<AppEntry> = self
+            .engine
+            .borrow()
+            .search(&query)
+            .into_iter()
+            .filter(|app| section.is_none_or(|section| app.section() == section))
+            .cloned()
+            .collect();
+        if query.is_empty() {
+            apps.sort_by_cached_key(|app| app.name.to_lowercase());
+        }
+
+        while let Some(child) = self.grid.first_child() {
+            self.grid.remove(&child);
+        }
+        for app in &apps {
+            self.grid.append(&self.tile(app));
+        }
+        self.empty.set_visible(apps.is_empty());
+        if let Some(first) = self.grid.child_at_index(0) {
+            self.grid.select_child(&first);
+        }
+        self.shown.replace(apps);
+    }
+
+    fn tile(self: &Rc<Self>, app: &AppEntry) -> FlowBoxChild {
+        let tile = GtkBox::builder()
+            .orientation(Orientation::Vertical)
+            .spacing(4)
+            .width_request(self.icon_size + TILE_PADDING)
+            .build();
+        tile.add_css_class("app-button");
+        if !app.description.is_empty() {
+            tile.set_tooltip_text(Some(&app.description));
+        }
+
+        let icon = if Path::new(&app.icon).is_absolute() {
+            Image::from_file(&app.icon)
+        } else {
+            Image::from_icon_name(&app.icon)
+        };
+        icon.set_pixel_size(self.icon_size);
+        icon.add_css_class("app-icon");
+        tile.append(&icon);
+
+        let label = Label::builder()
+            .label(&app.name)
+            .ellipsize(pango::EllipsizeMode::End)
+            .max_width_chars(12)
+            .justify(gtk4::Justification::Center)
+            .build();
+        label.add_css_class("app-label");
+        tile.append(&label);
+        if !app.actions.is_empty() {
+            self.add_actions_menu(&tile, app);
+        }
+
+        let child = FlowBoxChild::new();
+        child.set_child(Some(&tile));
+        child.add_css_class("app-flow-child");
+        child
+    }
+
+    /// Lists the app's desktop actions on right click.
+    fn add_actions_menu(self: &Rc<Self>, tile: &GtkBox, app: &AppEntry) {
+        let actions = gio::SimpleActionGroup::new();
+        let model = gio::Menu::new();
+        for (i, action) in app.actions.iter().enumerate() {
+            let name = format!("action-{}", i);
+            let simple = gio::SimpleAction::new(&name, None);
+            let weak = Rc::downgrade(self);
+            let (app, action) = (app.clone(), action.clone());
+            simple.connect_activate(move |_, _| {
+                if let Some(menu) = weak.upgrade() {
+                    menu.start(app.clone(), Some(action.clone()));
+                }
+            });
+            actions.add_action(&simple);
+            model.append(Some(&action.name), Some(&format!("desktop.{}", name)));
+        }
+        tile.insert_action_group("desktop", Some(&actions));
+
+        let popover = PopoverMenu::builder().menu_model(&model).has_arrow(false).build();
+        popover.add_css_class("app-actions-popover");
+        popover.set_parent(tile);
+        let popover_clone = popover.clone();
+        tile.connect_destroy(move |_| {
+            popover_clone.unparent();
+        });
+
+        let click = GestureClick::builder().button(gdk::BUTTON_SECONDARY).build();
+        click.connect_pressed(move |gesture, _, _, _| {
+            gesture.set_state(gtk4::EventSequenceState::Claimed);
+            popover.popup();
+        });
+        tile.add_controller(click);
+    }
+
+    fn launch(&self, index: i32) {
+        let Some(app) = self.shown.borrow().get(index as usize).cloned() else {
+            return;
+        };
+        self.start(app, None);
+    }
+
+    /// Launches off the main thread, and remembers the launch for the
+    /// search ranking.
+    fn start(&self, app: AppEntry, action: Option<AppAction>) {
+        match &action {
+            Some(action) => info!("Launching {} ({})", app.name, action.name),
+            None => info!("Launching {}", app.name),
+        }
+        {
+            let mut engine = self.engine.borrow_mut();
+            engine.record_launch(&app);
+            if let Err(e) = engine.history().save() {
+                error!("Failed to save launch history: {:#}", e);
+            }
+        }
+
+        std::thread::spawn(move || {
+            let result = match &action {
+                Some(action) => launcher::launch_action(&app, action),
+                None => launcher::launch(&app),
+            };
+            if let Err(e) = result {
+                warn!("{:#}", e);
+            }
+        });
+        self.hide();
+    }
+}
@@ -1,26 +1,13 @@
 //! Application menu module
 
+mod desktop_entry;
+mod entry;
+mod history;
 mod launcher;
+mod menu;
 mod search;
 
+pub use entry::{AppAction, AppEntry, OTHER_SECTION, SECTIONS};
 pub use launcher::AppLauncher;
+pub use menu::AppMenu;
 pub use search::SearchEngine;
-
-use tracing::info;
-
-pub fn show_app_menu() {
-    info!("Showing app menu");
-}
-
-pub fn hide_app_menu() {
-    info!("Hiding app menu");
-}
-
-#[derive(Debug, Clone)]
-pub struct AppEntry {
-    pub name: String,
-    pub exec: String,
-    pub icon: String,
-    pub description: String,
-    pub keywords: Vec<String>,
-}
@@ -1,37 +1,190 @@
 //! Search engine for application launcher
+//!
+//! Every word of the query has to match the app's name, generic name,
+//! keywords, program or description, and matches count in that order of
+//! weight. A field matching the whole word beats one starting with it,
+//! then one with a word starting with it, then one merely containing it.
+//! Words found nowhere may still fuzzily match the name, below all of
+//! those, so typos such as "frefox" work. Apps launched often and lately
+//! get a boost from their frecency, see `history`.
+//!
+//! This runs on every keystroke, so the fuzzy matcher, by far the most
+//! expensive part, only sees the names nothing else matched.
 
+use super::history::LaunchHistory;
 use super::AppEntry;
+use chrono::Local;
 use fuzzy_matcher::skim::SkimMatcherV2;
 use fuzzy_matcher::FuzzyMatcher;
 
+/// Out of 10, so a match in the name counts in full.
+const NAME_WEIGHT: i64 = 10;
+const GENERIC_NAME_WEIGHT: i64 = 7;
+const KEYWORD_WEIGHT: i64 = 6;
+const PROGRAM_WEIGHT: i64 = 5;
+const DESCRIPTION_WEIGHT: i64 = 3;
+
+const EXACT_MATCH: i64 = 100;
+const PREFIX_MATCH: i64 = 80;
+const WORD_PREFIX_MATCH: i64 = 60;
+const SUBSTRING_MATCH: i64 = 40;
+/// Fuzzy matches score below this, a tenth of the matcher's score.
+const FUZZY_MATCH_LIMIT: i64 = 30;
+/// The most frecency can add, as a fraction of the match score. Ranking
+/// by it never lifts a poor match over a much better one.
+const FRECENCY_BOOST: f64 = 0.5;
+
+/// An app's fields, lowercased once rather than on every keystroke.
+struct Indexed {
+    name: String,
+    generic_name: String,
+    keywords: Vec<String>,
+    program: String,
+    description: String,
+}
+
+impl Indexed {
+    fn new(app: &AppEntry) -> Self {
+        Self {
+            name: app.name.to_lowercase(),
+            generic_name: app.generic_name.to_lowercase(),
+            keywords: app
+                .keywords
+                .iter()
+                .map(|keyword| keyword.to_lowercase())
+                .collect(),
+            program: program(&app.exec).to_lowercase(),
+            description: app.description.to_lowercase(),
+        }
+    }
+}
+
+/// The name of the program `exec` runs, past `env` and its variables.
+fn program(exec: &str) -> String {
+    let args = spinner_launch::split(exec).unwrap_or_default();
+    args.iter()
+        .find(|arg| *arg != "env" && !arg.contains('='))
+        .map(|program| program.rsplit('/').next().unwrap_or(program).to_string())
+        .unwrap_or_default()
+}
+
 pub struct SearchEngine {
     apps: Vec<AppEntry>,
+    index: Vec<Indexed>,
     matcher: SkimMatcherV2,
+    history: LaunchHistory,
 }
 
 impl SearchEngine {
-    pub fn new(apps: Vec<AppEntry>) -> Self {
+    pub fn new(apps: Vec<AppEntry>, history: LaunchHistory) -> Self {
         Self {
+            index: apps.iter().map(Indexed::new).collect(),
             apps,
-            matcher: SkimMatcherV2::default(),
+            // Both sides are lowercased already.
+            matcher: SkimMatcherV2::default().respect_case(),
+            history,
         }
     }
-    
+
+    /// Replaces the apps, keeping the launch history.
+    pub fn set_apps(&mut self, apps: Vec<AppEntry>) {
+        self.index = apps.iter().map(Indexed::new).collect();
+        self.apps = apps;
+    }
+
+    pub fn history(&self) -> &LaunchHistory {
+        &self.history
+    }
+
+    pub fn record_launch(&mut self, app: &AppEntry) {
+        self.history.record(&app.id, Local::now());
+    }
+
+    /// Apps matching `query`, best first. An empty query lists every app.
     pub fn search(&self, query: &str) -> Vec<&AppEntry> {
-        if query.is_empty() {
+        if query.trim().is_empty() {
             return self.apps.iter().collect();
         }
-        
+
         let query = query.to_lowercase();
-        let mut results: Vec<(&AppEntry, i64)> = Vec::new();
-        
-        for app in &self.apps {
-            if let Some(score) = self.matcher.fuzzy_match(&app.name.to_lowercase(), &query) {
-                results.push((app, score));
-            }
+        let terms: Vec<&str> = query.split_whitespace().collect();
+        let now = Local::now();
+        let mut results: Vec<(&AppEntry, f64)> = Vec::new();
+
+        for (app, indexed) in self.apps.iter().zip(&self.index) {
+            let Some(score) = terms
+                .iter()
+                .map(|term| self.score_term(indexed, term))
+                .sum::<Option<i64>>()
+            else {
+                continue;
+            };
+            let frecency = self.history.score(&app.id, now);
+            let boost = 1.0 + FRECENCY_BOOST * frecency / (frecency + 1.0);
+            results.push((app, score as f64 * boost));
         }
-        
-        results.sort_by(|a, b| b.1.cmp(&a.1));
+
+        // Among equals, the shortest name is the closest match.
+        results.sort_by(|a, b| {
+            b.1.total_cmp(&a.1)
+                .then_with(|| a.0.name.len().cmp(&b.0.name.len()))
+                .then_with(|| a.0.name.cmp(&b.0.name))
+        });
         results.into_iter().map(|(app, _)| app).collect()
     }
+
+    /// The best weighted match of one query word among the app's fields.
+    fn score_term(&self, indexed: &Indexed, term: &str) -> Option<i64> {
+        let weighted = |field: &str, weight: i64| {
+            match_score(field, term).map(|score| score * weight / NAME_WEIGHT)
+        };
+
+        let best = [
+            weighted(&indexed.name, NAME_WEIGHT),
+            weighted(&indexed.generic_name, GENERIC_NAME_WEIGHT),
+            indexed
+                .keywords
+                .iter()
+                .filter_map(|keyword| weighted(keyword, KEYWORD_WEIGHT))
+                .max(),
+            weighted(&indexed.program, PROGRAM_WEIGHT),
+            weighted(&indexed.description, DESCRIPTION_WEIGHT),
+        ]
+        .into_iter()
+        .flatten()
+        .max();
+
+        // Checking the letters come in order first spares the matcher
+        // most names.
+        best.or_else(|| {
+            is_subsequence(&indexed.name, term)
+                .then(|| self.matcher.fuzzy_match(&indexed.name, term))
+                .flatten()
+                .map(|score| (score / 10).clamp(1, FUZZY_MATCH_LIMIT - 1))
+        })
+    }
+}
+
+/// How well `field` contains `term`, if at all.
+fn match_score(field: &str, term: &str) -> Option<i64> {
+    if field == term {
+        return Some(EXACT_MATCH);
+    }
+    if field.starts_with(term) {
+        return Some(PREFIX_MATCH);
+    }
+
+    let mut best = None;
+    for (i, _) in field.match_indices(term) {
+        if field[..i].ends_with(|c: char| !c.is_alphanumeric()) {
+            return Some(WORD_PREFIX_MATCH);
+        }
+        best = Some(SUBSTRING_MATCH);
+    }
+    best
+}
+
+fn is_subsequence(field: &str, term: &str) -> bool {
+    let mut chars = field.chars();
+    term.chars().all(|c| chars.any(|f| f == c))
 }
@@ -0,0 +1,434 @@
+//! Configuration management for SpinnerShell
+
+use anyhow::{Context, Result};
+use chrono::NaiveTime;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use tracing::warn;
+use xdg::BaseDirectories;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ShellConfig {
+    pub panel: PanelConfig,
+    pub clock: ClockConfig,
+    pub systray: SystrayConfig,
+    pub quick_settings: QuickSettingsConfig,
+    pub power: PowerConfig,
+    pub lock: LockConfig,
+    pub notifications: NotificationsConfig,
+    pub app_menu: AppMenuConfig,
+    pub theme: ThemeConfig,
+    /// Script modules, placed in the panel as `custom/<name>`.
+    pub custom: BTreeMap<String, CustomModuleConfig>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PanelPosition {
+    #[default]
+    Top,
+    Bottom,
+    Left,
+    Right,
+}
+
+impl PanelPosition {
+    pub fn is_vertical(self) -> bool {
+        matches!(self, Self::Left | Self::Right)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PanelConfig {
+    pub position: PanelPosition,
+    /// Thickness of the panel; its width when docked to the left or right.
+    pub height: i32,
+    pub background_opacity: f64,
+    pub blur_enabled: bool,
+    /// Connector names ("DP-1", "eDP-1") of the monitors that get a panel;
+    /// empty means every monitor.
+    pub outputs: Vec<String>,
+    /// Show only the windows on the panel's own monitor in its taskbar.
+    pub taskbar_per_output: bool,
+    pub left: PanelSectionConfig,
+    pub center: PanelSectionConfig,
+    pub right: PanelSectionConfig,
+}
+
+impl Default for PanelConfig {
+    fn default() -> Self {
+        Self {
+            position: PanelPosition::Top,
+            height: 48,
+            background_opacity: 0.95,
+            blur_enabled: true,
+            outputs: Vec::new(),
+            taskbar_per_output: false,
+            left: PanelSectionConfig::new(&["app_menu", "taskbar"]),
+            center: PanelSectionConfig::new(&["workspaces"]),
+            right: PanelSectionConfig::new(&["systray", "clock", "power"]),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PanelSectionConfig {
+    pub modules: Vec<String>,
+}
+
+impl PanelSectionConfig {
+    fn new(modules: &[&str]) -> Self {
+        Self {
+            modules: modules.iter().map(|m| m.to_string()).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClockConfig {
+    pub format_time: String,
+    pub format_date: String,
+    pub show_seconds: bool,
+    pub show_week_numbers: bool,
+    /// `.ics` files or directories of them; `~` expands to the home
+    /// directory.
+    pub calendars: Vec<String>,
+    /// How many days ahead the agenda looks.
+    pub agenda_days: u32,
+    /// Extra timezones shown in the tooltip and calendar popover.
+    pub world_clocks: Vec<WorldClockConfig>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorldClockConfig {
+    /// IANA name, e.g. "America/New_York".
+    pub timezone: String,
+    /// Defaults to the city part of the timezone name.
+    #[serde(default)]
+    pub label: Option<String>,
+}
+
+impl Default for ClockConfig {
+    fn default() -> Self {
+        Self {
+            format_time: "%H:%M".to_string(),
+            format_date: "%a, %b %d".to_string(),
+            show_seconds: false,
+            show_week_numbers: true,
+            calendars: vec!["~/.local/share/calendars".to_string()],
+            agenda_days: 7,
+            world_clocks: Vec::new(),
+        }
+    }
+}
+
+impl ClockConfig {
+    pub fn calendar_paths(&self) -> Vec<PathBuf> {
+        self.calendars
+            .iter()
+            .map(|path| match (path.strip_prefix("~/"), dirs::home_dir()) {
+                (Some(rest), Some(home)) => home.join(rest),
+                _ => PathBuf::from(path),
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SystrayConfig {
+    pub show_network: bool,
+    pub show_audio: bool,
+    pub show_battery: bool,
+    pub show_bluetooth: bool,
+    /// Icons apps put in the tray over StatusNotifierItem.
+    pub show_status_icons: bool,
+    /// Battery percentages that raise a notification while discharging;
+    /// 0 turns a warning off.
+    pub low_battery: u32,
+    pub critical_battery: u32,
+}
+
+impl Default for SystrayConfig {
+    fn default() -> Self {
+        Self {
+            show_network: true,
+            show_audio: true,
+            show_battery: true,
+            show_bluetooth: true,
+            show_status_icons: true,
+            low_battery: 15,
+            critical_battery: 5,
+        }
+    }
+}
+
+/// The quick settings popover at the end of the system tray.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QuickSettingsConfig {
+    pub enabled: bool,
+    /// Runs for as long as night light is on; the toggle is hidden when
+    /// the program isn't installed.
+    pub night_light_command: String,
+    pub settings_command: String,
+}
+
+impl Default for QuickSettingsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            night_light_command: "gammastep -O 4000".to_string(),
+            settings_command: "spinner-settings".to_string(),
+        }
+    }
+}
+
+/// The power menu behind the panel's power button.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PowerConfig {
+    /// Seconds to wait before logging out, restarting or shutting down,
+    /// with a chance to cancel; 0 acts at once.
+    pub confirm_seconds: u32,
+}
+
+impl Default for PowerConfig {
+    fn default() -> Self {
+        Self { confirm_seconds: 60 }
+    }
+}
+
+/// The lock screen.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LockConfig {
+    pub enabled: bool,
+    /// The PAM service the password is checked against, in `/etc/pam.d`.
+    pub pam_service: String,
+    /// Shows how many notifications are unread, but not their content.
+    pub show_notifications: bool,
+    /// Locks before suspend and hibernate, delaying them until locked.
+    pub lock_before_sleep: bool,
+}
+
+impl Default for LockConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            pam_service: "spinner-lock".to_string(),
+            show_notifications: true,
+            lock_before_sleep: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NotificationsConfig {
+    pub enabled: bool,
+    /// Screen corner (or edge center) the popups stack from.
+    pub position: NotificationPosition,
+    pub max_visible: u32,
+    /// Milliseconds; 0 keeps the notification until dismissed.
+    pub timeout_normal: u32,
+    pub timeout_critical: u32,
+    pub do_not_disturb: bool,
+    /// Daily period with Do Not Disturb on.
+    pub quiet_hours: Option<QuietHours>,
+    /// Do Not Disturb while a fullscreen window has focus.
+    pub dnd_when_fullscreen: bool,
+    /// Critical notifications still pop up during Do Not Disturb.
+    pub critical_bypasses_dnd: bool,
+    pub rules: Vec<NotificationRule>,
+    /// Most notifications kept in the history.
+    pub history_limit: u32,
+    /// Days the history is kept; 0 keeps it regardless of age.
+    pub history_days: u32,
+}
+
+/// Local times as "HH:MM"; a period ending before it starts runs past
+/// midnight.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuietHours {
+    pub start: String,
+    pub end: String,
+}
+
+impl QuietHours {
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        let parse = |value: &str| NaiveTime::parse_from_str(value.trim(), "%H:%M");
+        let (Ok(start), Ok(end)) = (parse(&self.start), parse(&self.end)) else {
+            warn!("Invalid quiet hours {:?} to {:?}, expected HH:MM", self.start, self.end);
+            return false;
+        };
+
+        if start <= end {
+            start <= time && time < end
+        } else {
+            time >= start || time < end
+        }
+    }
+}
+
+/// What happens to notifications from one app.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NotificationRule {
+    /// Matched case-insensitively against the app name or desktop entry.
+    pub app: String,
+    pub action: RuleAction,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RuleAction {
+    /// Dropped without a trace.
+    Mute,
+    /// Shown, but without playing its sound.
+    NoSound,
+    /// Listed in the notification center only.
+    NoPopup,
+    /// Goes straight to the history, as if it had already expired.
+    HistoryOnly,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum NotificationPosition {
+    TopLeft,
+    TopCenter,
+    #[default]
+    TopRight,
+    BottomLeft,
+    BottomCenter,
+    BottomRight,
+}
+
+impl NotificationPosition {
+    pub fn is_top(self) -> bool {
+        matches!(self, Self::TopLeft | Self::TopCenter | Self::TopRight)
+    }
+
+    pub fn is_left(self) -> bool {
+        matches!(self, Self::TopLeft | Self::BottomLeft)
+    }
+
+    pub fn is_center(self) -> bool {
+        matches!(self, Self::TopCenter | Self::BottomCenter)
+    }
+}
+
+impl Default for NotificationsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            position: NotificationPosition::TopRight,
+            max_visible: 5,
+            timeout_normal: 5000,
+            timeout_critical: 0,
+            do_not_disturb: false,
+            quiet_hours: None,
+            dnd_when_fullscreen: true,
+            critical_bypasses_dnd: true,
+            rules: Vec::new(),
+            history_limit: 100,
+            history_days: 7,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AppMenuConfig {
+    pub show_categories: bool,
+    pub show_search: bool,
+    pub icon_size: i32,
+    pub columns: u32,
+}
+
+impl Default for AppMenuConfig {
+    fn default() -> Self {
+        Self {
+            show_categories: true,
+            show_search: true,
+            icon_size: 48,
+            columns: 6,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ThemeConfig {
+    pub name: String,
+    pub variant: String,
+    pub accent_color: String,
+    pub enable_animations: bool,
+    pub animation_speed: u32,
+}
+
+impl Default for ThemeConfig {
+    fn default() -> Self {
+        Self {
+            name: "SpinnerOS Glass".to_string(),
+            variant: "dark".to_string(),
+            accent_color: "#88c0d0".to_string(),
+            enable_animations: true,
+            animation_speed: 200,
+        }
+    }
+}
+
+/// How a custom module's command reports its state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReturnType {
+    /// Lines of text, tooltip and CSS class.
+    #[default]
+    Text,
+    /// One JSON object with `text`, `tooltip`, `class` and `icon`.
+    Json,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CustomModuleConfig {
+    /// Run through `sh -c`.
+    pub exec: String,
+    /// Seconds between runs. Without an interval the command is kept
+    /// running and every line it prints is a new state.
+    #[serde(default)]
+    pub interval: Option<u64>,
+    #[serde(default)]
+    pub return_type: ReturnType,
+    /// Icon shown when the output does not name one.
+    #[serde(default)]
+    pub icon: Option<String>,
+    #[serde(default)]
+    pub on_click: Option<String>,
+}
+
+impl ShellConfig {
+    pub fn load() -> Result<Self> {
+        let config_path = Self::config_path()?;
+
+        if !config_path.exists() {
+            return Ok(Self::default());
+        }
+
+        let contents = fs::read_to_string(&config_path)
+            .with_context(|| format!("Failed to read config from {:?}", config_path))?;
+
+        toml::from_str(&contents).with_context(|| "Failed to parse config file")
+    }
+
+    fn config_path() -> Result<PathBuf> {
+        let xdg = BaseDirectories::with_prefix("spinneros")?;
+        Ok(xdg.get_config_home().join("spinner-shell.toml"))
+    }
+}
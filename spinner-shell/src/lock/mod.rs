@@ -0,0 +1,431 @@
+//! Lock screen
+//!
+//! Covers every monitor with an overlay layer surface in the
+//! `spinner-lock` namespace and holds SpinnerWM's session lock, so the
+//! compositor keeps the outputs blank if the shell goes away while locked.
+//! The password is checked through PAM.
+//!
+//! Locks from the `lock` application action (the power menu and `Super +
+//! Shift + L`), when logind asks (`loginctl lock-session`, idle daemons) and
+//! before suspend, holding a logind delay inhibitor until the screen is
+//! locked.
+
+mod pam;
+
+use crate::config::{ClockConfig, LockConfig};
+use crate::notifications::NotificationCenter;
+use crate::services::session::{self, LoginEvent, SleepInhibitor};
+use crate::wm;
+
+use anyhow::Result;
+use chrono::Local;
+use gtk4::prelude::*;
+use gtk4::{self, gdk, glib, Align, Box as GtkBox, Label, Orientation, PasswordEntry};
+use gtk4_layer_shell::{Edge, KeyboardMode, Layer, LayerShell};
+use libadwaita as adw;
+use std::cell::{Cell, RefCell};
+use std::rc::{Rc, Weak};
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+/// How long SpinnerWM gets to show the lock screen before a pending
+/// suspend goes ahead anyway.
+const LOCKED_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub struct LockScreen {
+    app: adw::Application,
+    config: LockConfig,
+    time_format: String,
+    center: Option<Weak<RefCell<NotificationCenter>>>,
+    windows: RefCell<Vec<gtk4::ApplicationWindow>>,
+    clocks: RefCell<Vec<(Label, Label)>>,
+    counts: RefCell<Vec<Label>>,
+    entry: RefCell<Option<PasswordEntry>>,
+    status: RefCell<Option<Label>>,
+    /// SpinnerWM's lock, until the screen is unlocked.
+    handle: RefCell<Option<wm::LockHandle>>,
+    inhibitor: RefCell<Option<SleepInhibitor>>,
+    /// Between logind's announcement of suspend and the wake-up.
+    sleeping: Cell<bool>,
+    authenticating: Cell<bool>,
+    /// Bumped on every lock, so the clock timer of an earlier one stops.
+    serial: Cell<u32>,
+}
+
+impl LockScreen {
+    pub fn new(
+        app: &adw::Application,
+        config: &LockConfig,
+        clock: &ClockConfig,
+        center: Option<&Rc<RefCell<NotificationCenter>>>,
+    ) -> Rc<Self> {
+        let screen = Rc::new(Self {
+            app: app.clone(),
+            config: config.clone(),
+            time_format: clock.format_time.clone(),
+            center: center.map(Rc::downgrade),
+            windows: RefCell::new(Vec::new()),
+            clocks: RefCell::new(Vec::new()),
+            counts: RefCell::new(Vec::new()),
+            entry: RefCell::new(None),
+            status: RefCell::new(None),
+            handle: RefCell::new(None),
+            inhibitor: RefCell::new(None),
+            sleeping: Cell::new(false),
+            authenticating: Cell::new(false),
+            serial: Cell::new(0),
+        });
+
+        if screen.config.lock_before_sleep {
+            screen.inhibit_sleep();
+        }
+
+        let weak = Rc::downgrade(&screen);
+        let receiver = session::subscribe();
+        glib::MainContext::default().spawn_local(async move {
+            while let Ok(event) = receiver.recv().await {
+                let Some(screen) = weak.upgrade() else {
+                    break;
+                };
+                screen.handle_event(event);
+            }
+        });
+
+        screen
+    }
+
+    pub fn is_locked(&self) -> bool {
+        !self.windows.borrow().is_empty()
+    }
+
+    fn handle_event(self: &Rc<Self>, event: LoginEvent) {
+        debug!("logind: {:?}", event);
+        match event {
+            LoginEvent::Lock => self.lock(),
+            LoginEvent::Unlock => self.unlock(),
+            LoginEvent::PrepareForSleep(true) => {
+                if !self.config.lock_before_sleep {
+                    return;
+                }
+                self.sleeping.set(true);
+                // Otherwise released once SpinnerWM shows the lock screen.
+                if self.is_locked() {
+                    self.inhibitor.borrow_mut().take();
+                } else {
+                    self.lock();
+                }
+            }
+            LoginEvent::PrepareForSleep(false) => {
+                self.sleeping.set(false);
+                if self.config.lock_before_sleep && self.inhibitor.borrow().is_none() {
+                    self.inhibit_sleep();
+                }
+            }
+        }
+    }
+
+    /// Takes a delay inhibitor, so suspend waits for the lock screen.
+    fn inhibit_sleep(self: &Rc<Self>) {
+        let (sender, receiver) = async_channel::bounded(1);
+        std::thread::spawn(move || {
+            let _ = sender.send_blocking(session::inhibit_sleep("Locking the screen"));
+        });
+
+        let weak = Rc::downgrade(self);
+        glib::MainContext::default().spawn_local(async move {
+            let Ok(result) = receiver.recv().await else {
+                return;
+            };
+            match result {
+                Ok(inhibitor) => {
+                    if let Some(screen) = weak.upgrade() {
+                        screen.inhibitor.replace(Some(inhibitor));
+                    }
+                }
+                Err(e) => debug!("Failed to inhibit sleep: {:#}", e),
+            }
+        });
+    }
+
+    pub fn lock(self: &Rc<Self>) {
+        if self.is_locked() {
+            return;
+        }
+        info!("Locking the screen");
+
+        let Some(display) = gdk::Display::default() else {
+            warn!("No display to lock");
+            return;
+        };
+        let monitors = display.monitors();
+        let monitors: Vec<gdk::Monitor> = (0..monitors.n_items())
+            .filter_map(|i| monitors.item(i))
+            .filter_map(|item| item.downcast::<gdk::Monitor>().ok())
+            .collect();
+        for (i, monitor) in monitors.iter().enumerate() {
+            let window = self.build_window(monitor, i == 0);
+            window.present();
+            self.windows.borrow_mut().push(window);
+        }
+        if let Some(entry) = self.entry.borrow().as_ref() {
+            entry.grab_focus();
+        }
+
+        let serial = self.serial.get().wrapping_add(1);
+        self.serial.set(serial);
+        self.update();
+        self.clone().tick(serial);
+
+        session::set_locked_hint(true);
+        self.take_wm_lock();
+    }
+
+    /// Asks SpinnerWM for the session lock and waits until it shows the
+    /// lock screen, then lets a pending suspend go ahead.
+    fn take_wm_lock(self: &Rc<Self>) {
+        let (sender, receiver) = async_channel::bounded(1);
+        std::thread::spawn(move || {
+            // The handle is kept even if the lock screen isn't shown in
+            // time: dropping it would leave SpinnerWM locked for good, while
+            // unlocking through it gives the lock up.
+            let result = wm::lock().map(|mut handle| {
+                if let Err(e) = handle.wait_locked(LOCKED_TIMEOUT) {
+                    warn!("SpinnerWM did not show the lock screen: {:#}", e);
+                }
+                handle
+            });
+            let _ = sender.send_blocking(result);
+        });
+
+        let weak = Rc::downgrade(self);
+        glib::MainContext::default().spawn_local(async move {
+            let Ok(result) = receiver.recv().await else {
+                return;
+            };
+            let Some(screen) = weak.upgrade() else {
+                return;
+            };
+            if screen.sleeping.get() {
+                screen.inhibitor.borrow_mut().take();
+            }
+            match result {
+                Ok(handle) if screen.is_locked() => {
+                    screen.handle.replace(Some(handle));
+                }
+                // Unlocked in the meantime.
+                Ok(handle) => release(handle),
+                Err(e) => warn!("Locking without SpinnerWM: {:#}", e),
+            }
+        });
+    }
+
+    fn build_window(
+        self: &Rc<Self>,
+        monitor: &gdk::Monitor,
+        primary: bool,
+    ) -> gtk4::ApplicationWindow {
+        let window = gtk4::ApplicationWindow::builder()
+            .application(&self.app)
+            .decorated(false)
+            .build();
+        window.add_css_class("lock-screen");
+
+        window.init_layer_shell();
+        window.set_layer(Layer::Overlay);
+        window.set_namespace("spinner-lock");
+        window.set_monitor(monitor);
+        window.set_exclusive_zone(-1);
+        for edge in [Edge::Top, Edge::Bottom, Edge::Left, Edge::Right] {
+            window.set_anchor(edge, true);
+        }
+        window.set_keyboard_mode(if primary {
+            KeyboardMode::Exclusive
+        } else {
+            KeyboardMode::None
+        });
+
+        let content = GtkBox::builder()
+            .orientation(Orientation::Vertical)
+            .spacing(12)
+            .halign(Align::Center)
+            .valign(Align::Center)
+            .build();
+
+        let time = Label::new(None);
+        time.add_css_class("lock-time");
+        content.append(&time);
+        let date = Label::new(None);
+        date.add_css_class("lock-date");
+        content.append(&date);
+        self.clocks.borrow_mut().push((time, date));
+
+        if self.config.show_notifications && self.center.is_some() {
+            let count = Label::builder().visible(false).build();
+            count.add_css_class("lock-notifications");
+            content.append(&count);
+            self.counts.borrow_mut().push(count);
+        }
+
+        if primary {
+            content.append(&self.build_prompt());
+        }
+
+        window.set_child(Some(&content));
+        window
+    }
+
+    fn build_prompt(self: &Rc<Self>) -> GtkBox {
+        let prompt = GtkBox::builder()
+            .orientation(Orientation::Vertical)
+            .spacing(8)
+            .margin_top(36)
+            .build();
+
+        let name = glib::real_name();
+        let name = name.to_string_lossy();
+        let user = glib::user_name();
+        let user = user.to_string_lossy();
+        let name = if name.is_empty() || name == "Unknown" {
+            &user
+        } else {
+            &name
+        };
+        let label = Label::new(Some(name));
+        label.add_css_class("lock-user");
+        prompt.append(&label);
+
+        let entry = PasswordEntry::builder()
+            .placeholder_text("Password")
+            .show_peek_icon(true)
+            .width_request(280)
+            .build();
+        entry.add_css_class("lock-entry");
+        prompt.append(&entry);
+
+        let status = Label::builder().wrap(true).build();
+        status.add_css_class("lock-status");
+        prompt.append(&status);
+
+        let weak = Rc::downgrade(self);
+        entry.connect_activate(move |entry| {
+            if let Some(screen) = weak.upgrade() {
+                screen.authenticate(entry.text().to_string());
+            }
+        });
+
+        self.entry.replace(Some(entry));
+        self.status.replace(Some(status));
+        prompt
+    }
+
+    fn tick(self: Rc<Self>, serial: u32) {
+        glib::timeout_add_local_once(Duration::from_secs(1), move || {
+            if self.serial.get() != serial || !self.is_locked() {
+                return;
+            }
+            self.update();
+            self.tick(serial);
+        });
+    }
+
+    fn update(&self) {
+        let now = Local::now();
+        let time = now.format(&self.time_format).to_string();
+        let date = now.format("%A, %B %-d").to_string();
+        for (time_label, date_label) in self.clocks.borrow().iter() {
+            time_label.set_label(&time);
+            date_label.set_label(&date);
+        }
+
+        let unread = self
+            .center
+            .as_ref()
+            .and_then(Weak::upgrade)
+            .and_then(|center| center.try_borrow().ok().map(|center| center.unread_count()))
+            .unwrap_or(0);
+        let text = match unread {
+            1 => "1 notification".to_string(),
+            n => format!("{} notifications", n),
+        };
+        for label in self.counts.borrow().iter() {
+            label.set_label(&text);
+            label.set_visible(unread > 0);
+        }
+    }
+
+    fn authenticate(self: &Rc<Self>, password: String) {
+        if self.authenticating.get() || password.is_empty() {
+            return;
+        }
+        self.authenticating.set(true);
+        self.set_status("Checking…");
+        if let Some(entry) = self.entry.borrow().as_ref() {
+            entry.set_sensitive(false);
+        }
+
+        let service = self.config.pam_service.clone();
+        let user = glib::user_name().to_string_lossy().into_owned();
+        let (sender, receiver) = async_channel::bounded(1);
+        std::thread::spawn(move || {
+            let _ = sender.send_blocking(pam::authenticate(&service, &user, &password));
+        });
+
+        let weak = Rc::downgrade(self);
+        glib::MainContext::default().spawn_local(async move {
+            let Ok(result) = receiver.recv().await else {
+                return;
+            };
+            if let Some(screen) = weak.upgrade() {
+                screen.authenticated(result);
+            }
+        });
+    }
+
+    fn authenticated(self: &Rc<Self>, result: Result<()>) {
+        self.authenticating.set(false);
+        if let Err(e) = result {
+            info!("Failed to unlock: {:#}", e);
+            self.set_status("Wrong password");
+            if let Some(entry) = self.entry.borrow().as_ref() {
+                entry.set_text("");
+                entry.set_sensitive(true);
+                entry.grab_focus();
+            }
+            return;
+        }
+        self.unlock();
+    }
+
+    fn set_status(&self, text: &str) {
+        if let Some(status) = self.status.borrow().as_ref() {
+            status.set_label(text);
+        }
+    }
+
+    pub fn unlock(&self) {
+        if !self.is_locked() {
+            return;
+        }
+        info!("Unlocking the screen");
+
+        // SpinnerWM first, so the desktop isn't shown blank in between.
+        if let Some(handle) = self.handle.borrow_mut().take() {
+            release(handle);
+        }
+        for window in self.windows.borrow_mut().drain(..) {
+            window.destroy();
+        }
+        self.clocks.borrow_mut().clear();
+        self.counts.borrow_mut().clear();
+        self.entry.replace(None);
+        self.status.replace(None);
+        self.authenticating.set(false);
+        session::set_locked_hint(false);
+    }
+}
+
+fn release(handle: wm::LockHandle) {
+    if let Err(e) = handle.unlock() {
+        warn!("Failed to unlock SpinnerWM: {:#}", e);
+    }
+}
@@ -0,0 +1,131 @@
+//! Password checks through PAM
+//!
+//! The service's stack lives in `/etc/pam.d/<service>`; SpinnerOS installs
+//! `spinner-lock`, which includes the system's `login` auth stack.
+
+use anyhow::{bail, Result};
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int, c_void};
+use std::ptr;
+
+const PAM_SUCCESS: c_int = 0;
+const PAM_BUF_ERR: c_int = 5;
+const PAM_CONV_ERR: c_int = 19;
+const PAM_PROMPT_ECHO_OFF: c_int = 1;
+const PAM_PROMPT_ECHO_ON: c_int = 2;
+
+#[repr(C)]
+struct PamMessage {
+    msg_style: c_int,
+    msg: *const c_char,
+}
+
+#[repr(C)]
+struct PamResponse {
+    resp: *mut c_char,
+    resp_retcode: c_int,
+}
+
+type Converse = unsafe extern "C" fn(
+    c_int,
+    *mut *const PamMessage,
+    *mut *mut PamResponse,
+    *mut c_void,
+) -> c_int;
+
+#[repr(C)]
+struct PamConv {
+    conv: Converse,
+    appdata_ptr: *mut c_void,
+}
+
+#[repr(C)]
+struct PamHandle {
+    _private: [u8; 0],
+}
+
+#[link(name = "pam")]
+extern "C" {
+    fn pam_start(
+        service_name: *const c_char,
+        user: *const c_char,
+        pam_conversation: *const PamConv,
+        pamh: *mut *mut PamHandle,
+    ) -> c_int;
+    fn pam_authenticate(pamh: *mut PamHandle, flags: c_int) -> c_int;
+    fn pam_end(pamh: *mut PamHandle, pam_status: c_int) -> c_int;
+    fn pam_strerror(pamh: *mut PamHandle, errnum: c_int) -> *const c_char;
+}
+
+/// Answers every prompt with the password; PAM frees the replies.
+unsafe extern "C" fn converse(
+    count: c_int,
+    messages: *mut *const PamMessage,
+    responses: *mut *mut PamResponse,
+    password: *mut c_void,
+) -> c_int {
+    if count <= 0 {
+        return PAM_CONV_ERR;
+    }
+    let count = count as usize;
+    let password = &*(password as *const CString);
+    let replies = libc::calloc(count, std::mem::size_of::<PamResponse>()) as *mut PamResponse;
+    if replies.is_null() {
+        return PAM_BUF_ERR;
+    }
+
+    for i in 0..count {
+        // Linux-PAM passes an array of pointers to messages.
+        let message = &**messages.add(i);
+        if !matches!(message.msg_style, PAM_PROMPT_ECHO_OFF | PAM_PROMPT_ECHO_ON) {
+            continue;
+        }
+        let reply = libc::strdup(password.as_ptr());
+        if reply.is_null() {
+            for j in 0..i {
+                libc::free((*replies.add(j)).resp as *mut c_void);
+            }
+            libc::free(replies as *mut c_void);
+            return PAM_BUF_ERR;
+        }
+        (*replies.add(i)).resp = reply;
+    }
+
+    *responses = replies;
+    PAM_SUCCESS
+}
+
+/// Checks `user`'s password against the PAM `service`. Blocks, for as
+/// long as PAM delays a failure.
+pub fn authenticate(service: &str, user: &str, password: &str) -> Result<()> {
+    let service = CString::new(service)?;
+    let user = CString::new(user)?;
+    let Ok(password) = CString::new(password) else {
+        bail!("Authentication failure");
+    };
+    let conversation = PamConv {
+        conv: converse,
+        appdata_ptr: &password as *const CString as *mut c_void,
+    };
+
+    unsafe {
+        let mut handle = ptr::null_mut();
+        let status = pam_start(service.as_ptr(), user.as_ptr(), &conversation, &mut handle);
+        if status != PAM_SUCCESS {
+            bail!("Failed to start PAM ({})", status);
+        }
+
+        let status = pam_authenticate(handle, 0);
+        let error = (status != PAM_SUCCESS).then(|| {
+            CStr::from_ptr(pam_strerror(handle, status))
+                .to_string_lossy()
+                .into_owned()
+        });
+        pam_end(handle, status);
+
+        match error {
+            None => Ok(()),
+            Some(error) => bail!(error),
+        }
+    }
+}
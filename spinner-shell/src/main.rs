@@ -1,23 +1,33 @@
 //! SpinnerShell - SpinnerOS Desktop Environment
 
+mod config;
 mod panel;
 mod app_menu;
+mod lock;
 mod notifications;
+mod services;
 mod theme;
+mod tray;
+mod wm;
 
 use gtk4::prelude::*;
 use gtk4::{gdk, gio, glib, Application};
 use libadwaita as adw;
-use tracing::info;
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use tracing::{error, info};
 use tracing_subscriber::{fmt, prelude::*, EnvFilter};
 
-use crate::panel::Panel;
+use crate::config::ShellConfig;
+use crate::panel::Panels;
+use crate::theme::ThemeManager;
 
 const APP_ID: &str = "org.spinneros.shell";
 
 fn setup_logging() {
     tracing_subscriber::registry()
-        .with(fmt::layer())
+        .with(fmt::layer().with_writer(std::io::stderr))
         .with(EnvFilter::from_default_env().add_directive("spinner_shell=info".parse().unwrap()))
         .init();
 }
@@ -25,11 +35,75 @@ fn setup_logging() {
 fn main() -> glib::ExitCode {
     setup_logging();
     info!("Starting SpinnerShell v{}", env!("CARGO_PKG_VERSION"));
-    
+
+    if std::env::args().any(|arg| arg == "--notification-daemon") {
+        return run_notification_daemon();
+    }
+
     let app = adw::Application::builder()
         .application_id(APP_ID)
         .flags(gio::ApplicationFlags::FLAGS_NONE)
         .build();
+
+    app.add_main_option(
+        "menu",
+        glib::Char::from(0),
+        glib::OptionFlags::NONE,
+        glib::OptionArg::None,
+        "Toggle the application menu of the running shell",
+        None,
+    );
+    app.add_main_option(
+        "notification-center",
+        glib::Char::from(0),
+        glib::OptionFlags::NONE,
+        glib::OptionArg::None,
+        "Toggle the notification center of the running shell",
+        None,
+    );
+    app.add_main_option(
+        "quick-settings",
+        glib::Char::from(0),
+        glib::OptionFlags::NONE,
+        glib::OptionArg::None,
+        "Toggle the quick settings of the running shell",
+        None,
+    );
+    app.add_main_option(
+        "lock",
+        glib::Char::from(0),
+        glib::OptionFlags::NONE,
+        glib::OptionArg::None,
+        "Lock the screen of the running shell",
+        None,
+    );
+    app.add_main_option(
+        "export-notifications",
+        glib::Char::from(0),
+        glib::OptionFlags::NONE,
+        glib::OptionArg::Filename,
+        "Write the notification history as JSON to FILE, or - for stdout",
+        Some("FILE"),
+    );
+
+    app.connect_handle_local_options(|app, options| {
+        if options.contains("menu") {
+            return activate_remote(app, "app-menu");
+        }
+        if options.contains("notification-center") {
+            return activate_remote(app, "notification-center");
+        }
+        if options.contains("quick-settings") {
+            return activate_remote(app, "quick-settings");
+        }
+        if options.contains("lock") {
+            return activate_remote(app, "lock");
+        }
+        if let Ok(Some(path)) = options.lookup::<PathBuf>("export-notifications") {
+            return export_notifications(&path);
+        }
+        -1
+    });
     
     app.connect_startup(|_app| {
         info!("Application startup");
@@ -60,9 +134,87 @@ fn setup_css() {
     info!("CSS theme loaded");
 }
 
+fn load_config() -> ShellConfig {
+    ShellConfig::load().unwrap_or_else(|e| {
+        error!("Failed to load config: {}, using defaults", e);
+        ShellConfig::default()
+    })
+}
+
 fn build_ui(app: &adw::Application) {
-    let panel = Panel::new();
-    let window = panel.create_window(app);
-    window.present();
+    let config = load_config();
+    let theme = Rc::new(RefCell::new(ThemeManager::new(&config.theme)));
+    theme.borrow().apply();
+
+    let menu = app_menu::AppMenu::new(app, &config.app_menu);
+    add_action(app, "app-menu", move || menu.toggle());
+
+    let mut notifications = None;
+    if config.notifications.enabled {
+        let center = notifications::start(&config.notifications);
+        notifications::Popups::start(app, &center, &config.notifications);
+        let drawer = notifications::Drawer::new(app, &center, &config.notifications);
+        add_action(app, "notification-center", move || drawer.toggle());
+        notifications = Some(center);
+    }
+    add_action(app, "quick-settings", panel::toggle_quick_settings);
+    if config.lock.enabled {
+        let screen = lock::LockScreen::new(app, &config.lock, &config.clock, notifications.as_ref());
+        add_action(app, "lock", move || screen.lock());
+    }
+
+    services::power::warn_on_low_battery(config.systray.low_battery, config.systray.critical_battery);
+    Panels::start(app, config, notifications, theme);
     info!("UI built and presented");
 }
+
+/// Registers an application action, which a second `spinner-shell`
+/// process can trigger in the running one.
+fn add_action(app: &adw::Application, name: &str, activate: impl Fn() + 'static) {
+    let action = gio::SimpleAction::new(name, None);
+    action.connect_activate(move |_, _| activate());
+    app.add_action(&action);
+}
+
+/// Forwards an action to the running shell and exits.
+fn activate_remote(app: &adw::Application, action: &str) -> i32 {
+    if let Err(e) = app.register(gio::Cancellable::NONE) {
+        error!("Failed to register application: {}", e);
+        return 1;
+    }
+    if !app.is_remote() {
+        error!("SpinnerShell is not running");
+        return 1;
+    }
+    app.activate_action(action, None);
+    0
+}
+
+fn export_notifications(path: &Path) -> i32 {
+    let json = match notifications::export_history() {
+        Ok(json) => json,
+        Err(e) => {
+            error!("Failed to export notifications: {:#}", e);
+            return 1;
+        }
+    };
+
+    if path == Path::new("-") {
+        println!("{}", json);
+    } else if let Err(e) = std::fs::write(path, json) {
+        error!("Failed to write {:?}: {}", path, e);
+        return 1;
+    }
+    0
+}
+
+/// Runs only the notification daemon, without a display. Useful for
+/// testing against a private bus.
+fn run_notification_daemon() -> glib::ExitCode {
+    info!("Running as notification daemon only");
+    let config = load_config();
+    let main_loop = glib::MainLoop::new(None, false);
+    let _center = notifications::start(&config.notifications);
+    main_loop.run();
+    glib::ExitCode::SUCCESS
+}
@@ -0,0 +1,250 @@
+//! Notification card, shared by the popups and the drawer
+
+use super::{ImageData, Notification, NotificationCenter, NotificationImage, Urgency};
+
+use chrono::Local;
+use gtk4::prelude::*;
+use gtk4::{self, gdk, glib, Align, Box as GtkBox, Button, GestureClick, Image, Label, Orientation};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+const IMAGE_SIZE: i32 = 48;
+const APP_ICON_SIZE: i32 = 16;
+
+/// The "default" action is invoked by clicking the card rather than shown
+/// as a button.
+const DEFAULT_ACTION: &str = "default";
+
+/// Builds a card for `notification`. Only live notifications get action
+/// buttons; for history entries the client may be long gone.
+pub fn notification_card(
+    notification: &Notification,
+    center: &Rc<RefCell<NotificationCenter>>,
+    live: bool,
+) -> GtkBox {
+    let card = GtkBox::builder()
+        .orientation(Orientation::Vertical)
+        .spacing(4)
+        .build();
+    card.add_css_class("notification-item");
+    if !notification.read {
+        card.add_css_class("unread");
+    }
+    match notification.urgency {
+        Urgency::Low => card.add_css_class("low"),
+        Urgency::Critical => card.add_css_class("critical"),
+        Urgency::Normal => {}
+    }
+
+    card.append(&header(notification, center));
+
+    let content = GtkBox::builder()
+        .orientation(Orientation::Horizontal)
+        .spacing(12)
+        .build();
+    if let Some(image) = notification.image.as_ref().and_then(image_widget) {
+        image.set_valign(Align::Start);
+        content.append(&image);
+    }
+
+    let text = GtkBox::builder()
+        .orientation(Orientation::Vertical)
+        .hexpand(true)
+        .build();
+    let summary = Label::builder()
+        .label(&notification.summary)
+        .xalign(0.0)
+        .wrap(true)
+        .wrap_mode(pango::WrapMode::WordChar)
+        .build();
+    summary.add_css_class("notification-summary");
+    text.append(&summary);
+
+    if !notification.body.is_empty() {
+        let body = Label::builder()
+            .label(&notification.body)
+            .xalign(0.0)
+            .wrap(true)
+            .wrap_mode(pango::WrapMode::WordChar)
+            .lines(4)
+            .ellipsize(pango::EllipsizeMode::End)
+            .build();
+        body.add_css_class("notification-body");
+        text.append(&body);
+    }
+    content.append(&text);
+    card.append(&content);
+
+    if live {
+        if let Some(actions) = action_buttons(notification, center) {
+            card.append(&actions);
+        }
+        connect_default_action(&card, notification, center);
+    }
+
+    card
+}
+
+fn header(notification: &Notification, center: &Rc<RefCell<NotificationCenter>>) -> GtkBox {
+    let header = GtkBox::builder()
+        .orientation(Orientation::Horizontal)
+        .spacing(6)
+        .build();
+
+    if !notification.app_icon.is_empty() {
+        let icon = icon_or_file(&notification.app_icon);
+        icon.set_pixel_size(APP_ICON_SIZE);
+        header.append(&icon);
+    }
+
+    let app_name = if notification.app_name.is_empty() {
+        "Notification"
+    } else {
+        notification.app_name.as_str()
+    };
+    let app = Label::builder()
+        .label(app_name)
+        .xalign(0.0)
+        .hexpand(true)
+        .ellipsize(pango::EllipsizeMode::End)
+        .build();
+    app.add_css_class("notification-app");
+    header.append(&app);
+
+    let time = Label::new(Some(&format_time(notification)));
+    time.add_css_class("notification-time");
+    header.append(&time);
+
+    let close = Button::builder()
+        .icon_name("window-close-symbolic")
+        .tooltip_text("Dismiss")
+        .build();
+    close.add_css_class("notification-close");
+    let id = notification.id;
+    let weak = Rc::downgrade(center);
+    close.connect_clicked(move |_| {
+        if let Some(center) = weak.upgrade() {
+            center.borrow_mut().dismiss(id);
+        }
+    });
+    header.append(&close);
+
+    header
+}
+
+fn action_buttons(notification: &Notification, center: &Rc<RefCell<NotificationCenter>>) -> Option<GtkBox> {
+    let actions: Vec<_> = notification
+        .actions
+        .iter()
+        .filter(|a| a.key != DEFAULT_ACTION)
+        .collect();
+    if actions.is_empty() {
+        return None;
+    }
+
+    let row = GtkBox::builder()
+        .orientation(Orientation::Horizontal)
+        .spacing(6)
+        .homogeneous(true)
+        .build();
+    row.add_css_class("notification-actions");
+
+    for action in actions {
+        let button = Button::with_label(&action.label);
+        button.add_css_class("notification-action");
+
+        let id = notification.id;
+        let key = action.key.clone();
+        let weak = Rc::downgrade(center);
+        button.connect_clicked(move |_| {
+            if let Some(center) = weak.upgrade() {
+                center.borrow_mut().invoke_action(id, &key);
+            }
+        });
+        row.append(&button);
+    }
+
+    Some(row)
+}
+
+/// Clicking the card invokes the default action if there is one, and
+/// otherwise just dismisses it.
+fn connect_default_action(card: &GtkBox, notification: &Notification, center: &Rc<RefCell<NotificationCenter>>) {
+    let has_default = notification.actions.iter().any(|a| a.key == DEFAULT_ACTION);
+    let id = notification.id;
+    let weak = Rc::downgrade(center);
+
+    let click = GestureClick::new();
+    click.connect_released(move |_, _, _, _| {
+        let Some(center) = weak.upgrade() else {
+            return;
+        };
+        let mut center = center.borrow_mut();
+        if has_default {
+            center.invoke_action(id, DEFAULT_ACTION);
+        } else {
+            center.dismiss(id);
+        }
+    });
+    card.add_controller(click);
+}
+
+fn format_time(notification: &Notification) -> String {
+    if notification.timestamp.date_naive() == Local::now().date_naive() {
+        notification.timestamp.format("%H:%M").to_string()
+    } else {
+        notification.timestamp.format("%b %-d").to_string()
+    }
+}
+
+fn image_widget(image: &NotificationImage) -> Option<Image> {
+    let widget = match image {
+        NotificationImage::Path(path) => icon_or_file(path),
+        NotificationImage::Data(data) => Image::from_paintable(Some(&texture(data)?)),
+    };
+    widget.set_pixel_size(IMAGE_SIZE);
+    widget.add_css_class("notification-image");
+    Some(widget)
+}
+
+/// `image-path` and `app_icon` may be a file path, a `file://` URI or an
+/// icon name.
+fn icon_or_file(name: &str) -> Image {
+    let path = name.strip_prefix("file://").unwrap_or(name);
+    if path.starts_with('/') {
+        Image::from_file(path)
+    } else {
+        Image::from_icon_name(name)
+    }
+}
+
+/// Wraps `image-data` pixels in a texture. Anything but 8-bit RGB or RGBA,
+/// a size that overflows, or a buffer too short for its size, is rejected.
+fn texture(data: &ImageData) -> Option<gdk::MemoryTexture> {
+    let format = match (data.bits_per_sample, data.channels, data.has_alpha) {
+        (8, 4, true) => gdk::MemoryFormat::R8g8b8a8,
+        (8, 3, false) => gdk::MemoryFormat::R8g8b8,
+        _ => return None,
+    };
+    if data.width <= 0 || data.height <= 0 {
+        return None;
+    }
+    let row = data.width.checked_mul(data.channels)?;
+    if data.rowstride < row {
+        return None;
+    }
+    let needed = (data.rowstride as usize)
+        .checked_mul(data.height as usize - 1)?
+        .checked_add(row as usize)?;
+    if data.data.len() < needed {
+        return None;
+    }
+
+    Some(gdk::MemoryTexture::new(
+        data.width,
+        data.height,
+        format,
+        &glib::Bytes::from(data.data.as_slice()),
+        data.rowstride as usize,
+    ))
+}
@@ -0,0 +1,423 @@
+//! `org.freedesktop.Notifications` server
+//!
+//! Implements the Desktop Notifications Specification 1.2. Requests are
+//! forwarded to the `NotificationCenter` on the GTK main thread; only id
+//! allocation happens here, since `Notify` must answer immediately.
+//!
+//! The bus comes from `DBUS_SESSION_BUS_ADDRESS`, so
+//! `dbus-run-session -- spinner-shell --notification-daemon` runs the
+//! daemon against a private bus for testing with `notify-send` or `gdbus`.
+
+use super::{Action, CloseReason, ImageData, Notification, NotificationImage, Request, Sound, Urgency};
+
+use anyhow::{Context, Result};
+use chrono::Local;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, Ordering};
+use tracing::{debug, warn};
+use zbus::zvariant::OwnedValue;
+use zbus::{dbus_interface, Connection, ConnectionBuilder, SignalContext};
+
+pub const BUS_NAME: &str = "org.freedesktop.Notifications";
+pub const OBJECT_PATH: &str = "/org/freedesktop/Notifications";
+
+const SPEC_VERSION: &str = "1.2";
+
+const CAPABILITIES: &[&str] = &["actions", "body", "icon-static", "persistence", "sound"];
+
+/// `image-data`: width, height, rowstride, has alpha, bits per sample,
+/// channels, pixels.
+type RawImage = (i32, i32, i32, bool, i32, i32, Vec<u8>);
+
+struct NotificationServer {
+    next_id: AtomicU32,
+    requests: async_channel::Sender<Request>,
+}
+
+impl NotificationServer {
+    async fn forward(&self, request: Request) {
+        if self.requests.send(request).await.is_err() {
+            warn!("Notification center is gone, dropping request");
+        }
+    }
+}
+
+#[dbus_interface(name = "org.freedesktop.Notifications")]
+impl NotificationServer {
+    #[allow(clippy::too_many_arguments)]
+    async fn notify(
+        &self,
+        app_name: String,
+        replaces_id: u32,
+        app_icon: String,
+        summary: String,
+        body: String,
+        actions: Vec<String>,
+        hints: HashMap<String, OwnedValue>,
+        expire_timeout: i32,
+    ) -> u32 {
+        // Only an id handed out before can be replaced. Any other gets a new
+        // id, since reusing it would collide with a later allocation.
+        let id = if replaces_id != 0 && replaces_id < self.next_id.load(Ordering::SeqCst) {
+            replaces_id
+        } else {
+            self.next_id.fetch_add(1, Ordering::SeqCst)
+        };
+        debug!("Notify {} from {}: {}", id, app_name, summary);
+
+        let notification = Notification {
+            id,
+            app_name,
+            app_icon,
+            summary,
+            body,
+            actions: parse_actions(actions),
+            urgency: hint::<u8>(&hints, "urgency").map(Urgency::from_byte).unwrap_or_default(),
+            category: hint(&hints, "category"),
+            desktop_entry: hint(&hints, "desktop-entry"),
+            image: parse_image(&hints),
+            sound: parse_sound(&hints),
+            resident: hint(&hints, "resident").unwrap_or(false),
+            transient: hint(&hints, "transient").unwrap_or(false),
+            expire_timeout,
+            timestamp: Local::now(),
+            read: false,
+        };
+
+        self.forward(Request::Notify(Box::new(notification))).await;
+        id
+    }
+
+    async fn close_notification(&self, id: u32) {
+        self.forward(Request::Close {
+            id,
+            reason: CloseReason::Closed,
+        })
+        .await;
+    }
+
+    fn get_capabilities(&self) -> Vec<String> {
+        CAPABILITIES.iter().map(|c| c.to_string()).collect()
+    }
+
+    #[dbus_interface(out_args("name", "vendor", "version", "spec_version"))]
+    fn get_server_information(&self) -> (String, String, String, String) {
+        (
+            "SpinnerShell".to_string(),
+            "SpinnerOS".to_string(),
+            env!("CARGO_PKG_VERSION").to_string(),
+            SPEC_VERSION.to_string(),
+        )
+    }
+
+    #[dbus_interface(signal)]
+    async fn notification_closed(ctxt: &SignalContext<'_>, id: u32, reason: u32) -> zbus::Result<()>;
+
+    #[dbus_interface(signal)]
+    async fn action_invoked(ctxt: &SignalContext<'_>, id: u32, action_key: &str) -> zbus::Result<()>;
+}
+
+/// Actions arrive as a flat list of key, label pairs.
+fn parse_actions(actions: Vec<String>) -> Vec<Action> {
+    actions
+        .chunks_exact(2)
+        .map(|pair| Action {
+            key: pair[0].clone(),
+            label: pair[1].clone(),
+        })
+        .collect()
+}
+
+fn hint<T>(hints: &HashMap<String, OwnedValue>, key: &str) -> Option<T>
+where
+    T: TryFrom<OwnedValue>,
+{
+    hints.get(key).and_then(|v| T::try_from(v.clone()).ok())
+}
+
+/// Picks the image by the spec's precedence: `image-data`, then
+/// `image-path`, then the deprecated `icon_data`. Older spec versions
+/// spelled the keys with underscores.
+fn parse_image(hints: &HashMap<String, OwnedValue>) -> Option<NotificationImage> {
+    let data = ["image-data", "image_data"]
+        .into_iter()
+        .find_map(|key| hint::<RawImage>(hints, key));
+    let path = ["image-path", "image_path"]
+        .into_iter()
+        .find_map(|key| hint::<String>(hints, key))
+        .filter(|path| !path.is_empty());
+
+    if let Some(image) = data {
+        return Some(image_from_raw(image));
+    }
+    if let Some(path) = path {
+        return Some(NotificationImage::Path(path));
+    }
+    hint::<RawImage>(hints, "icon_data").map(image_from_raw)
+}
+
+fn parse_sound(hints: &HashMap<String, OwnedValue>) -> Option<Sound> {
+    if hint(hints, "suppress-sound").unwrap_or(false) {
+        return None;
+    }
+    hint(hints, "sound-file")
+        .map(Sound::File)
+        .or_else(|| hint(hints, "sound-name").map(Sound::Name))
+}
+
+fn image_from_raw(
+    (width, height, rowstride, has_alpha, bits_per_sample, channels, data): RawImage,
+) -> NotificationImage {
+    NotificationImage::Data(ImageData {
+        width,
+        height,
+        rowstride,
+        has_alpha,
+        bits_per_sample,
+        channels,
+        data,
+    })
+}
+
+/// Serves on the session bus, numbering notifications from `first_id`.
+pub async fn serve(requests: async_channel::Sender<Request>, first_id: u32) -> Result<Connection> {
+    serve_on(ConnectionBuilder::session()?, requests, first_id).await
+}
+
+async fn serve_on(
+    builder: ConnectionBuilder<'_>,
+    requests: async_channel::Sender<Request>,
+    first_id: u32,
+) -> Result<Connection> {
+    let server = NotificationServer {
+        next_id: AtomicU32::new(first_id),
+        requests,
+    };
+
+    builder
+        .serve_at(OBJECT_PATH, server)?
+        .name(BUS_NAME)?
+        .build()
+        .await
+        .context("Failed to own org.freedesktop.Notifications; is another notification daemon running?")
+}
+
+pub async fn notification_closed(connection: &Connection, id: u32, reason: CloseReason) -> zbus::Result<()> {
+    let ctxt = SignalContext::new(connection, OBJECT_PATH)?;
+    NotificationServer::notification_closed(&ctxt, id, reason as u32).await
+}
+
+pub async fn action_invoked(connection: &Connection, id: u32, key: &str) -> zbus::Result<()> {
+    let ctxt = SignalContext::new(connection, OBJECT_PATH)?;
+    NotificationServer::action_invoked(&ctxt, id, key).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::{BufRead, BufReader};
+    use std::process::{Child, Command, Stdio};
+    use std::sync::{mpsc, Arc};
+    use std::time::Duration;
+    use zbus::blocking::{self, MessageIterator};
+    use zbus::zvariant::Value;
+    use zbus::Message;
+
+    /// A reply or signal that doesn't come fails the test instead of
+    /// hanging it.
+    const TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// The server on a private dbus-daemon. In place of the center, Notify
+    /// requests are recorded and Close requests answered with
+    /// NotificationClosed.
+    struct Daemon {
+        bus: Child,
+        address: String,
+        server: Connection,
+        notifications: mpsc::Receiver<Notification>,
+    }
+
+    impl Daemon {
+        fn start(first_id: u32) -> Option<Self> {
+            let mut bus = match Command::new("dbus-daemon")
+                .args(["--session", "--nofork", "--print-address"])
+                .stdout(Stdio::piped())
+                .stderr(Stdio::null())
+                .spawn()
+            {
+                Ok(bus) => bus,
+                Err(e) => {
+                    eprintln!("Skipping, dbus-daemon is not available: {}", e);
+                    return None;
+                }
+            };
+            let mut address = String::new();
+            BufReader::new(bus.stdout.take().unwrap())
+                .read_line(&mut address)
+                .unwrap();
+            let address = address.trim().to_string();
+
+            let (requests, received) = async_channel::unbounded();
+            let builder = ConnectionBuilder::address(address.as_str()).unwrap();
+            let server = zbus::block_on(serve_on(builder, requests, first_id)).unwrap();
+
+            let (recorded, notifications) = mpsc::channel();
+            let connection = server.clone();
+            std::thread::spawn(move || {
+                while let Ok(request) = received.recv_blocking() {
+                    match request {
+                        Request::Notify(notification) => {
+                            let _ = recorded.send(*notification);
+                        }
+                        Request::Close { id, reason } => {
+                            zbus::block_on(notification_closed(&connection, id, reason)).unwrap();
+                        }
+                        _ => {}
+                    }
+                }
+            });
+
+            Some(Self {
+                bus,
+                address,
+                server,
+                notifications,
+            })
+        }
+
+        fn client(&self) -> blocking::Connection {
+            blocking::ConnectionBuilder::address(self.address.as_str())
+                .unwrap()
+                .build()
+                .unwrap()
+        }
+
+        fn notification(&self) -> Notification {
+            self.notifications.recv_timeout(TIMEOUT).expect("No Notify request")
+        }
+    }
+
+    impl Drop for Daemon {
+        fn drop(&mut self) {
+            let _ = self.bus.kill();
+            let _ = self.bus.wait();
+        }
+    }
+
+    fn call<B, R>(client: &blocking::Connection, method: &str, body: &B) -> R
+    where
+        B: serde::Serialize + zbus::zvariant::DynamicType,
+        R: for<'d> serde::Deserialize<'d> + zbus::zvariant::Type,
+    {
+        client
+            .call_method(Some(BUS_NAME), OBJECT_PATH, Some(BUS_NAME), method, body)
+            .unwrap()
+            .body()
+            .unwrap()
+    }
+
+    fn notify(client: &blocking::Connection, replaces_id: u32, summary: &str, actions: &[&str]) -> u32 {
+        let hints: HashMap<&str, Value> = HashMap::new();
+        call(client, "Notify", &("test", replaces_id, "", summary, "", actions, hints, -1))
+    }
+
+    /// Signals named `member`, subscribed to before the call that causes
+    /// them.
+    fn subscribe(client: &blocking::Connection, member: &str) -> mpsc::Receiver<Arc<Message>> {
+        let rule = format!("type='signal',interface='{}',member='{}'", BUS_NAME, member);
+        let messages = MessageIterator::for_match_rule(rule.as_str(), client, None).unwrap();
+        let (sender, receiver) = mpsc::channel();
+        std::thread::spawn(move || {
+            for message in messages.flatten() {
+                if sender.send(message).is_err() {
+                    return;
+                }
+            }
+        });
+        receiver
+    }
+
+    #[test]
+    fn notify_replaces_only_handed_out_ids() {
+        let Some(daemon) = Daemon::start(5) else {
+            return;
+        };
+        let client = daemon.client();
+
+        assert_eq!(notify(&client, 0, "first", &[]), 5);
+        assert_eq!(notify(&client, 0, "second", &[]), 6);
+        assert_eq!(notify(&client, 5, "first again", &[]), 5);
+        // 42 was never handed out, so it isn't reused.
+        assert_eq!(notify(&client, 42, "third", &[]), 7);
+
+        let notifications: Vec<(u32, String)> = (0..4)
+            .map(|_| daemon.notification())
+            .map(|n| (n.id, n.summary))
+            .collect();
+        assert_eq!(
+            notifications,
+            [
+                (5, "first".to_string()),
+                (6, "second".to_string()),
+                (5, "first again".to_string()),
+                (7, "third".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn close_notification_signals_closed() {
+        let Some(daemon) = Daemon::start(1) else {
+            return;
+        };
+        let client = daemon.client();
+        let closed = subscribe(&client, "NotificationClosed");
+
+        let id = notify(&client, 0, "closing", &[]);
+        call::<_, ()>(&client, "CloseNotification", &(id,));
+
+        let signal = closed.recv_timeout(TIMEOUT).expect("No NotificationClosed");
+        assert_eq!(signal.body::<(u32, u32)>().unwrap(), (id, CloseReason::Closed as u32));
+    }
+
+    #[test]
+    fn actions_are_parsed_and_invoked() {
+        let Some(daemon) = Daemon::start(1) else {
+            return;
+        };
+        let client = daemon.client();
+        let invoked = subscribe(&client, "ActionInvoked");
+
+        let id = notify(&client, 0, "with actions", &["default", "Open", "reply", "Reply", "odd"]);
+        let notification = daemon.notification();
+        let keys: Vec<(&str, &str)> = notification
+            .actions
+            .iter()
+            .map(|a| (a.key.as_str(), a.label.as_str()))
+            .collect();
+        assert_eq!(keys, [("default", "Open"), ("reply", "Reply")]);
+
+        // What the center does when an action button is clicked.
+        zbus::block_on(action_invoked(&daemon.server, id, "reply")).unwrap();
+
+        let signal = invoked.recv_timeout(TIMEOUT).expect("No ActionInvoked");
+        assert_eq!(signal.body::<(u32, String)>().unwrap(), (id, "reply".to_string()));
+    }
+
+    #[test]
+    fn capabilities_and_server_information() {
+        let Some(daemon) = Daemon::start(1) else {
+            return;
+        };
+        let client = daemon.client();
+
+        let capabilities: Vec<String> = call(&client, "GetCapabilities", &());
+        assert_eq!(capabilities, CAPABILITIES);
+
+        let (name, vendor, _, spec_version): (String, String, String, String) =
+            call(&client, "GetServerInformation", &());
+        assert_eq!((name.as_str(), vendor.as_str()), ("SpinnerShell", "SpinnerOS"));
+        assert_eq!(spec_version, SPEC_VERSION);
+    }
+}
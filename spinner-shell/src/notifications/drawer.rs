@@ -0,0 +1,255 @@
+//! Notification drawer
+//!
+//! A side sheet listing live notifications and the history, grouped by
+//! app, with Do Not Disturb and clear-all. Toggled from the
+//! `notification-center` application action, so `spinner-shell
+//! --notification-center` opens it from a WM keybinding.
+
+use super::card::notification_card;
+use super::{CenterEvent, Notification, NotificationCenter};
+use crate::config::NotificationsConfig;
+
+use gtk4::prelude::*;
+use gtk4::{self, gdk, glib, Align, Box as GtkBox, Button, Label, Orientation, ScrolledWindow, Switch};
+use gtk4_layer_shell::{Edge, KeyboardMode, Layer, LayerShell};
+use libadwaita as adw;
+use std::cell::{Cell, RefCell};
+use std::rc::{Rc, Weak};
+
+const WIDTH: i32 = 400;
+const MARGIN: i32 = 8;
+
+pub struct Drawer {
+    window: gtk4::ApplicationWindow,
+    list: GtkBox,
+    dnd_switch: Switch,
+    center: Weak<RefCell<NotificationCenter>>,
+    /// A refresh is queued for the next idle.
+    refresh_pending: Cell<bool>,
+}
+
+impl Drawer {
+    pub fn new(
+        app: &adw::Application,
+        center: &Rc<RefCell<NotificationCenter>>,
+        config: &NotificationsConfig,
+    ) -> Rc<Self> {
+        let window = gtk4::ApplicationWindow::builder()
+            .application(app)
+            .decorated(false)
+            .resizable(false)
+            .default_width(WIDTH)
+            .build();
+        window.add_css_class("notification-drawer");
+
+        window.init_layer_shell();
+        window.set_layer(Layer::Overlay);
+        window.set_namespace("spinner-notification-center");
+        window.set_keyboard_mode(KeyboardMode::OnDemand);
+        let side = if config.position.is_left() { Edge::Left } else { Edge::Right };
+        for edge in [Edge::Top, Edge::Bottom, side] {
+            window.set_anchor(edge, true);
+            window.set_margin(edge, MARGIN);
+        }
+
+        let content = GtkBox::builder()
+            .orientation(Orientation::Vertical)
+            .build();
+        content.add_css_class("notification-center");
+
+        let header = GtkBox::builder()
+            .orientation(Orientation::Horizontal)
+            .spacing(8)
+            .build();
+        header.add_css_class("notification-header");
+
+        let title = Label::builder()
+            .label("Notifications")
+            .xalign(0.0)
+            .hexpand(true)
+            .build();
+        title.add_css_class("notification-title");
+        header.append(&title);
+
+        let dnd_label = Label::new(Some("Do Not Disturb"));
+        dnd_label.add_css_class("notification-app");
+        header.append(&dnd_label);
+
+        let dnd_switch = Switch::builder()
+            .active(center.borrow().do_not_disturb())
+            .valign(Align::Center)
+            .build();
+        header.append(&dnd_switch);
+
+        let clear = Button::with_label("Clear all");
+        clear.add_css_class("notification-clear");
+        header.append(&clear);
+        content.append(&header);
+
+        let list = GtkBox::builder()
+            .orientation(Orientation::Vertical)
+            .spacing(12)
+            .build();
+        let scrolled = ScrolledWindow::builder()
+            .hscrollbar_policy(gtk4::PolicyType::Never)
+            .vexpand(true)
+            .child(&list)
+            .build();
+        content.append(&scrolled);
+        window.set_child(Some(&content));
+
+        let drawer = Rc::new(Self {
+            window,
+            list,
+            dnd_switch,
+            center: Rc::downgrade(center),
+            refresh_pending: Cell::new(false),
+        });
+
+        let weak = Rc::downgrade(center);
+        clear.connect_clicked(move |_| {
+            if let Some(center) = weak.upgrade() {
+                center.borrow_mut().clear_all();
+            }
+        });
+
+        let weak = Rc::downgrade(center);
+        drawer.dnd_switch.connect_active_notify(move |switch| {
+            let Some(center) = weak.upgrade() else {
+                return;
+            };
+            // When the center itself changed the mode, it is still borrowed
+            // and already up to date.
+            let Ok(mut center) = center.try_borrow_mut() else {
+                return;
+            };
+            center.set_do_not_disturb(switch.is_active());
+        });
+
+        let keys = gtk4::EventControllerKey::new();
+        let weak = Rc::downgrade(&drawer);
+        keys.connect_key_pressed(move |_, key, _, _| {
+            if key == gdk::Key::Escape {
+                if let Some(drawer) = weak.upgrade() {
+                    drawer.hide();
+                }
+                return glib::Propagation::Stop;
+            }
+            glib::Propagation::Proceed
+        });
+        drawer.window.add_controller(keys);
+
+        let weak = Rc::downgrade(&drawer);
+        center.borrow_mut().connect(move |event| {
+            if let Some(drawer) = weak.upgrade() {
+                drawer.handle(event);
+            }
+        });
+
+        drawer
+    }
+
+    pub fn toggle(&self) {
+        if self.window.is_visible() {
+            self.hide();
+        } else {
+            self.refresh();
+            self.window.present();
+        }
+    }
+
+    /// Closing the drawer marks what it showed as read, so unread ones
+    /// stand out until they have been seen.
+    fn hide(&self) {
+        self.window.set_visible(false);
+        if let Some(center) = self.center.upgrade() {
+            center.borrow_mut().mark_all_read();
+        }
+    }
+
+    fn handle(self: &Rc<Self>, event: &CenterEvent) {
+        if let CenterEvent::DoNotDisturb(enabled) = event {
+            self.dnd_switch.set_active(*enabled);
+            return;
+        }
+        if !self.window.is_visible() || self.refresh_pending.replace(true) {
+            return;
+        }
+
+        // Listeners run while the center is borrowed, so read it once the
+        // current change is done.
+        let weak = Rc::downgrade(self);
+        glib::idle_add_local_once(move || {
+            if let Some(drawer) = weak.upgrade() {
+                drawer.refresh_pending.set(false);
+                drawer.refresh();
+            }
+        });
+    }
+
+    fn refresh(&self) {
+        let Some(center) = self.center.upgrade() else {
+            return;
+        };
+
+        while let Some(child) = self.list.first_child() {
+            self.list.remove(&child);
+        }
+
+        let state = center.borrow();
+        let mut live: Vec<&Notification> = state.notifications().collect();
+        live.reverse();
+        let entries: Vec<Listed> = live
+            .into_iter()
+            .map(|n| (n, true))
+            .chain(state.history().map(|n| (n, false)))
+            .collect();
+
+        if entries.is_empty() {
+            let empty = Label::new(Some("No notifications"));
+            empty.add_css_class("notification-empty");
+            self.list.append(&empty);
+            return;
+        }
+
+        for (app_name, group) in group_by_app(&entries) {
+            let section = GtkBox::builder()
+                .orientation(Orientation::Vertical)
+                .build();
+            section.add_css_class("notification-group");
+
+            let label = Label::builder()
+                .label(format!("{} ({})", app_name, group.len()))
+                .xalign(0.0)
+                .build();
+            label.add_css_class("notification-group-header");
+            section.append(&label);
+
+            for (notification, live) in group {
+                section.append(&notification_card(notification, &center, live));
+            }
+            self.list.append(&section);
+        }
+    }
+}
+
+/// A notification and whether it is still live.
+type Listed<'a> = (&'a Notification, bool);
+
+/// Groups newest-first entries by app, ordering the groups by their newest
+/// notification.
+fn group_by_app<'a>(entries: &[Listed<'a>]) -> Vec<(String, Vec<Listed<'a>>)> {
+    let mut groups: Vec<(String, Vec<Listed>)> = Vec::new();
+    for &(notification, live) in entries {
+        let app_name = if notification.app_name.is_empty() {
+            "Other".to_string()
+        } else {
+            notification.app_name.clone()
+        };
+        match groups.iter_mut().find(|(name, _)| *name == app_name) {
+            Some((_, group)) => group.push((notification, live)),
+            None => groups.push((app_name, vec![(notification, live)])),
+        }
+    }
+    groups
+}
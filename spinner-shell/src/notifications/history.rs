@@ -0,0 +1,123 @@
+//! Notification history on disk
+//!
+//! Live and expired notifications are kept in
+//! `$XDG_STATE_HOME/spinneros/notifications.json` so unread ones survive a
+//! shell restart. Actions, sounds and raw image data are not stored: after
+//! a restart the client that sent them is gone.
+
+use super::{Notification, NotificationImage, Urgency};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Local};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use xdg::BaseDirectories;
+
+const FILE_NAME: &str = "notifications.json";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredNotification {
+    pub id: u32,
+    pub app_name: String,
+    pub app_icon: String,
+    pub summary: String,
+    pub body: String,
+    pub urgency: Urgency,
+    pub category: Option<String>,
+    pub desktop_entry: Option<String>,
+    pub image_path: Option<String>,
+    pub timestamp: DateTime<Local>,
+    pub read: bool,
+}
+
+impl From<&Notification> for StoredNotification {
+    fn from(notification: &Notification) -> Self {
+        let image_path = match &notification.image {
+            Some(NotificationImage::Path(path)) => Some(path.clone()),
+            _ => None,
+        };
+        Self {
+            id: notification.id,
+            app_name: notification.app_name.clone(),
+            app_icon: notification.app_icon.clone(),
+            summary: notification.summary.clone(),
+            body: notification.body.clone(),
+            urgency: notification.urgency,
+            category: notification.category.clone(),
+            desktop_entry: notification.desktop_entry.clone(),
+            image_path,
+            timestamp: notification.timestamp,
+            read: notification.read,
+        }
+    }
+}
+
+impl From<StoredNotification> for Notification {
+    fn from(stored: StoredNotification) -> Self {
+        Self {
+            id: stored.id,
+            app_name: stored.app_name,
+            app_icon: stored.app_icon,
+            summary: stored.summary,
+            body: stored.body,
+            actions: Vec::new(),
+            urgency: stored.urgency,
+            category: stored.category,
+            desktop_entry: stored.desktop_entry,
+            image: stored.image_path.map(NotificationImage::Path),
+            sound: None,
+            resident: false,
+            transient: false,
+            expire_timeout: 0,
+            timestamp: stored.timestamp,
+            read: stored.read,
+        }
+    }
+}
+
+pub fn history_path() -> Result<PathBuf> {
+    let xdg = BaseDirectories::with_prefix("spinneros")?;
+    Ok(xdg.get_state_home().join(FILE_NAME))
+}
+
+/// Reads the stored history, newest first. A missing file is an empty
+/// history.
+pub fn load() -> Result<Vec<StoredNotification>> {
+    let path = history_path()?;
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+
+    let contents = fs::read_to_string(&path).with_context(|| format!("Failed to read {:?}", path))?;
+    serde_json::from_str(&contents).with_context(|| format!("Failed to parse {:?}", path))
+}
+
+/// Writes the history through a temporary file, so a crash mid-write
+/// leaves the previous one intact.
+pub fn save(entries: &[StoredNotification]) -> Result<()> {
+    let xdg = BaseDirectories::with_prefix("spinneros")?;
+    let path = xdg.place_state_file(FILE_NAME)?;
+    let temp = path.with_extension("json.tmp");
+
+    fs::write(&temp, serde_json::to_vec(entries)?).with_context(|| format!("Failed to write {:?}", temp))?;
+    fs::rename(&temp, &path).with_context(|| format!("Failed to replace {:?}", path))?;
+    Ok(())
+}
+
+/// Keeps at most `limit` newest-first entries, none older than `days`
+/// (0 keeps them regardless of age).
+pub fn prune(entries: &mut Vec<StoredNotification>, limit: usize, days: u32) {
+    if days > 0 {
+        let cutoff = Local::now() - Duration::days(days as i64);
+        entries.retain(|entry| entry.timestamp >= cutoff);
+    }
+    entries.truncate(limit);
+}
+
+/// The stored history as pretty-printed JSON, for
+/// `spinner-shell --export-notifications`.
+pub fn export() -> Result<String> {
+    let entries = load()?;
+    Ok(serde_json::to_string_pretty(&entries)?)
+}
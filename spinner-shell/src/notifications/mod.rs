@@ -1,38 +1,544 @@
 //! Notification center
+//!
+//! spinner-shell is the session's notification daemon: `dbus` owns
+//! `org.freedesktop.Notifications` and forwards every request here. The
+//! center keeps the live notifications and the history of expired ones,
+//! saving both across restarts, emits the closing and action signals, and
+//! tells listeners (`Popups`, the `Drawer`) what changed.
+//!
+//! Per-app rules and Do Not Disturb are applied here, before anything is
+//! shown: the manual toggle, quiet hours and a focused fullscreen window
+//! all hold back popups and sounds, except for critical notifications
+//! when `critical_bypasses_dnd` is set.
 
-use tracing::info;
+mod card;
+mod dbus;
+mod drawer;
+mod history;
+mod popup;
 
-pub struct NotificationCenter {
-    notifications: Vec<Notification>,
+pub use drawer::Drawer;
+pub use history::export as export_history;
+pub use popup::Popups;
+
+use crate::config::{NotificationsConfig, RuleAction};
+use crate::wm;
+
+use chrono::{DateTime, Local};
+use gtk4::glib;
+use serde::{Deserialize, Serialize};
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::process::Command;
+use std::rc::Rc;
+use std::time::Duration;
+use tracing::{debug, error, info, warn};
+
+/// Changes are written out at most this often.
+const SAVE_DELAY: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Urgency {
+    Low,
+    #[default]
+    Normal,
+    Critical,
+}
+
+impl Urgency {
+    fn from_byte(value: u8) -> Self {
+        match value {
+            0 => Urgency::Low,
+            2 => Urgency::Critical,
+            _ => Urgency::Normal,
+        }
+    }
+}
+
+/// Why a notification went away, as reported by `NotificationClosed`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CloseReason {
+    Expired = 1,
+    Dismissed = 2,
+    /// Closed by the client with `CloseNotification`.
+    Closed = 3,
+    Undefined = 4,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Action {
+    pub key: String,
+    pub label: String,
+}
+
+/// Raw pixels from the `image-data` hint.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageData {
+    pub width: i32,
+    pub height: i32,
+    pub rowstride: i32,
+    pub has_alpha: bool,
+    pub bits_per_sample: i32,
+    pub channels: i32,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum NotificationImage {
+    /// A file path, `file://` URI or icon name.
+    Path(String),
+    Data(ImageData),
+}
+
+/// From the `sound-file` and `sound-name` hints.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Sound {
+    File(String),
+    /// A name from the freedesktop sound theme.
+    Name(String),
+}
+
+impl Sound {
+    fn play(&self) {
+        let arg = match self {
+            Sound::File(path) => format!("--file={}", path),
+            Sound::Name(name) => format!("--id={}", name),
+        };
+        if let Err(e) = Command::new("canberra-gtk-play").arg(arg).spawn() {
+            debug!("Failed to play notification sound {:?}: {}", self, e);
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct Notification {
     pub id: u32,
     pub app_name: String,
+    pub app_icon: String,
     pub summary: String,
     pub body: String,
+    pub actions: Vec<Action>,
+    pub urgency: Urgency,
+    pub category: Option<String>,
+    pub desktop_entry: Option<String>,
+    pub image: Option<NotificationImage>,
+    pub sound: Option<Sound>,
+    /// Stays after an action is invoked.
+    pub resident: bool,
+    /// Never kept in history.
+    pub transient: bool,
+    /// Milliseconds as requested: -1 leaves it to the server, 0 never expires.
+    pub expire_timeout: i32,
+    pub timestamp: DateTime<Local>,
+    /// Seen in the notification center.
+    pub read: bool,
+}
+
+impl Notification {
+    /// Milliseconds until the notification expires, or `None` if it stays
+    /// until dismissed. A timeout left to the server comes from
+    /// `timeout_normal` or `timeout_critical`.
+    pub fn timeout(&self, config: &NotificationsConfig) -> Option<u32> {
+        let timeout = match self.expire_timeout {
+            timeout if timeout >= 0 => timeout as u32,
+            _ if self.urgency == Urgency::Critical => config.timeout_critical,
+            _ => config.timeout_normal,
+        };
+        (timeout > 0).then_some(timeout)
+    }
+}
+
+/// Work for the center, from D-Bus or from its own timers.
+#[derive(Debug)]
+pub enum Request {
+    Notify(Box<Notification>),
+    Close { id: u32, reason: CloseReason },
+    /// An expiry timer fired; `serial` ties it to the version of the
+    /// notification it was started for, so replacing one restarts its timer.
+    Expire { id: u32, serial: u64 },
+    /// Write the history to disk.
+    Save,
+}
+
+/// What changed. Events carry their data because listeners run while the
+/// center is borrowed.
+#[derive(Debug, Clone)]
+pub enum CenterEvent {
+    /// `popup` is false under Do Not Disturb or a `no-popup` rule.
+    Added { notification: Notification, popup: bool },
+    Replaced { notification: Notification, popup: bool },
+    Closed { id: u32, reason: CloseReason },
+    HistoryChanged,
+    DoNotDisturb(bool),
+}
+
+type Listener = Box<dyn Fn(&CenterEvent)>;
+
+struct Entry {
+    notification: Notification,
+    serial: u64,
+}
+
+pub struct NotificationCenter {
+    config: NotificationsConfig,
+    notifications: Vec<Entry>,
+    /// Expired notifications, newest first.
+    history: VecDeque<Notification>,
+    do_not_disturb: bool,
+    /// A fullscreen window has focus.
+    fullscreen: bool,
+    listeners: Vec<Listener>,
+    requests: async_channel::Sender<Request>,
+    connection: Option<zbus::Connection>,
+    next_serial: u64,
+    save_pending: Cell<bool>,
 }
 
 impl NotificationCenter {
-    pub fn new() -> Self {
+    pub fn new(config: NotificationsConfig, requests: async_channel::Sender<Request>) -> Self {
         Self {
+            do_not_disturb: config.do_not_disturb,
+            fullscreen: false,
+            config,
             notifications: Vec::new(),
+            history: VecDeque::new(),
+            listeners: Vec::new(),
+            requests,
+            connection: None,
+            next_serial: 0,
+            save_pending: Cell::new(false),
         }
     }
-    
+
+    pub fn connect(&mut self, listener: impl Fn(&CenterEvent) + 'static) {
+        self.listeners.push(Box::new(listener));
+    }
+
+    /// Live notifications, oldest first.
+    pub fn notifications(&self) -> impl Iterator<Item = &Notification> {
+        self.notifications.iter().map(|e| &e.notification)
+    }
+
+    pub fn get(&self, id: u32) -> Option<&Notification> {
+        self.notifications().find(|n| n.id == id)
+    }
+
+    /// Expired notifications, newest first.
+    pub fn history(&self) -> impl Iterator<Item = &Notification> {
+        self.history.iter()
+    }
+
+    pub fn unread_count(&self) -> usize {
+        self.notifications().chain(self.history()).filter(|n| !n.read).count()
+    }
+
+    pub fn mark_all_read(&mut self) {
+        if self.unread_count() == 0 {
+            return;
+        }
+        for entry in &mut self.notifications {
+            entry.notification.read = true;
+        }
+        for notification in &mut self.history {
+            notification.read = true;
+        }
+        self.emit(&CenterEvent::HistoryChanged);
+    }
+
+    pub fn do_not_disturb(&self) -> bool {
+        self.do_not_disturb
+    }
+
+    /// The manual toggle; popups stay hidden while it is on, but
+    /// notifications are still collected.
+    pub fn set_do_not_disturb(&mut self, enabled: bool) {
+        if self.do_not_disturb != enabled {
+            info!("Do Not Disturb {}", if enabled { "on" } else { "off" });
+            self.do_not_disturb = enabled;
+            self.emit(&CenterEvent::DoNotDisturb(enabled));
+        }
+    }
+
+    pub fn handle(&mut self, request: Request) {
+        match request {
+            Request::Notify(notification) => self.add_notification(*notification),
+            Request::Close { id, reason } => self.close(id, reason),
+            Request::Expire { id, serial } => {
+                if self.notifications.iter().any(|e| e.notification.id == id && e.serial == serial) {
+                    self.close(id, CloseReason::Expired);
+                }
+            }
+            Request::Save => self.save_history(),
+        }
+    }
+
+    pub fn set_fullscreen(&mut self, fullscreen: bool) {
+        if self.fullscreen != fullscreen {
+            debug!("Fullscreen window {}", if fullscreen { "focused" } else { "gone" });
+            self.fullscreen = fullscreen;
+        }
+    }
+
+    /// Whether Do Not Disturb is in effect right now, for any reason.
+    pub fn is_quiet(&self) -> bool {
+        self.do_not_disturb
+            || (self.config.dnd_when_fullscreen && self.fullscreen)
+            || self
+                .config
+                .quiet_hours
+                .as_ref()
+                .is_some_and(|hours| hours.contains(Local::now().time()))
+    }
+
+    /// The action of the first rule matching the notification's app.
+    fn rule_for(&self, notification: &Notification) -> Option<RuleAction> {
+        self.config
+            .rules
+            .iter()
+            .find(|rule| {
+                notification.app_name.eq_ignore_ascii_case(&rule.app)
+                    || notification
+                        .desktop_entry
+                        .as_deref()
+                        .is_some_and(|entry| entry.eq_ignore_ascii_case(&rule.app))
+            })
+            .map(|rule| rule.action)
+    }
+
+    /// Adds a notification, replacing any with the same id in place, after
+    /// applying the app's rule and Do Not Disturb.
     pub fn add_notification(&mut self, notification: Notification) {
-        info!("New notification: {}", notification.summary);
-        self.notifications.push(notification);
+        let rule = self.rule_for(&notification);
+        match rule {
+            Some(RuleAction::Mute) => {
+                debug!("Muted notification from {}", notification.app_name);
+                return;
+            }
+            Some(RuleAction::HistoryOnly) => {
+                debug!("Notification from {} goes to history only", notification.app_name);
+                self.push_history(notification);
+                return;
+            }
+            _ => {}
+        }
+        info!("New notification from {}: {}", notification.app_name, notification.summary);
+
+        self.next_serial += 1;
+        let serial = self.next_serial;
+        let id = notification.id;
+        let timeout = notification.timeout(&self.config);
+
+        let bypass = notification.urgency == Urgency::Critical && self.config.critical_bypasses_dnd;
+        let popup = (bypass || !self.is_quiet()) && rule != Some(RuleAction::NoPopup);
+        if popup && rule != Some(RuleAction::NoSound) {
+            if let Some(sound) = &notification.sound {
+                sound.play();
+            }
+        }
+
+        // Replacing an expired notification brings it back.
+        if let Some(index) = self.history.iter().position(|n| n.id == id) {
+            self.history.remove(index);
+            self.emit(&CenterEvent::HistoryChanged);
+        }
+
+        let event = match self.notifications.iter_mut().find(|e| e.notification.id == id) {
+            Some(entry) => {
+                entry.notification = notification.clone();
+                entry.serial = serial;
+                CenterEvent::Replaced { notification, popup }
+            }
+            None => {
+                self.notifications.push(Entry {
+                    notification: notification.clone(),
+                    serial,
+                });
+                CenterEvent::Added { notification, popup }
+            }
+        };
+        self.emit(&event);
+
+        if let Some(timeout) = timeout {
+            let requests = self.requests.clone();
+            glib::timeout_add_local_once(Duration::from_millis(timeout as u64), move || {
+                let _ = requests.send_blocking(Request::Expire { id, serial });
+            });
+        }
     }
-    
+
+    /// Closes a live notification. Expired ones move to the history
+    /// unless they are transient.
+    pub fn close(&mut self, id: u32, reason: CloseReason) {
+        let Some(index) = self.notifications.iter().position(|e| e.notification.id == id) else {
+            return;
+        };
+        let entry = self.notifications.remove(index);
+
+        if let Some(connection) = self.connection.clone() {
+            glib::MainContext::default().spawn_local(async move {
+                if let Err(e) = dbus::notification_closed(&connection, id, reason).await {
+                    warn!("Failed to signal closing of notification {}: {}", id, e);
+                }
+            });
+        }
+        self.emit(&CenterEvent::Closed { id, reason });
+
+        if reason == CloseReason::Expired {
+            self.push_history(entry.notification);
+        }
+    }
+
+    fn push_history(&mut self, notification: Notification) {
+        if notification.transient {
+            return;
+        }
+        self.history.retain(|n| n.id != notification.id);
+        self.history.push_front(notification);
+        self.history.truncate(self.config.history_limit as usize);
+        self.emit(&CenterEvent::HistoryChanged);
+    }
+
+    /// Removes a notification on the user's request, whether it is live or
+    /// in the history.
+    pub fn dismiss(&mut self, id: u32) {
+        if self.get(id).is_some() {
+            self.close(id, CloseReason::Dismissed);
+        } else if let Some(index) = self.history.iter().position(|n| n.id == id) {
+            self.history.remove(index);
+            self.emit(&CenterEvent::HistoryChanged);
+        }
+    }
+
+    /// Reports an action to the client. Unless the notification is
+    /// resident, invoking an action also dismisses it.
+    pub fn invoke_action(&mut self, id: u32, key: &str) {
+        let Some(notification) = self.get(id) else {
+            return;
+        };
+        let resident = notification.resident;
+
+        if let Some(connection) = self.connection.clone() {
+            let key = key.to_string();
+            glib::MainContext::default().spawn_local(async move {
+                if let Err(e) = dbus::action_invoked(&connection, id, &key).await {
+                    warn!("Failed to signal action {} on notification {}: {}", key, id, e);
+                }
+            });
+        }
+
+        if !resident {
+            self.close(id, CloseReason::Dismissed);
+        }
+    }
+
     pub fn clear_all(&mut self) {
-        self.notifications.clear();
+        let ids: Vec<u32> = self.notifications().map(|n| n.id).collect();
+        for id in ids {
+            self.close(id, CloseReason::Dismissed);
+        }
+        if !self.history.is_empty() {
+            self.history.clear();
+            self.emit(&CenterEvent::HistoryChanged);
+        }
+    }
+
+    /// Tells the listeners, and queues a save for anything but a change of
+    /// Do Not Disturb.
+    fn emit(&self, event: &CenterEvent) {
+        for listener in &self.listeners {
+            listener(event);
+        }
+        if !matches!(event, CenterEvent::DoNotDisturb(_)) {
+            self.schedule_save();
+        }
+    }
+
+    fn schedule_save(&self) {
+        if self.save_pending.replace(true) {
+            return;
+        }
+        let requests = self.requests.clone();
+        glib::timeout_add_local_once(SAVE_DELAY, move || {
+            let _ = requests.send_blocking(Request::Save);
+        });
+    }
+
+    /// Loads the stored history; everything in it, including notifications
+    /// that were live when the shell stopped, comes back as history.
+    fn restore_history(&mut self) {
+        let mut stored = history::load().unwrap_or_else(|e| {
+            error!("Failed to load notification history: {:#}", e);
+            Vec::new()
+        });
+        history::prune(&mut stored, self.config.history_limit as usize, self.config.history_days);
+
+        debug!("Restored {} notifications", stored.len());
+        self.history = stored.into_iter().map(Notification::from).collect();
+    }
+
+    fn save_history(&mut self) {
+        self.save_pending.set(false);
+
+        let mut live: Vec<&Notification> = self
+            .notifications()
+            .filter(|n| !n.transient)
+            .collect();
+        live.reverse();
+        let mut stored: Vec<history::StoredNotification> = live
+            .into_iter()
+            .chain(self.history.iter())
+            .map(history::StoredNotification::from)
+            .collect();
+        history::prune(&mut stored, self.config.history_limit as usize, self.config.history_days);
+
+        if let Err(e) = history::save(&stored) {
+            error!("Failed to save notification history: {:#}", e);
+        }
     }
-}
 
-impl Default for NotificationCenter {
-    fn default() -> Self {
-        Self::new()
+    /// The first id the D-Bus server may hand out without clashing with
+    /// the restored history.
+    fn next_free_id(&self) -> u32 {
+        self.history.iter().map(|n| n.id).max().unwrap_or(0) + 1
     }
 }
+
+/// Restores the history, claims `org.freedesktop.Notifications` on the
+/// session bus and feeds the returned center. If another daemon already
+/// owns the name, the error is logged and the center only has the history.
+pub fn start(config: &NotificationsConfig) -> Rc<RefCell<NotificationCenter>> {
+    let (sender, receiver) = async_channel::unbounded();
+    let center = Rc::new(RefCell::new(NotificationCenter::new(config.clone(), sender.clone())));
+    center.borrow_mut().restore_history();
+    let first_id = center.borrow().next_free_id();
+
+    let center_clone = center.clone();
+    glib::MainContext::default().spawn_local(async move {
+        match dbus::serve(sender, first_id).await {
+            Ok(connection) => {
+                info!("Serving {} on {}", dbus::BUS_NAME, dbus::OBJECT_PATH);
+                center_clone.borrow_mut().connection = Some(connection);
+            }
+            Err(e) => error!("Notification daemon not started: {:#}", e),
+        }
+
+        while let Ok(request) = receiver.recv().await {
+            center_clone.borrow_mut().handle(request);
+        }
+    });
+
+    let windows = wm::subscribe_windows();
+    let weak = Rc::downgrade(&center);
+    glib::MainContext::default().spawn_local(async move {
+        while let Ok(windows) = windows.recv().await {
+            let Some(center) = weak.upgrade() else {
+                break;
+            };
+            let fullscreen = windows.iter().any(|w| w.focused && w.fullscreen);
+            center.borrow_mut().set_fullscreen(fullscreen);
+        }
+    });
+
+    center
+}
@@ -0,0 +1,150 @@
+//! Notification popups
+//!
+//! New notifications appear as toasts in a layer-shell window at the
+//! configured corner, newest nearest the corner. At most `max_visible` are
+//! shown; older ones leave the stack but stay live until they expire or
+//! are dismissed.
+
+use super::card::notification_card;
+use super::{CenterEvent, Notification, NotificationCenter};
+use crate::config::{NotificationPosition, NotificationsConfig};
+
+use gtk4::prelude::*;
+use gtk4::{self, Box as GtkBox, Orientation};
+use gtk4_layer_shell::{Edge, KeyboardMode, Layer, LayerShell};
+use libadwaita as adw;
+use std::cell::RefCell;
+use std::rc::{Rc, Weak};
+
+const WIDTH: i32 = 380;
+const MARGIN: i32 = 12;
+
+pub struct Popups {
+    window: gtk4::ApplicationWindow,
+    stack: GtkBox,
+    center: Weak<RefCell<NotificationCenter>>,
+    position: NotificationPosition,
+    max_visible: usize,
+    /// Shown cards by notification id, oldest first.
+    cards: RefCell<Vec<(u32, GtkBox)>>,
+}
+
+impl Popups {
+    pub fn start(app: &adw::Application, center: &Rc<RefCell<NotificationCenter>>, config: &NotificationsConfig) {
+        let window = gtk4::ApplicationWindow::builder()
+            .application(app)
+            .decorated(false)
+            .resizable(false)
+            .default_width(WIDTH)
+            .build();
+        window.add_css_class("notification-popups");
+        setup_layer_shell(&window, config.position);
+
+        let stack = GtkBox::builder()
+            .orientation(Orientation::Vertical)
+            .spacing(8)
+            .build();
+        window.set_child(Some(&stack));
+
+        let popups = Rc::new(Self {
+            window,
+            stack,
+            center: Rc::downgrade(center),
+            position: config.position,
+            max_visible: config.max_visible.max(1) as usize,
+            cards: RefCell::new(Vec::new()),
+        });
+
+        // The center owns the popups from here on.
+        center.borrow_mut().connect(move |event| popups.handle(event));
+    }
+
+    fn handle(&self, event: &CenterEvent) {
+        match event {
+            CenterEvent::Added { notification, popup: true } => self.show(notification),
+            CenterEvent::Replaced { notification, popup } => {
+                if *popup || self.is_shown(notification.id) {
+                    self.show(notification);
+                }
+            }
+            CenterEvent::Closed { id, .. } => self.remove(*id),
+            CenterEvent::DoNotDisturb(true) => self.clear(),
+            _ => {}
+        }
+    }
+
+    fn is_shown(&self, id: u32) -> bool {
+        self.cards.borrow().iter().any(|(shown, _)| *shown == id)
+    }
+
+    /// Shows a card for the notification, replacing its current card in
+    /// place if there is one.
+    fn show(&self, notification: &Notification) {
+        let Some(center) = self.center.upgrade() else {
+            return;
+        };
+        let card = notification_card(notification, &center, true);
+        card.add_css_class("notification-popup");
+
+        let mut cards = self.cards.borrow_mut();
+        if let Some(slot) = cards.iter_mut().find(|(id, _)| *id == notification.id) {
+            self.stack.insert_child_after(&card, Some(&slot.1));
+            self.stack.remove(&slot.1);
+            slot.1 = card;
+            return;
+        }
+
+        // Newest nearest the corner: on top for top positions.
+        if self.position.is_top() {
+            self.stack.prepend(&card);
+        } else {
+            self.stack.append(&card);
+        }
+        cards.push((notification.id, card));
+
+        while cards.len() > self.max_visible {
+            let (_, oldest) = cards.remove(0);
+            self.stack.remove(&oldest);
+        }
+        drop(cards);
+
+        self.window.present();
+    }
+
+    fn remove(&self, id: u32) {
+        let mut cards = self.cards.borrow_mut();
+        if let Some(index) = cards.iter().position(|(shown, _)| *shown == id) {
+            let (_, card) = cards.remove(index);
+            self.stack.remove(&card);
+        }
+        if cards.is_empty() {
+            self.window.set_visible(false);
+        }
+    }
+
+    fn clear(&self) {
+        for (_, card) in self.cards.borrow_mut().drain(..) {
+            self.stack.remove(&card);
+        }
+        self.window.set_visible(false);
+    }
+}
+
+/// Pins the window to the configured corner above normal windows, without
+/// taking keyboard focus or reserving space.
+fn setup_layer_shell(window: &gtk4::ApplicationWindow, position: NotificationPosition) {
+    window.init_layer_shell();
+    window.set_layer(Layer::Overlay);
+    window.set_namespace("spinner-notifications");
+    window.set_keyboard_mode(KeyboardMode::None);
+
+    let vertical = if position.is_top() { Edge::Top } else { Edge::Bottom };
+    window.set_anchor(vertical, true);
+    window.set_margin(vertical, MARGIN);
+
+    if !position.is_center() {
+        let horizontal = if position.is_left() { Edge::Left } else { Edge::Right };
+        window.set_anchor(horizontal, true);
+        window.set_margin(horizontal, MARGIN);
+    }
+}
@@ -0,0 +1,298 @@
+//! Audio indicator - Default output volume, with a popover for devices,
+//! application streams and the microphone
+
+use super::popover::{clear, section};
+use crate::services::audio::{self, AudioState, MAX_VOLUME};
+
+use gtk4::prelude::*;
+use gtk4::{
+    self, glib, Align, Box as GtkBox, Button, CheckButton, EventControllerScroll,
+    EventControllerScrollFlags, Image, Label, Orientation, Popover, Scale, Switch,
+};
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+/// Volume change per scroll step.
+const SCROLL_STEP: u32 = 5;
+
+pub struct AudioIndicator {
+    button: Button,
+    popover: Popover,
+    mute_button: Button,
+    volume: Scale,
+    outputs: GtkBox,
+    streams_section: GtkBox,
+    streams: GtkBox,
+    mic_section: GtkBox,
+    mic_mute: Switch,
+    state: RefCell<AudioState>,
+    /// Set while widgets are updated from the server, so their handlers
+    /// don't echo the change back.
+    updating: Cell<bool>,
+    /// The volume last set from here, ahead of the server's report.
+    pending_volume: Cell<Option<u32>>,
+}
+
+impl AudioIndicator {
+    pub fn build() -> Button {
+        let button = Button::builder()
+            .icon_name(audio::volume_icon(0, true))
+            .tooltip_text("No audio output")
+            .build();
+        button.add_css_class("systray-button");
+        button.add_css_class("audio-indicator");
+
+        let content = GtkBox::builder()
+            .orientation(Orientation::Vertical)
+            .spacing(8)
+            .margin_top(12)
+            .margin_bottom(12)
+            .margin_start(12)
+            .margin_end(12)
+            .width_request(320)
+            .build();
+
+        let header = Label::builder().label("Sound").xalign(0.0).build();
+        header.add_css_class("popover-header");
+        content.append(&header);
+
+        let volume_row = GtkBox::builder()
+            .orientation(Orientation::Horizontal)
+            .spacing(8)
+            .build();
+        let mute_button = Button::builder()
+            .icon_name(audio::volume_icon(0, true))
+            .tooltip_text("Mute")
+            .build();
+        mute_button.add_css_class("systray-button");
+        volume_row.append(&mute_button);
+
+        let volume = Scale::with_range(Orientation::Horizontal, 0.0, MAX_VOLUME as f64, 1.0);
+        volume.set_hexpand(true);
+        volume.set_draw_value(true);
+        volume.set_value_pos(gtk4::PositionType::Right);
+        volume.add_css_class("volume-scale");
+        volume_row.append(&volume);
+        content.append(&volume_row);
+
+        let (outputs_section, outputs) = section("Output");
+        content.append(&outputs_section);
+
+        let (streams_section, streams) = section("Applications");
+        content.append(&streams_section);
+
+        let mic_section = GtkBox::builder()
+            .orientation(Orientation::Horizontal)
+            .spacing(8)
+            .build();
+        mic_section.add_css_class("popover-section");
+        let mic_label = Label::builder()
+            .label("Mute microphone")
+            .xalign(0.0)
+            .hexpand(true)
+            .build();
+        mic_section.append(&mic_label);
+        let mic_mute = Switch::builder().valign(Align::Center).build();
+        mic_section.append(&mic_mute);
+        content.append(&mic_section);
+
+        let popover = Popover::builder().child(&content).has_arrow(false).build();
+        popover.add_css_class("systray-popover");
+        popover.set_parent(&button);
+        let popover_clone = popover.clone();
+        button.connect_destroy(move |_| {
+            popover_clone.unparent();
+        });
+
+        let indicator = Rc::new(Self {
+            button: button.clone(),
+            popover,
+            mute_button,
+            volume,
+            outputs,
+            streams_section,
+            streams,
+            mic_section,
+            mic_mute,
+            state: RefCell::new(AudioState::default()),
+            updating: Cell::new(false),
+            pending_volume: Cell::new(None),
+        });
+        indicator.connect_signals();
+
+        // The task owns the indicator and stops with the button.
+        let receiver = audio::subscribe();
+        glib::MainContext::default().spawn_local(async move {
+            while let Ok(state) = receiver.recv().await {
+                if indicator.button.parent().is_none() {
+                    break;
+                }
+                indicator.update(state);
+            }
+        });
+
+        button
+    }
+
+    fn connect_signals(self: &Rc<Self>) {
+        let weak = Rc::downgrade(self);
+        self.button.connect_clicked(move |_| {
+            if let Some(indicator) = weak.upgrade() {
+                indicator.popover.popup();
+            }
+        });
+
+        let scroll = EventControllerScroll::new(EventControllerScrollFlags::VERTICAL);
+        let weak = Rc::downgrade(self);
+        scroll.connect_scroll(move |_, _, dy| {
+            if let Some(indicator) = weak.upgrade() {
+                indicator.scroll(dy);
+            }
+            glib::Propagation::Stop
+        });
+        self.button.add_controller(scroll);
+
+        let weak = Rc::downgrade(self);
+        self.mute_button.connect_clicked(move |_| {
+            if let Some(indicator) = weak.upgrade() {
+                let muted = indicator.state.borrow().default_sink().is_some_and(|s| s.mute);
+                audio::set_sink_mute(!muted);
+            }
+        });
+
+        let weak = Rc::downgrade(self);
+        self.volume.connect_value_changed(move |scale| {
+            if let Some(indicator) = weak.upgrade().filter(|i| !i.updating.get()) {
+                let volume = scale.value().round() as u32;
+                indicator.pending_volume.set(Some(volume));
+                audio::set_sink_volume(volume);
+            }
+        });
+
+        let weak = Rc::downgrade(self);
+        self.mic_mute.connect_active_notify(move |switch| {
+            if weak.upgrade().is_some_and(|i| !i.updating.get()) {
+                audio::set_source_mute(switch.is_active());
+            }
+        });
+    }
+
+    fn scroll(&self, dy: f64) {
+        let state = self.state.borrow();
+        let Some(sink) = state.default_sink() else {
+            return;
+        };
+        let current = self.pending_volume.get().unwrap_or(sink.volume);
+        let volume = if dy < 0.0 {
+            (current + SCROLL_STEP).min(MAX_VOLUME)
+        } else {
+            current.saturating_sub(SCROLL_STEP)
+        };
+        self.pending_volume.set(Some(volume));
+        audio::set_sink_volume(volume);
+    }
+
+    fn update(self: &Rc<Self>, state: AudioState) {
+        self.updating.set(true);
+        self.pending_volume.set(None);
+
+        match state.default_sink() {
+            Some(sink) => {
+                let icon = audio::volume_icon(sink.volume, sink.mute);
+                self.button.set_icon_name(icon);
+                self.button.set_tooltip_text(Some(&if sink.mute {
+                    format!("{}: muted", sink.description)
+                } else {
+                    format!("{}: {}%", sink.description, sink.volume)
+                }));
+                self.mute_button.set_icon_name(icon);
+                self.mute_button.set_tooltip_text(Some(if sink.mute { "Unmute" } else { "Mute" }));
+                self.volume.set_value(sink.volume.min(MAX_VOLUME) as f64);
+                self.volume.set_sensitive(true);
+            }
+            None => {
+                self.button.set_icon_name(audio::volume_icon(0, true));
+                self.button.set_tooltip_text(Some("No audio output"));
+                self.volume.set_sensitive(false);
+            }
+        }
+
+        self.fill_outputs(&state);
+        self.fill_streams(&state);
+
+        match state.default_source() {
+            Some(source) => {
+                self.mic_section.set_visible(true);
+                self.mic_mute.set_active(source.mute);
+            }
+            None => self.mic_section.set_visible(false),
+        }
+
+        *self.state.borrow_mut() = state;
+        self.updating.set(false);
+    }
+
+    fn fill_outputs(self: &Rc<Self>, state: &AudioState) {
+        clear(&self.outputs);
+
+        let mut group: Option<CheckButton> = None;
+        for sink in &state.sinks {
+            let check = CheckButton::builder()
+                .label(&sink.description)
+                .active(state.default_sink.as_ref() == Some(&sink.name))
+                .build();
+            check.set_group(group.as_ref());
+            group.get_or_insert_with(|| check.clone());
+
+            let name = sink.name.clone();
+            let weak = Rc::downgrade(self);
+            check.connect_toggled(move |check| {
+                if check.is_active() && weak.upgrade().is_some_and(|i| !i.updating.get()) {
+                    audio::set_default_sink(&name);
+                }
+            });
+            self.outputs.append(&check);
+        }
+    }
+
+    fn fill_streams(self: &Rc<Self>, state: &AudioState) {
+        clear(&self.streams);
+        self.streams_section.set_visible(!state.streams.is_empty());
+
+        for stream in &state.streams {
+            let row = GtkBox::builder()
+                .orientation(Orientation::Horizontal)
+                .spacing(8)
+                .build();
+            row.add_css_class("popover-row");
+            row.set_tooltip_text(Some(&stream.media_name));
+
+            let icon = Image::from_icon_name(stream.icon_name.as_deref().unwrap_or("application-x-executable"));
+            row.append(&icon);
+
+            let name = Label::builder()
+                .label(&stream.app_name)
+                .xalign(0.0)
+                .width_chars(12)
+                .max_width_chars(12)
+                .ellipsize(pango::EllipsizeMode::End)
+                .build();
+            row.append(&name);
+
+            let scale = Scale::with_range(Orientation::Horizontal, 0.0, MAX_VOLUME as f64, 1.0);
+            scale.set_hexpand(true);
+            scale.set_value(stream.volume.min(MAX_VOLUME) as f64);
+            scale.add_css_class("volume-scale");
+            let index = stream.index;
+            let weak = Rc::downgrade(self);
+            scale.connect_value_changed(move |scale| {
+                if weak.upgrade().is_some_and(|i| !i.updating.get()) {
+                    audio::set_stream_volume(index, scale.value().round() as u32);
+                }
+            });
+            row.append(&scale);
+
+            self.streams.append(&row);
+        }
+    }
+}
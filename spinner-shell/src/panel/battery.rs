@@ -0,0 +1,173 @@
+//! Battery indicator - Charge of the system batteries, with a popover
+//! listing each battery and peripheral. Hidden while there are none.
+
+use super::popover::{clear, section};
+use crate::services::power::{self, Battery, PowerState};
+
+use gtk4::prelude::*;
+use gtk4::{self, glib, Box as GtkBox, Button, Image, Label, Orientation, Popover};
+use std::rc::Rc;
+
+pub struct BatteryIndicator {
+    button: Button,
+    level: Label,
+    status: Label,
+    batteries_section: GtkBox,
+    batteries: GtkBox,
+    peripherals_section: GtkBox,
+    peripherals: GtkBox,
+}
+
+impl BatteryIndicator {
+    pub fn build() -> Button {
+        let button = Button::builder()
+            .icon_name("battery-missing-symbolic")
+            .visible(false)
+            .build();
+        button.add_css_class("systray-button");
+        button.add_css_class("battery-indicator");
+
+        let content = GtkBox::builder()
+            .orientation(Orientation::Vertical)
+            .spacing(8)
+            .margin_top(12)
+            .margin_bottom(12)
+            .margin_start(12)
+            .margin_end(12)
+            .width_request(280)
+            .build();
+
+        let header = Label::builder().label("Power").xalign(0.0).build();
+        header.add_css_class("popover-header");
+        content.append(&header);
+
+        let level = Label::builder().xalign(0.0).build();
+        level.add_css_class("battery-level-big");
+        content.append(&level);
+
+        let status = Label::builder().xalign(0.0).build();
+        status.add_css_class("battery-status");
+        content.append(&status);
+
+        let (batteries_section, batteries) = section("Batteries");
+        content.append(&batteries_section);
+
+        let (peripherals_section, peripherals) = section("Devices");
+        content.append(&peripherals_section);
+
+        let popover = Popover::builder().child(&content).has_arrow(false).build();
+        popover.add_css_class("systray-popover");
+        popover.set_parent(&button);
+        let popover_clone = popover.clone();
+        button.connect_destroy(move |_| {
+            popover_clone.unparent();
+        });
+        button.connect_clicked(move |_| {
+            popover.popup();
+        });
+
+        let indicator = Rc::new(Self {
+            button: button.clone(),
+            level,
+            status,
+            batteries_section,
+            batteries,
+            peripherals_section,
+            peripherals,
+        });
+
+        // The task owns the indicator and stops with the button.
+        let receiver = power::subscribe();
+        glib::MainContext::default().spawn_local(async move {
+            while let Ok(state) = receiver.recv().await {
+                if indicator.button.parent().is_none() {
+                    break;
+                }
+                indicator.update(&state);
+            }
+        });
+
+        button
+    }
+
+    fn update(&self, state: &PowerState) {
+        self.button.set_visible(!state.is_empty());
+
+        match state.summary() {
+            Some(summary) => {
+                let level = format!("{:.0}%", summary.percentage);
+                let mut status = summary.state.label().to_string();
+                if let Some(time) = summary.time {
+                    let until = if summary.state == power::ChargeState::Charging {
+                        "until full"
+                    } else {
+                        "remaining"
+                    };
+                    status = format!("{}, {} {}", status, power::format_duration(time), until);
+                }
+                self.button
+                    .set_icon_name(&power::battery_icon(summary.percentage, summary.state));
+                self.button
+                    .set_tooltip_text(Some(&format!("Battery {}: {}", level, status)));
+                self.level.set_label(&level);
+                self.status.set_label(&status);
+                self.level.set_visible(true);
+                self.status.set_visible(true);
+            }
+            None => {
+                // Only peripherals; show the one closest to running out.
+                let lowest = state
+                    .peripherals
+                    .iter()
+                    .min_by(|a, b| a.percentage.total_cmp(&b.percentage));
+                if let Some(battery) = lowest {
+                    self.button.set_icon_name(&battery.icon_name());
+                    self.button.set_tooltip_text(Some(&format!(
+                        "{}: {:.0}%",
+                        battery.name, battery.percentage
+                    )));
+                }
+                self.level.set_visible(false);
+                self.status.set_visible(false);
+            }
+        }
+
+        fill(&self.batteries, &state.batteries);
+        self.batteries_section.set_visible(state.batteries.len() > 1);
+        fill(&self.peripherals, &state.peripherals);
+        self.peripherals_section.set_visible(!state.peripherals.is_empty());
+    }
+}
+
+fn fill(container: &GtkBox, batteries: &[Battery]) {
+    clear(container);
+
+    for battery in batteries {
+        let row = GtkBox::builder()
+            .orientation(Orientation::Horizontal)
+            .spacing(8)
+            .build();
+        row.add_css_class("popover-row");
+
+        row.append(&Image::from_icon_name(&battery.icon_name()));
+
+        let name = Label::builder()
+            .label(&battery.name)
+            .xalign(0.0)
+            .hexpand(true)
+            .ellipsize(pango::EllipsizeMode::End)
+            .build();
+        row.append(&name);
+
+        let mut status = format!("{:.0}%", battery.percentage);
+        if let Some(time) = battery.time() {
+            status = format!("{} · {}", status, power::format_duration(time));
+        }
+        let status = Label::new(Some(&status));
+        status.add_css_class("battery-status");
+        status.set_tooltip_text(Some(battery.state.label()));
+        row.append(&status);
+
+        container.append(&row);
+    }
+}
@@ -0,0 +1,259 @@
+//! Bluetooth indicator - Adapter power and connected devices from BlueZ,
+//! with a popover to pair, connect and forget devices. Hidden without an
+//! adapter.
+
+use super::popover::{clear, section};
+
+use anyhow::Result;
+use gtk4::prelude::*;
+use gtk4::{self, glib, Align, Box as GtkBox, Button, Image, Label, Orientation, Popover, Switch};
+use spinner_bluez::{self as bluetooth, BluetoothState, Device, Discovery};
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+pub struct BluetoothIndicator {
+    button: Button,
+    popover: Popover,
+    status: Label,
+    error: Label,
+    power: Switch,
+    devices_section: GtkBox,
+    devices: GtkBox,
+    nearby_section: GtkBox,
+    nearby: GtkBox,
+    state: RefCell<BluetoothState>,
+    /// Held while the popover is open.
+    discovery: RefCell<Option<Discovery>>,
+    /// Set while widgets are updated from BlueZ, so their handlers don't
+    /// echo the change back.
+    updating: Cell<bool>,
+}
+
+impl BluetoothIndicator {
+    pub fn build() -> Button {
+        let button = Button::builder()
+            .icon_name("bluetooth-disabled-symbolic")
+            .visible(false)
+            .build();
+        button.add_css_class("systray-button");
+        button.add_css_class("bluetooth-indicator");
+
+        let content = GtkBox::builder()
+            .orientation(Orientation::Vertical)
+            .spacing(8)
+            .margin_top(12)
+            .margin_bottom(12)
+            .margin_start(12)
+            .margin_end(12)
+            .width_request(320)
+            .build();
+
+        let header = GtkBox::builder()
+            .orientation(Orientation::Horizontal)
+            .spacing(8)
+            .build();
+        let title = Label::builder()
+            .label("Bluetooth")
+            .xalign(0.0)
+            .hexpand(true)
+            .build();
+        title.add_css_class("popover-header");
+        header.append(&title);
+        let power = Switch::builder().valign(Align::Center).build();
+        header.append(&power);
+        content.append(&header);
+
+        let status = Label::builder().xalign(0.0).build();
+        status.add_css_class("network-status");
+        content.append(&status);
+
+        let error = Label::builder().xalign(0.0).wrap(true).visible(false).build();
+        error.add_css_class("network-error");
+        content.append(&error);
+
+        let (devices_section, devices) = section("Devices");
+        content.append(&devices_section);
+
+        let (nearby_section, nearby) = section("Nearby");
+        content.append(&nearby_section);
+
+        let popover = Popover::builder().child(&content).has_arrow(false).build();
+        popover.add_css_class("systray-popover");
+        popover.set_parent(&button);
+        let popover_clone = popover.clone();
+        button.connect_destroy(move |_| {
+            popover_clone.unparent();
+        });
+
+        let indicator = Rc::new(Self {
+            button: button.clone(),
+            popover: popover.clone(),
+            status,
+            error,
+            power,
+            devices_section,
+            devices,
+            nearby_section,
+            nearby,
+            state: RefCell::new(BluetoothState::default()),
+            discovery: RefCell::new(None),
+            updating: Cell::new(false),
+        });
+
+        let weak = Rc::downgrade(&indicator);
+        indicator.popover.connect_closed(move |_| {
+            if let Some(indicator) = weak.upgrade() {
+                indicator.discovery.borrow_mut().take();
+            }
+        });
+
+        let weak = Rc::downgrade(&indicator);
+        button.connect_clicked(move |_| {
+            if let Some(indicator) = weak.upgrade() {
+                indicator.error.set_visible(false);
+                indicator.start_discovery();
+                indicator.popover.popup();
+            }
+        });
+
+        let weak = Rc::downgrade(&indicator);
+        indicator.power.connect_active_notify(move |switch| {
+            if let Some(indicator) = weak.upgrade().filter(|i| !i.updating.get()) {
+                let result = bluetooth::set_powered(&indicator.state.borrow(), switch.is_active());
+                indicator.report(result);
+            }
+        });
+
+        // The task owns the indicator and stops with the button.
+        let receiver = bluetooth::subscribe();
+        glib::MainContext::default().spawn_local(async move {
+            while let Ok(state) = receiver.recv().await {
+                if indicator.button.parent().is_none() {
+                    break;
+                }
+                indicator.update(state);
+            }
+        });
+
+        button
+    }
+
+    fn update(self: &Rc<Self>, state: BluetoothState) {
+        self.updating.set(true);
+
+        let powered_on = state.powered && !self.state.borrow().powered;
+        let description = state.description();
+        self.button.set_visible(state.available);
+        self.button.set_icon_name(state.icon_name());
+        self.button.set_tooltip_text(Some(&description));
+        self.status.set_label(&description);
+        self.power.set_active(state.powered);
+
+        let (known, nearby): (Vec<&Device>, Vec<&Device>) = state.devices.iter().partition(|d| d.paired);
+        self.devices_section.set_visible(state.powered && !known.is_empty());
+        self.fill(&self.devices, &known);
+        self.nearby_section.set_visible(state.powered && !nearby.is_empty());
+        self.fill(&self.nearby, &nearby);
+
+        *self.state.borrow_mut() = state;
+        self.updating.set(false);
+
+        // Switched on from the open popover: look around right away.
+        if powered_on && self.popover.is_visible() {
+            self.start_discovery();
+        }
+    }
+
+    fn start_discovery(&self) {
+        let state = self.state.borrow();
+        if state.powered {
+            *self.discovery.borrow_mut() = Some(Discovery::start(&state));
+        }
+    }
+
+    fn fill(self: &Rc<Self>, container: &GtkBox, devices: &[&Device]) {
+        clear(container);
+
+        for device in devices {
+            let row = GtkBox::builder()
+                .orientation(Orientation::Horizontal)
+                .spacing(8)
+                .build();
+            row.add_css_class("popover-row");
+            row.append(&Image::from_icon_name(&device.icon_name()));
+
+            let name = Label::builder()
+                .label(&device.name)
+                .xalign(0.0)
+                .hexpand(true)
+                .ellipsize(pango::EllipsizeMode::End)
+                .build();
+            name.add_css_class("network-name");
+            name.set_tooltip_text(Some(&device.address));
+            row.append(&name);
+
+            if let Some(battery) = device.battery {
+                let level = Label::new(Some(&format!("{}%", battery)));
+                level.add_css_class("battery-status");
+                row.append(&level);
+            }
+
+            let (label, tooltip) = match (device.connected, device.paired) {
+                (true, _) => ("Disconnect", None),
+                (false, true) => ("Connect", None),
+                (false, false) => ("Pair", Some("Pair and connect")),
+            };
+            let action = Button::with_label(label);
+            action.add_css_class("flat");
+            action.set_tooltip_text(tooltip);
+            let target = (*device).clone();
+            let weak = Rc::downgrade(self);
+            action.connect_clicked(move |button| {
+                let Some(indicator) = weak.upgrade() else {
+                    return;
+                };
+                button.set_sensitive(false);
+                if target.connected {
+                    indicator.report(bluetooth::disconnect(&target));
+                } else {
+                    indicator.report(bluetooth::connect(&target));
+                }
+            });
+            row.append(&action);
+
+            if device.paired {
+                let forget = Button::builder()
+                    .icon_name("user-trash-symbolic")
+                    .tooltip_text("Forget")
+                    .build();
+                forget.add_css_class("flat");
+                let target = (*device).clone();
+                let weak = Rc::downgrade(self);
+                forget.connect_clicked(move |_| {
+                    if let Some(indicator) = weak.upgrade() {
+                        let result = bluetooth::forget(&indicator.state.borrow(), &target);
+                        indicator.report(result);
+                    }
+                });
+                row.append(&forget);
+            }
+
+            container.append(&row);
+        }
+    }
+
+    /// Shows the failure of a request in the popover, once it finishes.
+    fn report(self: &Rc<Self>, result: async_channel::Receiver<Result<()>>) {
+        self.error.set_visible(false);
+        let weak = Rc::downgrade(self);
+        glib::MainContext::default().spawn_local(async move {
+            let Ok(Err(e)) = result.recv().await else {
+                return;
+            };
+            if let Some(indicator) = weak.upgrade() {
+                indicator.error.set_label(&format!("{:#}", e));
+                indicator.error.set_visible(true);
+            }
+        });
+    }
+}
@@ -0,0 +1,406 @@
+//! Calendar events from local iCalendar files
+//!
+//! Calendars are `.ics` files or vdir-style directories of them, the layout
+//! CalDAV sync tools such as vdirsyncer write. Only what the agenda needs
+//! is read: VEVENT start, end, summary, location, EXDATEs and RRULEs with
+//! FREQ, INTERVAL, COUNT, UNTIL and, for weekly rules, BYDAY and WKST.
+//! Rules with other BY* parts are ignored, leaving only the first
+//! occurrence. TZIDs are looked up by IANA name rather than from the file's
+//! VTIMEZONE definitions.
+
+use chrono::{Datelike, Duration, Local, Months, NaiveDate, NaiveDateTime, TimeZone, Utc, Weekday};
+use chrono_tz::Tz;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{debug, warn};
+
+/// How deep to look for `.ics` files below a configured directory.
+const MAX_DEPTH: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Recurrence {
+    frequency: Frequency,
+    interval: u32,
+    count: Option<u32>,
+    until: Option<NaiveDateTime>,
+    /// Days of the week a weekly rule falls on; empty for the start's day.
+    by_day: Vec<Weekday>,
+    week_start: Weekday,
+}
+
+impl Recurrence {
+    fn parse(rule: &str) -> Option<Self> {
+        let mut frequency = None;
+        let mut interval = 1;
+        let mut count = None;
+        let mut until = None;
+        let mut by_day = Vec::new();
+        let mut week_start = Weekday::Mon;
+
+        for part in rule.split(';') {
+            let Some((key, value)) = part.split_once('=') else {
+                continue;
+            };
+            let key = key.to_ascii_uppercase();
+            match key.as_str() {
+                "FREQ" => {
+                    frequency = match value.to_ascii_uppercase().as_str() {
+                        "DAILY" => Some(Frequency::Daily),
+                        "WEEKLY" => Some(Frequency::Weekly),
+                        "MONTHLY" => Some(Frequency::Monthly),
+                        "YEARLY" => Some(Frequency::Yearly),
+                        _ => None,
+                    }
+                }
+                "INTERVAL" => interval = value.parse().unwrap_or(1).max(1),
+                "COUNT" => count = value.parse().ok(),
+                "UNTIL" => until = parse_time(value, &[]).map(|(time, _)| time),
+                "WKST" => week_start = parse_weekday(value).unwrap_or(Weekday::Mon),
+                // Days with an ordinal, such as 1MO, don't parse.
+                "BYDAY" => match value.split(',').map(parse_weekday).collect() {
+                    Some(days) => by_day = days,
+                    None => {
+                        warn!("Ignoring RRULE with unsupported BYDAY: {}", rule);
+                        return None;
+                    }
+                },
+                _ if key.starts_with("BY") => {
+                    warn!("Ignoring RRULE with unsupported {}: {}", key, rule);
+                    return None;
+                }
+                _ => {}
+            }
+        }
+
+        let frequency = frequency?;
+        if !by_day.is_empty() && frequency != Frequency::Weekly {
+            warn!("Ignoring RRULE with BYDAY that isn't weekly: {}", rule);
+            return None;
+        }
+
+        Some(Self {
+            frequency,
+            interval,
+            count,
+            until,
+            by_day,
+            week_start,
+        })
+    }
+
+    /// The `n`th occurrence after `start`.
+    fn nth(&self, start: NaiveDateTime, n: u32) -> Option<NaiveDateTime> {
+        let steps = n.checked_mul(self.interval)?;
+        match self.frequency {
+            Frequency::Daily => start.checked_add_signed(Duration::days(steps as i64)),
+            Frequency::Weekly => start.checked_add_signed(Duration::weeks(steps as i64)),
+            Frequency::Monthly => start.checked_add_months(Months::new(steps)),
+            Frequency::Yearly => start.checked_add_months(Months::new(steps.checked_mul(12)?)),
+        }
+    }
+
+    /// Occurrences in the `n`th period after `start`, in order. A weekly
+    /// rule with BYDAY has one on each listed day of the period's week,
+    /// though none before `start`.
+    fn period(&self, start: NaiveDateTime, n: u32) -> Option<Vec<NaiveDateTime>> {
+        let anchor = self.nth(start, n)?;
+        if self.by_day.is_empty() {
+            return Some(vec![anchor]);
+        }
+
+        let offset = |day: Weekday| {
+            (7 + day.num_days_from_monday() - self.week_start.num_days_from_monday()) as i64 % 7
+        };
+        let week = anchor - Duration::days(offset(anchor.weekday()));
+        let mut days: Vec<NaiveDateTime> = self
+            .by_day
+            .iter()
+            .map(|&day| week + Duration::days(offset(day)))
+            .filter(|&day| day >= start)
+            .collect();
+        days.sort();
+        days.dedup();
+        Some(days)
+    }
+
+    /// An index no later than the first occurrence at or after `from`,
+    /// so long-running rules are not walked from their first instance.
+    fn first_index_near(&self, start: NaiveDateTime, from: NaiveDateTime) -> u32 {
+        if self.count.is_some() || from <= start {
+            return 0;
+        }
+        let longest_period = match self.frequency {
+            Frequency::Daily => 1,
+            Frequency::Weekly => 7,
+            Frequency::Monthly => 31,
+            Frequency::Yearly => 366,
+        } * self.interval as i64;
+        ((from - start).num_days() / longest_period).saturating_sub(1) as u32
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    pub summary: String,
+    pub location: Option<String>,
+    pub start: NaiveDateTime,
+    pub end: Option<NaiveDateTime>,
+    pub all_day: bool,
+    recurrence: Option<Recurrence>,
+    exceptions: Vec<NaiveDateTime>,
+}
+
+impl Event {
+    pub fn duration(&self) -> Duration {
+        match self.end {
+            Some(end) if end > self.start => end - self.start,
+            _ if self.all_day => Duration::days(1),
+            _ => Duration::zero(),
+        }
+    }
+
+    /// Start times of every occurrence overlapping `from..to`.
+    pub fn occurrences(&self, from: NaiveDateTime, to: NaiveDateTime) -> Vec<NaiveDateTime> {
+        let duration = self.duration();
+        let overlaps = |start: NaiveDateTime| start < to && (start + duration > from || start >= from);
+
+        let Some(rule) = &self.recurrence else {
+            return if overlaps(self.start) { vec![self.start] } else { Vec::new() };
+        };
+
+        // Counting from the first period whenever COUNT is set.
+        let mut starts = Vec::new();
+        let mut n = rule.first_index_near(self.start, from - duration);
+        let mut counted = 0;
+        'periods: while let Some(period) = rule.period(self.start, n) {
+            for start in period {
+                if start >= to
+                    || rule.count.is_some_and(|count| counted >= count)
+                    || rule.until.is_some_and(|until| start > until)
+                {
+                    break 'periods;
+                }
+                if overlaps(start) && !self.exceptions.contains(&start) {
+                    starts.push(start);
+                }
+                counted += 1;
+            }
+            n += 1;
+        }
+        starts
+    }
+}
+
+/// One occurrence of an event, for the agenda.
+#[derive(Debug, Clone)]
+pub struct Occurrence<'a> {
+    pub event: &'a Event,
+    pub start: NaiveDateTime,
+}
+
+/// Every occurrence overlapping `from..to`, in start order.
+pub fn agenda(events: &[Event], from: NaiveDateTime, to: NaiveDateTime) -> Vec<Occurrence<'_>> {
+    let mut occurrences: Vec<Occurrence> = events
+        .iter()
+        .flat_map(|event| {
+            event
+                .occurrences(from, to)
+                .into_iter()
+                .map(move |start| Occurrence { event, start })
+        })
+        .collect();
+    occurrences.sort_by_key(|o| (o.start, !o.event.all_day));
+    occurrences
+}
+
+/// Reads events from every configured file or directory. Missing paths
+/// are skipped quietly, unreadable files are logged.
+pub fn load_events(paths: &[PathBuf]) -> Vec<Event> {
+    let mut files = Vec::new();
+    for path in paths {
+        collect_ics_files(path, 0, &mut files);
+    }
+
+    let mut events = Vec::new();
+    for file in files {
+        match fs::read_to_string(&file) {
+            Ok(contents) => events.extend(parse_ics(&contents)),
+            Err(e) => warn!("Failed to read calendar {:?}: {}", file, e),
+        }
+    }
+    debug!("Loaded {} calendar events", events.len());
+    events
+}
+
+fn collect_ics_files(path: &Path, depth: usize, files: &mut Vec<PathBuf>) {
+    if path.is_file() {
+        if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("ics")) {
+            files.push(path.to_path_buf());
+        }
+        return;
+    }
+
+    if depth > MAX_DEPTH {
+        return;
+    }
+    let Ok(entries) = fs::read_dir(path) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        collect_ics_files(&entry.path(), depth + 1, files);
+    }
+}
+
+#[derive(Default)]
+struct EventBuilder {
+    summary: Option<String>,
+    location: Option<String>,
+    start: Option<(NaiveDateTime, bool)>,
+    end: Option<NaiveDateTime>,
+    recurrence: Option<Recurrence>,
+    exceptions: Vec<NaiveDateTime>,
+}
+
+impl EventBuilder {
+    fn build(self) -> Option<Event> {
+        let (start, all_day) = self.start?;
+        Some(Event {
+            summary: self.summary.unwrap_or_else(|| "(No title)".to_string()),
+            location: self.location.filter(|l| !l.is_empty()),
+            start,
+            end: self.end,
+            all_day,
+            recurrence: self.recurrence,
+            exceptions: self.exceptions,
+        })
+    }
+}
+
+fn parse_weekday(value: &str) -> Option<Weekday> {
+    match value.trim().to_ascii_uppercase().as_str() {
+        "MO" => Some(Weekday::Mon),
+        "TU" => Some(Weekday::Tue),
+        "WE" => Some(Weekday::Wed),
+        "TH" => Some(Weekday::Thu),
+        "FR" => Some(Weekday::Fri),
+        "SA" => Some(Weekday::Sat),
+        "SU" => Some(Weekday::Sun),
+        _ => None,
+    }
+}
+
+pub fn parse_ics(contents: &str) -> Vec<Event> {
+    let mut events = Vec::new();
+    let mut current: Option<EventBuilder> = None;
+    // Components nested in the event, such as VALARM, whose properties
+    // must not override the event's own.
+    let mut nested = 0;
+
+    for line in unfold(contents) {
+        let Some((head, value)) = line.split_once(':') else {
+            continue;
+        };
+        let mut head = head.split(';');
+        let name = head.next().unwrap_or_default().to_ascii_uppercase();
+        let params: Vec<&str> = head.collect();
+
+        match (name.as_str(), current.as_mut()) {
+            ("BEGIN", None) if value.eq_ignore_ascii_case("VEVENT") => {
+                current = Some(EventBuilder::default());
+                nested = 0;
+            }
+            ("BEGIN", Some(_)) => nested += 1,
+            ("END", Some(_)) if nested > 0 => nested -= 1,
+            ("END", Some(_)) if value.eq_ignore_ascii_case("VEVENT") => {
+                if let Some(event) = current.take().and_then(EventBuilder::build) {
+                    events.push(event);
+                }
+            }
+            (_, Some(builder)) if nested == 0 => match name.as_str() {
+                "SUMMARY" => builder.summary = Some(unescape(value)),
+                "LOCATION" => builder.location = Some(unescape(value)),
+                "DTSTART" => builder.start = parse_time(value, &params),
+                "DTEND" => builder.end = parse_time(value, &params).map(|(time, _)| time),
+                "RRULE" => builder.recurrence = Recurrence::parse(value),
+                "EXDATE" => builder.exceptions.extend(
+                    value
+                        .split(',')
+                        .filter_map(|v| parse_time(v, &params))
+                        .map(|(time, _)| time),
+                ),
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+
+    events
+}
+
+/// Joins folded content lines (continuations start with a space or tab).
+fn unfold(contents: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in contents.lines() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(continuation), Some(last)) => last.push_str(continuation),
+            _ => lines.push(line.to_string()),
+        }
+    }
+    lines
+}
+
+fn unescape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n' | 'N') => out.push('\n'),
+            Some(other) => out.push(other),
+            None => {}
+        }
+    }
+    out
+}
+
+/// Parses a DATE or DATE-TIME value into local time, returning whether it
+/// was a whole day. UTC and TZID times are converted; floating times and
+/// unknown TZIDs are taken as local.
+fn parse_time(value: &str, params: &[&str]) -> Option<(NaiveDateTime, bool)> {
+    let value = value.trim();
+    let is_date = params.iter().any(|p| p.eq_ignore_ascii_case("VALUE=DATE")) || value.len() == 8;
+
+    if is_date {
+        let date = NaiveDate::parse_from_str(value, "%Y%m%d").ok()?;
+        return Some((date.and_hms_opt(0, 0, 0)?, true));
+    }
+
+    if let Some(utc) = value.strip_suffix('Z') {
+        let time = NaiveDateTime::parse_from_str(utc, "%Y%m%dT%H%M%S").ok()?;
+        let local = Utc.from_utc_datetime(&time).with_timezone(&Local);
+        return Some((local.naive_local(), false));
+    }
+
+    let time = NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S").ok()?;
+
+    let tz = params
+        .iter()
+        .find_map(|p| p.strip_prefix("TZID="))
+        .and_then(|tzid| tzid.trim_matches('"').parse::<Tz>().ok());
+    if let Some(local) = tz.and_then(|tz| tz.from_local_datetime(&time).earliest()) {
+        return Some((local.with_timezone(&Local).naive_local(), false));
+    }
+
+    Some((time, false))
+}
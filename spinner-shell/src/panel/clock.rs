@@ -1,58 +1,519 @@
 //! Clock widget with calendar popup
 
-use chrono::Local;
+use super::calendar::{self, Event, Occurrence};
+use super::timezone::{self, SystemZone, Zone};
+use super::{ModuleContext, PanelModule};
+use crate::config::{ClockConfig, WorldClockConfig};
+
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate, Timelike, Utc};
 use gtk4::prelude::*;
-use gtk4::{self, glib, Box as GtkBox, Button, Label, Orientation};
+use gtk4::{self, glib, Box as GtkBox, Button, Calendar, Label, Orientation, Popover};
+use std::cell::RefCell;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+/// An additional timezone shown next to the local time.
+#[derive(Debug, Clone)]
+struct WorldClock {
+    label: String,
+    zone: Zone,
+}
+
+impl WorldClock {
+    fn from_config(config: &WorldClockConfig) -> Option<Self> {
+        let tz = timezone::parse_zone(&config.timezone)?;
+        let label = config.label.clone().unwrap_or_else(|| {
+            let city = config.timezone.rsplit('/').next().unwrap_or(&config.timezone);
+            city.replace('_', " ")
+        });
+
+        Some(Self {
+            label,
+            zone: Zone::Named(tz),
+        })
+    }
+
+    /// The time there, with how far ahead or behind `local` it is.
+    fn describe(&self, now: DateTime<Utc>, local: Zone) -> (String, String) {
+        let time = self.zone.format(now, "%H:%M");
 
-pub struct Clock {}
+        let difference = self.zone.offset_seconds(now) - local.offset_seconds(now);
+        let mut relative = if difference == 0 {
+            "same time".to_string()
+        } else {
+            let sign = if difference < 0 { '-' } else { '+' };
+            let hours = difference.abs() / 3600;
+            let minutes = difference.abs() % 3600 / 60;
+            if minutes == 0 {
+                format!("{}{}h", sign, hours)
+            } else {
+                format!("{}{}h{:02}", sign, hours, minutes)
+            }
+        };
+
+        let there = self.zone.date_naive(now);
+        let here = local.date_naive(now);
+        if there > here {
+            relative.push_str(", tomorrow");
+        } else if there < here {
+            relative.push_str(", yesterday");
+        }
+
+        (time, relative)
+    }
+}
+
+pub struct Clock {
+    time_format: String,
+    date_format: String,
+    show_seconds: bool,
+    show_week_numbers: bool,
+    calendars: Vec<PathBuf>,
+    agenda_days: u32,
+    world_clocks: Vec<WorldClock>,
+}
 
 impl Clock {
-    pub fn new() -> Self {
-        Self {}
+    pub fn new(config: ClockConfig) -> Self {
+        let calendars = config.calendar_paths();
+        let show_seconds = config.show_seconds || config.format_time.contains("%S");
+        let time_format = if config.show_seconds && !config.format_time.contains("%S") {
+            format!("{}:%S", config.format_time)
+        } else {
+            config.format_time
+        };
+        let world_clocks = config
+            .world_clocks
+            .iter()
+            .filter_map(WorldClock::from_config)
+            .collect();
+
+        Self {
+            time_format,
+            date_format: config.format_date,
+            show_seconds,
+            show_week_numbers: config.show_week_numbers,
+            calendars,
+            agenda_days: config.agenda_days,
+            world_clocks,
+        }
     }
-    
+
     pub fn build_widget(&self) -> Button {
         let content = GtkBox::builder()
             .orientation(Orientation::Vertical)
             .spacing(0)
             .valign(gtk4::Align::Center)
             .build();
-        
+
         let time_label = Label::builder().build();
         time_label.add_css_class("clock-time");
         content.append(&time_label);
-        
+
         let date_label = Label::builder().build();
         date_label.add_css_class("clock-date");
         content.append(&date_label);
-        
-        // Initial update
-        let now = Local::now();
-        time_label.set_label(&now.format("%H:%M").to_string());
-        date_label.set_label(&now.format("%a, %b %d").to_string());
-        
+
         let button = Button::builder()
             .child(&content)
             .build();
         button.add_css_class("clock-button");
-        
-        // Update every second
-        let time_label_clone = time_label.clone();
-        let date_label_clone = date_label.clone();
-        
-        glib::timeout_add_seconds_local(1, move || {
-            let now = Local::now();
-            time_label_clone.set_label(&now.format("%H:%M").to_string());
-            date_label_clone.set_label(&now.format("%a, %b %d").to_string());
-            glib::ControlFlow::Continue
+
+        let popup = CalendarPopup::new(self);
+        popup.popover.set_parent(&button);
+
+        let popover = popup.popover.clone();
+        button.connect_destroy(move |_| {
+            popover.unparent();
+        });
+
+        let ticker = Rc::new(ClockTicker {
+            button: button.downgrade(),
+            time_label: time_label.downgrade(),
+            date_label: date_label.downgrade(),
+            popup,
+            system_zone: RefCell::new(SystemZone::new()),
+            time_format: self.time_format.clone(),
+            date_format: self.date_format.clone(),
+            show_seconds: self.show_seconds,
         });
-        
+
+        let ticker_clone = ticker.clone();
+        button.connect_clicked(move |_| {
+            let zone = ticker_clone.system_zone.borrow().zone();
+            ticker_clone.popup.show(Utc::now(), zone);
+        });
+
+        ticker.tick();
+        ticker.schedule();
+
         button
     }
 }
 
+/// Updates the clock on the second, or on the minute when seconds are not
+/// shown, picking up system timezone changes as it goes.
+struct ClockTicker {
+    button: glib::WeakRef<Button>,
+    time_label: glib::WeakRef<Label>,
+    date_label: glib::WeakRef<Label>,
+    popup: Rc<CalendarPopup>,
+    system_zone: RefCell<SystemZone>,
+    time_format: String,
+    date_format: String,
+    show_seconds: bool,
+}
+
+impl ClockTicker {
+    /// Returns `false` once the widget is gone.
+    fn tick(&self) -> bool {
+        let (Some(button), Some(time_label), Some(date_label)) =
+            (self.button.upgrade(), self.time_label.upgrade(), self.date_label.upgrade())
+        else {
+            return false;
+        };
+
+        self.system_zone.borrow_mut().refresh();
+        let zone = self.system_zone.borrow().zone();
+        let now = Utc::now();
+
+        time_label.set_label(&zone.format(now, &self.time_format));
+        date_label.set_label(&zone.format(now, &self.date_format));
+
+        let world_clocks = &self.popup.world_clocks;
+        if world_clocks.is_empty() {
+            button.set_tooltip_text(None);
+        } else {
+            let lines: Vec<String> = world_clocks
+                .iter()
+                .map(|clock| {
+                    let (time, relative) = clock.describe(now, zone);
+                    format!("{}  {} ({})", clock.label, time, relative)
+                })
+                .collect();
+            button.set_tooltip_text(Some(&lines.join("\n")));
+        }
+
+        if self.popup.popover.is_visible() {
+            self.popup.update_time(now, zone);
+        }
+        true
+    }
+
+    /// Re-arms a one-shot timer for the next boundary rather than using a
+    /// fixed interval, so the display never lags by up to a period and
+    /// catches up immediately after suspend.
+    fn schedule(self: Rc<Self>) {
+        let delay = next_tick(Utc::now(), self.show_seconds);
+        glib::timeout_add_local_once(delay, move || {
+            if self.tick() {
+                self.schedule();
+            }
+        });
+    }
+}
+
+fn next_tick(now: DateTime<Utc>, show_seconds: bool) -> std::time::Duration {
+    let millis = now.timestamp_subsec_millis() as u64;
+    let remaining = if show_seconds {
+        1000 - millis
+    } else {
+        (60 - now.second() as u64) * 1000 - millis
+    };
+    // Land just after the boundary, not just before it.
+    std::time::Duration::from_millis(remaining + 5)
+}
+
+/// Month calendar with an agenda of upcoming events. Calendars are re-read
+/// every time the popover opens, so edits show up without a restart.
+struct CalendarPopup {
+    popover: Popover,
+    time_label: Label,
+    date_label: Label,
+    calendar: Calendar,
+    events_header: Label,
+    events_list: GtkBox,
+    world_list: GtkBox,
+    events: RefCell<Vec<Event>>,
+    calendars: Vec<PathBuf>,
+    agenda_days: u32,
+    time_format: String,
+    world_clocks: Vec<WorldClock>,
+}
+
+impl CalendarPopup {
+    fn new(clock: &Clock) -> Rc<Self> {
+        let content = GtkBox::builder()
+            .orientation(Orientation::Vertical)
+            .spacing(8)
+            .margin_top(12)
+            .margin_bottom(12)
+            .margin_start(12)
+            .margin_end(12)
+            .build();
+
+        let time_label = Label::builder().xalign(0.0).build();
+        time_label.add_css_class("calendar-big-time");
+        content.append(&time_label);
+
+        let date_label = Label::builder().xalign(0.0).build();
+        date_label.add_css_class("calendar-header");
+        content.append(&date_label);
+
+        let calendar = Calendar::builder()
+            .show_week_numbers(clock.show_week_numbers)
+            .build();
+        calendar.add_css_class("calendar-widget");
+        content.append(&calendar);
+
+        let events_section = GtkBox::builder()
+            .orientation(Orientation::Vertical)
+            .spacing(6)
+            .build();
+        events_section.add_css_class("events-section");
+
+        let events_header = Label::builder().xalign(0.0).build();
+        events_header.add_css_class("events-header");
+        events_section.append(&events_header);
+
+        let events_list = GtkBox::builder()
+            .orientation(Orientation::Vertical)
+            .spacing(4)
+            .build();
+        events_section.append(&events_list);
+        content.append(&events_section);
+
+        let world_section = GtkBox::builder()
+            .orientation(Orientation::Vertical)
+            .spacing(6)
+            .visible(!clock.world_clocks.is_empty())
+            .build();
+        world_section.add_css_class("events-section");
+
+        let world_header = Label::builder().label("World clocks").xalign(0.0).build();
+        world_header.add_css_class("events-header");
+        world_section.append(&world_header);
+
+        let world_list = GtkBox::builder()
+            .orientation(Orientation::Vertical)
+            .spacing(4)
+            .build();
+        world_section.append(&world_list);
+        content.append(&world_section);
+
+        let popover = Popover::builder()
+            .child(&content)
+            .has_arrow(false)
+            .build();
+        popover.add_css_class("calendar-popover");
+
+        let popup = Rc::new(Self {
+            popover,
+            time_label,
+            date_label,
+            calendar,
+            events_header,
+            events_list,
+            world_list,
+            events: RefCell::new(Vec::new()),
+            calendars: clock.calendars.clone(),
+            agenda_days: clock.agenda_days,
+            time_format: clock.time_format.clone(),
+            world_clocks: clock.world_clocks.clone(),
+        });
+
+        let weak = Rc::downgrade(&popup);
+        popup.calendar.connect_day_selected(move |_| {
+            if let Some(popup) = weak.upgrade() {
+                popup.mark_month();
+                if let Some(date) = popup.selected_date() {
+                    popup.show_day(date);
+                }
+            }
+        });
+
+        // Paging through months keeps the marks in step with what is shown.
+        let weak = Rc::downgrade(&popup);
+        let remark = move |_: &Calendar| {
+            if let Some(popup) = weak.upgrade() {
+                popup.mark_month();
+            }
+        };
+        popup.calendar.connect_next_month(remark.clone());
+        popup.calendar.connect_prev_month(remark.clone());
+        popup.calendar.connect_next_year(remark.clone());
+        popup.calendar.connect_prev_year(remark);
+
+        popup
+    }
+
+    fn show(&self, now: DateTime<Utc>, zone: Zone) {
+        *self.events.borrow_mut() = calendar::load_events(&self.calendars);
+
+        if let Ok(today) = glib::DateTime::now_local() {
+            self.calendar.select_day(&today);
+        }
+        self.update_time(now, zone);
+        self.mark_month();
+        self.show_agenda();
+        self.popover.popup();
+    }
+
+    fn update_time(&self, now: DateTime<Utc>, zone: Zone) {
+        self.time_label.set_label(&zone.format(now, &self.time_format));
+        self.date_label.set_label(&zone.format(now, "%A, %B %-d, %Y"));
+
+        while let Some(child) = self.world_list.first_child() {
+            self.world_list.remove(&child);
+        }
+        for clock in &self.world_clocks {
+            self.world_list.append(&world_clock_row(clock, now, zone));
+        }
+    }
+
+    fn selected_date(&self) -> Option<NaiveDate> {
+        let date = self.calendar.date();
+        NaiveDate::from_ymd_opt(date.year(), date.month() as u32, date.day_of_month() as u32)
+    }
+
+    /// Marks the days of the displayed month that have events.
+    fn mark_month(&self) {
+        self.calendar.clear_marks();
+
+        let Some(first) = self.selected_date().and_then(|d| d.with_day(1)) else {
+            return;
+        };
+        let next_month = first.checked_add_months(chrono::Months::new(1)).unwrap_or(first);
+        let from = first.and_hms_opt(0, 0, 0).unwrap_or_default();
+        let to = next_month.and_hms_opt(0, 0, 0).unwrap_or_default();
+
+        let events = self.events.borrow();
+        for occurrence in calendar::agenda(&events, from, to) {
+            let last = occurrence.start + occurrence.event.duration() - Duration::seconds(1);
+
+            let mut day = occurrence.start.date().max(first);
+            while day < next_month && day <= last.date().max(occurrence.start.date()) {
+                self.calendar.mark_day(day.day());
+                day += Duration::days(1);
+            }
+        }
+    }
+
+    fn show_agenda(&self) {
+        let now = Local::now().naive_local();
+        let today = now.date().and_hms_opt(0, 0, 0).unwrap_or(now);
+        let to = today + Duration::days(self.agenda_days.max(1) as i64);
+
+        let events = self.events.borrow();
+        let upcoming: Vec<Occurrence> = calendar::agenda(&events, now, to);
+        self.fill_events("Upcoming events", &upcoming, true);
+    }
+
+    fn show_day(&self, date: NaiveDate) {
+        let Some(from) = date.and_hms_opt(0, 0, 0) else {
+            return;
+        };
+
+        let events = self.events.borrow();
+        let occurrences = calendar::agenda(&events, from, from + Duration::days(1));
+        let header = format!("Events on {}", date.format("%A, %B %-d"));
+        self.fill_events(&header, &occurrences, false);
+    }
+
+    fn fill_events(&self, header: &str, occurrences: &[Occurrence], show_date: bool) {
+        self.events_header.set_label(header);
+
+        while let Some(child) = self.events_list.first_child() {
+            self.events_list.remove(&child);
+        }
+
+        if occurrences.is_empty() {
+            let empty = Label::builder().label("No events").xalign(0.0).build();
+            empty.add_css_class("no-events");
+            self.events_list.append(&empty);
+            return;
+        }
+
+        for occurrence in occurrences {
+            self.events_list.append(&event_row(occurrence, show_date));
+        }
+    }
+}
+
+fn world_clock_row(clock: &WorldClock, now: DateTime<Utc>, local: Zone) -> GtkBox {
+    let row = GtkBox::builder()
+        .orientation(Orientation::Horizontal)
+        .spacing(8)
+        .build();
+    row.add_css_class("world-clock-row");
+
+    let (time, relative) = clock.describe(now, local);
+
+    let time_label = Label::builder().label(&time).xalign(0.0).build();
+    time_label.add_css_class("event-time");
+    row.append(&time_label);
+
+    let name = Label::builder()
+        .label(&clock.label)
+        .xalign(0.0)
+        .hexpand(true)
+        .build();
+    name.add_css_class("event-summary");
+    row.append(&name);
+
+    let relative_label = Label::builder().label(&relative).xalign(1.0).build();
+    relative_label.add_css_class("world-clock-offset");
+    row.append(&relative_label);
+
+    row
+}
+
+fn event_row(occurrence: &Occurrence, show_date: bool) -> GtkBox {
+    let row = GtkBox::builder()
+        .orientation(Orientation::Horizontal)
+        .spacing(8)
+        .build();
+    row.add_css_class("event-row");
+
+    let time = match (occurrence.event.all_day, show_date) {
+        (true, true) => occurrence.start.format("%a %-d · All day").to_string(),
+        (true, false) => "All day".to_string(),
+        (false, true) => occurrence.start.format("%a %-d · %H:%M").to_string(),
+        (false, false) => occurrence.start.format("%H:%M").to_string(),
+    };
+    let time_label = Label::builder().label(&time).xalign(0.0).build();
+    time_label.add_css_class("event-time");
+    row.append(&time_label);
+
+    let summary = Label::builder()
+        .label(&occurrence.event.summary)
+        .xalign(0.0)
+        .hexpand(true)
+        .ellipsize(pango::EllipsizeMode::End)
+        .max_width_chars(28)
+        .build();
+    summary.add_css_class("event-summary");
+    row.append(&summary);
+
+    if let Some(location) = &occurrence.event.location {
+        row.set_tooltip_text(Some(location));
+    }
+
+    row
+}
+
+impl PanelModule for Clock {
+    fn name(&self) -> &str {
+        "clock"
+    }
+
+    fn build(&self, _ctx: &ModuleContext) -> gtk4::Widget {
+        self.build_widget().upcast()
+    }
+}
+
 impl Default for Clock {
     fn default() -> Self {
-        Self::new()
+        Self::new(ClockConfig::default())
     }
 }
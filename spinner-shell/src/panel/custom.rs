@@ -0,0 +1,346 @@
+//! Custom script module - renders the output of a user command
+//!
+//! Configured as `[custom.<name>]` and placed with `custom/<name>`. The
+//! command either runs every `interval` seconds, or runs continuously and
+//! prints one state per line. Text output is waybar-style: the first line
+//! is the label, the second the tooltip and the third CSS classes.
+
+use super::{ModuleContext, PanelModule};
+use crate::config::{CustomModuleConfig, ReturnType};
+
+use anyhow::{bail, Context, Result};
+use gtk4::prelude::*;
+use gtk4::{self, glib, Box as GtkBox, Button, Image, Label, Orientation};
+use serde::{Deserialize, Deserializer};
+use std::cell::RefCell;
+use std::io::{BufRead, BufReader, Read};
+use std::os::unix::process::CommandExt;
+use std::process::{Child, Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{info, warn};
+
+const RESTART_DELAY: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct ModuleOutput {
+    pub text: String,
+    pub tooltip: Option<String>,
+    #[serde(deserialize_with = "one_or_many")]
+    pub class: Vec<String>,
+    pub icon: Option<String>,
+}
+
+impl ModuleOutput {
+    pub fn parse(output: &str, return_type: ReturnType) -> Result<Self> {
+        match return_type {
+            ReturnType::Json => {
+                serde_json::from_str(output.trim()).context("Invalid JSON from custom module")
+            }
+            ReturnType::Text => {
+                let mut lines = output.lines();
+                Ok(Self {
+                    text: lines.next().unwrap_or_default().trim().to_string(),
+                    tooltip: lines.next().map(str::trim).filter(|t| !t.is_empty()).map(String::from),
+                    class: lines
+                        .next()
+                        .map(|c| c.split_whitespace().map(String::from).collect())
+                        .unwrap_or_default(),
+                    icon: None,
+                })
+            }
+        }
+    }
+}
+
+/// `class` may be a single string or a list, as in waybar.
+fn one_or_many<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(class) => class.split_whitespace().map(String::from).collect(),
+        OneOrMany::Many(classes) => classes,
+    })
+}
+
+pub struct CustomModule {
+    name: String,
+    config: CustomModuleConfig,
+}
+
+impl CustomModule {
+    pub fn new(name: &str, config: CustomModuleConfig) -> Self {
+        Self {
+            name: name.to_string(),
+            config,
+        }
+    }
+
+    fn spawn_worker(&self) -> Worker {
+        let (sender, receiver) = async_channel::unbounded();
+        let running = Running::default();
+        let name = self.name.clone();
+        let config = self.config.clone();
+
+        let worker_running = running.clone();
+        std::thread::spawn(move || match config.interval {
+            Some(secs) => run_interval(&name, &config, secs, &sender),
+            None => run_stream(&name, &config, &sender, &worker_running),
+        });
+
+        Worker { receiver, running }
+    }
+}
+
+/// The command a streaming worker is reading from.
+type Running = Arc<Mutex<Option<Child>>>;
+
+struct Worker {
+    receiver: async_channel::Receiver<ModuleOutput>,
+    running: Running,
+}
+
+impl Worker {
+    /// Closes the channel and kills the streaming command, so the worker
+    /// stops without waiting for its next output.
+    fn stop(&self) {
+        self.receiver.close();
+        if let Some(child) = self.running.lock().unwrap().as_ref() {
+            kill_group(child);
+        }
+    }
+}
+
+/// Kills a command started in its own process group together with
+/// anything it started, which may be holding its stdout open.
+fn kill_group(child: &Child) {
+    // SAFETY: kill only sends a signal; the group can't have been reused
+    // because the child hasn't been waited for.
+    unsafe {
+        libc::kill(-(child.id() as libc::pid_t), libc::SIGTERM);
+    }
+}
+
+struct CustomWidgets {
+    /// Weak, so that the button can be destroyed while the worker runs.
+    button: glib::WeakRef<Button>,
+    icon: Image,
+    label: Label,
+    /// Classes set by the last output, removed before the next is applied.
+    classes: RefCell<Vec<String>>,
+    fallback_icon: Option<String>,
+}
+
+impl CustomWidgets {
+    fn apply(&self, output: &ModuleOutput) {
+        let Some(button) = self.button.upgrade() else {
+            return;
+        };
+        let icon = output.icon.as_ref().or(self.fallback_icon.as_ref());
+
+        self.label.set_label(&output.text);
+        self.label.set_visible(!output.text.is_empty());
+
+        if let Some(icon) = icon {
+            self.icon.set_icon_name(Some(icon));
+        }
+        self.icon.set_visible(icon.is_some());
+
+        button.set_tooltip_text(output.tooltip.as_deref());
+        button.set_visible(!output.text.is_empty() || icon.is_some());
+
+        let mut classes = self.classes.borrow_mut();
+        for class in classes.drain(..) {
+            button.remove_css_class(&class);
+        }
+        for class in &output.class {
+            button.add_css_class(class);
+            classes.push(class.clone());
+        }
+    }
+}
+
+impl PanelModule for CustomModule {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn build(&self, ctx: &ModuleContext) -> gtk4::Widget {
+        let content = GtkBox::builder()
+            .orientation(Orientation::Horizontal)
+            .spacing(6)
+            .build();
+
+        let icon = Image::builder().pixel_size(16).visible(false).build();
+        content.append(&icon);
+
+        let label = Label::builder().visible(false).build();
+        if ctx.orientation == Orientation::Vertical {
+            label.set_ellipsize(pango::EllipsizeMode::End);
+            label.set_max_width_chars(4);
+        }
+        content.append(&label);
+
+        let button = Button::builder()
+            .child(&content)
+            .visible(false)
+            .build();
+        button.add_css_class("panel-button");
+        button.add_css_class("custom-module");
+        button.add_css_class(&format!("custom-{}", self.name));
+
+        if let Some(command) = self.config.on_click.clone() {
+            let name = self.name.clone();
+            button.connect_clicked(move |_| {
+                info!("Custom module {} clicked", name);
+                match Command::new("sh").arg("-c").arg(&command).spawn() {
+                    Ok(mut child) => {
+                        std::thread::spawn(move || {
+                            let _ = child.wait();
+                        });
+                    }
+                    Err(e) => warn!("Failed to run on_click for {}: {}", name, e),
+                }
+            });
+        }
+
+        let widgets = CustomWidgets {
+            button: button.downgrade(),
+            icon,
+            label,
+            classes: RefCell::new(Vec::new()),
+            fallback_icon: self.config.icon.clone(),
+        };
+
+        // Destroying the button stops the worker, which ends this loop.
+        let worker = self.spawn_worker();
+        let receiver = worker.receiver.clone();
+        button.connect_destroy(move |_| worker.stop());
+        glib::MainContext::default().spawn_local(async move {
+            while let Ok(output) = receiver.recv().await {
+                widgets.apply(&output);
+            }
+        });
+
+        button.upcast()
+    }
+}
+
+fn run_command(exec: &str) -> Result<String> {
+    let output = Command::new("sh").arg("-c").arg(exec).output()?;
+    if !output.status.success() {
+        bail!("exited with {}", output.status);
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+fn run_interval(
+    name: &str,
+    config: &CustomModuleConfig,
+    secs: u64,
+    sender: &async_channel::Sender<ModuleOutput>,
+) {
+    while !sender.is_closed() {
+        let result = run_command(&config.exec)
+            .and_then(|stdout| ModuleOutput::parse(&stdout, config.return_type));
+
+        match result {
+            Ok(output) => {
+                if sender.send_blocking(output).is_err() {
+                    return;
+                }
+            }
+            Err(e) => warn!("Custom module {} failed: {:#}", name, e),
+        }
+
+        std::thread::sleep(Duration::from_secs(secs.max(1)));
+    }
+}
+
+fn run_stream(
+    name: &str,
+    config: &CustomModuleConfig,
+    sender: &async_channel::Sender<ModuleOutput>,
+    running: &Running,
+) {
+    loop {
+        match stream_command(name, config, sender, running) {
+            Ok(()) => return,
+            Err(e) => warn!("Custom module {} stopped: {:#}, restarting", name, e),
+        }
+        std::thread::sleep(RESTART_DELAY);
+    }
+}
+
+/// Returns `Ok` only when the widget has gone away.
+fn stream_command(
+    name: &str,
+    config: &CustomModuleConfig,
+    sender: &async_channel::Sender<ModuleOutput>,
+    running: &Running,
+) -> Result<()> {
+    if sender.is_closed() {
+        return Ok(());
+    }
+
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(&config.exec)
+        .stdout(Stdio::piped())
+        .process_group(0)
+        .spawn()
+        .with_context(|| format!("Failed to run {:?}", config.exec))?;
+    let stdout = child.stdout.take().context("No stdout from custom module")?;
+    *running.lock().unwrap() = Some(child);
+
+    // `Worker::stop` may have closed the channel before the child was
+    // stored; it kills any child it sees afterwards.
+    let result = if sender.is_closed() {
+        Ok(())
+    } else {
+        read_outputs(name, config, stdout, sender)
+    };
+
+    let mut child = running.lock().unwrap().take().context("Custom module lost its command")?;
+    if sender.is_closed() || result.is_err() {
+        kill_group(&child);
+    }
+    let status = child.wait()?;
+    if sender.is_closed() {
+        return Ok(());
+    }
+
+    result?;
+    bail!("command exited with {}", status)
+}
+
+/// Sends each output line until the command or the channel closes.
+fn read_outputs(
+    name: &str,
+    config: &CustomModuleConfig,
+    stdout: impl Read,
+    sender: &async_channel::Sender<ModuleOutput>,
+) -> Result<()> {
+    for line in BufReader::new(stdout).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        match ModuleOutput::parse(&line, config.return_type) {
+            Ok(output) => {
+                if sender.send_blocking(output).is_err() {
+                    break;
+                }
+            }
+            Err(e) => warn!("Custom module {}: {:#}", name, e),
+        }
+    }
+    Ok(())
+}
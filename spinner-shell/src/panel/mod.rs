@@ -1,175 +1,305 @@
-//! Panel module - Top bar with taskbar, system tray, and clock
+//! Panel module - Docked bar assembled from configured modules
 
 mod taskbar;
 mod systray;
 mod clock;
+mod modules;
+mod custom;
+mod calendar;
+mod timezone;
+mod popover;
+mod audio;
+mod battery;
+mod bluetooth;
+mod network;
+mod power;
+mod quick_settings;
+mod tray;
 
 pub use taskbar::Taskbar;
 pub use systray::SystemTray;
 pub use clock::Clock;
+pub use modules::{ModuleContext, ModuleFactory, ModuleRegistry, PanelModule};
+pub use quick_settings::toggle as toggle_quick_settings;
+
+use crate::config::{PanelPosition, ShellConfig};
+use crate::notifications::NotificationCenter;
+use crate::theme::ThemeManager;
 
 use gtk4::prelude::*;
-use gtk4::{self, Align, Box as GtkBox, Button, Orientation};
+use gtk4::{self, gdk, gio, Align, Box as GtkBox, Orientation};
+use gtk4_layer_shell::{Edge, Layer, LayerShell};
 use libadwaita as adw;
-use tracing::info;
-
-const PANEL_HEIGHT: i32 = 48;
+use std::cell::RefCell;
+use std::rc::Rc;
+use tracing::{debug, info, warn};
 
 pub struct Panel {
-    taskbar: Taskbar,
-    systray: SystemTray,
-    clock: Clock,
+    config: ShellConfig,
+    registry: ModuleRegistry,
+    notifications: Option<Rc<RefCell<NotificationCenter>>>,
+    theme: Rc<RefCell<ThemeManager>>,
 }
 
 impl Panel {
-    pub fn new() -> Self {
+    pub fn new(
+        config: ShellConfig,
+        notifications: Option<Rc<RefCell<NotificationCenter>>>,
+        theme: Rc<RefCell<ThemeManager>>,
+    ) -> Self {
         Self {
-            taskbar: Taskbar::new(),
-            systray: SystemTray::new(),
-            clock: Clock::new(),
+            config,
+            registry: ModuleRegistry::with_builtins(),
+            notifications,
+            theme,
         }
     }
     
-    pub fn create_window(&self, app: &adw::Application) -> gtk4::ApplicationWindow {
+    pub fn create_window(&self, app: &adw::Application, monitor: &gdk::Monitor) -> gtk4::ApplicationWindow {
         let window = gtk4::ApplicationWindow::builder()
             .application(app)
             .decorated(false)
             .resizable(false)
-            .default_height(PANEL_HEIGHT)
             .build();
         
         window.add_css_class("panel-window");
+        self.setup_layer_shell(&window, monitor);
+        
+        let panel = &self.config.panel;
+        let orientation = if panel.position.is_vertical() {
+            Orientation::Vertical
+        } else {
+            Orientation::Horizontal
+        };
         
         let main_box = GtkBox::builder()
-            .orientation(Orientation::Horizontal)
+            .orientation(orientation)
             .spacing(0)
             .hexpand(true)
+            .vexpand(true)
             .build();
         main_box.add_css_class("panel");
+        main_box.add_css_class(position_class(panel.position));
         
-        let left_section = self.build_left_section();
-        let center_section = self.build_center_section();
-        let right_section = self.build_right_section();
-        
-        main_box.append(&left_section);
-        main_box.append(&center_section);
-        main_box.append(&right_section);
+        let sections = [
+            ("left", &panel.left),
+            ("center", &panel.center),
+            ("right", &panel.right),
+        ];
+        for (name, section) in sections {
+            let widget = self.build_section(name, &section.modules, orientation, monitor);
+            main_box.append(&widget);
+        }
         
         window.set_child(Some(&main_box));
         
-        info!("Panel window created");
+        info!("Panel window created at {:?} on {}", panel.position, connector_name(monitor));
         window
     }
     
-    fn build_left_section(&self) -> GtkBox {
-        let section = GtkBox::builder()
-            .orientation(Orientation::Horizontal)
-            .spacing(8)
-            .margin_start(12)
-            .halign(Align::Start)
-            .build();
-        section.add_css_class("panel-section");
-        section.add_css_class("panel-left");
-        
-        let menu_button = Button::builder()
-            .icon_name("view-app-grid-symbolic")
-            .tooltip_text("Applications")
-            .build();
-        menu_button.add_css_class("panel-button");
-        menu_button.add_css_class("app-menu-button");
+    /// Docks the panel as a layer surface on the configured edge. The
+    /// exclusive zone follows the panel's size, so the compositor keeps
+    /// windows out of it.
+    fn setup_layer_shell(&self, window: &gtk4::ApplicationWindow, monitor: &gdk::Monitor) {
+        window.init_layer_shell();
+        window.set_monitor(monitor);
+        window.set_layer(Layer::Top);
+        window.set_namespace("spinner-panel");
+        window.auto_exclusive_zone_enable();
         
-        menu_button.connect_clicked(|_| {
-            info!("App menu clicked");
-        });
+        let (edge, stretch) = match self.config.panel.position {
+            PanelPosition::Top => (Edge::Top, [Edge::Left, Edge::Right]),
+            PanelPosition::Bottom => (Edge::Bottom, [Edge::Left, Edge::Right]),
+            PanelPosition::Left => (Edge::Left, [Edge::Top, Edge::Bottom]),
+            PanelPosition::Right => (Edge::Right, [Edge::Top, Edge::Bottom]),
+        };
         
-        section.append(&menu_button);
-        
-        let taskbar_widget = self.taskbar.build_widget();
-        section.append(&taskbar_widget);
+        window.set_anchor(edge, true);
+        for edge in stretch {
+            window.set_anchor(edge, true);
+        }
         
-        section
+        match self.config.panel.position {
+            PanelPosition::Top | PanelPosition::Bottom => {
+                window.set_default_size(-1, self.config.panel.height);
+            }
+            PanelPosition::Left | PanelPosition::Right => {
+                window.set_default_size(self.config.panel.height, -1);
+            }
+        }
     }
     
-    fn build_center_section(&self) -> GtkBox {
+    /// Builds one section from its configured module list. Names that are
+    /// not registered are reported and skipped rather than failing the panel.
+    fn build_section(
+        &self,
+        name: &str,
+        modules: &[String],
+        orientation: Orientation,
+        monitor: &gdk::Monitor,
+    ) -> GtkBox {
+        let vertical = orientation == Orientation::Vertical;
+        let align = match name {
+            "left" => Align::Start,
+            "right" => Align::End,
+            _ => Align::Center,
+        };
+        
         let section = GtkBox::builder()
-            .orientation(Orientation::Horizontal)
+            .orientation(orientation)
             .spacing(8)
-            .halign(Align::Center)
-            .hexpand(true)
             .build();
         section.add_css_class("panel-section");
-        section.add_css_class("panel-center");
+        section.add_css_class(&format!("panel-{}", name));
+        
+        if vertical {
+            section.set_valign(align);
+            section.set_vexpand(name == "center");
+        } else {
+            section.set_halign(align);
+            section.set_hexpand(name == "center");
+        }
+        match (name, vertical) {
+            ("left", false) => section.set_margin_start(12),
+            ("left", true) => section.set_margin_top(12),
+            ("right", false) => section.set_margin_end(12),
+            ("right", true) => section.set_margin_bottom(12),
+            _ => {}
+        }
+        
+        let ctx = ModuleContext {
+            config: &self.config,
+            orientation,
+            monitor,
+            notifications: self.notifications.as_ref(),
+            theme: &self.theme,
+        };
         
-        let workspaces = self.build_workspace_indicators();
-        section.append(&workspaces);
+        for module_name in modules {
+            match self.registry.create(module_name, &self.config) {
+                Some(module) => {
+                    debug!("Adding module {} to [panel.{}]", module.name(), name);
+                    section.append(&module.build(&ctx));
+                }
+                None => warn!(
+                    "Unknown panel module {:?} in [panel.{}], skipping (available: {})",
+                    module_name,
+                    name,
+                    self.registry.names(&self.config).join(", ")
+                ),
+            }
+        }
         
         section
     }
     
-    fn build_right_section(&self) -> GtkBox {
-        let section = GtkBox::builder()
-            .orientation(Orientation::Horizontal)
-            .spacing(8)
-            .margin_end(12)
-            .halign(Align::End)
-            .build();
-        section.add_css_class("panel-section");
-        section.add_css_class("panel-right");
-        
-        let systray_widget = self.systray.build_widget();
-        section.append(&systray_widget);
+    /// Applies `background_opacity` on top of the theme's panel background.
+    fn apply_background_opacity(&self, display: &gdk::Display) {        
+        let opacity = self.config.panel.background_opacity.clamp(0.0, 1.0);
+        let provider = gtk4::CssProvider::new();
+        provider.load_from_data(&format!(
+            ".panel {{ background: linear-gradient(180deg, alpha(@spinner_bg_dark, {:.2}) 0%, alpha(@spinner_bg_darker, {:.2}) 100%); }}",
+            opacity,
+            opacity * 0.95,
+        ));
         
-        let clock_widget = self.clock.build_widget();
-        section.append(&clock_widget);
-        
-        let power_button = Button::builder()
-            .icon_name("system-shutdown-symbolic")
-            .tooltip_text("Power")
-            .build();
-        power_button.add_css_class("panel-button");
-        power_button.add_css_class("power-button");
+        gtk4::style_context_add_provider_for_display(
+            display,
+            &provider,
+            gtk4::STYLE_PROVIDER_PRIORITY_APPLICATION + 1,
+        );
+    }
+}
+
+impl Default for Panel {
+    fn default() -> Self {
+        Self::new(ShellConfig::default(), None, Rc::default())
+    }
+}
+
+/// Keeps a panel on every configured monitor, adding and removing panels
+/// as monitors are plugged in and out.
+pub struct Panels {
+    app: adw::Application,
+    panel: Panel,
+    windows: RefCell<Vec<(gdk::Monitor, gtk4::ApplicationWindow)>>,
+}
+
+impl Panels {
+    pub fn start(
+        app: &adw::Application,
+        config: ShellConfig,
+        notifications: Option<Rc<RefCell<NotificationCenter>>>,
+        theme: Rc<RefCell<ThemeManager>>,
+    ) {
+        let Some(display) = gdk::Display::default() else {
+            warn!("No display, not creating panels");
+            return;
+        };
         
-        power_button.connect_clicked(|_| {
-            info!("Power button clicked");
+        let panels = Rc::new(Self {
+            app: app.clone(),
+            panel: Panel::new(config, notifications, theme),
+            windows: RefCell::new(Vec::new()),
         });
+        panels.panel.apply_background_opacity(&display);
         
-        section.append(&power_button);
+        let monitors = display.monitors();
+        panels.sync(&monitors);
         
-        section
+        // The handler owns the panels for as long as the display exists.
+        monitors.connect_items_changed(move |monitors, _, _, _| {
+            panels.sync(monitors);
+        });
     }
     
-    fn build_workspace_indicators(&self) -> GtkBox {
-        let container = GtkBox::builder()
-            .orientation(Orientation::Horizontal)
-            .spacing(4)
-            .build();
-        container.add_css_class("workspace-indicators");
-        
-        for i in 1..=5 {
-            let indicator = Button::builder()
-                .label(&i.to_string())
-                .build();
-            indicator.add_css_class("workspace-indicator");
-            
-            if i == 1 {
-                indicator.add_css_class("active");
+    fn sync(&self, monitors: &gio::ListModel) {
+        let current: Vec<gdk::Monitor> = (0..monitors.n_items())
+            .filter_map(|i| monitors.item(i))
+            .filter_map(|item| item.downcast().ok())
+            .collect();
+        
+        let mut windows = self.windows.borrow_mut();
+        windows.retain(|(monitor, window)| {
+            let keep = current.contains(monitor) && self.wants_monitor(monitor);
+            if !keep {
+                info!("Removing panel from {}", connector_name(monitor));
+                window.destroy();
             }
-            
-            let workspace_num = i;
-            indicator.connect_clicked(move |btn| {
-                info!("Workspace {} clicked", workspace_num);
-                btn.add_css_class("active");
-            });
-            
-            container.append(&indicator);
-        }
+            keep
+        });
         
-        container
+        for monitor in current {
+            if !self.wants_monitor(&monitor) || windows.iter().any(|(m, _)| *m == monitor) {
+                continue;
+            }
+            let window = self.panel.create_window(&self.app, &monitor);
+            window.present();
+            windows.push((monitor, window));
+        }
+    }
+    
+    fn wants_monitor(&self, monitor: &gdk::Monitor) -> bool {
+        let outputs = &self.panel.config.panel.outputs;
+        outputs.is_empty()
+            || monitor
+                .connector()
+                .is_some_and(|connector| outputs.iter().any(|o| o == connector.as_str()))
     }
 }
 
-impl Default for Panel {
-    fn default() -> Self {
-        Self::new()
+fn connector_name(monitor: &gdk::Monitor) -> String {
+    monitor
+        .connector()
+        .map(|c| c.to_string())
+        .unwrap_or_else(|| "unknown output".to_string())
+}
+
+fn position_class(position: PanelPosition) -> &'static str {
+    match position {
+        PanelPosition::Top => "top",
+        PanelPosition::Bottom => "bottom",
+        PanelPosition::Left => "left",
+        PanelPosition::Right => "right",
     }
 }
@@ -0,0 +1,160 @@
+//! Panel modules - the `PanelModule` trait and the registry that maps names
+//! in `[panel.*] modules` to them
+
+use super::custom::CustomModule;
+use super::power::PowerMenu;
+use super::{Clock, SystemTray, Taskbar};
+use crate::config::ShellConfig;
+use crate::notifications::NotificationCenter;
+use crate::theme::ThemeManager;
+
+use gtk4::prelude::*;
+use gtk4::{self, gdk, Box as GtkBox, Button, Orientation};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+use tracing::{info, warn};
+
+/// Prefix for script modules configured under `[custom.<name>]`.
+pub const CUSTOM_PREFIX: &str = "custom/";
+
+/// What a module needs to know to build its widget.
+pub struct ModuleContext<'a> {
+    pub config: &'a ShellConfig,
+    /// The panel's main axis; vertical when docked to the left or right.
+    pub orientation: Orientation,
+    /// The monitor the panel is on.
+    pub monitor: &'a gdk::Monitor,
+    /// `None` when the shell isn't the notification daemon.
+    pub notifications: Option<&'a Rc<RefCell<NotificationCenter>>>,
+    pub theme: &'a Rc<RefCell<ThemeManager>>,
+}
+
+/// A widget that can be placed in a panel section.
+pub trait PanelModule {
+    /// The name used in `[panel.*] modules`.
+    fn name(&self) -> &str;
+
+    /// Builds a fresh widget. Called once per panel the module appears in,
+    /// so implementations must not assume a single instance.
+    fn build(&self, ctx: &ModuleContext) -> gtk4::Widget;
+}
+
+pub type ModuleFactory = fn(&ShellConfig) -> Box<dyn PanelModule>;
+
+pub struct ModuleRegistry {
+    factories: BTreeMap<&'static str, ModuleFactory>,
+}
+
+impl ModuleRegistry {
+    pub fn new() -> Self {
+        Self {
+            factories: BTreeMap::new(),
+        }
+    }
+
+    /// A registry with every module shipped in spinner-shell.
+    pub fn with_builtins() -> Self {
+        let mut registry = Self::new();
+        registry.register("app_menu", |_| Box::new(AppMenuButton));
+        registry.register("taskbar", |config| Box::new(Taskbar::new(config.panel.taskbar_per_output)));
+        registry.register("workspaces", |_| Box::new(WorkspaceIndicators));
+        registry.register("systray", |config| {
+            Box::new(SystemTray::new(config.systray.clone(), config.quick_settings.clone()))
+        });
+        registry.register("clock", |config| Box::new(Clock::new(config.clock.clone())));
+        registry.register("power", |config| Box::new(PowerMenu::new(config.power.clone())));
+        registry
+    }
+
+    pub fn register(&mut self, name: &'static str, factory: ModuleFactory) {
+        self.factories.insert(name, factory);
+    }
+
+    /// Instantiates the named module. `custom/<name>` resolves against the
+    /// `[custom.<name>]` tables in the config.
+    pub fn create(&self, name: &str, config: &ShellConfig) -> Option<Box<dyn PanelModule>> {
+        if let Some(custom) = name.strip_prefix(CUSTOM_PREFIX) {
+            let module_config = config.custom.get(custom)?;
+            return Some(Box::new(CustomModule::new(custom, module_config.clone())));
+        }
+        self.factories.get(name).map(|factory| factory(config))
+    }
+
+    /// Every name `create` accepts for `config`.
+    pub fn names(&self, config: &ShellConfig) -> Vec<String> {
+        self.factories
+            .keys()
+            .map(|name| name.to_string())
+            .chain(config.custom.keys().map(|name| format!("{}{}", CUSTOM_PREFIX, name)))
+            .collect()
+    }
+}
+
+impl Default for ModuleRegistry {
+    fn default() -> Self {
+        Self::with_builtins()
+    }
+}
+
+struct AppMenuButton;
+
+impl PanelModule for AppMenuButton {
+    fn name(&self) -> &str {
+        "app_menu"
+    }
+
+    fn build(&self, _ctx: &ModuleContext) -> gtk4::Widget {
+        let menu_button = Button::builder()
+            .icon_name("view-app-grid-symbolic")
+            .tooltip_text("Applications")
+            .build();
+        menu_button.add_css_class("panel-button");
+        menu_button.add_css_class("app-menu-button");
+
+        menu_button.connect_clicked(|button| {
+            if let Err(e) = button.activate_action("app.app-menu", None) {
+                warn!("App menu unavailable: {}", e);
+            }
+        });
+
+        menu_button.upcast()
+    }
+}
+
+struct WorkspaceIndicators;
+
+impl PanelModule for WorkspaceIndicators {
+    fn name(&self) -> &str {
+        "workspaces"
+    }
+
+    fn build(&self, ctx: &ModuleContext) -> gtk4::Widget {
+        let container = GtkBox::builder()
+            .orientation(ctx.orientation)
+            .spacing(4)
+            .build();
+        container.add_css_class("workspace-indicators");
+
+        for i in 1..=5 {
+            let indicator = Button::builder()
+                .label(&i.to_string())
+                .build();
+            indicator.add_css_class("workspace-indicator");
+
+            if i == 1 {
+                indicator.add_css_class("active");
+            }
+
+            let workspace_num = i;
+            indicator.connect_clicked(move |btn| {
+                info!("Workspace {} clicked", workspace_num);
+                btn.add_css_class("active");
+            });
+
+            container.append(&indicator);
+        }
+
+        container.upcast()
+    }
+}
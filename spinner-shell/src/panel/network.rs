@@ -0,0 +1,366 @@
+//! Network indicator - Connectivity from NetworkManager, with a popover
+//! for Wi-Fi networks, airplane mode and VPNs
+
+use super::popover::{clear, section};
+use crate::services::network::{self, AccessPoint, NetworkState};
+
+use anyhow::Result;
+use gtk4::prelude::*;
+use gtk4::{
+    self, glib, Align, Box as GtkBox, Button, Image, Label, Orientation, PasswordEntry, Popover,
+    ScrolledWindow, Switch,
+};
+use gtk4_layer_shell::{KeyboardMode, LayerShell};
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+pub struct NetworkIndicator {
+    button: Button,
+    status: Label,
+    error: Label,
+    wifi_row: GtkBox,
+    wifi_switch: Switch,
+    airplane_switch: Switch,
+    networks_section: GtkBox,
+    networks: GtkBox,
+    password_box: GtkBox,
+    password_label: Label,
+    password: PasswordEntry,
+    vpn_section: GtkBox,
+    vpns: GtkBox,
+    state: RefCell<NetworkState>,
+    /// The secured network the password prompt is for.
+    pending: RefCell<Option<AccessPoint>>,
+    /// Set while widgets are updated from NetworkManager, so their
+    /// handlers don't echo the change back.
+    updating: Cell<bool>,
+}
+
+impl NetworkIndicator {
+    pub fn build() -> Button {
+        let button = Button::builder()
+            .icon_name("network-offline-symbolic")
+            .tooltip_text("Offline")
+            .build();
+        button.add_css_class("systray-button");
+        button.add_css_class("network-indicator");
+
+        let content = GtkBox::builder()
+            .orientation(Orientation::Vertical)
+            .spacing(8)
+            .margin_top(12)
+            .margin_bottom(12)
+            .margin_start(12)
+            .margin_end(12)
+            .width_request(320)
+            .build();
+
+        let header = Label::builder().label("Network").xalign(0.0).build();
+        header.add_css_class("popover-header");
+        content.append(&header);
+
+        let status = Label::builder().xalign(0.0).build();
+        status.add_css_class("network-status");
+        content.append(&status);
+
+        let error = Label::builder().xalign(0.0).wrap(true).visible(false).build();
+        error.add_css_class("network-error");
+        content.append(&error);
+
+        let (wifi_row, wifi_switch) = switch_row("Wi-Fi");
+        content.append(&wifi_row);
+        let (airplane_row, airplane_switch) = switch_row("Airplane mode");
+        content.append(&airplane_row);
+
+        let (networks_section, networks) = section("Wi-Fi networks");
+        let scrolled = ScrolledWindow::builder()
+            .hscrollbar_policy(gtk4::PolicyType::Never)
+            .propagate_natural_height(true)
+            .max_content_height(280)
+            .build();
+        // Move the rows into the scrolled window, keeping the header.
+        networks_section.remove(&networks);
+        scrolled.set_child(Some(&networks));
+        networks_section.append(&scrolled);
+        content.append(&networks_section);
+
+        let password_box = GtkBox::builder()
+            .orientation(Orientation::Vertical)
+            .spacing(6)
+            .visible(false)
+            .build();
+        password_box.add_css_class("popover-section");
+        let password_label = Label::builder().xalign(0.0).build();
+        password_label.add_css_class("network-name");
+        password_box.append(&password_label);
+        let password = PasswordEntry::builder().show_peek_icon(true).build();
+        password_box.append(&password);
+        let password_buttons = GtkBox::builder()
+            .orientation(Orientation::Horizontal)
+            .spacing(8)
+            .halign(Align::End)
+            .build();
+        let cancel = Button::with_label("Cancel");
+        cancel.add_css_class("popover-button");
+        password_buttons.append(&cancel);
+        let connect = Button::with_label("Connect");
+        connect.add_css_class("popover-button");
+        connect.add_css_class("suggested-action");
+        password_buttons.append(&connect);
+        password_box.append(&password_buttons);
+        content.append(&password_box);
+
+        let (vpn_section, vpns) = section("VPN");
+        content.append(&vpn_section);
+
+        let popover = Popover::builder().child(&content).has_arrow(false).build();
+        popover.add_css_class("systray-popover");
+        popover.set_parent(&button);
+        let popover_clone = popover.clone();
+        button.connect_destroy(move |_| {
+            popover_clone.unparent();
+        });
+
+        let indicator = Rc::new(Self {
+            button: button.clone(),
+            status,
+            error,
+            wifi_row,
+            wifi_switch,
+            airplane_switch,
+            networks_section,
+            networks,
+            password_box,
+            password_label,
+            password,
+            vpn_section,
+            vpns,
+            state: RefCell::new(NetworkState::default()),
+            pending: RefCell::new(None),
+            updating: Cell::new(false),
+        });
+
+        let weak = Rc::downgrade(&indicator);
+        popover.connect_closed(move |_| {
+            if let Some(indicator) = weak.upgrade() {
+                indicator.close_prompt();
+            }
+        });
+
+        let weak = Rc::downgrade(&indicator);
+        button.connect_clicked(move |_| {
+            if let Some(indicator) = weak.upgrade() {
+                indicator.error.set_visible(false);
+                network::request_scan(&indicator.state.borrow());
+                popover.popup();
+            }
+        });
+
+        let weak = Rc::downgrade(&indicator);
+        indicator.wifi_switch.connect_active_notify(move |switch| {
+            if let Some(indicator) = weak.upgrade().filter(|i| !i.updating.get()) {
+                indicator.report(network::set_wifi_enabled(switch.is_active()));
+            }
+        });
+
+        let weak = Rc::downgrade(&indicator);
+        indicator.airplane_switch.connect_active_notify(move |switch| {
+            if let Some(indicator) = weak.upgrade().filter(|i| !i.updating.get()) {
+                indicator.report(network::set_airplane_mode(switch.is_active()));
+            }
+        });
+
+        let weak = Rc::downgrade(&indicator);
+        cancel.connect_clicked(move |_| {
+            if let Some(indicator) = weak.upgrade() {
+                indicator.close_prompt();
+            }
+        });
+
+        let weak = Rc::downgrade(&indicator);
+        let submit = move || {
+            if let Some(indicator) = weak.upgrade() {
+                indicator.submit_password();
+            }
+        };
+        let submit_clone = submit.clone();
+        connect.connect_clicked(move |_| submit_clone());
+        indicator.password.connect_activate(move |_| submit());
+
+        // The task owns the indicator and stops with the button.
+        let receiver = network::subscribe();
+        glib::MainContext::default().spawn_local(async move {
+            while let Ok(state) = receiver.recv().await {
+                if indicator.button.parent().is_none() {
+                    break;
+                }
+                indicator.update(state);
+            }
+        });
+
+        button
+    }
+
+    fn update(self: &Rc<Self>, state: NetworkState) {
+        self.updating.set(true);
+
+        let description = state.description();
+        self.button.set_icon_name(state.icon_name());
+        self.button.set_tooltip_text(Some(&description));
+        self.status.set_label(&description);
+
+        self.wifi_row.set_visible(state.has_wifi);
+        self.wifi_switch.set_active(state.wifi_enabled);
+        self.airplane_switch.set_active(state.airplane);
+
+        self.networks_section
+            .set_visible(state.has_wifi && state.wifi_enabled && !state.access_points.is_empty());
+        self.fill_networks(&state);
+
+        self.vpn_section.set_visible(!state.vpns.is_empty());
+        self.fill_vpns(&state);
+
+        *self.state.borrow_mut() = state;
+        self.updating.set(false);
+    }
+
+    fn fill_networks(self: &Rc<Self>, state: &NetworkState) {
+        clear(&self.networks);
+
+        for ap in &state.access_points {
+            let row = GtkBox::builder()
+                .orientation(Orientation::Horizontal)
+                .spacing(8)
+                .build();
+            row.append(&Image::from_icon_name(network::wifi_icon(ap.strength)));
+
+            let name = Label::builder()
+                .label(&ap.ssid)
+                .xalign(0.0)
+                .hexpand(true)
+                .ellipsize(pango::EllipsizeMode::End)
+                .build();
+            name.add_css_class("network-name");
+            row.append(&name);
+
+            if ap.active {
+                let connected = Label::new(Some("Connected"));
+                connected.add_css_class("network-status");
+                row.append(&connected);
+            }
+            if ap.secured {
+                row.append(&Image::from_icon_name("network-wireless-encrypted-symbolic"));
+            }
+
+            let button = Button::builder().child(&row).build();
+            button.add_css_class("flat");
+            button.add_css_class("popover-row");
+            button.set_tooltip_text(Some(if ap.active {
+                "Disconnect"
+            } else {
+                "Connect"
+            }));
+
+            let ap = ap.clone();
+            let weak = Rc::downgrade(self);
+            button.connect_clicked(move |_| {
+                let Some(indicator) = weak.upgrade() else {
+                    return;
+                };
+                if ap.active {
+                    indicator.report(network::disconnect(&ap));
+                } else if ap.secured && !ap.known {
+                    indicator.prompt_password(&ap);
+                } else {
+                    indicator.report(network::connect_wifi(&ap, None));
+                }
+            });
+            self.networks.append(&button);
+        }
+    }
+
+    fn fill_vpns(self: &Rc<Self>, state: &NetworkState) {
+        clear(&self.vpns);
+
+        for vpn in &state.vpns {
+            let (row, switch) = switch_row(&vpn.name);
+            switch.set_active(vpn.active);
+
+            let vpn = vpn.clone();
+            let weak = Rc::downgrade(self);
+            switch.connect_active_notify(move |switch| {
+                if let Some(indicator) = weak.upgrade().filter(|i| !i.updating.get()) {
+                    indicator.report(network::set_vpn_active(&vpn, switch.is_active()));
+                }
+            });
+            self.vpns.append(&row);
+        }
+    }
+
+    fn prompt_password(&self, ap: &AccessPoint) {
+        self.password_label.set_label(&format!("Password for {}", ap.ssid));
+        self.password.set_text("");
+        self.password_box.set_visible(true);
+        self.set_keyboard_mode(KeyboardMode::OnDemand);
+        self.password.grab_focus();
+        *self.pending.borrow_mut() = Some(ap.clone());
+    }
+
+    fn close_prompt(&self) {
+        self.password.set_text("");
+        self.password_box.set_visible(false);
+        self.set_keyboard_mode(KeyboardMode::None);
+        self.pending.borrow_mut().take();
+    }
+
+    /// The panel takes no keyboard input, except while the password is
+    /// typed.
+    fn set_keyboard_mode(&self, mode: KeyboardMode) {
+        let window = self.button.root().and_downcast::<gtk4::Window>();
+        if let Some(window) = window {
+            window.set_keyboard_mode(mode);
+        }
+    }
+
+    fn submit_password(self: &Rc<Self>) {
+        let Some(ap) = self.pending.borrow_mut().take() else {
+            return;
+        };
+        let password = self.password.text().to_string();
+        self.close_prompt();
+        self.report(network::connect_wifi(&ap, Some(password)));
+    }
+
+    /// Shows the failure of a request in the popover, once it finishes.
+    fn report(self: &Rc<Self>, result: async_channel::Receiver<Result<()>>) {
+        self.error.set_visible(false);
+        let weak = Rc::downgrade(self);
+        glib::MainContext::default().spawn_local(async move {
+            let Ok(Err(e)) = result.recv().await else {
+                return;
+            };
+            if let Some(indicator) = weak.upgrade() {
+                indicator.error.set_label(&format!("{:#}", e));
+                indicator.error.set_visible(true);
+            }
+        });
+    }
+}
+
+fn switch_row(label: &str) -> (GtkBox, Switch) {
+    let row = GtkBox::builder()
+        .orientation(Orientation::Horizontal)
+        .spacing(8)
+        .build();
+    row.add_css_class("popover-row");
+    let label = Label::builder()
+        .label(label)
+        .xalign(0.0)
+        .hexpand(true)
+        .ellipsize(pango::EllipsizeMode::End)
+        .build();
+    row.append(&label);
+    let switch = Switch::builder().valign(Align::Center).build();
+    row.append(&switch);
+    (row, switch)
+}
@@ -0,0 +1,31 @@
+//! Layout helpers shared by the indicator popovers
+
+use gtk4::prelude::*;
+use gtk4::{Box as GtkBox, Label, Orientation};
+
+/// A titled popover section and the box its rows go in.
+pub fn section(title: &str) -> (GtkBox, GtkBox) {
+    let section = GtkBox::builder()
+        .orientation(Orientation::Vertical)
+        .spacing(6)
+        .build();
+    section.add_css_class("popover-section");
+
+    let header = Label::builder().label(title).xalign(0.0).build();
+    header.add_css_class("popover-section-header");
+    section.append(&header);
+
+    let rows = GtkBox::builder()
+        .orientation(Orientation::Vertical)
+        .spacing(4)
+        .build();
+    section.append(&rows);
+
+    (section, rows)
+}
+
+pub fn clear(container: &GtkBox) {
+    while let Some(child) = container.first_child() {
+        container.remove(&child);
+    }
+}
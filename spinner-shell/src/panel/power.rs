@@ -0,0 +1,310 @@
+//! Power menu - Lock, log out, suspend, hibernate, restart and shut down
+//! from the panel's power button
+//!
+//! Logging out, restarting and shutting down count down first, with a
+//! chance to cancel or go ahead at once.
+
+use super::popover::clear;
+use super::{ModuleContext, PanelModule};
+use crate::config::PowerConfig;
+use crate::services::session::{PowerAction, Session};
+
+use anyhow::Result;
+use gtk4::prelude::*;
+use gtk4::{self, glib, Align, Box as GtkBox, Button, Image, Label, Orientation, Popover, Stack};
+use std::cell::Cell;
+use std::rc::Rc;
+use std::time::Duration;
+
+pub struct PowerMenu {
+    config: PowerConfig,
+}
+
+impl PowerMenu {
+    pub fn new(config: PowerConfig) -> Self {
+        Self { config }
+    }
+}
+
+impl PanelModule for PowerMenu {
+    fn name(&self) -> &str {
+        "power"
+    }
+
+    fn build(&self, _ctx: &ModuleContext) -> gtk4::Widget {
+        PowerPopover::build(self.config.confirm_seconds).upcast()
+    }
+}
+
+struct PowerPopover {
+    button: Button,
+    popover: Popover,
+    stack: Stack,
+    actions: GtkBox,
+    error: Label,
+    confirm_title: Label,
+    countdown: Label,
+    confirm: Button,
+    session: Session,
+    confirm_seconds: u32,
+    pending: Cell<Option<PowerAction>>,
+    remaining: Cell<u32>,
+    /// Bumped on every start or cancel, so a stale timer stops.
+    serial: Cell<u32>,
+}
+
+impl PowerPopover {
+    fn build(confirm_seconds: u32) -> Button {
+        let button = Button::builder()
+            .icon_name("system-shutdown-symbolic")
+            .tooltip_text("Power")
+            .build();
+        button.add_css_class("panel-button");
+        button.add_css_class("power-button");
+
+        let actions = GtkBox::builder()
+            .orientation(Orientation::Vertical)
+            .spacing(4)
+            .build();
+
+        let confirm_page = GtkBox::builder()
+            .orientation(Orientation::Vertical)
+            .spacing(8)
+            .build();
+        let confirm_title = Label::builder().xalign(0.0).build();
+        confirm_title.add_css_class("popover-header");
+        confirm_page.append(&confirm_title);
+        let countdown = Label::builder().xalign(0.0).wrap(true).build();
+        countdown.add_css_class("power-countdown");
+        confirm_page.append(&countdown);
+        let confirm_buttons = GtkBox::builder()
+            .orientation(Orientation::Horizontal)
+            .spacing(8)
+            .halign(Align::End)
+            .build();
+        let cancel = Button::with_label("Cancel");
+        cancel.add_css_class("popover-button");
+        confirm_buttons.append(&cancel);
+        let confirm = Button::new();
+        confirm.add_css_class("popover-button");
+        confirm.add_css_class("destructive-action");
+        confirm_buttons.append(&confirm);
+        confirm_page.append(&confirm_buttons);
+
+        let stack = Stack::builder()
+            .transition_type(gtk4::StackTransitionType::Crossfade)
+            .vhomogeneous(false)
+            .build();
+        stack.add_named(&actions, Some("actions"));
+        stack.add_named(&confirm_page, Some("confirm"));
+
+        let content = GtkBox::builder()
+            .orientation(Orientation::Vertical)
+            .spacing(8)
+            .margin_top(12)
+            .margin_bottom(12)
+            .margin_start(12)
+            .margin_end(12)
+            .width_request(260)
+            .build();
+        content.append(&stack);
+        let error = Label::builder()
+            .xalign(0.0)
+            .wrap(true)
+            .visible(false)
+            .build();
+        error.add_css_class("network-error");
+        content.append(&error);
+
+        let popover = Popover::builder().child(&content).has_arrow(false).build();
+        popover.add_css_class("systray-popover");
+        popover.set_parent(&button);
+        let popover_clone = popover.clone();
+        button.connect_destroy(move |_| {
+            popover_clone.unparent();
+        });
+
+        let menu = Rc::new(Self {
+            button: button.clone(),
+            popover,
+            stack,
+            actions,
+            error,
+            confirm_title,
+            countdown,
+            confirm,
+            session: Session::default(),
+            confirm_seconds,
+            pending: Cell::new(None),
+            remaining: Cell::new(0),
+            serial: Cell::new(0),
+        });
+
+        let weak = Rc::downgrade(&menu);
+        button.connect_clicked(move |_| {
+            if let Some(menu) = weak.upgrade() {
+                menu.open();
+            }
+        });
+
+        let weak = Rc::downgrade(&menu);
+        menu.popover.connect_closed(move |_| {
+            if let Some(menu) = weak.upgrade() {
+                menu.cancel();
+            }
+        });
+
+        let weak = Rc::downgrade(&menu);
+        cancel.connect_clicked(move |_| {
+            if let Some(menu) = weak.upgrade() {
+                menu.cancel();
+            }
+        });
+
+        let weak = Rc::downgrade(&menu);
+        menu.confirm.connect_clicked(move |_| {
+            let Some(menu) = weak.upgrade() else {
+                return;
+            };
+            if let Some(action) = menu.pending.get() {
+                menu.run(action);
+            }
+        });
+
+        menu.refresh();
+        button
+    }
+
+    fn open(self: &Rc<Self>) {
+        self.cancel();
+        self.error.set_visible(false);
+        self.refresh();
+        self.popover.popup();
+    }
+
+    /// Lists the actions logind currently allows.
+    fn refresh(self: &Rc<Self>) {
+        let available = self.session.available();
+        let weak = Rc::downgrade(self);
+        glib::MainContext::default().spawn_local(async move {
+            let Ok(actions) = available.recv().await else {
+                return;
+            };
+            if let Some(menu) = weak.upgrade() {
+                menu.show_actions(&actions);
+            }
+        });
+    }
+
+    fn show_actions(self: &Rc<Self>, actions: &[PowerAction]) {
+        clear(&self.actions);
+        for &action in actions {
+            let row = GtkBox::builder()
+                .orientation(Orientation::Horizontal)
+                .spacing(10)
+                .build();
+            row.append(&Image::from_icon_name(action.icon_name()));
+            row.append(
+                &Label::builder()
+                    .label(action.label())
+                    .xalign(0.0)
+                    .hexpand(true)
+                    .build(),
+            );
+
+            let button = Button::builder().child(&row).build();
+            button.add_css_class("flat");
+            button.add_css_class("popover-row");
+            let weak = Rc::downgrade(self);
+            button.connect_clicked(move |_| {
+                let Some(menu) = weak.upgrade() else {
+                    return;
+                };
+                if action.ends_session() && menu.confirm_seconds > 0 {
+                    menu.start_countdown(action);
+                } else {
+                    menu.run(action);
+                }
+            });
+            self.actions.append(&button);
+        }
+    }
+
+    fn start_countdown(self: &Rc<Self>, action: PowerAction) {
+        let serial = self.serial.get().wrapping_add(1);
+        self.serial.set(serial);
+        self.pending.set(Some(action));
+        self.remaining.set(self.confirm_seconds);
+        self.error.set_visible(false);
+
+        self.confirm_title
+            .set_label(&format!("{}?", action.label()));
+        self.confirm.set_label(&format!("{} Now", action.label()));
+        self.update_countdown(action);
+        self.stack.set_visible_child_name("confirm");
+        self.clone().tick(serial);
+    }
+
+    fn tick(self: Rc<Self>, serial: u32) {
+        glib::timeout_add_local_once(Duration::from_secs(1), move || {
+            if self.serial.get() != serial || self.button.parent().is_none() {
+                return;
+            }
+            let Some(action) = self.pending.get() else {
+                return;
+            };
+            let remaining = self.remaining.get().saturating_sub(1);
+            self.remaining.set(remaining);
+            if remaining == 0 {
+                self.run(action);
+            } else {
+                self.update_countdown(action);
+                self.tick(serial);
+            }
+        });
+    }
+
+    fn update_countdown(&self, action: PowerAction) {
+        let doing = match action {
+            PowerAction::Logout => "Logging out",
+            PowerAction::Reboot => "Restarting",
+            _ => "Shutting down",
+        };
+        let seconds = self.remaining.get();
+        let unit = if seconds == 1 { "second" } else { "seconds" };
+        self.countdown
+            .set_label(&format!("{} automatically in {} {}.", doing, seconds, unit));
+    }
+
+    /// Stops a countdown and goes back to the list.
+    fn cancel(&self) {
+        self.serial.set(self.serial.get().wrapping_add(1));
+        self.pending.set(None);
+        self.stack.set_visible_child_name("actions");
+    }
+
+    fn run(self: &Rc<Self>, action: PowerAction) {
+        self.cancel();
+        self.popover.popdown();
+        // The shell's own lock screen, unless it's turned off.
+        if action == PowerAction::Lock && self.button.activate_action("app.lock", None).is_ok() {
+            return;
+        }
+        self.report(self.session.perform(action));
+    }
+
+    /// Reopens the popover with the failure, once the request finishes.
+    fn report(self: &Rc<Self>, result: async_channel::Receiver<Result<()>>) {
+        let weak = Rc::downgrade(self);
+        glib::MainContext::default().spawn_local(async move {
+            let Ok(Err(e)) = result.recv().await else {
+                return;
+            };
+            if let Some(menu) = weak.upgrade() {
+                menu.error.set_label(&format!("{:#}", e));
+                menu.error.set_visible(true);
+                menu.popover.popup();
+            }
+        });
+    }
+}
@@ -0,0 +1,542 @@
+//! Quick settings - Toggles for Wi-Fi, Bluetooth, dark mode, Do Not
+//! Disturb and night light, with volume and brightness sliders, in a
+//! popover at the end of the system tray. `spinner-shell --quick-settings`
+//! opens it on the first panel.
+
+use super::ModuleContext;
+use crate::config::QuickSettingsConfig;
+use crate::notifications::{CenterEvent, NotificationCenter};
+use crate::services::audio::{self, AudioState, MAX_VOLUME};
+use crate::services::brightness::{self, Backlight};
+use crate::services::network::{self, NetworkState, Primary};
+use crate::services::night_light;
+use crate::theme::ThemeManager;
+
+use anyhow::Result;
+use gtk4::prelude::*;
+use gtk4::{
+    self, glib, Align, Box as GtkBox, Button, Grid, Image, Label, Orientation, Popover, Scale,
+    ToggleButton,
+};
+use spinner_bluez::{self as bluetooth, BluetoothState};
+use std::cell::{Cell, RefCell};
+use std::process::Command;
+use std::rc::{Rc, Weak};
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// Brightness is polled; reports this soon after a change from the
+/// slider are stale.
+const BRIGHTNESS_SETTLE: Duration = Duration::from_secs(3);
+
+thread_local! {
+    /// Every panel's quick settings, for the shortcut.
+    static INSTANCES: RefCell<Vec<Weak<QuickSettings>>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Opens the quick settings of the first panel, or closes them if open.
+pub fn toggle() {
+    let instance = INSTANCES.with(|instances| {
+        let mut instances = instances.borrow_mut();
+        instances.retain(|weak| weak.strong_count() > 0);
+        instances
+            .iter()
+            .filter_map(Weak::upgrade)
+            .find(|instance| instance.button.is_mapped())
+    });
+    match instance {
+        Some(instance) if instance.popover.is_visible() => instance.popover.popdown(),
+        Some(instance) => instance.open(),
+        None => warn!("No quick settings to open"),
+    }
+}
+
+/// A pill with an icon, a title and the current state below it.
+struct QuickToggle {
+    button: ToggleButton,
+    icon: Image,
+    subtitle: Label,
+}
+
+impl QuickToggle {
+    fn new(icon_name: &str, title: &str) -> Self {
+        let row = GtkBox::builder()
+            .orientation(Orientation::Horizontal)
+            .spacing(10)
+            .build();
+        let icon = Image::from_icon_name(icon_name);
+        row.append(&icon);
+
+        let labels = GtkBox::builder()
+            .orientation(Orientation::Vertical)
+            .valign(Align::Center)
+            .build();
+        let title = Label::builder()
+            .label(title)
+            .xalign(0.0)
+            .ellipsize(pango::EllipsizeMode::End)
+            .build();
+        title.add_css_class("quick-toggle-title");
+        labels.append(&title);
+        let subtitle = Label::builder()
+            .xalign(0.0)
+            .visible(false)
+            .ellipsize(pango::EllipsizeMode::End)
+            .build();
+        subtitle.add_css_class("quick-toggle-subtitle");
+        labels.append(&subtitle);
+        row.append(&labels);
+
+        let button = ToggleButton::builder().child(&row).hexpand(true).build();
+        button.add_css_class("quick-toggle");
+        Self {
+            button,
+            icon,
+            subtitle,
+        }
+    }
+
+    fn set(&self, active: bool, subtitle: &str) {
+        self.button.set_active(active);
+        self.subtitle.set_label(subtitle);
+        self.subtitle.set_visible(!subtitle.is_empty());
+    }
+}
+
+pub struct QuickSettings {
+    button: Button,
+    popover: Popover,
+    config: QuickSettingsConfig,
+    error: Label,
+    toggles: Grid,
+    wifi: QuickToggle,
+    bluetooth: QuickToggle,
+    dark_mode: QuickToggle,
+    dnd: QuickToggle,
+    night_light: QuickToggle,
+    volume_icon: Button,
+    volume: Scale,
+    brightness_row: GtkBox,
+    brightness: Scale,
+    notifications: Option<Rc<RefCell<NotificationCenter>>>,
+    theme: Rc<RefCell<ThemeManager>>,
+    bluetooth_state: RefCell<BluetoothState>,
+    audio_state: RefCell<AudioState>,
+    backlight: RefCell<Option<Backlight>>,
+    brightness_changed: Cell<Option<Instant>>,
+    /// Set while widgets are updated from the services, so their handlers
+    /// don't echo the change back.
+    updating: Cell<bool>,
+}
+
+impl QuickSettings {
+    pub fn build(config: &QuickSettingsConfig, ctx: &ModuleContext) -> Button {
+        let button = Button::builder()
+            .icon_name("open-menu-symbolic")
+            .tooltip_text("Quick settings")
+            .build();
+        button.add_css_class("systray-button");
+        button.add_css_class("quick-settings-button");
+
+        let content = GtkBox::builder()
+            .orientation(Orientation::Vertical)
+            .spacing(8)
+            .margin_top(12)
+            .margin_bottom(12)
+            .margin_start(12)
+            .margin_end(12)
+            .width_request(360)
+            .build();
+
+        let wifi = QuickToggle::new("network-wireless-symbolic", "Wi-Fi");
+        let bluetooth = QuickToggle::new("bluetooth-symbolic", "Bluetooth");
+        let dark_mode = QuickToggle::new("weather-clear-night-symbolic", "Dark Mode");
+        let dnd = QuickToggle::new("notifications-disabled-symbolic", "Do Not Disturb");
+        let night_light = QuickToggle::new("night-light-symbolic", "Night Light");
+        // Wi-Fi and Bluetooth show up once the adapters are found.
+        wifi.button.set_visible(false);
+        bluetooth.button.set_visible(false);
+        dnd.button.set_visible(ctx.notifications.is_some());
+        night_light
+            .button
+            .set_visible(night_light::is_available(&config.night_light_command));
+
+        let toggles = Grid::builder()
+            .row_spacing(8)
+            .column_spacing(8)
+            .column_homogeneous(true)
+            .build();
+        content.append(&toggles);
+
+        let error = Label::builder()
+            .xalign(0.0)
+            .wrap(true)
+            .visible(false)
+            .build();
+        error.add_css_class("network-error");
+        content.append(&error);
+
+        let (volume_row, volume_icon, volume) =
+            slider_row(audio::volume_icon(0, true), MAX_VOLUME as f64);
+        content.append(&volume_row);
+        let (brightness_row, _, brightness) = slider_row("display-brightness-symbolic", 100.0);
+        brightness_row.set_visible(false);
+        content.append(&brightness_row);
+
+        let footer = GtkBox::builder()
+            .orientation(Orientation::Horizontal)
+            .halign(Align::End)
+            .build();
+        footer.add_css_class("popover-section");
+        let settings = Button::builder()
+            .icon_name("emblem-system-symbolic")
+            .tooltip_text("Settings")
+            .build();
+        settings.add_css_class("flat");
+        footer.append(&settings);
+        content.append(&footer);
+
+        let popover = Popover::builder().child(&content).has_arrow(false).build();
+        popover.add_css_class("systray-popover");
+        popover.set_parent(&button);
+        let popover_clone = popover.clone();
+        button.connect_destroy(move |_| {
+            popover_clone.unparent();
+        });
+
+        let quick = Rc::new(Self {
+            button: button.clone(),
+            popover,
+            config: config.clone(),
+            error,
+            toggles,
+            wifi,
+            bluetooth,
+            dark_mode,
+            dnd,
+            night_light,
+            volume_icon,
+            volume,
+            brightness_row,
+            brightness,
+            notifications: ctx.notifications.cloned(),
+            theme: ctx.theme.clone(),
+            bluetooth_state: RefCell::new(BluetoothState::default()),
+            audio_state: RefCell::new(AudioState::default()),
+            backlight: RefCell::new(None),
+            brightness_changed: Cell::new(None),
+            updating: Cell::new(false),
+        });
+        quick.layout_toggles();
+        quick.connect_signals(&settings);
+        quick.sync_local();
+        INSTANCES.with(|instances| instances.borrow_mut().push(Rc::downgrade(&quick)));
+
+        quick.spawn_updates();
+        button
+    }
+
+    /// Two toggles a row, skipping the hidden ones so they leave no gaps.
+    fn layout_toggles(&self) {
+        while let Some(child) = self.toggles.first_child() {
+            self.toggles.remove(&child);
+        }
+        let toggles = [
+            &self.wifi,
+            &self.bluetooth,
+            &self.dark_mode,
+            &self.dnd,
+            &self.night_light,
+        ];
+        let visible = toggles.iter().filter(|toggle| toggle.button.is_visible());
+        for (index, toggle) in visible.enumerate() {
+            self.toggles
+                .attach(&toggle.button, (index % 2) as i32, (index / 2) as i32, 1, 1);
+        }
+    }
+
+    fn connect_signals(self: &Rc<Self>, settings: &Button) {
+        let weak = Rc::downgrade(self);
+        self.button.connect_clicked(move |_| {
+            if let Some(quick) = weak.upgrade() {
+                quick.open();
+            }
+        });
+
+        let weak = Rc::downgrade(self);
+        self.wifi.button.connect_toggled(move |button| {
+            if let Some(quick) = weak.upgrade().filter(|q| !q.updating.get()) {
+                quick.report(network::set_wifi_enabled(button.is_active()));
+            }
+        });
+
+        let weak = Rc::downgrade(self);
+        self.bluetooth.button.connect_toggled(move |button| {
+            if let Some(quick) = weak.upgrade().filter(|q| !q.updating.get()) {
+                let result =
+                    bluetooth::set_powered(&quick.bluetooth_state.borrow(), button.is_active());
+                quick.report(result);
+            }
+        });
+
+        let weak = Rc::downgrade(self);
+        self.dark_mode.button.connect_toggled(move |button| {
+            if let Some(quick) = weak.upgrade().filter(|q| !q.updating.get()) {
+                quick.theme.borrow_mut().set_dark_mode(button.is_active());
+                quick.sync_local();
+            }
+        });
+
+        let weak = Rc::downgrade(self);
+        self.dnd.button.connect_toggled(move |button| {
+            let Some(quick) = weak.upgrade().filter(|q| !q.updating.get()) else {
+                return;
+            };
+            // When the center itself changed the mode, it is still borrowed
+            // and already up to date.
+            if let Some(Ok(mut center)) = quick.notifications.as_ref().map(|c| c.try_borrow_mut()) {
+                center.set_do_not_disturb(button.is_active());
+            }
+        });
+        if let Some(center) = &self.notifications {
+            let weak = Rc::downgrade(self);
+            center.borrow_mut().connect(move |event| {
+                if let (CenterEvent::DoNotDisturb(enabled), Some(quick)) = (event, weak.upgrade()) {
+                    quick.updating.set(true);
+                    quick.dnd.set(*enabled, "");
+                    quick.updating.set(false);
+                }
+            });
+        }
+
+        let weak = Rc::downgrade(self);
+        self.night_light.button.connect_toggled(move |button| {
+            if let Some(quick) = weak.upgrade().filter(|q| !q.updating.get()) {
+                if let Err(e) =
+                    night_light::set_enabled(&quick.config.night_light_command, button.is_active())
+                {
+                    quick.show_error(&e);
+                }
+                quick.sync_local();
+            }
+        });
+
+        let weak = Rc::downgrade(self);
+        self.volume_icon.connect_clicked(move |_| {
+            if let Some(quick) = weak.upgrade() {
+                let muted = quick
+                    .audio_state
+                    .borrow()
+                    .default_sink()
+                    .is_some_and(|s| s.mute);
+                audio::set_sink_mute(!muted);
+            }
+        });
+
+        let weak = Rc::downgrade(self);
+        self.volume.connect_value_changed(move |scale| {
+            if let Some(quick) = weak.upgrade().filter(|q| !q.updating.get()) {
+                audio::set_sink_volume(scale.value().round() as u32);
+            }
+        });
+
+        let weak = Rc::downgrade(self);
+        self.brightness.connect_value_changed(move |scale| {
+            let Some(quick) = weak.upgrade().filter(|q| !q.updating.get()) else {
+                return;
+            };
+            if let Some(backlight) = quick.backlight.borrow().as_ref() {
+                quick.brightness_changed.set(Some(Instant::now()));
+                brightness::set_brightness(backlight, scale.value().round() as u32);
+            }
+        });
+
+        let weak = Rc::downgrade(self);
+        settings.connect_clicked(move |_| {
+            let Some(quick) = weak.upgrade() else {
+                return;
+            };
+            quick.popover.popdown();
+            let command = &quick.config.settings_command;
+            if let Err(e) = Command::new("sh").arg("-c").arg(command).spawn() {
+                warn!("Failed to run {}: {}", command, e);
+            }
+        });
+    }
+
+    /// The tasks own the quick settings and stop with the button.
+    fn spawn_updates(self: &Rc<Self>) {
+        let context = glib::MainContext::default();
+
+        let quick = self.clone();
+        let receiver = network::subscribe();
+        context.spawn_local(async move {
+            while let Ok(state) = receiver.recv().await {
+                if quick.button.parent().is_none() {
+                    break;
+                }
+                quick.update_network(state);
+            }
+        });
+
+        let quick = self.clone();
+        let receiver = bluetooth::subscribe();
+        context.spawn_local(async move {
+            while let Ok(state) = receiver.recv().await {
+                if quick.button.parent().is_none() {
+                    break;
+                }
+                quick.update_bluetooth(state);
+            }
+        });
+
+        let quick = self.clone();
+        let receiver = audio::subscribe();
+        context.spawn_local(async move {
+            while let Ok(state) = receiver.recv().await {
+                if quick.button.parent().is_none() {
+                    break;
+                }
+                quick.update_audio(state);
+            }
+        });
+
+        let quick = self.clone();
+        let receiver = brightness::subscribe();
+        context.spawn_local(async move {
+            while let Ok(backlight) = receiver.recv().await {
+                if quick.button.parent().is_none() {
+                    break;
+                }
+                quick.update_brightness(backlight);
+            }
+        });
+    }
+
+    fn open(&self) {
+        self.error.set_visible(false);
+        self.sync_local();
+        self.popover.popup();
+    }
+
+    /// Dark mode, Do Not Disturb and night light change without events
+    /// from the other panels' quick settings; they are read on opening.
+    fn sync_local(&self) {
+        self.updating.set(true);
+        self.dark_mode.set(self.theme.borrow().is_dark_mode(), "");
+        if let Some(Ok(center)) = self.notifications.as_ref().map(|c| c.try_borrow()) {
+            self.dnd.set(center.do_not_disturb(), "");
+        }
+        self.night_light.set(night_light::is_enabled(), "");
+        self.updating.set(false);
+    }
+
+    fn update_network(&self, state: NetworkState) {
+        self.updating.set(true);
+        let subtitle = match &state.primary {
+            Primary::Wifi { ssid, .. } => ssid.clone(),
+            _ if state.wifi_enabled => "Not connected".to_string(),
+            _ => "Off".to_string(),
+        };
+        let icon = match &state.primary {
+            Primary::Wifi { strength, .. } => network::wifi_icon(*strength),
+            _ if state.wifi_enabled => "network-wireless-offline-symbolic",
+            _ => "network-wireless-disabled-symbolic",
+        };
+        if self.wifi.button.is_visible() != state.has_wifi {
+            self.wifi.button.set_visible(state.has_wifi);
+            self.layout_toggles();
+        }
+        self.wifi.icon.set_icon_name(Some(icon));
+        self.wifi.set(state.wifi_enabled, &subtitle);
+        self.updating.set(false);
+    }
+
+    fn update_bluetooth(&self, state: BluetoothState) {
+        self.updating.set(true);
+        let connected: Vec<&str> = state.connected().map(|d| d.name.as_str()).collect();
+        let subtitle = match connected.as_slice() {
+            _ if !state.powered => "Off".to_string(),
+            [] => "On".to_string(),
+            [name] => name.to_string(),
+            names => format!("{} devices", names.len()),
+        };
+        if self.bluetooth.button.is_visible() != state.available {
+            self.bluetooth.button.set_visible(state.available);
+            self.layout_toggles();
+        }
+        self.bluetooth.icon.set_icon_name(Some(state.icon_name()));
+        self.bluetooth.set(state.powered, &subtitle);
+        *self.bluetooth_state.borrow_mut() = state;
+        self.updating.set(false);
+    }
+
+    fn update_audio(&self, state: AudioState) {
+        self.updating.set(true);
+        match state.default_sink() {
+            Some(sink) => {
+                self.volume_icon
+                    .set_icon_name(audio::volume_icon(sink.volume, sink.mute));
+                self.volume_icon
+                    .set_tooltip_text(Some(if sink.mute { "Unmute" } else { "Mute" }));
+                self.volume.set_value(sink.volume as f64);
+                self.volume.set_sensitive(true);
+            }
+            None => {
+                self.volume_icon.set_icon_name(audio::volume_icon(0, true));
+                self.volume.set_sensitive(false);
+            }
+        }
+        *self.audio_state.borrow_mut() = state;
+        self.updating.set(false);
+    }
+
+    fn update_brightness(&self, backlight: Option<Backlight>) {
+        self.updating.set(true);
+        self.brightness_row.set_visible(backlight.is_some());
+        let settled = self
+            .brightness_changed
+            .get()
+            .is_none_or(|changed| changed.elapsed() > BRIGHTNESS_SETTLE);
+        if let (Some(backlight), true) = (&backlight, settled) {
+            self.brightness.set_value(backlight.percentage() as f64);
+        }
+        *self.backlight.borrow_mut() = backlight;
+        self.updating.set(false);
+    }
+
+    fn show_error(&self, error: &anyhow::Error) {
+        self.error.set_label(&format!("{:#}", error));
+        self.error.set_visible(true);
+    }
+
+    /// Shows the failure of a request in the popover, once it finishes.
+    fn report(self: &Rc<Self>, result: async_channel::Receiver<Result<()>>) {
+        self.error.set_visible(false);
+        let weak = Rc::downgrade(self);
+        glib::MainContext::default().spawn_local(async move {
+            let Ok(Err(e)) = result.recv().await else {
+                return;
+            };
+            if let Some(quick) = weak.upgrade() {
+                quick.show_error(&e);
+            }
+        });
+    }
+}
+
+fn slider_row(icon_name: &str, max: f64) -> (GtkBox, Button, Scale) {
+    let row = GtkBox::builder()
+        .orientation(Orientation::Horizontal)
+        .spacing(8)
+        .build();
+    row.add_css_class("popover-row");
+    let icon = Button::builder().icon_name(icon_name).build();
+    icon.add_css_class("flat");
+    row.append(&icon);
+    let scale = Scale::with_range(Orientation::Horizontal, 0.0, max, 1.0);
+    scale.set_hexpand(true);
+    scale.add_css_class("volume-scale");
+    row.append(&scale);
+    (row, icon, scale)
+}
@@ -1,113 +1,75 @@
-//! System Tray - Network, sound, battery indicators
+//! System Tray - Status icons of other apps, the network, sound,
+//! Bluetooth and battery indicators, and the quick settings button
+
+use super::audio::AudioIndicator;
+use super::battery::BatteryIndicator;
+use super::bluetooth::BluetoothIndicator;
+use super::network::NetworkIndicator;
+use super::quick_settings::QuickSettings;
+use super::tray::StatusIcons;
+use super::{ModuleContext, PanelModule};
+use crate::config::{QuickSettingsConfig, SystrayConfig};
 
 use gtk4::prelude::*;
-use gtk4::{self, Box as GtkBox, Button, Orientation};
-use tracing::info;
+use gtk4::{self, Box as GtkBox};
 
 pub struct SystemTray {
-    audio_level: u32,
-    battery_level: Option<u32>,
+    config: SystrayConfig,
+    quick_settings: QuickSettingsConfig,
 }
 
 impl SystemTray {
-    pub fn new() -> Self {
-        Self {
-            audio_level: 70,
-            battery_level: Some(85),
-        }
+    pub fn new(config: SystrayConfig, quick_settings: QuickSettingsConfig) -> Self {
+        Self { config, quick_settings }
     }
     
-    pub fn build_widget(&self) -> GtkBox {
+    pub fn build_widget(&self, ctx: &ModuleContext) -> GtkBox {
         let container = GtkBox::builder()
-            .orientation(Orientation::Horizontal)
+            .orientation(ctx.orientation)
             .spacing(4)
             .build();
         container.add_css_class("systray");
         
-        let network_btn = self.build_network_indicator();
-        container.append(&network_btn);
-        
-        let audio_btn = self.build_audio_indicator();
-        container.append(&audio_btn);
-        
-        if let Some(level) = self.battery_level {
-            let battery_btn = self.build_battery_indicator(level);
-            container.append(&battery_btn);
+        if self.config.show_status_icons {
+            container.append(&StatusIcons::build(ctx.orientation));
         }
         
-        container
-    }
-    
-    fn build_network_indicator(&self) -> Button {
-        let button = Button::builder()
-            .icon_name("network-wireless-signal-excellent-symbolic")
-            .tooltip_text("Network")
-            .build();
-        button.add_css_class("systray-button");
-        button.add_css_class("network-indicator");
+        if self.config.show_network {
+            container.append(&NetworkIndicator::build());
+        }
         
-        button.connect_clicked(|_| {
-            info!("Network indicator clicked");
-        });
+        if self.config.show_audio {
+            container.append(&AudioIndicator::build());
+        }
         
-        button
-    }
-    
-    fn build_audio_indicator(&self) -> Button {
-        let icon_name = if self.audio_level == 0 {
-            "audio-volume-muted-symbolic"
-        } else if self.audio_level < 33 {
-            "audio-volume-low-symbolic"
-        } else if self.audio_level < 66 {
-            "audio-volume-medium-symbolic"
-        } else {
-            "audio-volume-high-symbolic"
-        };
+        if self.config.show_bluetooth {
+            container.append(&BluetoothIndicator::build());
+        }
         
-        let button = Button::builder()
-            .icon_name(icon_name)
-            .tooltip_text(&format!("Volume: {}%", self.audio_level))
-            .build();
-        button.add_css_class("systray-button");
-        button.add_css_class("audio-indicator");
+        if self.config.show_battery {
+            container.append(&BatteryIndicator::build());
+        }
         
-        button.connect_clicked(|_| {
-            info!("Audio indicator clicked");
-        });
+        if self.quick_settings.enabled {
+            container.append(&QuickSettings::build(&self.quick_settings, ctx));
+        }
         
-        button
+        container
+    }
+}
+
+impl PanelModule for SystemTray {
+    fn name(&self) -> &str {
+        "systray"
     }
     
-    fn build_battery_indicator(&self, level: u32) -> Button {
-        let icon_name = if level > 80 {
-            "battery-level-100-symbolic"
-        } else if level > 60 {
-            "battery-level-80-symbolic"
-        } else if level > 40 {
-            "battery-level-60-symbolic"
-        } else if level > 20 {
-            "battery-level-40-symbolic"
-        } else {
-            "battery-level-20-symbolic"
-        };
-        
-        let button = Button::builder()
-            .icon_name(icon_name)
-            .tooltip_text(&format!("Battery: {}%", level))
-            .build();
-        button.add_css_class("systray-button");
-        button.add_css_class("battery-indicator");
-        
-        button.connect_clicked(|_| {
-            info!("Battery indicator clicked");
-        });
-        
-        button
+    fn build(&self, ctx: &ModuleContext) -> gtk4::Widget {
+        self.build_widget(ctx).upcast()
     }
 }
 
 impl Default for SystemTray {
     fn default() -> Self {
-        Self::new()
+        Self::new(SystrayConfig::default(), QuickSettingsConfig::default())
     }
 }
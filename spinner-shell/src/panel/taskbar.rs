@@ -1,8 +1,13 @@
 //! Taskbar - Window list in the panel
 
+use super::{ModuleContext, PanelModule};
+use crate::wm::{self, Request, WindowInfo};
+
 use gtk4::prelude::*;
-use gtk4::{self, Box as GtkBox, Button, Image, Label, Orientation};
-use tracing::info;
+use gtk4::{self, gdk, glib, Box as GtkBox, Button, Image, Label, Orientation};
+use tracing::{info, warn};
+
+const FALLBACK_ICON: &str = "application-x-executable-symbolic";
 
 #[derive(Debug, Clone)]
 pub struct TaskbarItem {
@@ -10,93 +15,150 @@ pub struct TaskbarItem {
     pub title: String,
     pub icon_name: String,
     pub is_focused: bool,
+    pub is_xwayland: bool,
+}
+
+impl From<&WindowInfo> for TaskbarItem {
+    fn from(window: &WindowInfo) -> Self {
+        Self {
+            id: window.id,
+            title: window.title.clone(),
+            icon_name: icon_for_app_id(&window.app_id),
+            is_focused: window.focused,
+            is_xwayland: window.xwayland,
+        }
+    }
+}
+
+/// Wayland app ids are usually the icon name; X11 WM_CLASS values are
+/// often capitalised ("Firefox"), so try the lowercase form as well.
+fn icon_for_app_id(app_id: &str) -> String {
+    let Some(display) = gtk4::gdk::Display::default() else {
+        return FALLBACK_ICON.to_string();
+    };
+    let theme = gtk4::IconTheme::for_display(&display);
+
+    [app_id.to_string(), app_id.to_lowercase()]
+        .into_iter()
+        .find(|name| !name.is_empty() && theme.has_icon(name))
+        .unwrap_or_else(|| FALLBACK_ICON.to_string())
+}
+
+/// Whether the centre of `window` lies on `monitor`.
+fn on_monitor(window: &WindowInfo, monitor: &gdk::Monitor) -> bool {
+    let (x, y) = window.geometry.center();
+    monitor.geometry().contains_point(x, y)
 }
 
 pub struct Taskbar {
-    items: Vec<TaskbarItem>,
+    per_output: bool,
 }
 
 impl Taskbar {
-    pub fn new() -> Self {
-        Self {
-            items: vec![
-                TaskbarItem {
-                    id: 1,
-                    title: "Files".to_string(),
-                    icon_name: "system-file-manager-symbolic".to_string(),
-                    is_focused: true,
-                },
-                TaskbarItem {
-                    id: 2,
-                    title: "Firefox".to_string(),
-                    icon_name: "web-browser-symbolic".to_string(),
-                    is_focused: false,
-                },
-                TaskbarItem {
-                    id: 3,
-                    title: "Terminal".to_string(),
-                    icon_name: "utilities-terminal-symbolic".to_string(),
-                    is_focused: false,
-                },
-            ],
-        }
+    /// With `per_output`, each taskbar lists only the windows on its own
+    /// monitor.
+    pub fn new(per_output: bool) -> Self {
+        Self { per_output }
     }
-    
-    pub fn build_widget(&self) -> GtkBox {
+
+    /// On a vertical panel the buttons show icons only.
+    pub fn build_widget(&self, orientation: Orientation, monitor: &gdk::Monitor) -> GtkBox {
         let container = GtkBox::builder()
-            .orientation(Orientation::Horizontal)
+            .orientation(orientation)
             .spacing(4)
             .build();
         container.add_css_class("taskbar");
-        
-        for item in &self.items {
-            let button = self.create_taskbar_button(item);
+
+        let receiver = wm::subscribe_windows();
+        let container_weak = container.downgrade();
+        let monitor = self.per_output.then(|| monitor.clone());
+        glib::MainContext::default().spawn_local(async move {
+            while let Ok(mut windows) = receiver.recv().await {
+                let Some(container) = container_weak.upgrade() else {
+                    break;
+                };
+                if let Some(monitor) = &monitor {
+                    windows.retain(|w| on_monitor(w, monitor));
+                }
+                Self::populate(&container, &windows, orientation);
+            }
+        });
+
+        container
+    }
+
+    fn populate(container: &GtkBox, windows: &[WindowInfo], orientation: Orientation) {
+        while let Some(child) = container.first_child() {
+            container.remove(&child);
+        }
+
+        for window in windows {
+            let button = Self::create_taskbar_button(
+                &TaskbarItem::from(window),
+                orientation == Orientation::Horizontal,
+            );
             container.append(&button);
         }
-        
-        container
     }
-    
-    fn create_taskbar_button(&self, item: &TaskbarItem) -> Button {
+
+    fn create_taskbar_button(item: &TaskbarItem, show_title: bool) -> Button {
         let content = GtkBox::builder()
             .orientation(Orientation::Horizontal)
             .spacing(6)
             .build();
-        
+
         let icon = Image::builder()
             .icon_name(&item.icon_name)
             .pixel_size(20)
             .build();
         content.append(&icon);
-        
-        let label = Label::builder()
-            .label(&item.title)
-            .ellipsize(pango::EllipsizeMode::End)
-            .max_width_chars(15)
-            .build();
-        content.append(&label);
-        
+
+        if show_title {
+            let label = Label::builder()
+                .label(&item.title)
+                .ellipsize(pango::EllipsizeMode::End)
+                .max_width_chars(15)
+                .build();
+            content.append(&label);
+        }
+
         let button = Button::builder()
             .child(&content)
             .tooltip_text(&item.title)
             .build();
         button.add_css_class("taskbar-item");
-        
+
         if item.is_focused {
             button.add_css_class("focused");
         }
-        
+        if item.is_xwayland {
+            button.add_css_class("xwayland");
+        }
+
         let window_id = item.id;
         button.connect_clicked(move |_| {
             info!("Taskbar item clicked: window {}", window_id);
+            if let Err(e) = wm::send(&Request::FocusWindow { id: window_id }) {
+                warn!("Failed to focus window {}: {:#}", window_id, e);
+            }
         });
-        
+
         button
     }
 }
 
+impl PanelModule for Taskbar {
+    fn name(&self) -> &str {
+        "taskbar"
+    }
+
+    fn build(&self, ctx: &ModuleContext) -> gtk4::Widget {
+        self.build_widget(ctx.orientation, ctx.monitor).upcast()
+    }
+}
+
 impl Default for Taskbar {
     fn default() -> Self {
-        Self::new()
+        Self::new(false)
     }
 }
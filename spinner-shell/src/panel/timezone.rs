@@ -0,0 +1,117 @@
+//! System and world clock timezones
+//!
+//! `chrono::Local` settles on a zone when it is first used, so a timezone
+//! changed with `timedatectl set-timezone` would only show after a restart.
+//! The system zone is instead resolved by name from `TZ`, the
+//! `/etc/localtime` symlink or `/etc/timezone`, and re-checked on every
+//! clock tick.
+
+use chrono::{DateTime, Local, Offset, TimeZone, Utc};
+use chrono_tz::Tz;
+use std::fs;
+use std::path::Path;
+use tracing::{info, warn};
+
+const LOCALTIME: &str = "/etc/localtime";
+const TIMEZONE_FILE: &str = "/etc/timezone";
+
+/// A zone to show time in. `Local` is the fallback when the system zone
+/// cannot be named, e.g. when `/etc/localtime` is a copy rather than a link.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Zone {
+    Named(Tz),
+    Local,
+}
+
+impl Zone {
+    pub fn format(&self, time: DateTime<Utc>, format: &str) -> String {
+        match self {
+            Zone::Named(tz) => time.with_timezone(tz).format(format).to_string(),
+            Zone::Local => time.with_timezone(&Local).format(format).to_string(),
+        }
+    }
+
+    /// Seconds east of UTC at `time`.
+    pub fn offset_seconds(&self, time: DateTime<Utc>) -> i32 {
+        match self {
+            Zone::Named(tz) => tz.offset_from_utc_datetime(&time.naive_utc()).fix().local_minus_utc(),
+            Zone::Local => Local.offset_from_utc_datetime(&time.naive_utc()).local_minus_utc(),
+        }
+    }
+
+    pub fn date_naive(&self, time: DateTime<Utc>) -> chrono::NaiveDate {
+        match self {
+            Zone::Named(tz) => time.with_timezone(tz).date_naive(),
+            Zone::Local => time.with_timezone(&Local).date_naive(),
+        }
+    }
+}
+
+/// Parses an IANA name such as "Europe/Berlin", logging unknown names.
+pub fn parse_zone(name: &str) -> Option<Tz> {
+    match name.trim().parse::<Tz>() {
+        Ok(tz) => Some(tz),
+        Err(_) => {
+            warn!("Unknown timezone {:?}", name);
+            None
+        }
+    }
+}
+
+/// Follows the system timezone across changes at runtime.
+pub struct SystemZone {
+    zone: Zone,
+}
+
+impl SystemZone {
+    pub fn new() -> Self {
+        Self {
+            zone: resolve_system_zone(),
+        }
+    }
+
+    pub fn zone(&self) -> Zone {
+        self.zone
+    }
+
+    /// Re-resolves the zone; returns whether it changed.
+    pub fn refresh(&mut self) -> bool {
+        let zone = resolve_system_zone();
+        if zone == self.zone {
+            return false;
+        }
+
+        info!("System timezone changed from {:?} to {:?}", self.zone, zone);
+        self.zone = zone;
+        true
+    }
+}
+
+impl Default for SystemZone {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn resolve_system_zone() -> Zone {
+    let from_env = std::env::var("TZ")
+        .ok()
+        .map(|tz| tz.trim_start_matches(':').to_string())
+        .filter(|tz| !tz.is_empty());
+
+    let name = from_env
+        .or_else(|| zone_from_link(Path::new(LOCALTIME)))
+        .or_else(|| fs::read_to_string(TIMEZONE_FILE).ok().map(|s| s.trim().to_string()));
+
+    name.and_then(|name| name.parse::<Tz>().ok())
+        .map(Zone::Named)
+        .unwrap_or(Zone::Local)
+}
+
+/// "/usr/share/zoneinfo/Europe/Berlin" -> "Europe/Berlin"
+fn zone_from_link(path: &Path) -> Option<String> {
+    let target = fs::read_link(path).ok()?;
+    let target = target.to_string_lossy();
+    let (_, name) = target.split_once("zoneinfo/")?;
+    Some(name.to_string())
+}
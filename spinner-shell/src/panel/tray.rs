@@ -0,0 +1,287 @@
+//! Status icons - Third-party tray items, with their tooltips and menus
+
+use crate::tray::{self, MenuItem, Pixmap, Status, Toggle, TrayEvent, TrayItem};
+
+use gtk4::prelude::*;
+use gtk4::{
+    self, gdk, gio, glib, Box as GtkBox, Button, EventControllerScroll, EventControllerScrollFlags, GestureClick,
+    IconTheme, Image, Orientation, PopoverMenu,
+};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+const ICON_SIZE: i32 = 16;
+
+pub struct StatusIcons {
+    container: GtkBox,
+    items: RefCell<HashMap<String, Rc<ItemButton>>>,
+}
+
+impl StatusIcons {
+    pub fn build(orientation: Orientation) -> GtkBox {
+        let container = GtkBox::builder()
+            .orientation(orientation)
+            .spacing(4)
+            .visible(false)
+            .build();
+        container.add_css_class("status-icons");
+
+        let icons = Self {
+            container: container.clone(),
+            items: RefCell::new(HashMap::new()),
+        };
+
+        // The task owns the icons and stops with the container.
+        let receiver = tray::subscribe();
+        glib::MainContext::default().spawn_local(async move {
+            while let Ok(event) = receiver.recv().await {
+                if icons.container.parent().is_none() {
+                    break;
+                }
+                icons.update(event);
+            }
+        });
+
+        container
+    }
+
+    fn update(&self, event: TrayEvent) {
+        match event {
+            TrayEvent::Changed(item) => {
+                let existing = self.items.borrow().get(&item.id).cloned();
+                match existing {
+                    Some(button) => button.update(*item),
+                    None => {
+                        let button = ItemButton::build(*item);
+                        self.container.append(&button.button);
+                        self.items.borrow_mut().insert(button.item.borrow().id.clone(), button);
+                    }
+                }
+            }
+            TrayEvent::Removed(id) => {
+                if let Some(button) = self.items.borrow_mut().remove(&id) {
+                    self.container.remove(&button.button);
+                }
+            }
+        }
+
+        let visible = self.items.borrow().values().any(|b| b.button.is_visible());
+        self.container.set_visible(visible);
+    }
+}
+
+struct ItemButton {
+    button: Button,
+    image: Image,
+    menu: PopoverMenu,
+    item: RefCell<TrayItem>,
+}
+
+impl ItemButton {
+    fn build(item: TrayItem) -> Rc<Self> {
+        let image = Image::builder().pixel_size(ICON_SIZE).build();
+        let button = Button::builder().child(&image).build();
+        button.add_css_class("systray-button");
+        button.add_css_class("status-icon");
+
+        let menu = PopoverMenu::builder().has_arrow(false).build();
+        menu.add_css_class("systray-popover");
+        menu.set_parent(&button);
+        let menu_clone = menu.clone();
+        button.connect_destroy(move |_| {
+            menu_clone.unparent();
+        });
+
+        let this = Rc::new(Self {
+            button,
+            image,
+            menu,
+            item: RefCell::new(item.clone()),
+        });
+        this.update(item);
+        this.connect_signals();
+        this
+    }
+
+    fn connect_signals(self: &Rc<Self>) {
+        let weak = Rc::downgrade(self);
+        self.button.connect_clicked(move |_| {
+            if let Some(this) = weak.upgrade() {
+                this.activate();
+            }
+        });
+
+        // Right click opens the menu, middle click is the secondary action.
+        let click = GestureClick::builder().button(0).build();
+        let weak = Rc::downgrade(self);
+        click.connect_pressed(move |gesture, _, _, _| {
+            let Some(this) = weak.upgrade() else {
+                return;
+            };
+            match gesture.current_button() {
+                gdk::BUTTON_SECONDARY => this.open_menu(),
+                gdk::BUTTON_MIDDLE => {
+                    tray::secondary_activate(&this.item.borrow(), 0, 0);
+                }
+                _ => return,
+            }
+            gesture.set_state(gtk4::EventSequenceState::Claimed);
+        });
+        self.button.add_controller(click);
+
+        let flags = EventControllerScrollFlags::BOTH_AXES | EventControllerScrollFlags::DISCRETE;
+        let scroll = EventControllerScroll::new(flags);
+        let weak = Rc::downgrade(self);
+        scroll.connect_scroll(move |_, dx, dy| {
+            if let Some(this) = weak.upgrade() {
+                let item = this.item.borrow();
+                if dy != 0.0 {
+                    tray::scroll(&item, dy.round() as i32, true);
+                } else if dx != 0.0 {
+                    tray::scroll(&item, dx.round() as i32, false);
+                }
+            }
+            glib::Propagation::Stop
+        });
+        self.button.add_controller(scroll);
+    }
+
+    fn update(&self, item: TrayItem) {
+        self.button.set_visible(item.status != Status::Passive);
+        self.set_icon(&item);
+
+        match &item.tooltip {
+            Some(tooltip) => {
+                let title = glib::markup_escape_text(&tooltip.title);
+                let description = if pango::parse_markup(&tooltip.description, '\0').is_ok() {
+                    tooltip.description.clone()
+                } else {
+                    glib::markup_escape_text(&tooltip.description).to_string()
+                };
+                let markup = match (title.is_empty(), description.is_empty()) {
+                    (false, false) => format!("<b>{}</b>\n{}", title, description),
+                    (false, true) => title.to_string(),
+                    (true, _) => description,
+                };
+                self.button.set_tooltip_markup(Some(&markup));
+            }
+            None => self.button.set_tooltip_text(Some(&item.title)),
+        }
+
+        *self.item.borrow_mut() = item;
+    }
+
+    /// Prefers the named icon, from the theme or the item's own directory,
+    /// and falls back to the pixmap.
+    fn set_icon(&self, item: &TrayItem) {
+        let theme = IconTheme::for_display(&self.button.display());
+        if let Some(path) = &item.icon_theme_path {
+            if !theme.search_path().iter().any(|p| p.as_os_str() == path.as_str()) {
+                theme.add_search_path(path);
+            }
+        }
+
+        match item.current_icon() {
+            (Some(name), _) if name.starts_with('/') => self.image.set_from_file(Some(name)),
+            (Some(name), _) if theme.has_icon(name) => self.image.set_from_icon_name(Some(name)),
+            (_, Some(pixmap)) => self.image.set_from_paintable(Some(&texture(pixmap))),
+            (Some(name), None) => self.image.set_from_icon_name(Some(name)),
+            (None, None) => self.image.set_from_icon_name(Some("image-missing")),
+        }
+    }
+
+    /// Items that only have a menu, or don't implement `Activate`, get
+    /// their menu instead.
+    fn activate(self: &Rc<Self>) {
+        if self.item.borrow().item_is_menu {
+            self.open_menu();
+            return;
+        }
+        let result = tray::activate(&self.item.borrow(), 0, 0);
+        let weak = Rc::downgrade(self);
+        glib::MainContext::default().spawn_local(async move {
+            if let (Ok(Err(_)), Some(this)) = (result.recv().await, weak.upgrade()) {
+                this.open_menu();
+            }
+        });
+    }
+
+    fn open_menu(self: &Rc<Self>) {
+        if self.item.borrow().menu.is_none() {
+            tray::context_menu(&self.item.borrow(), 0, 0);
+            return;
+        }
+
+        let result = tray::fetch_menu(&self.item.borrow());
+        let weak = Rc::downgrade(self);
+        glib::MainContext::default().spawn_local(async move {
+            let (Ok(result), Some(this)) = (result.recv().await, weak.upgrade()) else {
+                return;
+            };
+            let Ok(entries) = result else {
+                return;
+            };
+            if entries.is_empty() {
+                return;
+            }
+            let actions = gio::SimpleActionGroup::new();
+            let model = menu_model(&entries, &actions, &this.item.borrow());
+            this.button.insert_action_group("tray", Some(&actions));
+            this.menu.set_menu_model(Some(&model));
+            this.menu.popup();
+        });
+    }
+}
+
+/// Converts a D-Bus menu to a menu model, with an action per entry in
+/// `actions`. Separators start a new section.
+fn menu_model(entries: &[MenuItem], actions: &gio::SimpleActionGroup, item: &TrayItem) -> gio::Menu {
+    let menu = gio::Menu::new();
+    let mut section = gio::Menu::new();
+
+    for entry in entries {
+        if entry.separator {
+            if section.n_items() > 0 {
+                menu.append_section(None, &section);
+                section = gio::Menu::new();
+            }
+            continue;
+        }
+        if !entry.children.is_empty() {
+            section.append_submenu(Some(&entry.label), &menu_model(&entry.children, actions, item));
+            continue;
+        }
+
+        let name = format!("item-{}", entry.id);
+        let action = match entry.toggle {
+            Toggle::None => gio::SimpleAction::new(&name, None),
+            Toggle::Checkmark(active) | Toggle::Radio(active) => {
+                gio::SimpleAction::new_stateful(&name, None, &active.to_variant())
+            }
+        };
+        action.set_enabled(entry.enabled);
+        let target = item.clone();
+        let id = entry.id;
+        action.connect_activate(move |_, _| {
+            tray::activate_menu_item(&target, id);
+        });
+        actions.add_action(&action);
+        section.append(Some(&entry.label), Some(&format!("tray.{}", name)));
+    }
+
+    if section.n_items() > 0 {
+        menu.append_section(None, &section);
+    }
+    menu
+}
+
+fn texture(pixmap: &Pixmap) -> gdk::MemoryTexture {
+    gdk::MemoryTexture::new(
+        pixmap.width,
+        pixmap.height,
+        gdk::MemoryFormat::R8g8b8a8,
+        &glib::Bytes::from(pixmap.data.as_slice()),
+        pixmap.width as usize * 4,
+    )
+}
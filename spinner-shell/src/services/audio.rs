@@ -0,0 +1,366 @@
+//! Audio through the PulseAudio API
+//!
+//! Talks to the sound server with `pactl`, which PipeWire serves through
+//! pipewire-pulse as well as PulseAudio itself. State is read with
+//! `pactl -f json` and refreshed whenever `pactl subscribe` reports a
+//! change. A null sink (`pactl load-module module-null-sink`) is enough
+//! to try it without sound hardware.
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader};
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::time::Duration;
+use tracing::{debug, warn};
+
+/// `PA_VOLUME_NORM`, the raw volume of 100%.
+const VOLUME_NORM: f64 = 65536.0;
+/// Highest volume offered by the sliders and scrolling.
+pub const MAX_VOLUME: u32 = 100;
+const RESTART_DELAY: Duration = Duration::from_secs(5);
+/// Bursts of events, e.g. while a slider is dragged, are coalesced.
+const SETTLE_DELAY: Duration = Duration::from_millis(50);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Device {
+    pub name: String,
+    pub description: String,
+    /// Percent of the normal volume, averaged over channels.
+    pub volume: u32,
+    pub mute: bool,
+}
+
+/// An application's playback stream (a sink input).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stream {
+    pub index: u32,
+    pub app_name: String,
+    pub media_name: String,
+    pub icon_name: Option<String>,
+    pub volume: u32,
+    pub mute: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AudioState {
+    pub sinks: Vec<Device>,
+    /// Inputs, without the monitors of sinks.
+    pub sources: Vec<Device>,
+    pub streams: Vec<Stream>,
+    pub default_sink: Option<String>,
+    pub default_source: Option<String>,
+}
+
+impl AudioState {
+    pub fn default_sink(&self) -> Option<&Device> {
+        let name = self.default_sink.as_ref()?;
+        self.sinks.iter().find(|s| &s.name == name)
+    }
+
+    pub fn default_source(&self) -> Option<&Device> {
+        let name = self.default_source.as_ref()?;
+        self.sources.iter().find(|s| &s.name == name)
+    }
+}
+
+pub fn volume_icon(volume: u32, mute: bool) -> &'static str {
+    if mute || volume == 0 {
+        "audio-volume-muted-symbolic"
+    } else if volume < 33 {
+        "audio-volume-low-symbolic"
+    } else if volume < 66 {
+        "audio-volume-medium-symbolic"
+    } else {
+        "audio-volume-high-symbolic"
+    }
+}
+
+#[derive(Deserialize)]
+struct RawInfo {
+    default_sink_name: Option<String>,
+    default_source_name: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct RawChannel {
+    value: f64,
+}
+
+#[derive(Deserialize)]
+struct RawDevice {
+    name: String,
+    #[serde(default)]
+    description: String,
+    mute: bool,
+    volume: BTreeMap<String, RawChannel>,
+    /// Set on sources that monitor a sink; "n/a" otherwise.
+    #[serde(default)]
+    monitor_of_sink: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct RawStream {
+    index: u32,
+    mute: bool,
+    volume: BTreeMap<String, RawChannel>,
+    #[serde(default)]
+    properties: BTreeMap<String, serde_json::Value>,
+}
+
+impl RawStream {
+    fn property(&self, key: &str) -> Option<String> {
+        self.properties.get(key)?.as_str().map(str::to_string)
+    }
+}
+
+fn percent(volume: &BTreeMap<String, RawChannel>) -> u32 {
+    if volume.is_empty() {
+        return 0;
+    }
+    let average = volume.values().map(|c| c.value).sum::<f64>() / volume.len() as f64;
+    (average / VOLUME_NORM * 100.0).round() as u32
+}
+
+impl From<RawDevice> for Device {
+    fn from(raw: RawDevice) -> Self {
+        Self {
+            volume: percent(&raw.volume),
+            description: if raw.description.is_empty() {
+                raw.name.clone()
+            } else {
+                raw.description
+            },
+            name: raw.name,
+            mute: raw.mute,
+        }
+    }
+}
+
+impl From<RawStream> for Stream {
+    fn from(raw: RawStream) -> Self {
+        Self {
+            index: raw.index,
+            app_name: raw
+                .property("application.name")
+                .unwrap_or_else(|| "Unknown application".to_string()),
+            media_name: raw.property("media.name").unwrap_or_default(),
+            icon_name: raw.property("application.icon_name"),
+            volume: percent(&raw.volume),
+            mute: raw.mute,
+        }
+    }
+}
+
+fn pactl_json<T: for<'de> Deserialize<'de>>(args: &[&str]) -> Result<T> {
+    let output = Command::new("pactl")
+        .args(["-f", "json"])
+        .args(args)
+        .output()
+        .context("Failed to run pactl")?;
+    if !output.status.success() {
+        bail!(
+            "pactl {} exited with {}: {}",
+            args.join(" "),
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    serde_json::from_slice(&output.stdout).with_context(|| format!("Unexpected output from pactl {}", args.join(" ")))
+}
+
+pub fn query() -> Result<AudioState> {
+    let info: RawInfo = pactl_json(&["info"])?;
+    let sinks: Vec<RawDevice> = pactl_json(&["list", "sinks"])?;
+    let sources: Vec<RawDevice> = pactl_json(&["list", "sources"])?;
+    let streams: Vec<RawStream> = pactl_json(&["list", "sink-inputs"])?;
+
+    Ok(AudioState {
+        sinks: sinks.into_iter().map(Device::from).collect(),
+        sources: sources
+            .into_iter()
+            .filter(|s| s.monitor_of_sink.as_deref().is_none_or(|m| m == "n/a"))
+            .map(Device::from)
+            .collect(),
+        streams: streams.into_iter().map(Stream::from).collect(),
+        default_sink: info.default_sink_name,
+        default_source: info.default_source_name,
+    })
+}
+
+/// Streams the audio state, refreshed on every change the sound server
+/// reports. The workers stop once the receiver is dropped.
+pub fn subscribe() -> async_channel::Receiver<AudioState> {
+    let (sender, receiver) = async_channel::unbounded();
+    let (changes, changed) = mpsc::channel();
+
+    std::thread::spawn(move || loop {
+        match watch_events(&changes) {
+            Ok(()) => return,
+            Err(e) => debug!("Audio event stream interrupted: {:#}", e),
+        }
+        std::thread::sleep(RESTART_DELAY);
+        // The server may have restarted; pick up whatever changed meanwhile.
+        if changes.send(()).is_err() {
+            return;
+        }
+    });
+
+    std::thread::spawn(move || {
+        let mut last = None;
+        loop {
+            match query() {
+                Ok(state) if last.as_ref() != Some(&state) => {
+                    if sender.send_blocking(state.clone()).is_err() {
+                        return;
+                    }
+                    last = Some(state);
+                }
+                Ok(_) => {}
+                Err(e) => warn!("Failed to read audio state: {:#}", e),
+            }
+
+            if changed.recv().is_err() {
+                return;
+            }
+            std::thread::sleep(SETTLE_DELAY);
+            while changed.try_recv().is_ok() {}
+        }
+    });
+
+    receiver
+}
+
+/// Returns `Ok` only when nobody is listening any more.
+fn watch_events(changes: &mpsc::Sender<()>) -> Result<()> {
+    let mut child = Command::new("pactl")
+        .arg("subscribe")
+        .stdout(Stdio::piped())
+        .spawn()
+        .context("Failed to run pactl subscribe")?;
+    let stdout = child.stdout.take().context("No stdout from pactl subscribe")?;
+
+    for line in BufReader::new(stdout).lines() {
+        // "Event 'change' on sink #54"
+        let line = line?;
+        let relevant = ["sink", "source", "server"].iter().any(|kind| line.contains(kind));
+        if relevant && changes.send(()).is_err() {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Ok(());
+        }
+    }
+
+    let status = child.wait()?;
+    bail!("pactl subscribe exited with {}", status)
+}
+
+/// Runs a pactl command without waiting for it; the change comes back
+/// through the subscription.
+fn pactl(args: &[&str]) {
+    debug!("pactl {}", args.join(" "));
+    match Command::new("pactl").args(args).spawn() {
+        Ok(mut child) => {
+            std::thread::spawn(move || child.wait());
+        }
+        Err(e) => warn!("Failed to run pactl {}: {}", args.join(" "), e),
+    }
+}
+
+pub fn set_sink_volume(volume: u32) {
+    pactl(&["set-sink-volume", "@DEFAULT_SINK@", &format!("{}%", volume.min(MAX_VOLUME))]);
+}
+
+pub fn set_sink_mute(mute: bool) {
+    pactl(&["set-sink-mute", "@DEFAULT_SINK@", if mute { "1" } else { "0" }]);
+}
+
+pub fn set_default_sink(name: &str) {
+    pactl(&["set-default-sink", name]);
+}
+
+pub fn set_source_mute(mute: bool) {
+    pactl(&["set-source-mute", "@DEFAULT_SOURCE@", if mute { "1" } else { "0" }]);
+}
+
+pub fn set_stream_volume(index: u32, volume: u32) {
+    pactl(&[
+        "set-sink-input-volume",
+        &index.to_string(),
+        &format!("{}%", volume.min(MAX_VOLUME)),
+    ]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A null sink, unloaded again on drop so a failed assertion doesn't
+    /// leave it behind.
+    struct NullSink {
+        module: String,
+        name: String,
+    }
+
+    impl NullSink {
+        /// `None` when there is no pactl or no sound server to load into.
+        fn load() -> Option<Self> {
+            let name = format!("spinner_test_{}", std::process::id());
+            let output = Command::new("pactl")
+                .args(["load-module", "module-null-sink", &format!("sink_name={}", name)])
+                .output();
+            match output {
+                Ok(output) if output.status.success() => Some(Self {
+                    module: String::from_utf8_lossy(&output.stdout).trim().to_string(),
+                    name,
+                }),
+                Ok(output) => {
+                    eprintln!("Skipping, no sound server: {}", String::from_utf8_lossy(&output.stderr).trim());
+                    None
+                }
+                Err(e) => {
+                    eprintln!("Skipping, pactl is not available: {}", e);
+                    None
+                }
+            }
+        }
+    }
+
+    impl Drop for NullSink {
+        fn drop(&mut self) {
+            let _ = Command::new("pactl").args(["unload-module", &self.module]).status();
+        }
+    }
+
+    /// Unlike `pactl()`, waits for the change to be made.
+    fn run(args: &[&str]) {
+        let status = Command::new("pactl").args(args).status().unwrap();
+        assert!(status.success(), "pactl {} exited with {}", args.join(" "), status);
+    }
+
+    #[test]
+    fn query_sees_a_null_sink() {
+        let Some(sink) = NullSink::load() else {
+            return;
+        };
+        run(&["set-sink-volume", &sink.name, "37%"]);
+        run(&["set-sink-mute", &sink.name, "1"]);
+
+        let state = query().unwrap();
+        let device = state
+            .sinks
+            .iter()
+            .find(|d| d.name == sink.name)
+            .expect("The null sink is not listed");
+        assert_eq!((device.volume, device.mute), (37, true));
+
+        // Its monitor is not an input.
+        let monitor = format!("{}.monitor", sink.name);
+        assert!(state.sources.iter().all(|s| s.name != monitor));
+
+        run(&["set-sink-mute", &sink.name, "0"]);
+        let state = query().unwrap();
+        assert!(state.sinks.iter().any(|d| d.name == sink.name && !d.mute));
+    }
+}
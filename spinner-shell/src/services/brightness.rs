@@ -0,0 +1,140 @@
+//! Screen brightness through the kernel's backlight class
+//!
+//! The level is polled from `/sys/class/backlight`, which sends no change
+//! events, and set through logind's `SetBrightness` so the shell needs no
+//! write access to sysfs.
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::Path;
+use std::sync::mpsc;
+use std::sync::OnceLock;
+use std::time::Duration;
+use tracing::{debug, warn};
+use zbus::blocking::Connection;
+
+const BACKLIGHT: &str = "/sys/class/backlight";
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Preferred first, as the kernel documentation suggests: firmware
+/// interfaces know the panel best, raw registers least.
+const TYPES: [&str; 3] = ["firmware", "platform", "raw"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Backlight {
+    /// The device under `/sys/class/backlight`.
+    pub name: String,
+    pub brightness: u32,
+    pub max_brightness: u32,
+}
+
+impl Backlight {
+    pub fn percentage(&self) -> u32 {
+        if self.max_brightness == 0 {
+            return 0;
+        }
+        ((self.brightness as f64 / self.max_brightness as f64) * 100.0).round() as u32
+    }
+}
+
+/// Streams the screen's backlight, or `None` for screens without one.
+/// The worker stops once the receiver is dropped.
+pub fn subscribe() -> async_channel::Receiver<Option<Backlight>> {
+    let (sender, receiver) = async_channel::unbounded();
+
+    std::thread::spawn(move || {
+        let mut last = None;
+        loop {
+            let backlight = query().unwrap_or_else(|e| {
+                debug!("No backlight: {:#}", e);
+                None
+            });
+            if last.as_ref() != Some(&backlight) {
+                if sender.send_blocking(backlight.clone()).is_err() {
+                    return;
+                }
+                last = Some(backlight);
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        }
+    });
+
+    receiver
+}
+
+fn read_number(dir: &Path, name: &str) -> Option<u32> {
+    fs::read_to_string(dir.join(name)).ok()?.trim().parse().ok()
+}
+
+fn query() -> Result<Option<Backlight>> {
+    let mut devices: Vec<(usize, String)> = fs::read_dir(BACKLIGHT)?
+        .filter_map(|e| e.ok())
+        .filter_map(|entry| {
+            let kind = fs::read_to_string(entry.path().join("type")).ok()?;
+            let rank = TYPES
+                .iter()
+                .position(|t| *t == kind.trim())
+                .unwrap_or(TYPES.len());
+            Some((rank, entry.file_name().to_string_lossy().into_owned()))
+        })
+        .collect();
+    devices.sort();
+    let Some((_, name)) = devices.into_iter().next() else {
+        return Ok(None);
+    };
+
+    let dir = Path::new(BACKLIGHT).join(&name);
+    // actual_brightness is what the hardware reports, which may lag.
+    let brightness = read_number(&dir, "actual_brightness")
+        .or_else(|| read_number(&dir, "brightness"))
+        .context("Unreadable brightness")?;
+    let max_brightness =
+        read_number(&dir, "max_brightness").context("Unreadable max_brightness")?;
+    Ok(Some(Backlight {
+        name,
+        brightness,
+        max_brightness,
+    }))
+}
+
+/// Sets the brightness in percent. Never goes fully dark, which would
+/// leave no way to see the slider again. Requests are handled on one
+/// worker, which skips to the latest while a slider is dragged.
+pub fn set_brightness(backlight: &Backlight, percent: u32) {
+    static WORKER: OnceLock<mpsc::Sender<(String, u32)>> = OnceLock::new();
+
+    let value = (backlight.max_brightness as u64 * percent.min(100) as u64 / 100).max(1) as u32;
+    let worker = WORKER.get_or_init(|| {
+        let (sender, requests) = mpsc::channel::<(String, u32)>();
+        std::thread::spawn(move || {
+            let connection = match Connection::system() {
+                Ok(connection) => connection,
+                Err(e) => {
+                    warn!("Failed to connect to the system bus: {}", e);
+                    return;
+                }
+            };
+            while let Ok(mut request) = requests.recv() {
+                while let Ok(newer) = requests.try_recv() {
+                    request = newer;
+                }
+                if let Err(e) = logind_set_brightness(&connection, &request.0, request.1) {
+                    warn!("Failed to set the brightness: {:#}", e);
+                }
+            }
+        });
+        sender
+    });
+    let _ = worker.send((backlight.name.clone(), value));
+}
+
+fn logind_set_brightness(connection: &Connection, name: &str, value: u32) -> Result<()> {
+    connection.call_method(
+        Some("org.freedesktop.login1"),
+        "/org/freedesktop/login1/session/auto",
+        Some("org.freedesktop.login1.Session"),
+        "SetBrightness",
+        &("backlight", name, value),
+    )?;
+    Ok(())
+}
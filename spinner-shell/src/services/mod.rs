@@ -0,0 +1,28 @@
+//! Clients for the system services behind the panel's indicators
+
+pub mod audio;
+pub mod brightness;
+pub mod network;
+pub mod night_light;
+pub mod power;
+pub mod session;
+
+use std::sync::{mpsc, Arc};
+use zbus::blocking::MessageIterator;
+use zbus::Message;
+
+/// Moves a signal stream onto a thread of its own, so that a burst of
+/// signals can be drained after the first and answered with one query.
+/// The thread ends with the stream, or with the next signal once the
+/// receiver is gone.
+pub(super) fn signal_channel(signals: MessageIterator) -> mpsc::Receiver<zbus::Result<Arc<Message>>> {
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        for signal in signals {
+            if sender.send(signal).is_err() {
+                return;
+            }
+        }
+    });
+    receiver
+}
@@ -0,0 +1,597 @@
+//! Networking through NetworkManager
+//!
+//! State is read from `org.freedesktop.NetworkManager` on the system bus
+//! and re-read whenever it signals a change. Connecting, toggling Wi-Fi and
+//! VPNs go through the same API; airplane mode is rfkill's, since
+//! NetworkManager only knows about its own radios.
+
+use anyhow::{bail, Context, Result};
+use std::collections::HashMap;
+use std::fs;
+use std::process::Command;
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+use zbus::blocking::{Connection, MessageIterator};
+use zbus::zvariant::{ObjectPath, OwnedObjectPath, OwnedValue, Value};
+
+const NM: &str = "org.freedesktop.NetworkManager";
+const NM_PATH: &str = "/org/freedesktop/NetworkManager";
+const SETTINGS_PATH: &str = "/org/freedesktop/NetworkManager/Settings";
+const SETTINGS: &str = "org.freedesktop.NetworkManager.Settings";
+const SETTINGS_CONNECTION: &str = "org.freedesktop.NetworkManager.Settings.Connection";
+const ACTIVE: &str = "org.freedesktop.NetworkManager.Connection.Active";
+const DEVICE: &str = "org.freedesktop.NetworkManager.Device";
+const WIRELESS: &str = "org.freedesktop.NetworkManager.Device.Wireless";
+const ACCESS_POINT: &str = "org.freedesktop.NetworkManager.AccessPoint";
+const PROPERTIES: &str = "org.freedesktop.DBus.Properties";
+const RFKILL: &str = "/sys/class/rfkill";
+
+const RESTART_DELAY: Duration = Duration::from_secs(5);
+/// Access points report signal changes constantly; they are coalesced.
+const SETTLE_DELAY: Duration = Duration::from_millis(500);
+/// How long a new connection may take to come up before it counts as
+/// failed, e.g. on a wrong password.
+const ACTIVATION_TIMEOUT: Duration = Duration::from_secs(30);
+
+// NM_DEVICE_TYPE_*
+const DEVICE_WIFI: u32 = 2;
+// NM_ACTIVE_CONNECTION_STATE_*
+const ACTIVATING: u32 = 1;
+const ACTIVATED: u32 = 2;
+const DEACTIVATED: u32 = 4;
+// NM_CONNECTIVITY_*; unknown when checking is off.
+const CONNECTIVITY_UNKNOWN: u32 = 0;
+const CONNECTIVITY_FULL: u32 = 4;
+// NM_802_11_AP_FLAGS_PRIVACY
+const AP_PRIVACY: u32 = 0x1;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum Primary {
+    #[default]
+    None,
+    Wired {
+        name: String,
+    },
+    Wifi {
+        ssid: String,
+        strength: u8,
+    },
+    Other {
+        name: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AccessPoint {
+    pub ssid: String,
+    /// Percent.
+    pub strength: u8,
+    pub secured: bool,
+    pub active: bool,
+    /// Whether a saved connection exists, so no password is needed.
+    pub known: bool,
+    path: OwnedObjectPath,
+    device: OwnedObjectPath,
+    connection: Option<OwnedObjectPath>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Vpn {
+    pub name: String,
+    pub active: bool,
+    connection: OwnedObjectPath,
+    active_connection: Option<OwnedObjectPath>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NetworkState {
+    pub primary: Primary,
+    /// A connection is coming up.
+    pub connecting: bool,
+    /// Connected, but without a route to the internet (or behind a portal).
+    pub limited: bool,
+    pub has_wifi: bool,
+    pub wifi_enabled: bool,
+    pub airplane: bool,
+    /// One entry per network name, strongest first, the active one on top.
+    pub access_points: Vec<AccessPoint>,
+    pub vpns: Vec<Vpn>,
+}
+
+impl NetworkState {
+    pub fn vpn_active(&self) -> bool {
+        self.vpns.iter().any(|v| v.active)
+    }
+
+    pub fn icon_name(&self) -> &'static str {
+        if self.airplane && self.primary == Primary::None {
+            return "airplane-mode-symbolic";
+        }
+        if self.vpn_active() {
+            return "network-vpn-symbolic";
+        }
+        match &self.primary {
+            Primary::Wired { .. } if self.limited => "network-wired-no-route-symbolic",
+            Primary::Wired { .. } | Primary::Other { .. } => "network-wired-symbolic",
+            Primary::Wifi { .. } if self.limited => "network-wireless-no-route-symbolic",
+            Primary::Wifi { strength, .. } => wifi_icon(*strength),
+            Primary::None if self.connecting => "network-wireless-acquiring-symbolic",
+            Primary::None if self.has_wifi && !self.wifi_enabled => "network-wireless-disabled-symbolic",
+            Primary::None => "network-offline-symbolic",
+        }
+    }
+
+    pub fn description(&self) -> String {
+        let status = match &self.primary {
+            Primary::None if self.connecting => "Connecting…".to_string(),
+            Primary::None if self.airplane => "Airplane mode".to_string(),
+            Primary::None => "Offline".to_string(),
+            Primary::Wired { name } | Primary::Other { name } => name.clone(),
+            Primary::Wifi { ssid, strength } => format!("{} ({}%)", ssid, strength),
+        };
+        let mut parts = vec![status];
+        if self.limited {
+            parts.push("limited connectivity".to_string());
+        }
+        if let Some(vpn) = self.vpns.iter().find(|v| v.active) {
+            parts.push(format!("VPN {}", vpn.name));
+        }
+        parts.join(", ")
+    }
+}
+
+pub fn wifi_icon(strength: u8) -> &'static str {
+    match strength {
+        81.. => "network-wireless-signal-excellent-symbolic",
+        56..=80 => "network-wireless-signal-good-symbolic",
+        31..=55 => "network-wireless-signal-ok-symbolic",
+        6..=30 => "network-wireless-signal-weak-symbolic",
+        _ => "network-wireless-signal-none-symbolic",
+    }
+}
+
+/// Streams the network state, refreshed on every change NetworkManager
+/// reports. The worker stops once the receiver is dropped.
+pub fn subscribe() -> async_channel::Receiver<NetworkState> {
+    let (sender, receiver) = async_channel::unbounded();
+
+    std::thread::spawn(move || {
+        let mut last = None;
+        loop {
+            match watch(&sender, &mut last) {
+                Ok(()) => return,
+                Err(e) => debug!("NetworkManager unavailable: {:#}", e),
+            }
+            // Shown as offline until NetworkManager (re)appears.
+            if last.is_none() {
+                if sender.send_blocking(NetworkState::default()).is_err() {
+                    return;
+                }
+                last = Some(NetworkState::default());
+            }
+            std::thread::sleep(RESTART_DELAY);
+        }
+    });
+
+    receiver
+}
+
+/// Returns `Ok` only when nobody is listening any more.
+fn watch(sender: &async_channel::Sender<NetworkState>, last: &mut Option<NetworkState>) -> Result<()> {
+    let connection = Connection::system().context("Failed to connect to the system bus")?;
+    let rule = format!("type='signal',sender='{}'", NM);
+    let signals = super::signal_channel(MessageIterator::for_match_rule(rule.as_str(), &connection, None)?);
+
+    let mut send = |state: NetworkState| {
+        if last.as_ref() == Some(&state) {
+            return true;
+        }
+        let sent = sender.send_blocking(state.clone()).is_ok();
+        *last = Some(state);
+        sent
+    };
+
+    if !send(query(&connection)?) {
+        return Ok(());
+    }
+    while let Ok(signal) = signals.recv() {
+        signal?;
+        std::thread::sleep(SETTLE_DELAY);
+        while let Ok(signal) = signals.try_recv() {
+            signal?;
+        }
+        match query(&connection) {
+            Ok(state) => {
+                if !send(state) {
+                    return Ok(());
+                }
+            }
+            Err(e) => warn!("Failed to read network state: {:#}", e),
+        }
+    }
+    bail!("Signal stream ended")
+}
+
+type Properties = HashMap<String, OwnedValue>;
+
+fn get_all(connection: &Connection, path: &str, interface: &str) -> Result<Properties> {
+    Ok(connection
+        .call_method(Some(NM), path, Some(PROPERTIES), "GetAll", &interface)?
+        .body()?)
+}
+
+fn property<T>(properties: &Properties, key: &str) -> Option<T>
+where
+    T: TryFrom<OwnedValue>,
+{
+    properties.get(key).and_then(|v| T::try_from(v.clone()).ok())
+}
+
+fn object_path(properties: &Properties, key: &str) -> Option<OwnedObjectPath> {
+    property::<OwnedObjectPath>(properties, key).filter(|p| p.as_str() != "/")
+}
+
+fn ssid(properties: &Properties) -> Option<String> {
+    let bytes = properties.get("Ssid").cloned()?;
+    let bytes: Vec<u8> = Vec::try_from(bytes).ok()?;
+    Some(String::from_utf8_lossy(&bytes).into_owned()).filter(|s| !s.is_empty())
+}
+
+/// A saved connection's type, name and, for Wi-Fi, network name.
+struct Saved {
+    path: OwnedObjectPath,
+    kind: String,
+    id: String,
+    ssid: Option<String>,
+}
+
+fn saved_connections(connection: &Connection) -> Result<Vec<Saved>> {
+    let paths: Vec<OwnedObjectPath> = connection
+        .call_method(Some(NM), SETTINGS_PATH, Some(SETTINGS), "ListConnections", &())?
+        .body()?;
+
+    let mut saved = Vec::new();
+    for path in paths {
+        let settings: HashMap<String, HashMap<String, OwnedValue>> = match connection
+            .call_method(Some(NM), path.as_str(), Some(SETTINGS_CONNECTION), "GetSettings", &())
+            .and_then(|reply| reply.body())
+        {
+            Ok(settings) => settings,
+            Err(e) => {
+                debug!("Skipping connection {}: {}", path.as_str(), e);
+                continue;
+            }
+        };
+        let Some(general) = settings.get("connection") else {
+            continue;
+        };
+        saved.push(Saved {
+            kind: property(general, "type").unwrap_or_default(),
+            id: property(general, "id").unwrap_or_default(),
+            ssid: settings.get("802-11-wireless").and_then(ssid_setting),
+            path,
+        });
+    }
+    Ok(saved)
+}
+
+fn ssid_setting(wireless: &HashMap<String, OwnedValue>) -> Option<String> {
+    let bytes: Vec<u8> = Vec::try_from(wireless.get("ssid")?.clone()).ok()?;
+    Some(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+fn query(connection: &Connection) -> Result<NetworkState> {
+    let manager = get_all(connection, NM_PATH, NM)?;
+    let saved = saved_connections(connection).unwrap_or_else(|e| {
+        warn!("Failed to list saved connections: {:#}", e);
+        Vec::new()
+    });
+
+    let mut state = NetworkState {
+        wifi_enabled: property(&manager, "WirelessEnabled").unwrap_or(false),
+        airplane: airplane_mode(),
+        ..Default::default()
+    };
+
+    // Active connections: the primary one, and which VPNs are up.
+    let primary = object_path(&manager, "PrimaryConnection");
+    let active: Vec<OwnedObjectPath> = property(&manager, "ActiveConnections").unwrap_or_default();
+    let mut active_by_settings = HashMap::new();
+    for path in active {
+        let Ok(properties) = get_all(connection, path.as_str(), ACTIVE) else {
+            continue;
+        };
+        let active_state: u32 = property(&properties, "State").unwrap_or(0);
+        if active_state == ACTIVATING {
+            state.connecting = true;
+        }
+        if let Some(settings) = object_path(&properties, "Connection") {
+            active_by_settings.insert(settings, (path.clone(), active_state));
+        }
+        if primary.as_ref() != Some(&path) || active_state != ACTIVATED {
+            continue;
+        }
+
+        let name: String = property(&properties, "Id").unwrap_or_default();
+        let kind: String = property(&properties, "Type").unwrap_or_default();
+        state.primary = match kind.as_str() {
+            "802-3-ethernet" => Primary::Wired { name },
+            "802-11-wireless" => {
+                // The connection's name may differ from the network's.
+                let ap = object_path(&properties, "SpecificObject")
+                    .and_then(|ap| get_all(connection, ap.as_str(), ACCESS_POINT).ok());
+                Primary::Wifi {
+                    ssid: ap.as_ref().and_then(ssid).unwrap_or(name),
+                    strength: ap.and_then(|ap| property(&ap, "Strength")).unwrap_or(0),
+                }
+            }
+            _ => Primary::Other { name },
+        };
+    }
+
+    let connectivity: u32 = property(&manager, "Connectivity").unwrap_or(CONNECTIVITY_UNKNOWN);
+    state.limited = state.primary != Primary::None
+        && connectivity != CONNECTIVITY_UNKNOWN
+        && connectivity != CONNECTIVITY_FULL;
+
+    for saved in saved.iter().filter(|s| matches!(s.kind.as_str(), "vpn" | "wireguard")) {
+        let active = active_by_settings.get(&saved.path);
+        state.vpns.push(Vpn {
+            name: saved.id.clone(),
+            active: active.is_some_and(|(_, s)| *s == ACTIVATED),
+            connection: saved.path.clone(),
+            active_connection: active.map(|(path, _)| path.clone()),
+        });
+    }
+
+    let devices: Vec<OwnedObjectPath> = connection
+        .call_method(Some(NM), NM_PATH, Some(NM), "GetDevices", &())?
+        .body()?;
+    for device in devices {
+        let Ok(properties) = get_all(connection, device.as_str(), DEVICE) else {
+            continue;
+        };
+        if property::<u32>(&properties, "DeviceType") == Some(DEVICE_WIFI) {
+            state.has_wifi = true;
+            if state.wifi_enabled {
+                scan_results(connection, &device, &saved, &mut state.access_points)?;
+            }
+        }
+    }
+
+    // One entry per network: the active one first, then by strength.
+    state.access_points.sort_by(|a, b| b.active.cmp(&a.active).then(b.strength.cmp(&a.strength)));
+    let mut seen = Vec::new();
+    state.access_points.retain(|ap| {
+        let new = !seen.contains(&ap.ssid);
+        seen.push(ap.ssid.clone());
+        new
+    });
+
+    Ok(state)
+}
+
+fn scan_results(
+    connection: &Connection,
+    device: &OwnedObjectPath,
+    saved: &[Saved],
+    access_points: &mut Vec<AccessPoint>,
+) -> Result<()> {
+    let wireless = get_all(connection, device.as_str(), WIRELESS)?;
+    let active = object_path(&wireless, "ActiveAccessPoint");
+    let paths: Vec<OwnedObjectPath> = property(&wireless, "AccessPoints").unwrap_or_default();
+
+    for path in paths {
+        let Ok(properties) = get_all(connection, path.as_str(), ACCESS_POINT) else {
+            continue;
+        };
+        let Some(ssid) = ssid(&properties) else {
+            continue;
+        };
+        let flags: u32 = property(&properties, "Flags").unwrap_or(0);
+        let wpa: u32 = property(&properties, "WpaFlags").unwrap_or(0);
+        let rsn: u32 = property(&properties, "RsnFlags").unwrap_or(0);
+        let known = saved
+            .iter()
+            .find(|s| s.kind == "802-11-wireless" && s.ssid.as_deref() == Some(ssid.as_str()));
+
+        access_points.push(AccessPoint {
+            strength: property(&properties, "Strength").unwrap_or(0),
+            secured: flags & AP_PRIVACY != 0 || wpa != 0 || rsn != 0,
+            active: active.as_ref() == Some(&path),
+            known: known.is_some(),
+            connection: known.map(|s| s.path.clone()),
+            device: device.clone(),
+            path,
+            ssid,
+        });
+    }
+    Ok(())
+}
+
+/// All radios soft-blocked.
+fn airplane_mode() -> bool {
+    let Ok(entries) = fs::read_dir(RFKILL) else {
+        return false;
+    };
+    let mut radios = 0;
+    for entry in entries.filter_map(|e| e.ok()) {
+        let soft = fs::read_to_string(entry.path().join("soft")).unwrap_or_default();
+        if soft.trim() != "1" {
+            return false;
+        }
+        radios += 1;
+    }
+    radios > 0
+}
+
+/// Runs a blocking request on its own thread; the result comes back on
+/// the returned channel. Failures are logged as well.
+fn spawn<F>(what: &'static str, request: F) -> async_channel::Receiver<Result<()>>
+where
+    F: FnOnce(&Connection) -> Result<()> + Send + 'static,
+{
+    let (sender, receiver) = async_channel::bounded(1);
+    std::thread::spawn(move || {
+        let result = Connection::system()
+            .context("Failed to connect to the system bus")
+            .and_then(|connection| request(&connection));
+        if let Err(e) = &result {
+            warn!("Failed to {}: {:#}", what, e);
+        }
+        let _ = sender.send_blocking(result);
+    });
+    receiver
+}
+
+pub fn set_wifi_enabled(enabled: bool) -> async_channel::Receiver<Result<()>> {
+    spawn("toggle Wi-Fi", move |connection| {
+        connection.call_method(
+            Some(NM),
+            NM_PATH,
+            Some(PROPERTIES),
+            "Set",
+            &(NM, "WirelessEnabled", Value::from(enabled)),
+        )?;
+        Ok(())
+    })
+}
+
+pub fn set_airplane_mode(enabled: bool) -> async_channel::Receiver<Result<()>> {
+    spawn("toggle airplane mode", move |_| {
+        let status = Command::new("rfkill")
+            .args([if enabled { "block" } else { "unblock" }, "all"])
+            .status()
+            .context("Failed to run rfkill")?;
+        if !status.success() {
+            bail!("rfkill exited with {}", status);
+        }
+        Ok(())
+    })
+}
+
+/// Asks the Wi-Fi devices to look for networks again.
+pub fn request_scan(state: &NetworkState) {
+    let mut devices: Vec<OwnedObjectPath> = Vec::new();
+    for ap in &state.access_points {
+        if !devices.contains(&ap.device) {
+            devices.push(ap.device.clone());
+        }
+    }
+    spawn("scan for networks", move |connection| {
+        if devices.is_empty() {
+            let all: Vec<OwnedObjectPath> = connection
+                .call_method(Some(NM), NM_PATH, Some(NM), "GetDevices", &())?
+                .body()?;
+            for device in all {
+                let properties = get_all(connection, device.as_str(), DEVICE)?;
+                if property::<u32>(&properties, "DeviceType") == Some(DEVICE_WIFI) {
+                    devices.push(device);
+                }
+            }
+        }
+        for device in devices {
+            let options: HashMap<&str, Value> = HashMap::new();
+            // Refused while a scan is already running, which is fine.
+            if let Err(e) = connection.call_method(Some(NM), device.as_str(), Some(WIRELESS), "RequestScan", &options) {
+                debug!("Scan on {} refused: {}", device.as_str(), e);
+            }
+        }
+        Ok(())
+    });
+}
+
+/// Connects to a network, with the password for secured networks that
+/// have no saved connection. A new connection that fails to come up is
+/// removed again, so the next attempt asks for the password again.
+pub fn connect_wifi(ap: &AccessPoint, password: Option<String>) -> async_channel::Receiver<Result<()>> {
+    let ap = ap.clone();
+    spawn("connect to Wi-Fi", move |connection| {
+        info!("Connecting to {}", ap.ssid);
+        if let Some(saved) = &ap.connection {
+            let active: OwnedObjectPath = connection
+                .call_method(
+                    Some(NM),
+                    NM_PATH,
+                    Some(NM),
+                    "ActivateConnection",
+                    &(saved, &ap.device, &ap.path),
+                )?
+                .body()?;
+            return wait_for_activation(connection, &active);
+        }
+
+        let mut settings: HashMap<&str, HashMap<&str, Value>> = HashMap::new();
+        if let Some(password) = password {
+            let mut security = HashMap::new();
+            security.insert("key-mgmt", Value::from("wpa-psk"));
+            security.insert("psk", Value::from(password));
+            settings.insert("802-11-wireless-security", security);
+        }
+        let (saved, active): (OwnedObjectPath, OwnedObjectPath) = connection
+            .call_method(
+                Some(NM),
+                NM_PATH,
+                Some(NM),
+                "AddAndActivateConnection",
+                &(settings, &ap.device, &ap.path),
+            )?
+            .body()?;
+
+        let result = wait_for_activation(connection, &active);
+        if result.is_err() {
+            let _ = connection.call_method(Some(NM), saved.as_str(), Some(SETTINGS_CONNECTION), "Delete", &());
+        }
+        result
+    })
+}
+
+fn wait_for_activation(connection: &Connection, active: &OwnedObjectPath) -> Result<()> {
+    let started = Instant::now();
+    while started.elapsed() < ACTIVATION_TIMEOUT {
+        // The object disappears once the attempt is given up.
+        let state = get_all(connection, active.as_str(), ACTIVE)
+            .ok()
+            .and_then(|p| property::<u32>(&p, "State"))
+            .unwrap_or(DEACTIVATED);
+        match state {
+            ACTIVATED => return Ok(()),
+            DEACTIVATED => bail!("The connection could not be established; check the password"),
+            _ => std::thread::sleep(Duration::from_millis(250)),
+        }
+    }
+    bail!("Timed out connecting")
+}
+
+pub fn disconnect(ap: &AccessPoint) -> async_channel::Receiver<Result<()>> {
+    let device = ap.device.clone();
+    spawn("disconnect", move |connection| {
+        connection.call_method(Some(NM), device.as_str(), Some(DEVICE), "Disconnect", &())?;
+        Ok(())
+    })
+}
+
+pub fn set_vpn_active(vpn: &Vpn, active: bool) -> async_channel::Receiver<Result<()>> {
+    let vpn = vpn.clone();
+    spawn("toggle VPN", move |connection| {
+        if active {
+            let root = ObjectPath::try_from("/")?;
+            let path: OwnedObjectPath = connection
+                .call_method(
+                    Some(NM),
+                    NM_PATH,
+                    Some(NM),
+                    "ActivateConnection",
+                    &(&vpn.connection, &root, &root),
+                )?
+                .body()?;
+            wait_for_activation(connection, &path)
+        } else {
+            let Some(path) = &vpn.active_connection else {
+                return Ok(());
+            };
+            connection.call_method(Some(NM), NM_PATH, Some(NM), "DeactivateConnection", path)?;
+            Ok(())
+        }
+    })
+}
@@ -0,0 +1,65 @@
+//! Night light - Warmer colors by running a gamma tool such as gammastep
+//! or wlsunset for as long as it is on
+//!
+//! The tool needs the compositor's gamma control; the command comes from
+//! `[quick_settings] night_light_command`.
+
+use anyhow::{bail, Context, Result};
+use std::process::{Child, Command};
+use std::sync::Mutex;
+use tracing::{info, warn};
+
+/// Shared by every panel's quick settings.
+static RUNNING: Mutex<Option<Child>> = Mutex::new(None);
+
+/// Whether the command's program is installed.
+pub fn is_available(command: &str) -> bool {
+    let Some(program) = command.split_whitespace().next() else {
+        return false;
+    };
+    if program.contains('/') {
+        return std::path::Path::new(program).exists();
+    }
+    std::env::var_os("PATH")
+        .is_some_and(|path| std::env::split_paths(&path).any(|dir| dir.join(program).is_file()))
+}
+
+/// On while the tool runs; it may also have exited by itself.
+pub fn is_enabled() -> bool {
+    let mut running = RUNNING.lock().unwrap();
+    let exited = match running.as_mut() {
+        Some(child) => !matches!(child.try_wait(), Ok(None)),
+        None => return false,
+    };
+    if exited {
+        running.take();
+    }
+    !exited
+}
+
+pub fn set_enabled(command: &str, enabled: bool) -> Result<()> {
+    let mut running = RUNNING.lock().unwrap();
+    if let Some(mut child) = running.take() {
+        if let Err(e) = child.kill() {
+            warn!("Failed to stop night light: {}", e);
+        }
+        let _ = child.wait();
+    }
+    if !enabled {
+        info!("Night light off");
+        return Ok(());
+    }
+
+    if command.trim().is_empty() {
+        bail!("No night light command");
+    }
+    // exec, so that the child is the tool itself and killing it works.
+    let child = Command::new("sh")
+        .arg("-c")
+        .arg(format!("exec {}", command))
+        .spawn()
+        .with_context(|| format!("Failed to run {}", command))?;
+    info!("Night light on");
+    *running = Some(child);
+    Ok(())
+}
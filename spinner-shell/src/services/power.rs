@@ -0,0 +1,503 @@
+//! Batteries through UPower
+//!
+//! Devices are read from `org.freedesktop.UPower` on the system bus and
+//! re-read whenever it signals a change. Without UPower the batteries in
+//! `/sys/class/power_supply` are polled instead. Laptop batteries are
+//! reported apart from peripherals such as mice and headsets.
+
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+use tracing::{debug, info, warn};
+use zbus::blocking::{Connection, MessageIterator};
+use zbus::zvariant::{OwnedObjectPath, OwnedValue};
+
+const UPOWER: &str = "org.freedesktop.UPower";
+const UPOWER_PATH: &str = "/org/freedesktop/UPower";
+const DEVICE_INTERFACE: &str = "org.freedesktop.UPower.Device";
+const POWER_SUPPLY: &str = "/sys/class/power_supply";
+const POLL_INTERVAL: Duration = Duration::from_secs(30);
+/// Bursts of property changes, e.g. on plugging in, are coalesced.
+const SETTLE_DELAY: Duration = Duration::from_millis(200);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BatteryKind {
+    /// Powers the computer itself.
+    System,
+    Mouse,
+    Keyboard,
+    Headset,
+    Gamepad,
+    Phone,
+    Tablet,
+    Pen,
+    Other,
+}
+
+impl BatteryKind {
+    /// UPower's `Type`; `None` for mains power.
+    fn from_upower(kind: u32) -> Option<Self> {
+        Some(match kind {
+            1 => return None,
+            2 => Self::System,
+            5 | 14 => Self::Mouse,
+            6 => Self::Keyboard,
+            17..=19 => Self::Headset,
+            12 => Self::Gamepad,
+            8 => Self::Phone,
+            10 => Self::Tablet,
+            13 => Self::Pen,
+            _ => Self::Other,
+        })
+    }
+
+    pub fn icon_name(self) -> Option<&'static str> {
+        match self {
+            Self::System | Self::Other => None,
+            Self::Mouse => Some("input-mouse-symbolic"),
+            Self::Keyboard => Some("input-keyboard-symbolic"),
+            Self::Headset => Some("audio-headphones-symbolic"),
+            Self::Gamepad => Some("input-gaming-symbolic"),
+            Self::Phone => Some("phone-symbolic"),
+            Self::Tablet | Self::Pen => Some("input-tablet-symbolic"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChargeState {
+    Charging,
+    Discharging,
+    Full,
+    /// Plugged in but held below full, e.g. by a charge threshold.
+    NotCharging,
+    Unknown,
+}
+
+impl ChargeState {
+    fn from_upower(state: u32) -> Self {
+        match state {
+            1 | 5 => Self::Charging,
+            2 | 3 | 6 => Self::Discharging,
+            4 => Self::Full,
+            _ => Self::Unknown,
+        }
+    }
+
+    fn from_sysfs(status: &str) -> Self {
+        match status {
+            "Charging" => Self::Charging,
+            "Discharging" => Self::Discharging,
+            "Full" => Self::Full,
+            "Not charging" => Self::NotCharging,
+            _ => Self::Unknown,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Charging => "Charging",
+            Self::Discharging => "Discharging",
+            Self::Full => "Fully charged",
+            Self::NotCharging => "Not charging",
+            Self::Unknown => "Unknown",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Battery {
+    pub name: String,
+    pub kind: BatteryKind,
+    pub percentage: f64,
+    pub state: ChargeState,
+    pub time_to_empty: Option<Duration>,
+    pub time_to_full: Option<Duration>,
+    /// Wh; 0 when the device does not report it.
+    energy: f64,
+    energy_full: f64,
+    /// W, positive in either direction.
+    energy_rate: f64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PowerState {
+    pub batteries: Vec<Battery>,
+    pub peripherals: Vec<Battery>,
+}
+
+/// The system batteries taken together.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Summary {
+    pub percentage: f64,
+    pub state: ChargeState,
+    /// Until empty while discharging, until full while charging.
+    pub time: Option<Duration>,
+}
+
+impl PowerState {
+    pub fn is_empty(&self) -> bool {
+        self.batteries.is_empty() && self.peripherals.is_empty()
+    }
+
+    /// Combines the system batteries the way UPower's display device does:
+    /// weighted by capacity, with the time taken from the total rate.
+    pub fn summary(&self) -> Option<Summary> {
+        let first = self.batteries.first()?;
+        if self.batteries.len() == 1 {
+            return Some(Summary {
+                percentage: first.percentage,
+                state: first.state,
+                time: first.time(),
+            });
+        }
+
+        let energy: f64 = self.batteries.iter().map(|b| b.energy).sum();
+        let energy_full: f64 = self.batteries.iter().map(|b| b.energy_full).sum();
+        let rate: f64 = self.batteries.iter().map(|b| b.energy_rate).sum();
+        let percentage = if energy_full > 0.0 {
+            energy / energy_full * 100.0
+        } else {
+            self.batteries.iter().map(|b| b.percentage).sum::<f64>() / self.batteries.len() as f64
+        };
+
+        let states = || self.batteries.iter().map(|b| b.state);
+        let state = if states().any(|s| s == ChargeState::Charging) {
+            ChargeState::Charging
+        } else if states().any(|s| s == ChargeState::Discharging) {
+            ChargeState::Discharging
+        } else if states().all(|s| s == ChargeState::Full) {
+            ChargeState::Full
+        } else if states().any(|s| s == ChargeState::NotCharging) {
+            ChargeState::NotCharging
+        } else {
+            ChargeState::Unknown
+        };
+
+        let hours = match state {
+            ChargeState::Discharging if rate > 0.0 => Some(energy / rate),
+            ChargeState::Charging if rate > 0.0 => Some((energy_full - energy) / rate),
+            _ => None,
+        };
+        let time = hours
+            .filter(|h| h.is_finite() && *h > 0.0)
+            .map(|h| Duration::from_secs_f64(h * 3600.0))
+            .or_else(|| self.batteries.iter().filter_map(Battery::time).max());
+
+        Some(Summary { percentage, state, time })
+    }
+}
+
+impl Battery {
+    pub fn time(&self) -> Option<Duration> {
+        match self.state {
+            ChargeState::Discharging => self.time_to_empty,
+            ChargeState::Charging => self.time_to_full,
+            _ => None,
+        }
+    }
+
+    pub fn icon_name(&self) -> String {
+        self.kind
+            .icon_name()
+            .map(str::to_string)
+            .unwrap_or_else(|| battery_icon(self.percentage, self.state))
+    }
+}
+
+pub fn battery_icon(percentage: f64, state: ChargeState) -> String {
+    if state == ChargeState::Full {
+        return "battery-full-charged-symbolic".to_string();
+    }
+    let level = ((percentage / 10.0).round() as u32).min(10) * 10;
+    if state == ChargeState::Charging {
+        format!("battery-level-{}-charging-symbolic", level)
+    } else {
+        format!("battery-level-{}-symbolic", level)
+    }
+}
+
+/// "2 h 15 min", or "40 min" under an hour.
+pub fn format_duration(duration: Duration) -> String {
+    let minutes = duration.as_secs() / 60;
+    match (minutes / 60, minutes % 60) {
+        (0, minutes) => format!("{} min", minutes),
+        (hours, 0) => format!("{} h", hours),
+        (hours, minutes) => format!("{} h {} min", hours, minutes),
+    }
+}
+
+/// Streams the battery state, refreshed on every change UPower reports,
+/// or polled from sysfs without it. The worker stops once the receiver is
+/// dropped.
+pub fn subscribe() -> async_channel::Receiver<PowerState> {
+    let (sender, receiver) = async_channel::unbounded();
+
+    std::thread::spawn(move || {
+        if let Err(e) = watch_upower(&sender) {
+            info!("UPower unavailable, reading {}: {:#}", POWER_SUPPLY, e);
+            poll_sysfs(&sender);
+        }
+    });
+
+    receiver
+}
+
+/// Returns `Ok` only when nobody is listening any more.
+fn watch_upower(sender: &async_channel::Sender<PowerState>) -> Result<()> {
+    let connection = Connection::system().context("Failed to connect to the system bus")?;
+    let mut state = query_upower(&connection)?;
+    if sender.send_blocking(state.clone()).is_err() {
+        return Ok(());
+    }
+
+    // Covers DeviceAdded/DeviceRemoved as well as every device's
+    // PropertiesChanged.
+    let rule = format!("type='signal',sender='{}'", UPOWER);
+    let signals = super::signal_channel(MessageIterator::for_match_rule(rule.as_str(), &connection, None)?);
+    while let Ok(signal) = signals.recv() {
+        signal?;
+        std::thread::sleep(SETTLE_DELAY);
+        while let Ok(signal) = signals.try_recv() {
+            signal?;
+        }
+
+        match query_upower(&connection) {
+            Ok(new_state) if new_state != state => {
+                if sender.send_blocking(new_state.clone()).is_err() {
+                    return Ok(());
+                }
+                state = new_state;
+            }
+            Ok(_) => {}
+            Err(e) => warn!("Failed to read batteries: {:#}", e),
+        }
+    }
+    Ok(())
+}
+
+fn query_upower(connection: &Connection) -> Result<PowerState> {
+    let devices: Vec<OwnedObjectPath> = connection
+        .call_method(Some(UPOWER), UPOWER_PATH, Some(UPOWER), "EnumerateDevices", &())?
+        .body()?;
+
+    let mut state = PowerState::default();
+    for path in devices {
+        let properties: HashMap<String, OwnedValue> = connection
+            .call_method(
+                Some(UPOWER),
+                path.as_str(),
+                Some("org.freedesktop.DBus.Properties"),
+                "GetAll",
+                &DEVICE_INTERFACE,
+            )?
+            .body()?;
+        let Some(battery) = battery_from_upower(&properties) else {
+            continue;
+        };
+        debug!("UPower device {}: {:?}", path.as_str(), battery);
+        if battery.kind == BatteryKind::System {
+            state.batteries.push(battery);
+        } else {
+            state.peripherals.push(battery);
+        }
+    }
+    Ok(state)
+}
+
+fn property<T>(properties: &HashMap<String, OwnedValue>, key: &str) -> Option<T>
+where
+    T: TryFrom<OwnedValue>,
+{
+    properties.get(key).and_then(|v| T::try_from(v.clone()).ok())
+}
+
+fn battery_from_upower(properties: &HashMap<String, OwnedValue>) -> Option<Battery> {
+    let kind = BatteryKind::from_upower(property(properties, "Type")?)?;
+    if !property::<bool>(properties, "IsPresent").unwrap_or(true) {
+        return None;
+    }
+    // Batteries that power the machine but aren't its own, like a UPS.
+    if kind == BatteryKind::Other && property::<bool>(properties, "PowerSupply").unwrap_or(false) {
+        return None;
+    }
+
+    let name = property::<String>(properties, "Model")
+        .filter(|m| !m.is_empty())
+        .or_else(|| property::<String>(properties, "NativePath"))
+        .unwrap_or_else(|| "Battery".to_string());
+    let seconds = |key| {
+        property::<i64>(properties, key)
+            .filter(|s| *s > 0)
+            .map(|s| Duration::from_secs(s as u64))
+    };
+
+    Some(Battery {
+        name,
+        kind,
+        percentage: property(properties, "Percentage").unwrap_or(0.0),
+        state: ChargeState::from_upower(property(properties, "State").unwrap_or(0)),
+        time_to_empty: seconds("TimeToEmpty"),
+        time_to_full: seconds("TimeToFull"),
+        energy: property(properties, "Energy").unwrap_or(0.0),
+        energy_full: property(properties, "EnergyFull").unwrap_or(0.0),
+        energy_rate: property::<f64>(properties, "EnergyRate").unwrap_or(0.0).abs(),
+    })
+}
+
+fn poll_sysfs(sender: &async_channel::Sender<PowerState>) {
+    let mut last = None;
+    loop {
+        let state = query_sysfs().unwrap_or_else(|e| {
+            warn!("Failed to read {}: {:#}", POWER_SUPPLY, e);
+            PowerState::default()
+        });
+        if last.as_ref() != Some(&state) {
+            if sender.send_blocking(state.clone()).is_err() {
+                return;
+            }
+            last = Some(state);
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
+
+fn query_sysfs() -> Result<PowerState> {
+    let mut state = PowerState::default();
+    let mut entries: Vec<_> = fs::read_dir(POWER_SUPPLY)?.filter_map(|e| e.ok()).collect();
+    entries.sort_by_key(|e| e.file_name());
+
+    for entry in entries {
+        let Some(battery) = battery_from_sysfs(&entry.path()) else {
+            continue;
+        };
+        if battery.kind == BatteryKind::System {
+            state.batteries.push(battery);
+        } else {
+            state.peripherals.push(battery);
+        }
+    }
+    Ok(state)
+}
+
+fn read_attribute(dir: &Path, name: &str) -> Option<String> {
+    fs::read_to_string(dir.join(name)).ok().map(|s| s.trim().to_string())
+}
+
+/// A sysfs value in micro-units (µWh, µW, µAh, µA).
+fn read_micro(dir: &Path, name: &str) -> Option<f64> {
+    read_attribute(dir, name)?.parse::<f64>().ok().map(|v| v / 1_000_000.0)
+}
+
+fn battery_from_sysfs(dir: &Path) -> Option<Battery> {
+    if read_attribute(dir, "type")? != "Battery" || read_attribute(dir, "present").as_deref() == Some("0") {
+        return None;
+    }
+    // Peripherals report scope "Device"; the machine's own batteries
+    // "System" or nothing.
+    let kind = if read_attribute(dir, "scope").as_deref() == Some("Device") {
+        BatteryKind::Other
+    } else {
+        BatteryKind::System
+    };
+
+    // Either energy (Wh, W) or charge (Ah, A); the ratios come out the same.
+    let (energy, energy_full, rate) = match read_micro(dir, "energy_now") {
+        Some(energy) => (
+            energy,
+            read_micro(dir, "energy_full").unwrap_or(0.0),
+            read_micro(dir, "power_now").unwrap_or(0.0),
+        ),
+        None => (
+            read_micro(dir, "charge_now").unwrap_or(0.0),
+            read_micro(dir, "charge_full").unwrap_or(0.0),
+            read_micro(dir, "current_now").unwrap_or(0.0),
+        ),
+    };
+    let rate = rate.abs();
+    let percentage = read_attribute(dir, "capacity")
+        .and_then(|c| c.parse().ok())
+        .unwrap_or_else(|| if energy_full > 0.0 { energy / energy_full * 100.0 } else { 0.0 });
+    let state = ChargeState::from_sysfs(&read_attribute(dir, "status").unwrap_or_default());
+
+    let hours = |amount: f64| (rate > 0.0 && amount > 0.0).then(|| Duration::from_secs_f64(amount / rate * 3600.0));
+    let name = read_attribute(dir, "model_name")
+        .filter(|m| !m.is_empty())
+        .or_else(|| dir.file_name().map(|n| n.to_string_lossy().into_owned()))
+        .unwrap_or_else(|| "Battery".to_string());
+
+    Some(Battery {
+        name,
+        kind,
+        percentage,
+        state,
+        time_to_empty: (state == ChargeState::Discharging).then(|| hours(energy)).flatten(),
+        time_to_full: (state == ChargeState::Charging).then(|| hours(energy_full - energy)).flatten(),
+        energy,
+        energy_full,
+        energy_rate: rate,
+    })
+}
+
+/// Raises a notification when the system batteries run low, once per
+/// threshold until they are charging again. A threshold of 0 disables it.
+pub fn warn_on_low_battery(low: u32, critical: u32) {
+    if low == 0 && critical == 0 {
+        return;
+    }
+
+    let receiver = subscribe();
+    std::thread::spawn(move || {
+        // The lowest threshold already warned about.
+        let mut warned: Option<u32> = None;
+        while let Ok(state) = receiver.recv_blocking() {
+            let Some(summary) = state.summary() else {
+                continue;
+            };
+            if summary.state != ChargeState::Discharging {
+                warned = None;
+                continue;
+            }
+
+            let percentage = summary.percentage.round() as u32;
+            let level = [critical, low]
+                .into_iter()
+                .find(|&threshold| threshold > 0 && percentage <= threshold);
+            let Some(level) = level.filter(|&l| warned.is_none_or(|w| l < w)) else {
+                continue;
+            };
+            warned = Some(level);
+
+            let is_critical = level == critical;
+            let mut body = format!("{}% remaining", percentage);
+            if let Some(time) = summary.time {
+                body = format!("{}, about {}", body, format_duration(time));
+            }
+            let summary = if is_critical { "Battery critically low" } else { "Battery low" };
+            if let Err(e) = notify(summary, &body, &battery_icon(percentage as f64, ChargeState::Discharging), is_critical) {
+                warn!("Failed to send low battery notification: {:#}", e);
+            }
+        }
+    });
+}
+
+/// Sends a notification through whichever daemon owns the name on the
+/// session bus, which may be this shell; call it off the main thread.
+fn notify(summary: &str, body: &str, icon: &str, critical: bool) -> Result<()> {
+    let connection = Connection::session().context("Failed to connect to the session bus")?;
+    let mut hints: HashMap<&str, zbus::zvariant::Value> = HashMap::new();
+    hints.insert("urgency", (if critical { 2u8 } else { 1u8 }).into());
+    hints.insert("category", "device".into());
+    hints.insert("desktop-entry", "spinner-shell".into());
+
+    connection.call_method(
+        Some("org.freedesktop.Notifications"),
+        "/org/freedesktop/Notifications",
+        Some("org.freedesktop.Notifications"),
+        "Notify",
+        &("Power", 0u32, icon, summary, body, Vec::<&str>::new(), hints, -1i32),
+    )?;
+    Ok(())
+}
@@ -0,0 +1,440 @@
+//! Ending or pausing the session - suspend, hibernate, reboot and power
+//! off through systemd-logind, logging out through SpinnerWM
+//!
+//! logind also asks the session to lock, e.g. for `loginctl lock-session`
+//! or an idle daemon, and announces suspend; `subscribe` passes both on.
+//!
+//! The calls sit behind the `Backend` trait so they can be replaced; with
+//! `SPINNER_POWER_DRY_RUN` set they are only logged, which makes the power
+//! menu safe to try out.
+
+use crate::wm;
+
+use anyhow::{bail, Context, Result};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, info, warn};
+use zbus::blocking::{Connection, MessageIterator};
+use zbus::zvariant::{OwnedFd, OwnedObjectPath};
+
+const LOGIN1: &str = "org.freedesktop.login1";
+const LOGIN1_PATH: &str = "/org/freedesktop/login1";
+const MANAGER_INTERFACE: &str = "org.freedesktop.login1.Manager";
+/// The caller's own session.
+const SESSION_PATH: &str = "/org/freedesktop/login1/session/auto";
+const SESSION_INTERFACE: &str = "org.freedesktop.login1.Session";
+
+const DRY_RUN_ENV: &str = "SPINNER_POWER_DRY_RUN";
+const RESTART_DELAY: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerAction {
+    Lock,
+    Logout,
+    Suspend,
+    Hibernate,
+    Reboot,
+    PowerOff,
+}
+
+impl PowerAction {
+    /// In menu order.
+    pub const ALL: [Self; 6] = [
+        Self::Lock,
+        Self::Logout,
+        Self::Suspend,
+        Self::Hibernate,
+        Self::Reboot,
+        Self::PowerOff,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Lock => "Lock",
+            Self::Logout => "Log Out",
+            Self::Suspend => "Suspend",
+            Self::Hibernate => "Hibernate",
+            Self::Reboot => "Restart",
+            Self::PowerOff => "Shut Down",
+        }
+    }
+
+    pub fn icon_name(self) -> &'static str {
+        match self {
+            Self::Lock => "system-lock-screen-symbolic",
+            Self::Logout => "system-log-out-symbolic",
+            Self::Suspend => "media-playback-pause-symbolic",
+            Self::Hibernate => "document-save-symbolic",
+            Self::Reboot => "system-reboot-symbolic",
+            Self::PowerOff => "system-shutdown-symbolic",
+        }
+    }
+
+    /// Whether the action ends the session, so it deserves a second
+    /// thought before running.
+    pub fn ends_session(self) -> bool {
+        matches!(self, Self::Logout | Self::Reboot | Self::PowerOff)
+    }
+
+    /// The logind Manager method, for the actions logind performs itself.
+    fn logind_method(self) -> Option<&'static str> {
+        match self {
+            Self::Lock | Self::Logout => None,
+            Self::Suspend => Some("Suspend"),
+            Self::Hibernate => Some("Hibernate"),
+            Self::Reboot => Some("Reboot"),
+            Self::PowerOff => Some("PowerOff"),
+        }
+    }
+}
+
+/// What the power menu calls on the system.
+pub trait Backend: Send + Sync {
+    /// logind's answer to `Can<method>`: "yes", "no", "challenge" (allowed
+    /// after authenticating) or "na".
+    fn can(&self, method: &str) -> Result<String>;
+
+    /// Calls a Manager method such as `Suspend`, letting polkit ask for a
+    /// password if needed.
+    fn call(&self, method: &str) -> Result<()>;
+
+    /// Asks the session's screen locker to lock.
+    fn lock_session(&self) -> Result<()>;
+
+    /// Ends the session by closing the windows and the compositor.
+    fn logout(&self) -> Result<()>;
+}
+
+/// systemd-logind on the system bus, and SpinnerWM.
+pub struct System;
+
+impl Backend for System {
+    fn can(&self, method: &str) -> Result<String> {
+        let reply = system_bus()?.call_method(
+            Some(LOGIN1),
+            LOGIN1_PATH,
+            Some(MANAGER_INTERFACE),
+            format!("Can{}", method).as_str(),
+            &(),
+        )?;
+        Ok(reply.body()?)
+    }
+
+    fn call(&self, method: &str) -> Result<()> {
+        system_bus()?.call_method(
+            Some(LOGIN1),
+            LOGIN1_PATH,
+            Some(MANAGER_INTERFACE),
+            method,
+            &(true,),
+        )?;
+        Ok(())
+    }
+
+    fn lock_session(&self) -> Result<()> {
+        system_bus()?.call_method(
+            Some(LOGIN1),
+            SESSION_PATH,
+            Some(SESSION_INTERFACE),
+            "Lock",
+            &(),
+        )?;
+        Ok(())
+    }
+
+    fn logout(&self) -> Result<()> {
+        match wm::send(&wm::Request::Exit)? {
+            wm::Response::Error { message } => bail!("SpinnerWM refused to exit: {}", message),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn system_bus() -> Result<Connection> {
+    Connection::system().context("Failed to connect to the system bus")
+}
+
+/// Allows everything and only logs what would have happened.
+pub struct DryRun;
+
+impl Backend for DryRun {
+    fn can(&self, _method: &str) -> Result<String> {
+        Ok("yes".to_string())
+    }
+
+    fn call(&self, method: &str) -> Result<()> {
+        info!("Dry run: would call logind {}", method);
+        Ok(())
+    }
+
+    fn lock_session(&self) -> Result<()> {
+        info!("Dry run: would lock the session");
+        Ok(())
+    }
+
+    fn logout(&self) -> Result<()> {
+        info!("Dry run: would ask SpinnerWM to exit");
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+pub struct Session {
+    backend: Arc<dyn Backend>,
+}
+
+impl Session {
+    pub fn new(backend: impl Backend + 'static) -> Self {
+        Self {
+            backend: Arc::new(backend),
+        }
+    }
+
+    /// The actions this system allows, in menu order. Lock and log out are
+    /// always offered.
+    pub fn available(&self) -> async_channel::Receiver<Vec<PowerAction>> {
+        let (sender, receiver) = async_channel::bounded(1);
+        let backend = self.backend.clone();
+        std::thread::spawn(move || {
+            let actions = PowerAction::ALL
+                .into_iter()
+                .filter(|action| match action.logind_method() {
+                    None => true,
+                    Some(method) => match backend.can(method) {
+                        Ok(answer) => answer == "yes" || answer == "challenge",
+                        Err(e) => {
+                            warn!("Failed to ask logind about {}: {:#}", method, e);
+                            false
+                        }
+                    },
+                })
+                .collect();
+            let _ = sender.send_blocking(actions);
+        });
+        receiver
+    }
+
+    /// Runs the action on its own thread; the result comes back on the
+    /// returned channel. Failures are logged as well.
+    pub fn perform(&self, action: PowerAction) -> async_channel::Receiver<Result<()>> {
+        let (sender, receiver) = async_channel::bounded(1);
+        let backend = self.backend.clone();
+        std::thread::spawn(move || {
+            info!("{} requested", action.label());
+            let result = match action.logind_method() {
+                Some(method) => backend.call(method),
+                None if action == PowerAction::Lock => backend.lock_session(),
+                None => backend.logout(),
+            };
+            if let Err(e) = &result {
+                warn!("Failed to {}: {:#}", action.label().to_lowercase(), e);
+            }
+            let _ = sender.send_blocking(result);
+        });
+        receiver
+    }
+}
+
+impl Default for Session {
+    /// The real system, or the dry run when `SPINNER_POWER_DRY_RUN` is set.
+    fn default() -> Self {
+        if std::env::var_os(DRY_RUN_ENV).is_some() {
+            Self::new(DryRun)
+        } else {
+            Self::new(System)
+        }
+    }
+}
+
+/// What logind tells this session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoginEvent {
+    Lock,
+    Unlock,
+    /// The system is about to sleep (`true`) or has woken up.
+    PrepareForSleep(bool),
+}
+
+/// Streams logind's requests to this session. The worker reconnects if
+/// logind restarts and stops once the receiver is dropped.
+pub fn subscribe() -> async_channel::Receiver<LoginEvent> {
+    let (sender, receiver) = async_channel::unbounded();
+
+    std::thread::spawn(move || loop {
+        match listen(&sender) {
+            Ok(()) => return,
+            Err(e) => debug!("logind signals interrupted: {:#}", e),
+        }
+        std::thread::sleep(RESTART_DELAY);
+    });
+
+    receiver
+}
+
+/// Returns `Ok` only when the receiving side has gone away.
+fn listen(sender: &async_channel::Sender<LoginEvent>) -> Result<()> {
+    let connection = system_bus()?;
+    let session: OwnedObjectPath = connection
+        .call_method(
+            Some(LOGIN1),
+            LOGIN1_PATH,
+            Some(MANAGER_INTERFACE),
+            "GetSessionByPID",
+            &(std::process::id(),),
+        )?
+        .body()?;
+
+    let rule = format!("type='signal',sender='{}'", LOGIN1);
+    for message in MessageIterator::for_match_rule(rule.as_str(), &connection, None)? {
+        let message = message?;
+        let header = message.header()?;
+        let (Some(path), Some(member)) = (header.path()?, header.member()?) else {
+            continue;
+        };
+        let ours = path.as_str() == session.as_str();
+        let event = match member.as_str() {
+            "Lock" if ours => LoginEvent::Lock,
+            "Unlock" if ours => LoginEvent::Unlock,
+            "PrepareForSleep" => LoginEvent::PrepareForSleep(message.body()?),
+            _ => continue,
+        };
+        if sender.send_blocking(event).is_err() {
+            return Ok(());
+        }
+    }
+
+    bail!("logind closed the connection")
+}
+
+/// Holds off suspend until dropped, so the screen can lock first.
+pub struct SleepInhibitor {
+    _fd: OwnedFd,
+}
+
+pub fn inhibit_sleep(why: &str) -> Result<SleepInhibitor> {
+    let fd = system_bus()?
+        .call_method(
+            Some(LOGIN1),
+            LOGIN1_PATH,
+            Some(MANAGER_INTERFACE),
+            "Inhibit",
+            &("sleep", "SpinnerShell", why, "delay"),
+        )?
+        .body()?;
+    Ok(SleepInhibitor { _fd: fd })
+}
+
+/// Tells logind whether the session is locked, for idle daemons and the
+/// login manager.
+pub fn set_locked_hint(locked: bool) {
+    std::thread::spawn(move || {
+        let result = system_bus().and_then(|connection| {
+            connection.call_method(
+                Some(LOGIN1),
+                SESSION_PATH,
+                Some(SESSION_INTERFACE),
+                "SetLockedHint",
+                &(locked,),
+            )?;
+            Ok(())
+        });
+        if let Err(e) = result {
+            debug!("Failed to set the locked hint: {:#}", e);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    /// Answers `can` from a table and records every other call.
+    #[derive(Default)]
+    struct Recording {
+        answers: HashMap<&'static str, &'static str>,
+        calls: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl Recording {
+        fn record(&self, call: String) -> Result<()> {
+            self.calls.lock().unwrap().push(call);
+            Ok(())
+        }
+    }
+
+    impl Backend for Recording {
+        fn can(&self, method: &str) -> Result<String> {
+            match self.answers.get(method) {
+                Some(answer) => Ok(answer.to_string()),
+                None => bail!("No answer for {}", method),
+            }
+        }
+
+        fn call(&self, method: &str) -> Result<()> {
+            self.record(format!("call {}", method))
+        }
+
+        fn lock_session(&self) -> Result<()> {
+            self.record("lock_session".to_string())
+        }
+
+        fn logout(&self) -> Result<()> {
+            self.record("logout".to_string())
+        }
+    }
+
+    fn available(answers: &[(&'static str, &'static str)]) -> Vec<PowerAction> {
+        let backend = Recording {
+            answers: answers.iter().copied().collect(),
+            ..Default::default()
+        };
+        Session::new(backend).available().recv_blocking().unwrap()
+    }
+
+    #[test]
+    fn available_follows_logind_answers() {
+        let actions = available(&[
+            ("Suspend", "yes"),
+            ("Hibernate", "challenge"),
+            ("Reboot", "na"),
+            ("PowerOff", "no"),
+        ]);
+        assert_eq!(
+            actions,
+            [
+                PowerAction::Lock,
+                PowerAction::Logout,
+                PowerAction::Suspend,
+                PowerAction::Hibernate
+            ]
+        );
+    }
+
+    #[test]
+    fn available_keeps_lock_and_logout_without_logind() {
+        assert_eq!(available(&[]), [PowerAction::Lock, PowerAction::Logout]);
+    }
+
+    #[test]
+    fn perform_routes_each_action() {
+        let backend = Recording::default();
+        let calls = backend.calls.clone();
+        let session = Session::new(backend);
+
+        for action in [
+            PowerAction::Lock,
+            PowerAction::Logout,
+            PowerAction::Suspend,
+            PowerAction::PowerOff,
+        ] {
+            session.perform(action).recv_blocking().unwrap().unwrap();
+        }
+        assert_eq!(
+            *calls.lock().unwrap(),
+            ["lock_session", "logout", "call Suspend", "call PowerOff"]
+        );
+    }
+}
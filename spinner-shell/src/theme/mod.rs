@@ -1,26 +1,69 @@
 //! Theme management
+//!
+//! Dark mode is the desktop-wide color scheme: libadwaita widgets in the
+//! shell follow it, and apps read it from the GNOME interface settings,
+//! which also keep it across sessions. `[theme] variant` is only the
+//! starting point on systems without those settings.
+
+use crate::config::ThemeConfig;
+
+use gtk4::gio;
+use gtk4::prelude::*;
+use libadwaita as adw;
+use tracing::{info, warn};
+
+const INTERFACE_SCHEMA: &str = "org.gnome.desktop.interface";
 
 pub struct ThemeManager {
     dark_mode: bool,
     accent_color: String,
+    /// `None` when the schema isn't installed.
+    interface: Option<gio::Settings>,
 }
 
 impl ThemeManager {
-    pub fn new() -> Self {
+    pub fn new(config: &ThemeConfig) -> Self {
+        let interface = gio::SettingsSchemaSource::default()
+            .and_then(|source| source.lookup(INTERFACE_SCHEMA, true))
+            .filter(|schema| schema.has_key("color-scheme"))
+            .map(|_| gio::Settings::new(INTERFACE_SCHEMA));
+        let dark_mode = match &interface {
+            Some(settings) => settings.string("color-scheme") == "prefer-dark",
+            None => config.variant != "light",
+        };
+
         Self {
-            dark_mode: true,
-            accent_color: "#88c0d0".to_string(),
+            dark_mode,
+            accent_color: config.accent_color.clone(),
+            interface,
         }
     }
-    
+
     pub fn is_dark_mode(&self) -> bool {
         self.dark_mode
     }
-    
+
     pub fn set_dark_mode(&mut self, enabled: bool) {
         self.dark_mode = enabled;
+        info!("Dark mode {}", if enabled { "on" } else { "off" });
+        if let Some(settings) = &self.interface {
+            let scheme = if enabled { "prefer-dark" } else { "default" };
+            if let Err(e) = settings.set_string("color-scheme", scheme) {
+                warn!("Failed to save the color scheme: {}", e);
+            }
+        }
+        self.apply();
     }
-    
+
+    /// Applies the color scheme to the shell's own widgets.
+    pub fn apply(&self) {
+        adw::StyleManager::default().set_color_scheme(if self.dark_mode {
+            adw::ColorScheme::ForceDark
+        } else {
+            adw::ColorScheme::ForceLight
+        });
+    }
+
     pub fn accent_color(&self) -> &str {
         &self.accent_color
     }
@@ -28,6 +71,6 @@ impl ThemeManager {
 
 impl Default for ThemeManager {
     fn default() -> Self {
-        Self::new()
+        Self::new(&ThemeConfig::default())
     }
 }
@@ -0,0 +1,403 @@
+//! `org.kde.StatusNotifierHost` - Follows the watcher's items and streams
+//! their state
+
+use super::item::{self, TrayItem};
+use super::watcher::{self, split_id};
+
+use anyhow::{bail, Context, Result};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{mpsc, Once};
+use std::time::Duration;
+use tracing::{debug, info, warn};
+use zbus::blocking::{Connection, MessageIterator};
+use zbus::zvariant::OwnedValue;
+
+const RESTART_DELAY: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone)]
+pub enum TrayEvent {
+    /// A new item, or new properties of a known one.
+    Changed(Box<TrayItem>),
+    /// The id of an item that went away.
+    Removed(String),
+}
+
+enum Signal {
+    Registered(String),
+    Unregistered(String),
+    /// One of an item's `New*` signals, from its owner and path.
+    ItemChanged(String, String),
+}
+
+static SERVE_WATCHER: Once = Once::new();
+static NEXT_HOST: AtomicU32 = AtomicU32::new(1);
+
+/// Streams the tray items, serving the watcher as well if no other
+/// component does. The worker stops once the receiver is dropped.
+pub fn subscribe() -> async_channel::Receiver<TrayEvent> {
+    let (sender, receiver) = async_channel::unbounded();
+
+    std::thread::spawn(move || {
+        SERVE_WATCHER.call_once(|| {
+            if let Err(e) = watcher::serve() {
+                info!("Using the running tray watcher: {:#}", e);
+            }
+        });
+
+        loop {
+            let mut items = HashMap::new();
+            match host(&sender, &mut items) {
+                Ok(()) => return,
+                Err(e) => warn!("Tray host stopped: {:#}", e),
+            }
+            for id in items.into_keys() {
+                if sender.send_blocking(TrayEvent::Removed(id)).is_err() {
+                    return;
+                }
+            }
+            std::thread::sleep(RESTART_DELAY);
+        }
+    });
+
+    receiver
+}
+
+/// Forwards the signals matching `rule` until the stream or the host
+/// ends.
+fn listen<F>(connection: &Connection, rule: &str, signals: &mpsc::Sender<Result<Signal>>, parse: F) -> Result<()>
+where
+    F: Fn(&zbus::Message) -> Option<Signal> + Send + 'static,
+{
+    let messages = MessageIterator::for_match_rule(rule, connection, None)?;
+    let signals = signals.clone();
+    std::thread::spawn(move || {
+        for message in messages {
+            let signal = match message {
+                Ok(message) => match parse(&message) {
+                    Some(signal) => Ok(signal),
+                    None => continue,
+                },
+                Err(e) => Err(e.into()),
+            };
+            if signals.send(signal).is_err() {
+                return;
+            }
+        }
+        let _ = signals.send(Err(anyhow::anyhow!("Signal stream ended")));
+    });
+    Ok(())
+}
+
+/// Returns `Ok` only when nobody is listening any more.
+fn host(sender: &async_channel::Sender<TrayEvent>, items: &mut HashMap<String, TrayItem>) -> Result<()> {
+    let connection = Connection::session().context("Failed to connect to the session bus")?;
+    host_on(&connection, sender, items)
+}
+
+fn host_on(
+    connection: &Connection,
+    sender: &async_channel::Sender<TrayEvent>,
+    items: &mut HashMap<String, TrayItem>,
+) -> Result<()> {
+    let (signals, received) = mpsc::channel();
+    let rule = format!("type='signal',sender='{}',interface='{}'", watcher::BUS_NAME, watcher::INTERFACE);
+    listen(connection, &rule, &signals, |message| {
+        let member = message.member()?;
+        let id: String = message.body().ok()?;
+        match member.as_str() {
+            "StatusNotifierItemRegistered" => Some(Signal::Registered(id)),
+            "StatusNotifierItemUnregistered" => Some(Signal::Unregistered(id)),
+            _ => None,
+        }
+    })?;
+    let rule = format!("type='signal',interface='{}'", item::INTERFACE);
+    listen(connection, &rule, &signals, |message| {
+        let header = message.header().ok()?;
+        let owner = header.sender().ok()??.to_string();
+        let path = message.path()?.to_string();
+        Some(Signal::ItemChanged(owner, path))
+    })?;
+    drop(signals);
+
+    let name = format!("org.kde.StatusNotifierHost-{}-{}", std::process::id(), NEXT_HOST.fetch_add(1, Ordering::SeqCst));
+    connection.request_name(name.as_str())?;
+    connection
+        .call_method(
+            Some(watcher::BUS_NAME),
+            watcher::OBJECT_PATH,
+            Some(watcher::INTERFACE),
+            "RegisterStatusNotifierHost",
+            &(name.as_str(),),
+        )
+        .context("No tray watcher")?;
+
+    let registered: Vec<String> = connection
+        .call_method(
+            Some(watcher::BUS_NAME),
+            watcher::OBJECT_PATH,
+            Some("org.freedesktop.DBus.Properties"),
+            "Get",
+            &(watcher::INTERFACE, "RegisteredStatusNotifierItems"),
+        )?
+        .body::<OwnedValue>()?
+        .try_into()?;
+
+    let mut host = Host {
+        connection,
+        sender,
+        items,
+        owners: HashMap::new(),
+    };
+    for id in &registered {
+        if !host.update(id) {
+            return Ok(());
+        }
+    }
+
+    for signal in received {
+        let alive = match signal? {
+            Signal::Registered(id) => host.update(&id),
+            Signal::ItemChanged(owner, path) => host
+                .find(&owner, &path)
+                .iter()
+                .all(|id| host.update(id)),
+            Signal::Unregistered(id) => host.remove(id),
+        };
+        if !alive {
+            return Ok(());
+        }
+    }
+    bail!("Signal streams ended")
+}
+
+struct Host<'a> {
+    connection: &'a Connection,
+    sender: &'a async_channel::Sender<TrayEvent>,
+    items: &'a mut HashMap<String, TrayItem>,
+    /// Unique names of the items' owners; signals carry those, while
+    /// items may register with any name.
+    owners: HashMap<String, String>,
+}
+
+impl Host<'_> {
+    /// Re-reads an item; returns `false` once nobody is listening.
+    fn update(&mut self, id: &str) -> bool {
+        let item = match item::fetch(self.connection, id) {
+            Ok(item) => item,
+            Err(e) => {
+                debug!("{:#}", e);
+                return true;
+            }
+        };
+        if !self.owners.contains_key(id) {
+            let owner = self
+                .connection
+                .call_method(
+                    Some("org.freedesktop.DBus"),
+                    "/org/freedesktop/DBus",
+                    Some("org.freedesktop.DBus"),
+                    "GetNameOwner",
+                    &(split_id(id).0,),
+                )
+                .and_then(|reply| reply.body::<String>());
+            if let Ok(owner) = owner {
+                self.owners.insert(id.to_string(), owner);
+            }
+        }
+
+        if self.items.get(id) == Some(&item) {
+            return true;
+        }
+        self.items.insert(id.to_string(), item.clone());
+        self.sender.send_blocking(TrayEvent::Changed(Box::new(item))).is_ok()
+    }
+
+    fn remove(&mut self, id: String) -> bool {
+        self.owners.remove(&id);
+        self.items.remove(&id).is_none() || self.sender.send_blocking(TrayEvent::Removed(id)).is_ok()
+    }
+
+    /// The items served by `owner` at `path`.
+    fn find(&self, owner: &str, path: &str) -> Vec<String> {
+        self.items
+            .keys()
+            .filter(|id| {
+                let (name, item_path) = split_id(id);
+                item_path == path && (name == owner || self.owners.get(*id).map(String::as_str) == Some(owner))
+            })
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::{BufRead, BufReader};
+    use std::process::{Child, Command, Stdio};
+    use zbus::blocking::ConnectionBuilder;
+    use zbus::{dbus_interface, SignalContext};
+
+    /// An event that doesn't come fails the test instead of hanging it.
+    const TIMEOUT: Duration = Duration::from_secs(10);
+
+    const ITEM_NAME: &str = "org.spinneros.TrayTest";
+    const ITEM_PATH: &str = "/StatusNotifierItem";
+
+    struct MockItem {
+        icon_name: String,
+    }
+
+    #[dbus_interface(name = "org.kde.StatusNotifierItem")]
+    impl MockItem {
+        #[dbus_interface(property)]
+        fn id(&self) -> &str {
+            "tray-test"
+        }
+
+        #[dbus_interface(property)]
+        fn title(&self) -> &str {
+            "Tray test"
+        }
+
+        #[dbus_interface(property)]
+        fn status(&self) -> &str {
+            "Active"
+        }
+
+        #[dbus_interface(property)]
+        fn icon_name(&self) -> &str {
+            &self.icon_name
+        }
+
+        #[dbus_interface(signal)]
+        async fn new_icon(ctxt: &SignalContext<'_>) -> zbus::Result<()>;
+    }
+
+    /// A private dbus-daemon running the watcher, with a host whose
+    /// events come out of `events`.
+    struct Bus {
+        daemon: Child,
+        address: String,
+        events: mpsc::Receiver<TrayEvent>,
+    }
+
+    impl Bus {
+        fn start() -> Option<Self> {
+            let mut daemon = match Command::new("dbus-daemon")
+                .args(["--session", "--nofork", "--print-address"])
+                .stdout(Stdio::piped())
+                .stderr(Stdio::null())
+                .spawn()
+            {
+                Ok(daemon) => daemon,
+                Err(e) => {
+                    eprintln!("Skipping, dbus-daemon is not available: {}", e);
+                    return None;
+                }
+            };
+            let mut address = String::new();
+            BufReader::new(daemon.stdout.take().unwrap())
+                .read_line(&mut address)
+                .unwrap();
+            let address = address.trim().to_string();
+
+            watcher::serve_on(ConnectionBuilder::address(address.as_str()).unwrap()).unwrap();
+
+            let connection = ConnectionBuilder::address(address.as_str()).unwrap().build().unwrap();
+            let (sender, receiver) = async_channel::unbounded();
+            std::thread::spawn(move || {
+                let mut items = HashMap::new();
+                let _ = host_on(&connection, &sender, &mut items);
+            });
+            // async_channel has no timeouts of its own.
+            let (forward, events) = mpsc::channel();
+            std::thread::spawn(move || {
+                while let Ok(event) = receiver.recv_blocking() {
+                    if forward.send(event).is_err() {
+                        return;
+                    }
+                }
+            });
+
+            Some(Self { daemon, address, events })
+        }
+
+        /// Serves a mock item under `ITEM_NAME` and registers it.
+        fn item(&self, icon_name: &str) -> Connection {
+            let item = MockItem {
+                icon_name: icon_name.to_string(),
+            };
+            let connection = ConnectionBuilder::address(self.address.as_str())
+                .unwrap()
+                .serve_at(ITEM_PATH, item)
+                .unwrap()
+                .name(ITEM_NAME)
+                .unwrap()
+                .build()
+                .unwrap();
+            connection
+                .call_method(
+                    Some(watcher::BUS_NAME),
+                    watcher::OBJECT_PATH,
+                    Some(watcher::INTERFACE),
+                    "RegisterStatusNotifierItem",
+                    &(ITEM_NAME,),
+                )
+                .unwrap();
+            connection
+        }
+
+        fn event(&self) -> TrayEvent {
+            self.events.recv_timeout(TIMEOUT).expect("No tray event")
+        }
+
+        fn changed(&self) -> TrayItem {
+            match self.event() {
+                TrayEvent::Changed(item) => *item,
+                TrayEvent::Removed(id) => panic!("Expected a change, {} was removed", id),
+            }
+        }
+    }
+
+    impl Drop for Bus {
+        fn drop(&mut self) {
+            let _ = self.daemon.kill();
+            let _ = self.daemon.wait();
+        }
+    }
+
+    #[test]
+    fn follows_registered_items() {
+        let Some(bus) = Bus::start() else {
+            return;
+        };
+        let id = format!("{}{}", ITEM_NAME, ITEM_PATH);
+
+        let connection = bus.item("first-icon");
+        let item = bus.changed();
+        assert_eq!(item.id, id);
+        assert_eq!(item.title, "Tray test");
+        assert_eq!(item.icon_name.as_deref(), Some("first-icon"));
+
+        // The host re-reads the item on NewIcon, sent from its unique name.
+        let mock = connection
+            .object_server()
+            .interface::<_, MockItem>(ITEM_PATH)
+            .unwrap();
+        mock.get_mut().icon_name = "second-icon".to_string();
+        zbus::block_on(MockItem::new_icon(mock.signal_context())).unwrap();
+        let item = bus.changed();
+        assert_eq!(item.id, id);
+        assert_eq!(item.icon_name.as_deref(), Some("second-icon"));
+
+        // Giving up the name makes the watcher send StatusNotifierItemUnregistered.
+        connection.release_name(ITEM_NAME).unwrap();
+        match bus.event() {
+            TrayEvent::Removed(removed) => assert_eq!(removed, id),
+            TrayEvent::Changed(item) => panic!("Expected a removal, {} changed", item.id),
+        }
+    }
+}
@@ -0,0 +1,218 @@
+//! `org.kde.StatusNotifierItem` client - An item's icon, tooltip and menu,
+//! and the clicks forwarded to it
+
+use super::watcher::split_id;
+
+use anyhow::{Context, Result};
+use tracing::warn;
+use zbus::blocking::Connection;
+use zbus::zvariant::{DeserializeDict, OwnedObjectPath, Type};
+
+pub const INTERFACE: &str = "org.kde.StatusNotifierItem";
+const PROPERTIES: &str = "org.freedesktop.DBus.Properties";
+
+/// Pixmaps are picked for this size; GTK scales the rest of the way.
+const PIXMAP_SIZE: i32 = 32;
+
+/// Width, height and ARGB32 pixels in network byte order.
+type RawPixmap = (i32, i32, Vec<u8>);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Status {
+    /// Nothing to show; the item stays hidden.
+    Passive,
+    #[default]
+    Active,
+    NeedsAttention,
+}
+
+impl Status {
+    fn parse(status: &str) -> Self {
+        match status {
+            "Passive" => Self::Passive,
+            "NeedsAttention" => Self::NeedsAttention,
+            _ => Self::Active,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pixmap {
+    pub width: i32,
+    pub height: i32,
+    /// RGBA, 4 bytes a pixel without padding.
+    pub data: Vec<u8>,
+}
+
+impl Pixmap {
+    /// The smallest pixmap at least `PIXMAP_SIZE` wide, or else the
+    /// largest one.
+    fn pick(pixmaps: Vec<RawPixmap>) -> Option<Self> {
+        let mut pixmaps: Vec<RawPixmap> = pixmaps
+            .into_iter()
+            .filter(|(w, h, data)| *w > 0 && *h > 0 && data.len() == (*w as usize) * (*h as usize) * 4)
+            .collect();
+        pixmaps.sort_by_key(|(width, _, _)| *width);
+        let index = pixmaps
+            .iter()
+            .position(|(width, _, _)| *width >= PIXMAP_SIZE)
+            .unwrap_or(pixmaps.len().checked_sub(1)?);
+        let (width, height, mut data) = pixmaps.swap_remove(index);
+
+        // ARGB to RGBA
+        for pixel in data.chunks_exact_mut(4) {
+            pixel.rotate_left(1);
+        }
+        Some(Self { width, height, data })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tooltip {
+    pub title: String,
+    /// May contain the basic markup of notification bodies.
+    pub description: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrayItem {
+    /// Bus name and object path, as registered with the watcher.
+    pub id: String,
+    pub title: String,
+    pub status: Status,
+    pub icon_name: Option<String>,
+    /// An extra directory to look up `icon_name` in.
+    pub icon_theme_path: Option<String>,
+    pub icon: Option<Pixmap>,
+    pub attention_icon_name: Option<String>,
+    pub attention_icon: Option<Pixmap>,
+    pub tooltip: Option<Tooltip>,
+    /// A `com.canonical.dbusmenu` object on the item's connection.
+    pub menu: Option<OwnedObjectPath>,
+    /// The item only has a menu; clicks should open it.
+    pub item_is_menu: bool,
+    destination: String,
+    path: String,
+}
+
+impl TrayItem {
+    /// The icon name and pixmap to show for the current status.
+    pub fn current_icon(&self) -> (Option<&str>, Option<&Pixmap>) {
+        let attention = self.status == Status::NeedsAttention
+            && (self.attention_icon_name.is_some() || self.attention_icon.is_some());
+        if attention {
+            (self.attention_icon_name.as_deref(), self.attention_icon.as_ref())
+        } else {
+            (self.icon_name.as_deref(), self.icon.as_ref())
+        }
+    }
+
+    /// The bus name and object path of the item's menu.
+    pub(super) fn menu_address(&self) -> Option<(String, OwnedObjectPath)> {
+        Some((self.destination.clone(), self.menu.clone()?))
+    }
+}
+
+#[derive(DeserializeDict, Type)]
+#[zvariant(signature = "dict", rename_all = "PascalCase")]
+struct Properties {
+    id: Option<String>,
+    title: Option<String>,
+    status: Option<String>,
+    icon_name: Option<String>,
+    icon_theme_path: Option<String>,
+    icon_pixmap: Option<Vec<RawPixmap>>,
+    attention_icon_name: Option<String>,
+    attention_icon_pixmap: Option<Vec<RawPixmap>>,
+    tool_tip: Option<(String, Vec<RawPixmap>, String, String)>,
+    menu: Option<OwnedObjectPath>,
+    item_is_menu: Option<bool>,
+}
+
+fn non_empty(value: Option<String>) -> Option<String> {
+    value.filter(|v| !v.is_empty())
+}
+
+/// Reads the item registered as `id`.
+pub fn fetch(connection: &Connection, id: &str) -> Result<TrayItem> {
+    let (destination, path) = split_id(id);
+    let properties: Properties = connection
+        .call_method(Some(destination), path, Some(PROPERTIES), "GetAll", &(INTERFACE,))
+        .with_context(|| format!("Failed to read tray item {}", id))?
+        .body()?;
+
+    let tooltip = properties
+        .tool_tip
+        .map(|(_, _, title, description)| Tooltip { title, description })
+        .filter(|tooltip| !tooltip.title.is_empty() || !tooltip.description.is_empty());
+    let title = non_empty(properties.title)
+        .or(properties.id)
+        .unwrap_or_else(|| destination.to_string());
+
+    Ok(TrayItem {
+        id: id.to_string(),
+        title,
+        status: properties.status.as_deref().map(Status::parse).unwrap_or_default(),
+        icon_name: non_empty(properties.icon_name),
+        icon_theme_path: non_empty(properties.icon_theme_path),
+        icon: properties.icon_pixmap.and_then(Pixmap::pick),
+        attention_icon_name: non_empty(properties.attention_icon_name),
+        attention_icon: properties.attention_icon_pixmap.and_then(Pixmap::pick),
+        tooltip,
+        menu: properties.menu.filter(|menu| menu.as_str() != "/"),
+        item_is_menu: properties.item_is_menu.unwrap_or(false),
+        destination: destination.to_string(),
+        path: path.to_string(),
+    })
+}
+
+/// Runs a blocking request on its own thread; the result comes back on
+/// the returned channel. Failures are logged as well.
+pub(super) fn spawn<F>(what: &'static str, request: F) -> async_channel::Receiver<Result<()>>
+where
+    F: FnOnce(&Connection) -> Result<()> + Send + 'static,
+{
+    let (sender, receiver) = async_channel::bounded(1);
+    std::thread::spawn(move || {
+        let result = Connection::session()
+            .context("Failed to connect to the session bus")
+            .and_then(|connection| request(&connection));
+        if let Err(e) = &result {
+            warn!("Failed to {}: {:#}", what, e);
+        }
+        let _ = sender.send_blocking(result);
+    });
+    receiver
+}
+
+fn call<B>(item: &TrayItem, what: &'static str, method: &'static str, body: B) -> async_channel::Receiver<Result<()>>
+where
+    B: serde::Serialize + zbus::zvariant::DynamicType + Send + 'static,
+{
+    let destination = item.destination.clone();
+    let path = item.path.clone();
+    spawn(what, move |connection| {
+        connection.call_method(Some(destination.as_str()), path.as_str(), Some(INTERFACE), method, &body)?;
+        Ok(())
+    })
+}
+
+/// The primary action, usually showing the app's window. Fails for items
+/// that only have a menu.
+pub fn activate(item: &TrayItem, x: i32, y: i32) -> async_channel::Receiver<Result<()>> {
+    call(item, "activate the tray item", "Activate", (x, y))
+}
+
+pub fn secondary_activate(item: &TrayItem, x: i32, y: i32) -> async_channel::Receiver<Result<()>> {
+    call(item, "activate the tray item", "SecondaryActivate", (x, y))
+}
+
+/// Asks an item without a D-Bus menu to show its own.
+pub fn context_menu(item: &TrayItem, x: i32, y: i32) -> async_channel::Receiver<Result<()>> {
+    call(item, "open the tray item's menu", "ContextMenu", (x, y))
+}
+
+pub fn scroll(item: &TrayItem, delta: i32, vertical: bool) -> async_channel::Receiver<Result<()>> {
+    let orientation = if vertical { "vertical" } else { "horizontal" };
+    call(item, "scroll the tray item", "Scroll", (delta, orientation))
+}
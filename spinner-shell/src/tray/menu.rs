@@ -0,0 +1,133 @@
+//! `com.canonical.dbusmenu` client - The context menus of tray items
+
+use super::item::{spawn, TrayItem};
+
+use anyhow::{bail, Context, Result};
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::Deserialize;
+use zbus::blocking::Connection;
+use zbus::zvariant::{OwnedValue, Type, Value};
+
+const INTERFACE: &str = "com.canonical.dbusmenu";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Toggle {
+    None,
+    Checkmark(bool),
+    Radio(bool),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MenuItem {
+    pub id: i32,
+    /// With `_` before the mnemonic, as GTK expects.
+    pub label: String,
+    pub enabled: bool,
+    pub separator: bool,
+    pub toggle: Toggle,
+    /// Items with children open a submenu.
+    pub children: Vec<MenuItem>,
+}
+
+type Properties = HashMap<String, OwnedValue>;
+
+fn property<T>(properties: &Properties, key: &str) -> Option<T>
+where
+    T: TryFrom<OwnedValue>,
+{
+    properties.get(key).and_then(|v| T::try_from(v.clone()).ok())
+}
+
+/// A node of the layout: id, properties and children, each a variant
+/// holding another node.
+#[derive(Deserialize, Type)]
+struct Layout(i32, Properties, Vec<OwnedValue>);
+
+/// Parses a layout node; hidden items come back as `None`.
+fn parse_node(id: i32, properties: Properties, children: &[Value]) -> Result<Option<MenuItem>> {
+    if !property(&properties, "visible").unwrap_or(true) {
+        return Ok(None);
+    }
+
+    let toggle_state = property::<i32>(&properties, "toggle-state") == Some(1);
+    let toggle = match property::<String>(&properties, "toggle-type").as_deref() {
+        Some("checkmark") => Toggle::Checkmark(toggle_state),
+        Some("radio") => Toggle::Radio(toggle_state),
+        _ => Toggle::None,
+    };
+
+    let mut item = MenuItem {
+        id,
+        label: property(&properties, "label").unwrap_or_default(),
+        enabled: property(&properties, "enabled").unwrap_or(true),
+        separator: property::<String>(&properties, "type").as_deref() == Some("separator"),
+        toggle,
+        children: Vec::new(),
+    };
+    for child in children {
+        if let Some(child) = parse_child(child)? {
+            item.children.push(child);
+        }
+    }
+    Ok(Some(item))
+}
+
+fn parse_child(child: &Value) -> Result<Option<MenuItem>> {
+    let child = match child {
+        Value::Value(inner) => inner.as_ref(),
+        other => other,
+    };
+    let Value::Structure(node) = child else {
+        bail!("Unexpected menu item {}", child.value_signature());
+    };
+    let [Value::I32(id), Value::Dict(properties), Value::Array(children)] = node.fields() else {
+        bail!("Unexpected menu item {}", node.full_signature());
+    };
+    parse_node(*id, Properties::try_from(properties.clone())?, children.get())
+}
+
+/// Reads the whole menu of an item, after telling it the menu is about to
+/// be shown so it can fill it in.
+pub fn fetch(item: &TrayItem) -> async_channel::Receiver<Result<Vec<MenuItem>>> {
+    let (sender, receiver) = async_channel::bounded(1);
+    let address = item.menu_address();
+
+    std::thread::spawn(move || {
+        let result = address.context("The item has no menu").and_then(|(destination, path)| {
+            let connection = Connection::session().context("Failed to connect to the session bus")?;
+            let destination = Some(destination.as_str());
+            // Returns whether the menu needs an update; it is read in full
+            // either way.
+            let _ = connection.call_method(destination, path.as_str(), Some(INTERFACE), "AboutToShow", &(0i32,));
+            let (_, Layout(id, properties, children)): (u32, Layout) = connection
+                .call_method(destination, path.as_str(), Some(INTERFACE), "GetLayout", &(0i32, -1i32, Vec::<&str>::new()))?
+                .body()?;
+            let children: Vec<Value> = children.iter().map(|child| Value::clone(child)).collect();
+            Ok(parse_node(id, properties, &children)?.map(|root| root.children).unwrap_or_default())
+        });
+        let _ = sender.send_blocking(result);
+    });
+
+    receiver
+}
+
+/// Tells the item that a menu entry was chosen.
+pub fn activate(item: &TrayItem, id: i32) -> async_channel::Receiver<Result<()>> {
+    let address = item.menu_address();
+    spawn("activate the menu item", move |connection| {
+        let (destination, path) = address.context("The item has no menu")?;
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|t| t.as_secs() as u32)
+            .unwrap_or(0);
+        connection.call_method(
+            Some(destination.as_str()),
+            path.as_str(),
+            Some(INTERFACE),
+            "Event",
+            &(id, "clicked", Value::I32(0), timestamp),
+        )?;
+        Ok(())
+    })
+}
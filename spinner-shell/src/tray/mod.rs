@@ -0,0 +1,21 @@
+//! Status notifier tray
+//!
+//! Third-party tray icons use the StatusNotifierItem protocol: apps serve
+//! an `org.kde.StatusNotifierItem` object and register it with the
+//! `org.kde.StatusNotifierWatcher`, which the shell serves unless another
+//! component already does. Each panel's tray runs a host that follows the
+//! watcher and reads the items' icons, tooltips and `com.canonical.dbusmenu`
+//! menus.
+//!
+//! Like the notification daemon, the tray uses the bus from
+//! `DBUS_SESSION_BUS_ADDRESS`, so it can be tried against a private bus:
+//! `dbus-run-session -- sh -c 'spinner-shell & cargo run --example tray_item'`.
+
+mod host;
+mod item;
+mod menu;
+mod watcher;
+
+pub use host::{subscribe, TrayEvent};
+pub use item::{activate, context_menu, scroll, secondary_activate, Pixmap, Status, TrayItem};
+pub use menu::{activate as activate_menu_item, fetch as fetch_menu, MenuItem, Toggle};
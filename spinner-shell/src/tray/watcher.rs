@@ -0,0 +1,175 @@
+//! `org.kde.StatusNotifierWatcher` server
+//!
+//! Keeps the list of registered items and drops those whose owner leaves
+//! the bus. When another desktop component already runs a watcher, the
+//! shell's host uses that one instead.
+
+use anyhow::{Context, Result};
+use tracing::{debug, info, warn};
+use zbus::blocking::{Connection, ConnectionBuilder, MessageIterator};
+use zbus::{dbus_interface, fdo, MessageHeader, SignalContext};
+
+pub const BUS_NAME: &str = "org.kde.StatusNotifierWatcher";
+pub const OBJECT_PATH: &str = "/StatusNotifierWatcher";
+pub const INTERFACE: &str = "org.kde.StatusNotifierWatcher";
+
+/// Where items are served when they register with a bus name only.
+pub const DEFAULT_ITEM_PATH: &str = "/StatusNotifierItem";
+
+/// The id of an item, as listed in `RegisteredStatusNotifierItems`: its
+/// bus name followed by its object path.
+fn item_id(sender: &str, service: &str) -> String {
+    if service.starts_with('/') {
+        // Ayatana-style registration with just an object path.
+        format!("{}{}", sender, service)
+    } else if service.contains('/') {
+        service.to_string()
+    } else {
+        format!("{}{}", service, DEFAULT_ITEM_PATH)
+    }
+}
+
+/// Splits an item id into bus name and object path.
+pub fn split_id(id: &str) -> (&str, &str) {
+    match id.find('/') {
+        Some(index) => id.split_at(index),
+        None => (id, DEFAULT_ITEM_PATH),
+    }
+}
+
+#[derive(Default)]
+struct Watcher {
+    items: Vec<String>,
+    hosts: Vec<String>,
+}
+
+#[dbus_interface(name = "org.kde.StatusNotifierWatcher")]
+impl Watcher {
+    async fn register_status_notifier_item(
+        &mut self,
+        service: String,
+        #[zbus(header)] header: MessageHeader<'_>,
+        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+    ) -> fdo::Result<()> {
+        let sender = header
+            .sender()?
+            .ok_or_else(|| fdo::Error::InvalidArgs("No sender".to_string()))?;
+        let id = item_id(sender.as_str(), &service);
+        if self.items.contains(&id) {
+            return Ok(());
+        }
+
+        debug!("Tray item registered: {}", id);
+        self.items.push(id.clone());
+        Self::status_notifier_item_registered(&ctxt, &id).await?;
+        self.registered_status_notifier_items_changed(&ctxt).await?;
+        Ok(())
+    }
+
+    async fn register_status_notifier_host(
+        &mut self,
+        service: String,
+        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+    ) -> fdo::Result<()> {
+        if !self.hosts.contains(&service) {
+            self.hosts.push(service);
+            Self::status_notifier_host_registered(&ctxt).await?;
+            self.is_status_notifier_host_registered_changed(&ctxt).await?;
+        }
+        Ok(())
+    }
+
+    #[dbus_interface(property)]
+    fn registered_status_notifier_items(&self) -> Vec<String> {
+        self.items.clone()
+    }
+
+    #[dbus_interface(property)]
+    fn is_status_notifier_host_registered(&self) -> bool {
+        !self.hosts.is_empty()
+    }
+
+    #[dbus_interface(property)]
+    fn protocol_version(&self) -> i32 {
+        0
+    }
+
+    #[dbus_interface(signal)]
+    async fn status_notifier_item_registered(ctxt: &SignalContext<'_>, service: &str) -> zbus::Result<()>;
+
+    #[dbus_interface(signal)]
+    async fn status_notifier_item_unregistered(ctxt: &SignalContext<'_>, service: &str) -> zbus::Result<()>;
+
+    #[dbus_interface(signal)]
+    async fn status_notifier_host_registered(ctxt: &SignalContext<'_>) -> zbus::Result<()>;
+
+    #[dbus_interface(signal)]
+    async fn status_notifier_host_unregistered(ctxt: &SignalContext<'_>) -> zbus::Result<()>;
+}
+
+impl Watcher {
+    /// Forgets everything `name` registered, now that it left the bus.
+    async fn remove_owner(&mut self, name: &str, ctxt: &SignalContext<'_>) -> zbus::Result<()> {
+        let (gone, kept): (Vec<String>, Vec<String>) = self
+            .items
+            .drain(..)
+            .partition(|id| split_id(id).0 == name);
+        self.items = kept;
+        for id in &gone {
+            debug!("Tray item unregistered: {}", id);
+            Self::status_notifier_item_unregistered(ctxt, id).await?;
+        }
+        if !gone.is_empty() {
+            self.registered_status_notifier_items_changed(ctxt).await?;
+        }
+
+        if let Some(index) = self.hosts.iter().position(|host| host == name) {
+            self.hosts.remove(index);
+            Self::status_notifier_host_unregistered(ctxt).await?;
+            self.is_status_notifier_host_registered_changed(ctxt).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Claims the watcher name on the session bus and serves it from a
+/// thread of its own. Fails if another watcher owns the name.
+pub fn serve() -> Result<()> {
+    serve_on(ConnectionBuilder::session()?)
+}
+
+pub(super) fn serve_on(builder: ConnectionBuilder<'_>) -> Result<()> {
+    let connection = builder
+        .serve_at(OBJECT_PATH, Watcher::default())?
+        .name(BUS_NAME)?
+        .build()
+        .context("Failed to own org.kde.StatusNotifierWatcher")?;
+    info!("Serving {} on {}", BUS_NAME, OBJECT_PATH);
+
+    // Both well-known and unique names report their owner leaving.
+    let rule = "type='signal',sender='org.freedesktop.DBus',interface='org.freedesktop.DBus',member='NameOwnerChanged'";
+    let changes = MessageIterator::for_match_rule(rule, &connection, None)?;
+    std::thread::spawn(move || {
+        for message in changes {
+            let Ok(message) = message else {
+                continue;
+            };
+            let Ok((name, _, new_owner)) = message.body::<(String, String, String)>() else {
+                continue;
+            };
+            if new_owner.is_empty() {
+                if let Err(e) = remove_owner(&connection, &name) {
+                    warn!("Failed to unregister tray items of {}: {}", name, e);
+                }
+            }
+        }
+    });
+    Ok(())
+}
+
+fn remove_owner(connection: &Connection, name: &str) -> zbus::Result<()> {
+    let watcher = connection.object_server().interface::<_, Watcher>(OBJECT_PATH)?;
+    let ctxt = watcher.signal_context().clone();
+    let result = zbus::block_on(watcher.get_mut().remove_owner(name, &ctxt));
+    result
+}
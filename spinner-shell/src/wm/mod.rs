@@ -0,0 +1,198 @@
+//! Client for the SpinnerWM IPC socket
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::time::Duration;
+use tracing::{debug, warn};
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type")]
+pub enum Request {
+    GetWindows,
+    Subscribe,
+    FocusWindow { id: u32 },
+    CloseWindow { id: u32 },
+    /// Closes every window and ends the session.
+    Exit,
+    Lock,
+    Unlock,
+    ActivationToken { app_id: Option<String> },
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct WindowInfo {
+    pub id: u32,
+    pub title: String,
+    pub app_id: String,
+    pub workspace: u32,
+    pub focused: bool,
+    pub minimized: bool,
+    pub fullscreen: bool,
+    pub xwayland: bool,
+    pub geometry: Geometry,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub struct Geometry {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Geometry {
+    pub fn center(&self) -> (i32, i32) {
+        (self.x + self.width as i32 / 2, self.y + self.height as i32 / 2)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type")]
+pub enum Response {
+    Ok,
+    Error { message: String },
+    Windows { windows: Vec<WindowInfo> },
+    /// Only the lock screen is shown.
+    Locked,
+    ActivationToken { token: String },
+}
+
+fn socket_path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os("SPINNERWM_SOCK") {
+        return Some(PathBuf::from(path));
+    }
+    std::env::var_os("XDG_RUNTIME_DIR").map(|dir| PathBuf::from(dir).join("spinner-wm.sock"))
+}
+
+fn connect() -> Result<UnixStream> {
+    let path = socket_path().context("Cannot locate the SpinnerWM socket")?;
+    UnixStream::connect(&path).with_context(|| format!("Failed to connect to {:?}", path))
+}
+
+fn write_request(stream: &mut UnixStream, request: &Request) -> Result<()> {
+    let mut line = serde_json::to_vec(request)?;
+    line.push(b'\n');
+    stream.write_all(&line)?;
+    Ok(())
+}
+
+/// Sends a single request and waits for its response.
+pub fn send(request: &Request) -> Result<Response> {
+    let mut stream = connect()?;
+    stream.set_read_timeout(Some(Duration::from_secs(2)))?;
+    write_request(&mut stream, request)?;
+
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line)?;
+    let response = serde_json::from_str(&line).context("Invalid response from SpinnerWM")?;
+
+    if let Response::Error { message } = &response {
+        warn!("SpinnerWM rejected {:?}: {}", request, message);
+    }
+    Ok(response)
+}
+
+/// An xdg-activation token for an app about to be launched, so its
+/// window is allowed to take focus.
+pub fn activation_token(app_id: &str) -> Result<String> {
+    let request = Request::ActivationToken {
+        app_id: Some(app_id.to_string()),
+    };
+    match send(&request)? {
+        Response::ActivationToken { token } => Ok(token),
+        response => anyhow::bail!("Unexpected response from SpinnerWM: {:?}", response),
+    }
+}
+
+/// The session lock in SpinnerWM, held on its own connection. Should the
+/// connection close without `unlock`, SpinnerWM keeps the screen blank.
+pub struct LockHandle {
+    stream: UnixStream,
+}
+
+/// Makes this process the session's lock client. The lock screen must be
+/// mapped by this process as an overlay layer surface in the
+/// `spinner-lock` namespace.
+pub fn lock() -> Result<LockHandle> {
+    let mut stream = connect()?;
+    stream.set_read_timeout(Some(Duration::from_secs(2)))?;
+    write_request(&mut stream, &Request::Lock)?;
+    let mut handle = LockHandle { stream };
+    match handle.read_response()? {
+        Response::Error { message } => anyhow::bail!("SpinnerWM refused to lock: {}", message),
+        _ => Ok(handle),
+    }
+}
+
+impl LockHandle {
+    fn read_response(&mut self) -> Result<Response> {
+        // One byte at a time, so nothing after the line is consumed.
+        let mut line = Vec::new();
+        let mut byte = [0u8; 1];
+        while byte[0] != b'\n' {
+            if self.stream.read(&mut byte)? == 0 {
+                anyhow::bail!("SpinnerWM closed the connection");
+            }
+            line.push(byte[0]);
+        }
+        serde_json::from_slice(&line).context("Invalid response from SpinnerWM")
+    }
+
+    /// Waits until SpinnerWM shows nothing but the lock screen.
+    pub fn wait_locked(&mut self, timeout: Duration) -> Result<()> {
+        self.stream.set_read_timeout(Some(timeout))?;
+        loop {
+            if let Response::Locked = self.read_response()? {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Ends the lock, or gives it up if the lock screen was never shown.
+    pub fn unlock(mut self) -> Result<()> {
+        self.stream.set_read_timeout(Some(Duration::from_secs(2)))?;
+        write_request(&mut self.stream, &Request::Unlock)?;
+        loop {
+            match self.read_response()? {
+                Response::Ok => return Ok(()),
+                Response::Error { message } => anyhow::bail!("SpinnerWM refused to unlock: {}", message),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Streams the window list from SpinnerWM, reconnecting if the compositor
+/// restarts. Updates are delivered on the returned channel.
+pub fn subscribe_windows() -> async_channel::Receiver<Vec<WindowInfo>> {
+    let (sender, receiver) = async_channel::unbounded();
+
+    std::thread::spawn(move || loop {
+        match stream_windows(&sender) {
+            Ok(()) => return,
+            Err(e) => debug!("Window subscription interrupted: {:#}", e),
+        }
+        std::thread::sleep(Duration::from_secs(2));
+    });
+
+    receiver
+}
+
+/// Returns `Ok` only when the receiving side has gone away.
+fn stream_windows(sender: &async_channel::Sender<Vec<WindowInfo>>) -> Result<()> {
+    let mut stream = connect()?;
+    write_request(&mut stream, &Request::Subscribe)?;
+
+    for line in BufReader::new(stream).lines() {
+        if let Response::Windows { windows } = serde_json::from_str(&line?)? {
+            if sender.send_blocking(windows).is_err() {
+                return Ok(());
+            }
+        }
+    }
+
+    anyhow::bail!("SpinnerWM closed the connection")
+}
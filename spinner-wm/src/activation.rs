@@ -0,0 +1,56 @@
+//! xdg-activation-v1 tokens for SpinnerWM
+//!
+//! A token lets the surface it is handed to take focus, so a freshly
+//! launched app comes to the front instead of opening behind the window
+//! the user was in. The compositor creates one for every command it
+//! spawns, and IPC clients such as the app menu ask for one before
+//! launching. Tokens are single use and expire after `TOKEN_LIFETIME`.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::debug;
+
+/// Long enough for a slow app to start and map its first window.
+pub const TOKEN_LIFETIME: Duration = Duration::from_secs(30);
+
+#[derive(Default)]
+pub struct Activation {
+    /// Unredeemed tokens and when they were handed out.
+    tokens: HashMap<String, Instant>,
+    serial: u64,
+}
+
+impl Activation {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A new token, for the app with `app_id` if known.
+    pub fn create_token(&mut self, app_id: Option<&str>, now: Instant) -> String {
+        self.expire(now);
+        self.serial += 1;
+        // Hard to guess, so clients can't make up their own.
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.subsec_nanos());
+        let token = format!("spinner-{:x}-{:08x}", self.serial, nanos);
+        debug!(
+            "Activation token {} for {}",
+            token,
+            app_id.unwrap_or("a command")
+        );
+        self.tokens.insert(token.clone(), now);
+        token
+    }
+
+    /// Uses up `token`. False if it is unknown or has expired.
+    pub fn redeem(&mut self, token: &str, now: Instant) -> bool {
+        self.expire(now);
+        self.tokens.remove(token).is_some()
+    }
+
+    fn expire(&mut self, now: Instant) {
+        self.tokens
+            .retain(|_, created| now.duration_since(*created) < TOKEN_LIFETIME);
+    }
+}
@@ -1,54 +1,112 @@
 //! Wayland compositor implementation for SpinnerWM
 
+use crate::activation::Activation;
 use crate::config::Config;
+use crate::idle::{IdleManager, IdleStage, InhibitSurface, OutputPower};
 use crate::input::{Action, DragOperation, InputHandler, MouseState};
-use crate::window::{ManagedWindow, WindowId, WindowManager};
+use crate::ipc::{ClientId, IpcServer, Request, Response};
+use crate::layer_shell::{Layer, LayerConfigure, LayerError, LayerShell, LayerSurfaceId};
+use crate::session_lock::{LockClient, LockError, LockState, LockSurface, OutputContent, SessionLock, LOCK_NAMESPACE};
+use crate::wayland::Wayland;
+use crate::window::{Geometry, WindowId, WindowManager, WindowSurface};
+use crate::xwayland::XWayland;
+use crate::xwm::Xwm;
 
 use anyhow::{Context, Result};
-use calloop::{EventLoop, LoopSignal};
-use std::process::Command;
-use std::time::Duration;
+use calloop::generic::Generic;
+use calloop::{EventLoop, Interest, LoopHandle, LoopSignal, Mode, PostAction, RegistrationToken};
+use spinner_launch::Launch;
+use std::io::Read;
+use std::os::fd::OwnedFd;
+use std::os::unix::net::UnixStream;
+use std::time::{Duration, Instant};
 use tracing::{debug, error, info, warn};
+use wayland_server::{Display, DisplayHandle, ListeningSocket};
 
 pub struct SpinnerCompositor {
     config: Config,
     window_manager: WindowManager,
+    layer_shell: LayerShell,
+    session_lock: SessionLock,
+    idle: IdleManager,
+    activation: Activation,
+    wayland: Wayland,
+    display: Option<DisplayHandle>,
     input_handler: InputHandler,
     mouse_state: MouseState,
     drag_operation: DragOperation,
     running: bool,
+    /// Set once the session is ending; the compositor stops when the
+    /// windows are gone or this passes.
+    exit_deadline: Option<Instant>,
     loop_signal: Option<LoopSignal>,
+    loop_handle: Option<LoopHandle<'static, Self>>,
+    ipc: Option<IpcServer>,
+    xwayland: Option<XWayland>,
+    xwayland_listeners: Vec<RegistrationToken>,
+    xwm: Option<Xwm>,
+    xwm_source: Option<RegistrationToken>,
 }
 
 impl SpinnerCompositor {
     pub fn new(config: Config) -> Result<Self> {
         let screen_width = 1920;
         let screen_height = 1080;
-        let panel_height = 48;
         
-        let window_manager = WindowManager::new(screen_width, screen_height, panel_height);
+        // The usable area starts as the whole output and shrinks as
+        // layer surfaces such as the panel claim exclusive zones.
+        let window_manager = WindowManager::new(screen_width, screen_height);
         let input_handler = InputHandler::new(&config);
         
         Ok(Self {
             config,
             window_manager,
+            layer_shell: LayerShell::new(),
+            session_lock: SessionLock::new(Geometry::new(0, 0, screen_width, screen_height)),
+            idle: IdleManager::new(),
+            activation: Activation::new(),
+            wayland: Wayland::new(),
+            display: None,
             input_handler,
             mouse_state: MouseState::default(),
             drag_operation: DragOperation::None,
             running: true,
+            exit_deadline: None,
             loop_signal: None,
+            loop_handle: None,
+            ipc: None,
+            xwayland: None,
+            xwayland_listeners: Vec::new(),
+            xwm: None,
+            xwm_source: None,
         })
     }
     
     pub fn run(&mut self) -> Result<()> {
         info!("Starting SpinnerWM event loop");
         
-        self.run_autostart()?;
-        
-        let mut event_loop: EventLoop<Self> = EventLoop::try_new()
+        let mut event_loop: EventLoop<'static, Self> = EventLoop::try_new()
             .context("Failed to create event loop")?;
         
         self.loop_signal = Some(event_loop.get_signal());
+        self.loop_handle = Some(event_loop.handle());
+        
+        if let Err(e) = self.setup_wayland() {
+            warn!("Wayland clients disabled: {:#}", e);
+        }
+        
+        if let Err(e) = self.setup_ipc() {
+            warn!("IPC disabled: {:#}", e);
+        }
+        
+        if self.config.xwayland.enabled {
+            if let Err(e) = self.setup_xwayland() {
+                warn!("XWayland disabled: {:#}", e);
+            }
+        }
+        
+        // Children inherit WAYLAND_DISPLAY, DISPLAY and the IPC socket path.
+        self.run_autostart()?;
         
         info!("SpinnerWM is running. Press Mod4+Shift+E to exit.");
         
@@ -64,20 +122,307 @@ impl SpinnerCompositor {
         Ok(())
     }
     
+    fn setup_wayland(&mut self) -> Result<()> {
+        let mut display: Display<Self> = Display::new().context("Failed to create Wayland display")?;
+        let display_handle = display.handle();
+        Wayland::create_globals(&display_handle);
+        
+        let socket = ListeningSocket::bind_auto("wayland", 1..33).context("Failed to bind Wayland socket")?;
+        let socket_name = socket.socket_name().context("Wayland socket has no name")?.to_owned();
+        
+        let handle = self.loop_handle.clone().context("Event loop not running")?;
+        handle
+            .insert_source(
+                Generic::new(socket, Interest::READ, Mode::Level),
+                |_, socket, state: &mut Self| {
+                    while let Some(stream) = socket.accept()? {
+                        if let Some(display) = &mut state.display {
+                            if let Err(e) = display.insert_client(stream, std::sync::Arc::new(())) {
+                                warn!("Failed to add Wayland client: {}", e);
+                            }
+                        }
+                    }
+                    Ok(PostAction::Continue)
+                },
+            )
+            .map_err(|e| anyhow::anyhow!("Failed to register Wayland socket: {}", e))?;
+        
+        let fd = display.backend().poll_fd().try_clone_to_owned()?;
+        handle
+            .insert_source(
+                Generic::new(fd, Interest::READ, Mode::Level),
+                move |_, _, state: &mut Self| {
+                    display.dispatch_clients(state)?;
+                    display.flush_clients()?;
+                    Ok(PostAction::Continue)
+                },
+            )
+            .map_err(|e| anyhow::anyhow!("Failed to register Wayland display: {}", e))?;
+        
+        std::env::set_var("WAYLAND_DISPLAY", &socket_name);
+        info!("Listening for Wayland clients on {:?}", socket_name);
+        self.display = Some(display_handle);
+        Ok(())
+    }
+    
+    fn setup_ipc(&mut self) -> Result<()> {
+        let ipc = IpcServer::bind()?;
+        let listener = ipc.listener()?;
+        self.ipc = Some(ipc);
+        
+        let handle = self.loop_handle.clone().context("Event loop not running")?;
+        handle
+            .insert_source(
+                Generic::new(listener, Interest::READ, Mode::Level),
+                |_, _, state: &mut Self| {
+                    state.accept_ipc_clients();
+                    Ok(PostAction::Continue)
+                },
+            )
+            .map_err(|e| anyhow::anyhow!("Failed to register IPC socket: {}", e))?;
+        
+        Ok(())
+    }
+    
+    fn accept_ipc_clients(&mut self) {
+        let Some(handle) = self.loop_handle.clone() else {
+            return;
+        };
+        
+        while let Some((id, stream)) = self.ipc.as_mut().and_then(|ipc| ipc.accept()) {
+            let source = Generic::new(stream, Interest::READ, Mode::Level);
+            let result = handle.insert_source(source, move |_, _, state: &mut Self| {
+                Ok(state.handle_ipc_client(id))
+            });
+            
+            if let Err(e) = result {
+                warn!("Failed to register IPC client: {}", e);
+                if let Some(ipc) = &mut self.ipc {
+                    ipc.disconnect(id);
+                }
+            }
+        }
+    }
+    
+    fn handle_ipc_client(&mut self, id: ClientId) -> PostAction {
+        let Some(requests) = self.ipc.as_mut().and_then(|ipc| ipc.read_requests(id)) else {
+            self.session_lock.client_gone(LockClient::Ipc(id));
+            return PostAction::Remove;
+        };
+        
+        for request in requests {
+            let response = match request {
+                Request::FocusWindow { .. } | Request::CloseWindow { .. } | Request::Exit
+                    if self.session_lock.is_locked() =>
+                {
+                    Response::Error { message: "The session is locked".to_string() }
+                }
+                Request::GetWindows => Response::Windows {
+                    windows: crate::ipc::listed_windows(&self.window_manager),
+                },
+                Request::Subscribe => {
+                    if let Some(ipc) = &mut self.ipc {
+                        ipc.subscribe(id, &self.window_manager);
+                    }
+                    continue;
+                }
+                Request::FocusWindow { id: window } => {
+                    let window = WindowId::from_u32(window);
+                    if let Some(workspace) = self.window_manager.window(window).map(|w| w.workspace) {
+                        self.window_manager.switch_workspace(workspace);
+                        self.window_manager.focus_window(window);
+                        Response::Ok
+                    } else {
+                        Response::Error { message: "No such window".to_string() }
+                    }
+                }
+                Request::CloseWindow { id: window } => {
+                    self.close_window(WindowId::from_u32(window));
+                    Response::Ok
+                }
+                Request::Exit => {
+                    self.exit_session();
+                    Response::Ok
+                }
+                Request::Lock => match self.session_lock.lock(LockClient::Ipc(id)) {
+                    Ok(()) => {
+                        if let Some(ipc) = &mut self.ipc {
+                            ipc.send(id, &Response::Ok);
+                        }
+                        self.adopt_lock_surface();
+                        continue;
+                    }
+                    Err(e) => lock_error(e),
+                },
+                Request::Unlock => {
+                    // Before `Locked` this gives the lock up, e.g. when the
+                    // lock screen was not shown in time.
+                    let result = if self.session_lock.state() == LockState::Locking {
+                        self.session_lock.cancel(LockClient::Ipc(id))
+                    } else {
+                        self.session_lock.unlock(LockClient::Ipc(id))
+                    };
+                    match result {
+                        Ok(()) => Response::Ok,
+                        Err(e) => lock_error(e),
+                    }
+                }
+                Request::ActivationToken { app_id } => Response::ActivationToken {
+                    token: self.activation.create_token(app_id.as_deref(), Instant::now()),
+                },
+            };
+            
+            if let Some(ipc) = &mut self.ipc {
+                ipc.send(id, &response);
+            }
+        }
+        
+        PostAction::Continue
+    }
+    
+    fn setup_xwayland(&mut self) -> Result<()> {
+        let xwayland = XWayland::prepare()?;
+        std::env::set_var("DISPLAY", xwayland.display_name());
+        
+        let handle = self.loop_handle.clone().context("Event loop not running")?;
+        for listener in xwayland.listeners() {
+            let token = handle
+                .insert_source(
+                    Generic::new(listener, Interest::READ, Mode::Level),
+                    |_, _, state: &mut Self| {
+                        // Xwayland takes over the listening sockets until it exits.
+                        state.start_xwayland();
+                        Ok(PostAction::Disable)
+                    },
+                )
+                .map_err(|e| anyhow::anyhow!("Failed to register X11 socket: {}", e))?;
+            self.xwayland_listeners.push(token);
+        }
+        
+        info!("XWayland will start on demand on {}", xwayland.display_name());
+        self.xwayland = Some(xwayland);
+        Ok(())
+    }
+    
+    fn start_xwayland(&mut self) {
+        let Some(xwayland) = &mut self.xwayland else {
+            return;
+        };
+        if xwayland.is_running() {
+            return;
+        }
+        
+        let startup = match xwayland.start() {
+            Ok(startup) => startup,
+            Err(e) => {
+                error!("Failed to start Xwayland: {:#}", e);
+                return;
+            }
+        };
+        
+        if let Some(handle) = &self.loop_handle {
+            for token in &self.xwayland_listeners {
+                let _ = handle.disable(token);
+            }
+            
+            let mut wm_stream = Some(startup.wm_stream);
+            let result = handle.insert_source(
+                Generic::new(startup.ready, Interest::READ, Mode::Level),
+                move |_, ready, state: &mut Self| {
+                    if let Some(stream) = wm_stream.take() {
+                        state.attach_xwm(ready, stream);
+                    }
+                    Ok(PostAction::Remove)
+                },
+            );
+            if let Err(e) = result {
+                error!("Failed to wait for Xwayland: {}", e);
+            }
+        }
+    }
+    
+    fn attach_xwm(&mut self, ready: &OwnedFd, stream: UnixStream) {
+        // Xwayland writes its display number once it is ready.
+        let mut buf = [0u8; 16];
+        let read = ready
+            .try_clone()
+            .and_then(|fd| std::fs::File::from(fd).read(&mut buf));
+        match read {
+            Ok(n) if n > 0 => {}
+            _ => {
+                warn!("Xwayland exited before becoming ready");
+                return;
+            }
+        }
+        
+        let xwm = match Xwm::new(stream) {
+            Ok(xwm) => xwm,
+            Err(e) => {
+                error!("Failed to start X11 window manager: {:#}", e);
+                return;
+            }
+        };
+        
+        let (Some(handle), Ok(fd)) = (&self.loop_handle, xwm.poll_fd()) else {
+            return;
+        };
+        
+        match handle.insert_source(
+            Generic::new(fd, Interest::READ, Mode::Level),
+            |_, _, state: &mut Self| {
+                state.dispatch_xwm();
+                Ok(PostAction::Continue)
+            },
+        ) {
+            Ok(token) => {
+                self.xwm = Some(xwm);
+                self.xwm_source = Some(token);
+            }
+            Err(e) => error!("Failed to register X11 connection: {}", e),
+        }
+    }
+    
+    fn dispatch_xwm(&mut self) {
+        if let Some(xwm) = &mut self.xwm {
+            if let Err(e) = xwm.dispatch(&mut self.window_manager) {
+                warn!("X11 connection error: {:#}", e);
+            }
+        }
+    }
+    
+    /// Tears down the X11 side once Xwayland exits and re-arms the sockets
+    /// so the next X11 client starts it again.
+    fn reap_xwayland(&mut self) {
+        if !self.xwayland.as_mut().is_some_and(|x| x.reap()) {
+            return;
+        }
+        
+        if let Some(xwm) = self.xwm.take() {
+            let ids: Vec<WindowId> = xwm.window_ids().collect();
+            for id in ids {
+                self.window_manager.remove_window(id);
+            }
+        }
+        
+        if let Some(handle) = &self.loop_handle {
+            if let Some(token) = self.xwm_source.take() {
+                handle.remove(token);
+            }
+            for token in &self.xwayland_listeners {
+                let _ = handle.enable(token);
+            }
+        }
+    }
+    
     fn run_autostart(&self) -> Result<()> {
         info!("Running autostart applications");
         
         for cmd in &self.config.general.autostart {
             info!("Starting: {}", cmd);
             
-            let parts: Vec<&str> = cmd.split_whitespace().collect();
-            if parts.is_empty() {
-                continue;
-            }
-            
-            match Command::new(parts[0]).args(&parts[1..]).spawn() {
-                Ok(_) => debug!("Started: {}", cmd),
-                Err(e) => warn!("Failed to start {}: {}", cmd, e),
+            match Launch::command(cmd).and_then(Launch::spawn) {
+                Ok(()) => debug!("Started: {}", cmd),
+                Err(e) => warn!("Failed to start {}: {:#}", cmd, e),
             }
         }
         
@@ -86,16 +431,136 @@ impl SpinnerCompositor {
     
     fn process_frame(&mut self) {
         // Frame processing - animations, damage tracking, etc.
+        self.reap_xwayland();
+        
+        if let Some(xwm) = &mut self.xwm {
+            if let Err(e) = xwm.sync(&self.window_manager) {
+                warn!("Failed to sync X11 windows: {:#}", e);
+            }
+        }
+        
+        if let Some(ipc) = &mut self.ipc {
+            ipc.broadcast_windows(&self.window_manager);
+        }
+        
+        self.update_idle();
+        
+        self.wayland.send_idle_events(self.idle.take_events());
+        let (content, power) = (self.output_content(), self.output_power());
+        self.wayland.frame(&self.window_manager, content, power);
+        if let Some(display) = &mut self.display {
+            if let Err(e) = display.flush_clients() {
+                warn!("Failed to flush Wayland clients: {}", e);
+            }
+        }
+        
+        if let Some(deadline) = self.exit_deadline {
+            let remaining = self.window_manager.windows().count();
+            if remaining == 0 {
+                self.stop();
+            } else if Instant::now() >= deadline {
+                warn!("{} windows did not close in time", remaining);
+                self.stop();
+            }
+        }
+    }
+    
+    /// Runs the idle stages that have come due.
+    fn update_idle(&mut self) {
+        let stages = self.idle.tick(Instant::now(), self.idle_inhibited(), &self.config.idle);
+        for stage in stages {
+            info!("Idle: {:?}", stage);
+            match stage {
+                IdleStage::Lock if !self.session_lock.is_locked() => {
+                    let command = self.config.idle.lock_command.clone();
+                    self.spawn_command(&command);
+                }
+                IdleStage::Suspend => {
+                    let command = self.config.idle.suspend_command.clone();
+                    self.spawn_command(&command);
+                }
+                _ => {}
+            }
+        }
     }
     
+    /// Whether an inhibitor on a visible surface, or a fullscreen window
+    /// with focus, keeps the session awake. Nothing does while locked,
+    /// since only the lock screen is shown.
+    fn idle_inhibited(&self) -> bool {
+        if self.session_lock.is_locked() {
+            return false;
+        }
+        
+        let workspace = self.window_manager.active_workspace();
+        let inhibitor = self.idle.inhibitor_surfaces().any(|surface| match surface {
+            InhibitSurface::Window(id) => self
+                .window_manager
+                .window(id)
+                .is_some_and(|w| w.workspace == workspace && !w.minimized),
+            InhibitSurface::Layer(id) => self.layer_shell.surfaces().any(|s| s.id == id && s.mapped),
+        });
+        let fullscreen = self.config.idle.inhibit_when_fullscreen
+            && self
+                .window_manager
+                .focused_window()
+                .is_some_and(|w| w.fullscreen && !w.minimized);
+        inhibitor || fullscreen
+    }
+    
+    /// Asks every window to close; `process_frame` stops the compositor
+    /// once they have, or after `exit_timeout`.
+    fn exit_session(&mut self) {
+        if self.exit_deadline.is_some() {
+            return;
+        }
+        info!("Exit requested, closing windows");
+        
+        let ids: Vec<WindowId> = self.window_manager.windows().map(|w| w.id).collect();
+        for id in ids {
+            self.close_window(id);
+        }
+        self.exit_deadline = Some(Instant::now() + Duration::from_secs(self.config.general.exit_timeout));
+    }
+    
+    fn stop(&mut self) {
+        info!("Exiting");
+        self.running = false;
+        if let Some(signal) = &self.loop_signal {
+            signal.stop();
+        }
+    }
+    
+    // The input entry points wait for an input backend to call them.
+    #[allow(dead_code)]
     pub fn handle_key_press(&mut self, key: &str) {
+        self.idle.activity(Instant::now());
+        // Keys go to the lock surface only.
+        if self.session_lock.is_locked() {
+            return;
+        }
         if let Some(action) = self.input_handler.key_pressed(key) {
             self.execute_action(action);
         }
     }
     
+    #[allow(dead_code)]
+    pub fn handle_key_release(&mut self, key: &str) {
+        if self.session_lock.is_locked() {
+            return;
+        }
+        if let Some(action) = self.input_handler.key_released(key) {
+            self.execute_action(action);
+        }
+    }
+    
+    #[allow(dead_code)]
     pub fn handle_mouse_motion(&mut self, x: f64, y: f64) {
+        self.idle.activity(Instant::now());
         self.mouse_state.update_position(x, y);
+        if self.session_lock.is_locked() {
+            return;
+        }
         
         match self.drag_operation {
             DragOperation::Move { start_x, start_y, window_x, window_y } => {
@@ -128,7 +593,12 @@ impl SpinnerCompositor {
         }
     }
     
+    #[allow(dead_code)]
     pub fn handle_mouse_button(&mut self, button: u32, pressed: bool) {
+        self.idle.activity(Instant::now());
+        if pressed {
+            self.input_handler.button_pressed();
+        }
         match button {
             0x110 => self.mouse_state.button_left = pressed,
             0x111 => self.mouse_state.button_right = pressed,
@@ -136,6 +606,11 @@ impl SpinnerCompositor {
             _ => {}
         }
         
+        if self.session_lock.is_locked() {
+            self.drag_operation = DragOperation::None;
+            return;
+        }
+        
         if pressed {
             let modifiers = self.input_handler.current_modifiers();
             let has_super = modifiers.iter().any(|m| matches!(m, crate::input::Modifier::Super));
@@ -184,8 +659,9 @@ impl SpinnerCompositor {
                 }
             }
             Action::Fullscreen => {
+                let (width, height) = self.window_manager.screen_size();
                 if let Some(window) = self.window_manager.focused_window_mut() {
-                    window.toggle_fullscreen(1920, 1080);
+                    window.toggle_fullscreen(width, height);
                 }
             }
             Action::ToggleFloating => {
@@ -194,8 +670,9 @@ impl SpinnerCompositor {
                 }
             }
             Action::Maximize => {
+                let area = self.window_manager.usable_area();
                 if let Some(window) = self.window_manager.focused_window_mut() {
-                    window.toggle_maximize(1920, 1080, 48);
+                    window.toggle_maximize(area);
                 }
             }
             Action::Minimize => {
@@ -203,13 +680,17 @@ impl SpinnerCompositor {
                     window.minimize();
                 }
             }
-            Action::Exit => {
-                info!("Exit requested");
-                self.running = false;
-                if let Some(signal) = &self.loop_signal {
-                    signal.stop();
+            Action::ReloadConfig => {
+                match Config::load() {
+                    Ok(config) => {
+                        self.input_handler = InputHandler::new(&config);
+                        self.config = config;
+                        info!("Configuration reloaded");
+                    }
+                    Err(e) => error!("Failed to reload config: {:#}", e),
                 }
             }
+            Action::Exit => self.exit_session(),
             Action::Workspace(n) => {
                 self.window_manager.switch_workspace(n);
                 info!("Switched to workspace {}", n);
@@ -230,32 +711,152 @@ impl SpinnerCompositor {
             Action::Resize(_direction, _amount) => {
                 debug!("Resize direction not yet implemented");
             }
-            Action::None => {}
         }
     }
     
-    fn spawn_command(&self, cmd: &str) {
+    /// Runs a command from a keybinding or the idle stages, with an
+    /// activation token so its window comes to the front.
+    fn spawn_command(&mut self, cmd: &str) {
         info!("Spawning: {}", cmd);
         
-        let parts: Vec<&str> = cmd.split_whitespace().collect();
-        if parts.is_empty() {
-            return;
+        let token = self.activation.create_token(None, Instant::now());
+        match Launch::command(cmd).and_then(|launch| launch.activation_token(token).spawn()) {
+            Ok(()) => debug!("Spawned: {}", cmd),
+            Err(e) => error!("Failed to spawn {}: {:#}", cmd, e),
         }
-        
-        match Command::new(parts[0]).args(&parts[1..]).spawn() {
-            Ok(_) => debug!("Spawned: {}", cmd),
-            Err(e) => error!("Failed to spawn {}: {}", cmd, e),
+    }
+    
+    /// Handles xdg_activation_v1.activate: with a valid token the window
+    /// comes to the front, on its workspace. Nothing takes focus from the
+    /// lock screen.
+    pub fn activate_window(&mut self, id: WindowId, token: &str) -> bool {
+        if !self.activation.redeem(token, Instant::now()) {
+            debug!("Ignoring activation of {:?} with an invalid token", id);
+            return false;
+        }
+        if self.session_lock.is_locked() {
+            return false;
         }
+        let Some(workspace) = self.window_manager.window(id).map(|w| w.workspace) else {
+            return false;
+        };
+        self.window_manager.switch_workspace(workspace);
+        self.window_manager.focus_window(id);
+        true
     }
     
     fn close_window(&mut self, id: WindowId) {
         info!("Closing window {:?}", id);
-        self.window_manager.remove_window(id);
+        
+        let surface = self.window_manager.window(id).map(|w| w.surface);
+        match (surface, &mut self.xwm) {
+            // X11 windows are removed once the client unmaps them.
+            (Some(WindowSurface::X11 { xid, .. }), Some(xwm)) => {
+                if let Err(e) = xwm.close(xid) {
+                    warn!("Failed to close X11 window: {:#}", e);
+                }
+            }
+            (Some(WindowSurface::Wayland), _) if self.wayland.close(id) => {}
+            _ => self.window_manager.remove_window(id),
+        }
+    }
+    
+    /// Applies a layer surface commit and re-arranges the output.
+    pub fn commit_layer_surface(&mut self, id: LayerSurfaceId, mapped: bool) -> Result<Vec<LayerConfigure>, LayerError> {
+        self.layer_shell.commit(id, mapped)?;
+        let configures = self.arrange_layers();
+        self.adopt_lock_surface();
+        Ok(configures)
+    }
+    
+    /// Removes a layer surface; the others may need a new size.
+    pub fn destroy_layer_surface(&mut self, id: LayerSurfaceId) -> Vec<LayerConfigure> {
+        self.idle.surface_destroyed(InhibitSurface::Layer(id));
+        self.session_lock.surface_destroyed(LockSurface::Layer(id));
+        self.layer_shell.destroy(id);
+        let configures = self.arrange_layers();
+        self.adopt_lock_surface();
+        configures
+    }
+    
+    /// Shows the IPC lock client's lock screen once it is mapped, and tells
+    /// the client when that completes the lock. Only a surface of the lock
+    /// client's own process qualifies; any client can pick the namespace.
+    fn adopt_lock_surface(&mut self) {
+        let Some(LockClient::Ipc(client)) = self.session_lock.client() else {
+            return;
+        };
+        if self.session_lock.content() != OutputContent::Blank {
+            return;
+        }
+        let Some(pid) = self.ipc.as_ref().and_then(|ipc| ipc.peer_pid(client)) else {
+            return;
+        };
+        let surface = self
+            .layer_shell
+            .surfaces()
+            .find(|s| {
+                s.mapped
+                    && s.pid == Some(pid)
+                    && s.namespace == LOCK_NAMESPACE
+                    && s.current.layer == Layer::Overlay
+            })
+            .map(|s| s.id);
+        if let Some(surface) = surface {
+            if self.session_lock.attach_layer_surface(surface) {
+                if let Some(ipc) = &mut self.ipc {
+                    ipc.send(client, &Response::Locked);
+                }
+            }
+        }
+    }
+    
+    /// What the renderer draws on the output. While the session is locked
+    /// that is the lock surface or nothing at all, never windows.
+    pub fn output_content(&self) -> OutputContent {
+        self.session_lock.content()
+    }
+    
+    fn arrange_layers(&mut self) -> Vec<LayerConfigure> {
+        let (width, height) = self.window_manager.screen_size();
+        let output = Geometry::new(0, 0, width, height);
+        
+        let (configures, usable_area) = self.layer_shell.arrange(output);
+        if usable_area != self.window_manager.usable_area() {
+            debug!("Usable area is now {:?}", usable_area);
+            self.window_manager.set_usable_area(usable_area);
+        }
+        configures
     }
     
-    pub fn add_window(&mut self, title: String, app_id: String, width: u32, height: u32) {
-        let window = ManagedWindow::new(title, app_id, 0, 0, width, height);
-        self.window_manager.add_window(window);
+    /// Whether the renderer shows the output normally, dimmed or not at
+    /// all.
+    pub fn output_power(&self) -> OutputPower {
+        self.idle.power()
+    }
+    
+    pub fn idle_mut(&mut self) -> &mut IdleManager {
+        &mut self.idle
+    }
+    
+    pub fn activation_mut(&mut self) -> &mut Activation {
+        &mut self.activation
+    }
+    
+    pub fn session_lock_mut(&mut self) -> &mut SessionLock {
+        &mut self.session_lock
+    }
+    
+    pub fn layer_shell_mut(&mut self) -> &mut LayerShell {
+        &mut self.layer_shell
+    }
+    
+    pub fn wayland(&self) -> &Wayland {
+        &self.wayland
+    }
+    
+    pub fn wayland_mut(&mut self) -> &mut Wayland {
+        &mut self.wayland
     }
     
     pub fn window_manager(&self) -> &WindowManager {
@@ -266,3 +867,13 @@ impl SpinnerCompositor {
         &mut self.window_manager
     }
 }
+
+fn lock_error(error: LockError) -> Response {
+    let message = match error {
+        LockError::AlreadyLocked => "The session is already locked",
+        LockError::NotLockClient => "Not the lock client",
+        LockError::InvalidUnlock => "The session is not locked yet",
+        _ => "Invalid lock request",
+    };
+    Response::Error { message: message.to_string() }
+}
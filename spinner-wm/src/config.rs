@@ -12,6 +12,10 @@ pub struct Config {
     pub general: GeneralConfig,
     pub appearance: AppearanceConfig,
     pub keybindings: HashMap<String, String>,
+    #[serde(default)]
+    pub xwayland: XWaylandConfig,
+    #[serde(default)]
+    pub idle: IdleConfig,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -19,6 +23,16 @@ pub struct GeneralConfig {
     pub focus_follows_mouse: bool,
     pub cursor_theme: String,
     pub cursor_size: u32,
+    #[serde(default)]
+    pub autostart: Vec<String>,
+    /// Seconds clients get to close their windows when the session ends
+    /// before the compositor exits anyway.
+    #[serde(default = "default_exit_timeout")]
+    pub exit_timeout: u64,
+}
+
+fn default_exit_timeout() -> u64 {
+    5
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -30,6 +44,49 @@ pub struct AppearanceConfig {
     pub gap_outer: u32,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct XWaylandConfig {
+    /// Start Xwayland when the first X11 client connects.
+    pub enabled: bool,
+}
+
+impl Default for XWaylandConfig {
+    fn default() -> Self {
+        Self { enabled: true }
+    }
+}
+
+/// What happens while the user is away. Timeouts are seconds without
+/// input; 0 turns the stage off. The stages are off by default: no input
+/// backend reports activity yet, so idle time only counts from startup.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IdleConfig {
+    pub dim_timeout: u64,
+    pub lock_timeout: u64,
+    /// Turns the outputs off.
+    pub blank_timeout: u64,
+    pub suspend_timeout: u64,
+    pub lock_command: String,
+    pub suspend_command: String,
+    /// A fullscreen window keeps the session awake, like an inhibitor.
+    pub inhibit_when_fullscreen: bool,
+}
+
+impl Default for IdleConfig {
+    fn default() -> Self {
+        Self {
+            dim_timeout: 0,
+            lock_timeout: 0,
+            blank_timeout: 0,
+            suspend_timeout: 0,
+            lock_command: "spinner-shell --lock".to_string(),
+            suspend_command: "systemctl suspend".to_string(),
+            inhibit_when_fullscreen: true,
+        }
+    }
+}
+
 impl Default for Config {
     fn default() -> Self {
         let mut keybindings = HashMap::new();
@@ -42,6 +99,8 @@ impl Default for Config {
                 focus_follows_mouse: true,
                 cursor_theme: "Adwaita".to_string(),
                 cursor_size: 24,
+                autostart: vec!["spinner-shell".to_string()],
+                exit_timeout: default_exit_timeout(),
             },
             appearance: AppearanceConfig {
                 border_width: 2,
@@ -51,6 +110,8 @@ impl Default for Config {
                 gap_outer: 16,
             },
             keybindings,
+            xwayland: XWaylandConfig::default(),
+            idle: IdleConfig::default(),
         }
     }
 }
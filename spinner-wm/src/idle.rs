@@ -0,0 +1,415 @@
+//! Idle management for SpinnerWM - ext-idle-notify-v1, idle-inhibit and
+//! the configured idle stages
+//!
+//! Clients ask to hear when the user has been idle for a while
+//! (`get_idle_notification`) and get `idled`, then `resumed` on the next
+//! input. Clients such as video players keep the session awake with an
+//! inhibitor on one of their surfaces, which only counts while the surface
+//! is visible; so does a fullscreen window unless the config says
+//! otherwise. Input idle notifications (version 2) ignore inhibitors.
+//!
+//! The compositor runs its own stages from `[idle]` in spinner-wm.toml:
+//! dim the output, lock the session, blank the output and suspend. Input
+//! undoes dimming and blanking; the lock stays until unlocked.
+
+use crate::config::IdleConfig;
+use crate::layer_shell::LayerSurfaceId;
+use crate::window::WindowId;
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::{Duration, Instant};
+use tracing::info;
+
+static IDLE_NOTIFICATION_ID_COUNTER: AtomicU32 = AtomicU32::new(1);
+static IDLE_INHIBITOR_ID_COUNTER: AtomicU32 = AtomicU32::new(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IdleNotificationId(u32);
+
+impl IdleNotificationId {
+    fn new() -> Self {
+        Self(IDLE_NOTIFICATION_ID_COUNTER.fetch_add(1, Ordering::SeqCst))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IdleInhibitorId(u32);
+
+impl IdleInhibitorId {
+    fn new() -> Self {
+        Self(IDLE_INHIBITOR_ID_COUNTER.fetch_add(1, Ordering::SeqCst))
+    }
+}
+
+/// The surface an inhibitor is attached to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InhibitSurface {
+    Window(WindowId),
+    Layer(LayerSurfaceId),
+}
+
+/// An event for an `ext_idle_notification_v1` object.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdleEvent {
+    Idled(IdleNotificationId),
+    Resumed(IdleNotificationId),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdleStage {
+    Dim,
+    Lock,
+    Blank,
+    Suspend,
+}
+
+impl IdleStage {
+    pub const ALL: [Self; 4] = [Self::Dim, Self::Lock, Self::Blank, Self::Suspend];
+
+    /// Seconds of idle time before the stage; 0 turns it off.
+    fn timeout(self, config: &IdleConfig) -> u64 {
+        match self {
+            Self::Dim => config.dim_timeout,
+            Self::Lock => config.lock_timeout,
+            Self::Blank => config.blank_timeout,
+            Self::Suspend => config.suspend_timeout,
+        }
+    }
+}
+
+/// The output's power state, as the renderer should apply it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputPower {
+    On,
+    Dimmed,
+    /// DPMS off.
+    Off,
+}
+
+struct Notification {
+    id: IdleNotificationId,
+    timeout: Duration,
+    respect_inhibitors: bool,
+    idled: bool,
+}
+
+pub struct IdleManager {
+    last_input: Instant,
+    /// The last time an inhibitor was in effect; idle time counts from
+    /// here once it goes away.
+    last_inhibited: Option<Instant>,
+    notifications: Vec<Notification>,
+    inhibitors: HashMap<IdleInhibitorId, InhibitSurface>,
+    /// Stages run since the last input.
+    reached: Vec<IdleStage>,
+    power: OutputPower,
+    events: Vec<IdleEvent>,
+}
+
+impl IdleManager {
+    pub fn new() -> Self {
+        Self {
+            last_input: Instant::now(),
+            last_inhibited: None,
+            notifications: Vec::new(),
+            inhibitors: HashMap::new(),
+            reached: Vec::new(),
+            power: OutputPower::On,
+            events: Vec::new(),
+        }
+    }
+
+    /// `get_idle_notification`, or `get_input_idle_notification` with
+    /// `respect_inhibitors` false.
+    pub fn get_idle_notification(&mut self, timeout_ms: u32, respect_inhibitors: bool) -> IdleNotificationId {
+        let id = IdleNotificationId::new();
+        self.notifications.push(Notification {
+            id,
+            timeout: Duration::from_millis(timeout_ms as u64),
+            respect_inhibitors,
+            idled: false,
+        });
+        id
+    }
+
+    pub fn destroy_notification(&mut self, id: IdleNotificationId) {
+        self.notifications.retain(|n| n.id != id);
+    }
+
+    pub fn create_inhibitor(&mut self, surface: InhibitSurface) -> IdleInhibitorId {
+        let id = IdleInhibitorId::new();
+        self.inhibitors.insert(id, surface);
+        id
+    }
+
+    pub fn destroy_inhibitor(&mut self, id: IdleInhibitorId) {
+        self.inhibitors.remove(&id);
+    }
+
+    pub fn surface_destroyed(&mut self, surface: InhibitSurface) {
+        self.inhibitors.retain(|_, s| *s != surface);
+    }
+
+    pub fn inhibitor_surfaces(&self) -> impl Iterator<Item = InhibitSurface> + '_ {
+        self.inhibitors.values().copied()
+    }
+
+    pub fn power(&self) -> OutputPower {
+        self.power
+    }
+
+    /// User input: resumes idled notifications and wakes the output.
+    pub fn activity(&mut self, now: Instant) {
+        self.last_input = now;
+        for notification in &mut self.notifications {
+            if notification.idled {
+                notification.idled = false;
+                self.events.push(IdleEvent::Resumed(notification.id));
+            }
+        }
+        if self.power != OutputPower::On {
+            info!("Waking the output");
+            self.power = OutputPower::On;
+        }
+        self.reached.clear();
+    }
+
+    /// Advances the timers. `inhibited` is whether a visible inhibitor or
+    /// a fullscreen window holds off idling. Returns the stages to run.
+    pub fn tick(&mut self, now: Instant, inhibited: bool, config: &IdleConfig) -> Vec<IdleStage> {
+        if inhibited {
+            self.last_inhibited = Some(now);
+        }
+        let input_idle = now.saturating_duration_since(self.last_input);
+        let idle = match self.last_inhibited {
+            Some(inhibited) => input_idle.min(now.saturating_duration_since(inhibited)),
+            None => input_idle,
+        };
+
+        for notification in &mut self.notifications {
+            let elapsed = if notification.respect_inhibitors {
+                idle
+            } else {
+                input_idle
+            };
+            if !notification.idled && elapsed >= notification.timeout {
+                notification.idled = true;
+                self.events.push(IdleEvent::Idled(notification.id));
+            }
+        }
+
+        let mut stages = Vec::new();
+        for stage in IdleStage::ALL {
+            let timeout = stage.timeout(config);
+            if timeout == 0 || self.reached.contains(&stage) || idle < Duration::from_secs(timeout)
+            {
+                continue;
+            }
+            self.reached.push(stage);
+            match stage {
+                IdleStage::Dim if self.power == OutputPower::On => self.power = OutputPower::Dimmed,
+                IdleStage::Blank => self.power = OutputPower::Off,
+                _ => {}
+            }
+            stages.push(stage);
+        }
+        stages
+    }
+
+    /// Events for the protocol objects, in order.
+    pub fn take_events(&mut self) -> Vec<IdleEvent> {
+        std::mem::take(&mut self.events)
+    }
+}
+
+impl Default for IdleManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layer_shell::{Layer, LayerShell};
+
+    fn config() -> IdleConfig {
+        IdleConfig {
+            dim_timeout: 10,
+            lock_timeout: 20,
+            blank_timeout: 30,
+            suspend_timeout: 40,
+            ..Default::default()
+        }
+    }
+
+    /// A manager whose last input was at `start`.
+    fn manager() -> (IdleManager, Instant) {
+        let mut idle = IdleManager::new();
+        let start = Instant::now();
+        idle.activity(start);
+        (idle, start)
+    }
+
+    fn secs(start: Instant, secs: u64) -> Instant {
+        start + Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn stages_run_in_order_once() {
+        let (mut idle, start) = manager();
+        let config = config();
+
+        assert!(idle.tick(secs(start, 9), false, &config).is_empty());
+        assert_eq!(idle.tick(secs(start, 10), false, &config), [IdleStage::Dim]);
+        assert_eq!(idle.power(), OutputPower::Dimmed);
+        assert!(idle.tick(secs(start, 15), false, &config).is_empty());
+
+        // A late tick runs every stage that came due, in order.
+        assert_eq!(
+            idle.tick(secs(start, 35), false, &config),
+            [IdleStage::Lock, IdleStage::Blank]
+        );
+        assert_eq!(idle.power(), OutputPower::Off);
+        assert_eq!(
+            idle.tick(secs(start, 40), false, &config),
+            [IdleStage::Suspend]
+        );
+        assert!(idle.tick(secs(start, 100), false, &config).is_empty());
+    }
+
+    #[test]
+    fn activity_wakes_the_output_and_rearms_the_stages() {
+        let (mut idle, start) = manager();
+        let config = config();
+        idle.tick(secs(start, 30), false, &config);
+        assert_eq!(idle.power(), OutputPower::Off);
+
+        idle.activity(secs(start, 31));
+        assert_eq!(idle.power(), OutputPower::On);
+        assert!(idle.tick(secs(start, 40), false, &config).is_empty());
+        assert_eq!(idle.tick(secs(start, 41), false, &config), [IdleStage::Dim]);
+    }
+
+    #[test]
+    fn disabled_stages_are_skipped() {
+        let (mut idle, start) = manager();
+        assert!(idle
+            .tick(secs(start, 3600), false, &IdleConfig::default())
+            .is_empty());
+        assert_eq!(idle.power(), OutputPower::On);
+
+        let config = IdleConfig {
+            dim_timeout: 0,
+            suspend_timeout: 0,
+            ..config()
+        };
+        assert_eq!(
+            idle.tick(secs(start, 3600), false, &config),
+            [IdleStage::Lock, IdleStage::Blank]
+        );
+    }
+
+    #[test]
+    fn dimming_does_not_wake_a_blank_output() {
+        let (mut idle, start) = manager();
+        let config = IdleConfig {
+            dim_timeout: 30,
+            blank_timeout: 10,
+            ..config()
+        };
+        assert_eq!(
+            idle.tick(secs(start, 10), false, &config),
+            [IdleStage::Blank]
+        );
+        assert_eq!(idle.power(), OutputPower::Off);
+        assert_eq!(
+            idle.tick(secs(start, 30), false, &config),
+            [IdleStage::Dim, IdleStage::Lock]
+        );
+        assert_eq!(idle.power(), OutputPower::Off);
+    }
+
+    #[test]
+    fn notifications_idle_once_and_resume_on_activity() {
+        let (mut idle, start) = manager();
+        let config = IdleConfig::default();
+        let id = idle.get_idle_notification(5000, true);
+
+        idle.tick(secs(start, 4), false, &config);
+        assert!(idle.take_events().is_empty());
+        idle.tick(secs(start, 5), false, &config);
+        assert_eq!(idle.take_events(), [IdleEvent::Idled(id)]);
+        idle.tick(secs(start, 6), false, &config);
+        assert!(idle.take_events().is_empty());
+
+        idle.activity(secs(start, 7));
+        assert_eq!(idle.take_events(), [IdleEvent::Resumed(id)]);
+        idle.activity(secs(start, 8));
+        assert!(idle.take_events().is_empty());
+
+        idle.destroy_notification(id);
+        idle.tick(secs(start, 100), false, &config);
+        assert!(idle.take_events().is_empty());
+    }
+
+    #[test]
+    fn input_notifications_ignore_inhibitors() {
+        let (mut idle, start) = manager();
+        let config = IdleConfig {
+            dim_timeout: 5,
+            ..IdleConfig::default()
+        };
+        let respecting = idle.get_idle_notification(5000, true);
+        let input = idle.get_idle_notification(5000, false);
+
+        assert!(idle.tick(secs(start, 3), true, &config).is_empty());
+        // 6 s without input, but only 3 s since the inhibitor went away.
+        assert!(idle.tick(secs(start, 6), false, &config).is_empty());
+        assert_eq!(idle.take_events(), [IdleEvent::Idled(input)]);
+
+        assert_eq!(idle.tick(secs(start, 8), false, &config), [IdleStage::Dim]);
+        assert_eq!(idle.take_events(), [IdleEvent::Idled(respecting)]);
+    }
+
+    #[test]
+    fn idle_time_counts_from_the_last_inhibition() {
+        let (mut idle, start) = manager();
+        let config = config();
+
+        assert!(idle.tick(secs(start, 100), true, &config).is_empty());
+        assert_eq!(idle.last_inhibited, Some(secs(start, 100)));
+        assert_eq!(idle.power(), OutputPower::On);
+
+        assert!(idle.tick(secs(start, 109), false, &config).is_empty());
+        assert_eq!(
+            idle.tick(secs(start, 110), false, &config),
+            [IdleStage::Dim]
+        );
+
+        // Input since the inhibitor went away counts from the input.
+        idle.activity(secs(start, 115));
+        assert!(idle.tick(secs(start, 124), false, &config).is_empty());
+        assert_eq!(
+            idle.tick(secs(start, 125), false, &config),
+            [IdleStage::Dim]
+        );
+    }
+
+    #[test]
+    fn inhibitors_go_with_their_surface() {
+        let mut idle = IdleManager::new();
+        let window = InhibitSurface::Window(WindowId::new());
+        let layer = LayerShell::new().create_surface("osd".to_string(), Layer::Overlay, None);
+        let layer = InhibitSurface::Layer(layer);
+        idle.create_inhibitor(window);
+        idle.create_inhibitor(window);
+        let kept = idle.create_inhibitor(layer);
+
+        idle.surface_destroyed(window);
+        assert_eq!(idle.inhibitor_surfaces().collect::<Vec<_>>(), [layer]);
+        idle.destroy_inhibitor(kept);
+        assert_eq!(idle.inhibitor_surfaces().count(), 0);
+    }
+}
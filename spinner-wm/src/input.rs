@@ -1,6 +1,8 @@
 //! Input handling for SpinnerWM
 
+use crate::config::Config;
 use std::collections::HashMap;
+use tracing::warn;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Modifier {
@@ -10,37 +12,230 @@ pub enum Modifier {
     Super,
 }
 
+impl Modifier {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "Shift" => Some(Self::Shift),
+            "Control" | "Ctrl" => Some(Self::Control),
+            "Alt" | "Mod1" => Some(Self::Alt),
+            "Super" | "Mod4" => Some(Self::Super),
+            _ => None,
+        }
+    }
+
+    /// The modifier a key such as `Super_L` holds.
+    fn from_key(key: &str) -> Option<Self> {
+        match key {
+            "Shift_L" | "Shift_R" => Some(Self::Shift),
+            "Control_L" | "Control_R" => Some(Self::Control),
+            "Alt_L" | "Alt_R" => Some(Self::Alt),
+            "Super_L" | "Super_R" => Some(Self::Super),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+impl Direction {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "left" => Some(Self::Left),
+            "right" => Some(Self::Right),
+            "up" => Some(Self::Up),
+            "down" => Some(Self::Down),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum Action {
     Spawn(String),
     Close,
+    Fullscreen,
+    ToggleFloating,
+    Maximize,
+    Minimize,
     Exit,
+    ReloadConfig,
     Workspace(u32),
+    MoveToWorkspace(u32),
+    Focus(Direction),
+    Move(Direction),
+    Resize(Direction, i32),
+}
+
+impl Action {
+    /// Parses a keybinding value such as `spawn:firefox` or `workspace:2`.
+    pub fn parse(value: &str) -> Option<Self> {
+        let (name, arg) = match value.split_once(':') {
+            Some((name, arg)) => (name, Some(arg)),
+            None => (value, None),
+        };
+
+        match (name, arg) {
+            ("spawn", Some(cmd)) => Some(Self::Spawn(cmd.to_string())),
+            ("close", None) => Some(Self::Close),
+            ("fullscreen", None) => Some(Self::Fullscreen),
+            ("toggle_floating", None) => Some(Self::ToggleFloating),
+            ("maximize", None) => Some(Self::Maximize),
+            ("minimize", None) => Some(Self::Minimize),
+            ("exit", None) => Some(Self::Exit),
+            ("reload_config", None) => Some(Self::ReloadConfig),
+            ("workspace", Some(n)) => n.parse().ok().map(Self::Workspace),
+            ("move_to_workspace", Some(n)) => n.parse().ok().map(Self::MoveToWorkspace),
+            ("focus", Some(dir)) => Direction::from_name(dir).map(Self::Focus),
+            ("move", Some(dir)) => Direction::from_name(dir).map(Self::Move),
+            ("resize", Some(arg)) => {
+                let (dir, amount) = arg.split_once(':').unwrap_or((arg, "20"));
+                Some(Self::Resize(Direction::from_name(dir)?, amount.parse().ok()?))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// A key combination, e.g. `Mod4+Shift+Return`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct KeyCombo {
+    modifiers: Vec<Modifier>,
+    key: String,
+}
+
+impl KeyCombo {
+    pub fn parse(combo: &str) -> Option<Self> {
+        let mut parts: Vec<&str> = combo.split('+').collect();
+        let key = parts.pop()?.to_string();
+
+        let mut modifiers = parts
+            .into_iter()
+            .map(Modifier::from_name)
+            .collect::<Option<Vec<_>>>()?;
+        modifiers.sort_by_key(|m| *m as u8);
+        modifiers.dedup();
+
+        Some(Self { modifiers, key })
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MouseState {
+    pub x: f64,
+    pub y: f64,
+    pub button_left: bool,
+    pub button_right: bool,
+    pub button_middle: bool,
+}
+
+impl MouseState {
+    pub fn update_position(&mut self, x: f64, y: f64) {
+        self.x = x;
+        self.y = y;
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum DragOperation {
     None,
+    Move {
+        start_x: f64,
+        start_y: f64,
+        window_x: i32,
+        window_y: i32,
+    },
+    Resize {
+        start_x: f64,
+        start_y: f64,
+        original_width: u32,
+        original_height: u32,
+    },
 }
 
 pub struct InputHandler {
-    keybindings: HashMap<String, Action>,
+    keybindings: HashMap<KeyCombo, Action>,
+    modifiers: Vec<Modifier>,
+    /// A modifier key pressed with nothing else since.
+    tapped: Option<String>,
 }
 
 impl InputHandler {
-    pub fn new() -> Self {
-        Self {
+    pub fn new(config: &Config) -> Self {
+        let mut handler = Self {
             keybindings: HashMap::new(),
+            modifiers: Vec::new(),
+            tapped: None,
+        };
+
+        for (combo, value) in &config.keybindings {
+            match (KeyCombo::parse(combo), Action::parse(value)) {
+                (Some(combo), Some(action)) => handler.add_keybinding(combo, action),
+                _ => warn!("Ignoring invalid keybinding {} = {}", combo, value),
+            }
+        }
+
+        handler
+    }
+
+    pub fn add_keybinding(&mut self, combo: KeyCombo, action: Action) {
+        self.keybindings.insert(combo, action);
+    }
+
+    // For the input backend, once there is one.
+    #[allow(dead_code)]
+    pub fn set_modifier(&mut self, modifier: Modifier, pressed: bool) {
+        self.modifiers.retain(|m| *m != modifier);
+        if pressed {
+            self.modifiers.push(modifier);
+            self.modifiers.sort_by_key(|m| *m as u8);
         }
     }
-    
-    pub fn add_keybinding(&mut self, key: String, action: Action) {
-        self.keybindings.insert(key, action);
+
+    pub fn current_modifiers(&self) -> &[Modifier] {
+        &self.modifiers
+    }
+
+    /// Looks up the action bound to `key` with the currently held modifiers.
+    /// Bindings on a modifier key itself wait for `key_released`.
+    pub fn key_pressed(&mut self, key: &str) -> Option<Action> {
+        if Modifier::from_key(key).is_some() {
+            self.tapped = Some(key.to_string());
+            return None;
+        }
+        self.tapped = None;
+
+        let combo = KeyCombo {
+            modifiers: self.modifiers.clone(),
+            key: key.to_string(),
+        };
+        self.keybindings.get(&combo).cloned()
     }
-    
-    pub fn get_action(&self, key: &str) -> Option<&Action> {
-        self.keybindings.get(key)
+
+    /// Fires a binding such as `Super_L` when the modifier key is released
+    /// without another key pressed in between, so `Super_L` and `Mod4+q`
+    /// can both be bound.
+    pub fn key_released(&mut self, key: &str) -> Option<Action> {
+        if self.tapped.as_deref() != Some(key) {
+            return None;
+        }
+        self.tapped = None;
+
+        let held = Modifier::from_key(key);
+        let combo = KeyCombo {
+            modifiers: self.modifiers.iter().copied().filter(|m| Some(*m) != held).collect(),
+            key: key.to_string(),
+        };
+        self.keybindings.get(&combo).cloned()
     }
-}
 
-impl Default for InputHandler {
-    fn default() -> Self {
-        Self::new()
+    /// A click also counts as another key, e.g. for Super+drag.
+    pub fn button_pressed(&mut self) {
+        self.tapped = None;
     }
 }
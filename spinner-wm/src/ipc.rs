@@ -0,0 +1,280 @@
+//! IPC socket for the shell and other clients
+//!
+//! Clients connect to `$XDG_RUNTIME_DIR/spinner-wm.sock` and exchange
+//! newline-delimited JSON. `Subscribe` keeps the connection open and
+//! pushes a `Windows` message whenever the window list changes. `Exit`
+//! ends the session: windows are asked to close and the compositor quits
+//! once they are gone or `exit_timeout` runs out. `Lock` makes the client
+//! the session's lock client until it sends `Unlock`, see `session_lock`;
+//! it gets `Locked` once only its lock screen is shown, and `Unlock` before
+//! that gives the lock up. `ActivationToken` hands out an xdg-activation
+//! token for an app about to be launched.
+
+use crate::window::{Geometry, ManagedWindow, WindowManager};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{ErrorKind, Read, Write};
+use std::os::fd::AsRawFd;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use tracing::{debug, warn};
+
+pub const SOCKET_ENV: &str = "SPINNERWM_SOCK";
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type")]
+pub enum Request {
+    GetWindows,
+    Subscribe,
+    FocusWindow { id: u32 },
+    CloseWindow { id: u32 },
+    Exit,
+    Lock,
+    Unlock,
+    ActivationToken { app_id: Option<String> },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct WindowInfo {
+    pub id: u32,
+    pub title: String,
+    pub app_id: String,
+    pub workspace: u32,
+    pub focused: bool,
+    pub minimized: bool,
+    pub fullscreen: bool,
+    pub xwayland: bool,
+    /// Position in the global layout, so clients can tell which output a
+    /// window is on.
+    pub geometry: Geometry,
+}
+
+impl From<&ManagedWindow> for WindowInfo {
+    fn from(window: &ManagedWindow) -> Self {
+        Self {
+            id: window.id.as_u32(),
+            title: window.title.clone(),
+            app_id: window.app_id.clone(),
+            workspace: window.workspace,
+            focused: window.focused,
+            minimized: window.minimized,
+            fullscreen: window.fullscreen,
+            xwayland: window.is_x11(),
+            geometry: window.geometry,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type")]
+pub enum Response {
+    Ok,
+    Error { message: String },
+    Windows { windows: Vec<WindowInfo> },
+    Locked,
+    ActivationToken { token: String },
+}
+
+/// Windows as shown in the taskbar.
+pub fn listed_windows(wm: &WindowManager) -> Vec<WindowInfo> {
+    wm.windows()
+        .filter(|w| w.is_listed())
+        .map(WindowInfo::from)
+        .collect()
+}
+
+pub type ClientId = u64;
+
+struct Client {
+    stream: UnixStream,
+    /// The peer process, from the socket's credentials.
+    pid: Option<u32>,
+    buffer: Vec<u8>,
+    subscribed: bool,
+}
+
+pub struct IpcServer {
+    path: PathBuf,
+    listener: UnixListener,
+    clients: HashMap<ClientId, Client>,
+    next_id: ClientId,
+    last_windows: Vec<WindowInfo>,
+}
+
+impl IpcServer {
+    pub fn bind() -> Result<Self> {
+        let runtime_dir = std::env::var_os("XDG_RUNTIME_DIR").context("XDG_RUNTIME_DIR is not set")?;
+        let path = PathBuf::from(runtime_dir).join("spinner-wm.sock");
+        let _ = std::fs::remove_file(&path);
+
+        let listener =
+            UnixListener::bind(&path).with_context(|| format!("Failed to bind {:?}", path))?;
+        listener.set_nonblocking(true)?;
+
+        std::env::set_var(SOCKET_ENV, &path);
+
+        Ok(Self {
+            path,
+            listener,
+            clients: HashMap::new(),
+            next_id: 0,
+            last_windows: Vec::new(),
+        })
+    }
+
+    pub fn listener(&self) -> Result<UnixListener> {
+        Ok(self.listener.try_clone()?)
+    }
+
+    /// Accepts a pending connection, returning a stream to poll for it.
+    pub fn accept(&mut self) -> Option<(ClientId, UnixStream)> {
+        let stream = match self.listener.accept() {
+            Ok((stream, _)) => stream,
+            Err(e) if e.kind() == ErrorKind::WouldBlock => return None,
+            Err(e) => {
+                warn!("Failed to accept IPC client: {}", e);
+                return None;
+            }
+        };
+
+        let poll_stream = stream.set_nonblocking(true).and_then(|_| stream.try_clone()).ok()?;
+
+        let id = self.next_id;
+        self.next_id += 1;
+        self.clients.insert(
+            id,
+            Client {
+                pid: peer_pid(&stream),
+                stream,
+                buffer: Vec::new(),
+                subscribed: false,
+            },
+        );
+
+        debug!("IPC client {} connected", id);
+        Some((id, poll_stream))
+    }
+
+    /// Reads complete requests from a client. Returns `None` once the
+    /// client has disconnected.
+    pub fn read_requests(&mut self, id: ClientId) -> Option<Vec<Request>> {
+        let client = self.clients.get_mut(&id)?;
+        let mut chunk = [0u8; 4096];
+
+        loop {
+            match client.stream.read(&mut chunk) {
+                Ok(0) => {
+                    self.disconnect(id);
+                    return None;
+                }
+                Ok(n) => client.buffer.extend_from_slice(&chunk[..n]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(_) => {
+                    self.disconnect(id);
+                    return None;
+                }
+            }
+        }
+
+        let mut requests = Vec::new();
+        while let Some(end) = client.buffer.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = client.buffer.drain(..=end).collect();
+            match serde_json::from_slice(&line) {
+                Ok(request) => requests.push(request),
+                Err(e) => {
+                    let message = format!("Invalid request: {}", e);
+                    self.send(id, &Response::Error { message });
+                    return Some(requests);
+                }
+            }
+        }
+
+        Some(requests)
+    }
+
+    pub fn peer_pid(&self, id: ClientId) -> Option<u32> {
+        self.clients.get(&id).and_then(|c| c.pid)
+    }
+
+    pub fn subscribe(&mut self, id: ClientId, wm: &WindowManager) {
+        if let Some(client) = self.clients.get_mut(&id) {
+            client.subscribed = true;
+        }
+        let windows = listed_windows(wm);
+        self.send(id, &Response::Windows { windows });
+    }
+
+    pub fn send(&mut self, id: ClientId, response: &Response) {
+        let Some(client) = self.clients.get_mut(&id) else {
+            return;
+        };
+
+        let mut line = match serde_json::to_vec(response) {
+            Ok(line) => line,
+            Err(e) => {
+                warn!("Failed to encode IPC response: {}", e);
+                return;
+            }
+        };
+        line.push(b'\n');
+
+        // Messages are small; a client too slow to take one is dropped.
+        if client.stream.write_all(&line).is_err() {
+            self.disconnect(id);
+        }
+    }
+
+    /// Pushes the window list to subscribers if it changed.
+    pub fn broadcast_windows(&mut self, wm: &WindowManager) {
+        let windows = listed_windows(wm);
+        if windows == self.last_windows {
+            return;
+        }
+        self.last_windows = windows.clone();
+
+        let subscribers: Vec<ClientId> = self
+            .clients
+            .iter()
+            .filter(|(_, c)| c.subscribed)
+            .map(|(id, _)| *id)
+            .collect();
+
+        let response = Response::Windows { windows };
+        for id in subscribers {
+            self.send(id, &response);
+        }
+    }
+
+    pub fn disconnect(&mut self, id: ClientId) {
+        if self.clients.remove(&id).is_some() {
+            debug!("IPC client {} disconnected", id);
+        }
+    }
+}
+
+/// The process on the other end of a connection, so the lock client's
+/// layer surfaces can be told apart from others.
+fn peer_pid(stream: &UnixStream) -> Option<u32> {
+    let mut cred = libc::ucred { pid: 0, uid: 0, gid: 0 };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    // SAFETY: SO_PEERCRED fills a ucred of the given length.
+    let result = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            &mut cred as *mut libc::ucred as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    (result == 0 && cred.pid > 0).then_some(cred.pid as u32)
+}
+
+impl Drop for IpcServer {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
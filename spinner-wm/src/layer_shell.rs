@@ -0,0 +1,313 @@
+//! wlr-layer-shell surfaces for SpinnerWM
+//!
+//! Panels, docks, backgrounds and overlays are layer surfaces rather than
+//! windows. Each surface is anchored to output edges, and a positive
+//! exclusive zone reserves space on its edge; the remaining usable area is
+//! what `WindowManager` maximizes and places windows into.
+//!
+//! State follows the protocol's double buffering: requests modify the
+//! pending state and only `commit` makes it current. The first commit gets
+//! the surface laid out and configured; it is mapped, and its exclusive
+//! zone applies, once a commit brings a buffer.
+
+use crate::window::Geometry;
+
+use bitflags::bitflags;
+use std::sync::atomic::{AtomicU32, Ordering};
+use tracing::warn;
+
+static LAYER_SURFACE_ID_COUNTER: AtomicU32 = AtomicU32::new(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LayerSurfaceId(u32);
+
+impl LayerSurfaceId {
+    fn new() -> Self {
+        Self(LAYER_SURFACE_ID_COUNTER.fetch_add(1, Ordering::SeqCst))
+    }
+}
+
+bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    pub struct Anchor: u32 {
+        const TOP = 1;
+        const BOTTOM = 2;
+        const LEFT = 4;
+        const RIGHT = 8;
+    }
+}
+
+impl Anchor {
+    /// The single edge an exclusive zone applies to, if any. A surface
+    /// anchored to one edge, or to one edge and both of its neighbours,
+    /// reserves space on that edge.
+    fn exclusive_edge(self) -> Option<Anchor> {
+        let horizontal = Anchor::LEFT | Anchor::RIGHT;
+        let vertical = Anchor::TOP | Anchor::BOTTOM;
+
+        [Anchor::TOP, Anchor::BOTTOM, Anchor::LEFT, Anchor::RIGHT]
+            .into_iter()
+            .find(|&edge| {
+                let perpendicular = if vertical.contains(edge) { horizontal } else { vertical };
+                self == edge || self == edge | perpendicular
+            })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Layer {
+    Background,
+    Bottom,
+    Top,
+    Overlay,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Margins {
+    pub top: i32,
+    pub right: i32,
+    pub bottom: i32,
+    pub left: i32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LayerSurfaceState {
+    pub layer: Layer,
+    pub anchor: Anchor,
+    /// Positive values reserve space, 0 avoids other exclusive zones,
+    /// -1 extends over them to the output edge.
+    pub exclusive_zone: i32,
+    pub margin: Margins,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct LayerSurface {
+    pub id: LayerSurfaceId,
+    pub namespace: String,
+    /// The process of the client that created it, if its socket tells.
+    pub pid: Option<u32>,
+    pub pending: LayerSurfaceState,
+    pub current: LayerSurfaceState,
+    pub geometry: Geometry,
+    /// Committed at least once, so it is laid out.
+    pub committed: bool,
+    /// The client was told its size since it was last unmapped.
+    pub configured: bool,
+    pub mapped: bool,
+}
+
+impl LayerSurface {
+    pub fn set_size(&mut self, width: u32, height: u32) {
+        self.pending.width = width;
+        self.pending.height = height;
+    }
+
+    pub fn set_anchor(&mut self, anchor: Anchor) {
+        self.pending.anchor = anchor;
+    }
+
+    pub fn set_exclusive_zone(&mut self, zone: i32) {
+        self.pending.exclusive_zone = zone;
+    }
+
+    pub fn set_margin(&mut self, margin: Margins) {
+        self.pending.margin = margin;
+    }
+
+    pub fn set_layer(&mut self, layer: Layer) {
+        self.pending.layer = layer;
+    }
+}
+
+/// A size the client must be told about in a `configure` event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LayerConfigure {
+    pub id: LayerSurfaceId,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LayerError {
+    /// Width or height is 0 without anchoring to both opposite edges.
+    InvalidSize,
+    UnknownSurface,
+}
+
+pub struct LayerShell {
+    surfaces: Vec<LayerSurface>,
+}
+
+impl LayerShell {
+    pub fn new() -> Self {
+        Self {
+            surfaces: Vec::new(),
+        }
+    }
+
+    pub fn create_surface(&mut self, namespace: String, layer: Layer, pid: Option<u32>) -> LayerSurfaceId {
+        let state = LayerSurfaceState {
+            layer,
+            anchor: Anchor::empty(),
+            exclusive_zone: 0,
+            margin: Margins::default(),
+            width: 0,
+            height: 0,
+        };
+
+        let id = LayerSurfaceId::new();
+        self.surfaces.push(LayerSurface {
+            id,
+            namespace,
+            pid,
+            pending: state,
+            current: state,
+            geometry: Geometry::new(0, 0, 0, 0),
+            committed: false,
+            configured: false,
+            mapped: false,
+        });
+        id
+    }
+
+    pub fn surface_mut(&mut self, id: LayerSurfaceId) -> Option<&mut LayerSurface> {
+        self.surfaces.iter_mut().find(|s| s.id == id)
+    }
+
+    pub fn surfaces(&self) -> impl Iterator<Item = &LayerSurface> {
+        self.surfaces.iter()
+    }
+
+    /// Applies a commit; `mapped` is whether the surface has a buffer.
+    /// Committing without one unmaps it, and the next commit starts over
+    /// with a new configure.
+    pub fn commit(&mut self, id: LayerSurfaceId, mapped: bool) -> Result<(), LayerError> {
+        let surface = self.surface_mut(id).ok_or(LayerError::UnknownSurface)?;
+        let state = surface.pending;
+
+        let stretches_x = state.anchor.contains(Anchor::LEFT | Anchor::RIGHT);
+        let stretches_y = state.anchor.contains(Anchor::TOP | Anchor::BOTTOM);
+        if (state.width == 0 && !stretches_x) || (state.height == 0 && !stretches_y) {
+            warn!("Layer surface {:?} committed an invalid size", surface.namespace);
+            return Err(LayerError::InvalidSize);
+        }
+
+        surface.current = state;
+        surface.committed = true;
+        if !mapped {
+            surface.configured = false;
+        }
+        surface.mapped = mapped;
+        Ok(())
+    }
+
+    pub fn destroy(&mut self, id: LayerSurfaceId) {
+        self.surfaces.retain(|s| s.id != id);
+    }
+
+    /// Lays out every committed surface on `output` and returns the sizes
+    /// to configure plus the area left for windows.
+    pub fn arrange(&mut self, output: Geometry) -> (Vec<LayerConfigure>, Geometry) {
+        let mut usable = output;
+        let mut configures = Vec::new();
+
+        // Exclusive zones are applied first, top layers before bottom, so
+        // every other surface and all windows are placed around them.
+        let mut order: Vec<usize> = (0..self.surfaces.len()).collect();
+        order.sort_by_key(|&i| {
+            let state = &self.surfaces[i].current;
+            (state.exclusive_zone <= 0, std::cmp::Reverse(state.layer))
+        });
+
+        for i in order {
+            let surface = &mut self.surfaces[i];
+            if !surface.committed {
+                continue;
+            }
+            let state = surface.current;
+
+            let bounds = if state.exclusive_zone == -1 { output } else { usable };
+            let geometry = place(&state, bounds);
+
+            let resized = geometry.width != surface.geometry.width || geometry.height != surface.geometry.height;
+            if resized || !surface.configured {
+                surface.configured = true;
+                configures.push(LayerConfigure {
+                    id: surface.id,
+                    width: geometry.width,
+                    height: geometry.height,
+                });
+            }
+            surface.geometry = geometry;
+
+            if surface.mapped && state.exclusive_zone > 0 {
+                if let Some(edge) = state.anchor.exclusive_edge() {
+                    usable = reserve(usable, edge, state.exclusive_zone, &state.margin);
+                }
+            }
+        }
+
+        (configures, usable)
+    }
+}
+
+impl Default for LayerShell {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn place(state: &LayerSurfaceState, bounds: Geometry) -> Geometry {
+    let m = state.margin;
+
+    let width = if state.width == 0 {
+        (bounds.width as i32 - m.left - m.right).max(0) as u32
+    } else {
+        state.width
+    };
+    let height = if state.height == 0 {
+        (bounds.height as i32 - m.top - m.bottom).max(0) as u32
+    } else {
+        state.height
+    };
+
+    let left = state.anchor.contains(Anchor::LEFT);
+    let right = state.anchor.contains(Anchor::RIGHT);
+    let x = match (left, right) {
+        (true, false) => bounds.x + m.left,
+        (false, true) => bounds.x + bounds.width as i32 - width as i32 - m.right,
+        _ => bounds.x + (bounds.width as i32 - width as i32) / 2,
+    };
+
+    let top = state.anchor.contains(Anchor::TOP);
+    let bottom = state.anchor.contains(Anchor::BOTTOM);
+    let y = match (top, bottom) {
+        (true, false) => bounds.y + m.top,
+        (false, true) => bounds.y + bounds.height as i32 - height as i32 - m.bottom,
+        _ => bounds.y + (bounds.height as i32 - height as i32) / 2,
+    };
+
+    Geometry::new(x, y, width, height)
+}
+
+fn reserve(area: Geometry, edge: Anchor, zone: i32, margin: &Margins) -> Geometry {
+    let mut area = area;
+
+    if edge == Anchor::TOP {
+        let amount = (zone + margin.top).max(0);
+        area.y += amount;
+        area.height = area.height.saturating_sub(amount as u32);
+    } else if edge == Anchor::BOTTOM {
+        area.height = area.height.saturating_sub((zone + margin.bottom).max(0) as u32);
+    } else if edge == Anchor::LEFT {
+        let amount = (zone + margin.left).max(0);
+        area.x += amount;
+        area.width = area.width.saturating_sub(amount as u32);
+    } else if edge == Anchor::RIGHT {
+        area.width = area.width.saturating_sub((zone + margin.right).max(0) as u32);
+    }
+
+    area
+}
@@ -1,13 +1,23 @@
 //! SpinnerWM - SpinnerOS Wayland Compositor
 
+mod activation;
+mod compositor;
 mod config;
+mod idle;
 mod input;
+mod ipc;
+mod layer_shell;
+mod session_lock;
+mod wayland;
 mod window;
+mod xwayland;
+mod xwm;
 
 use anyhow::Result;
 use tracing::{info, error};
 use tracing_subscriber::{fmt, prelude::*, EnvFilter};
 
+use crate::compositor::SpinnerCompositor;
 use crate::config::Config;
 
 fn main() -> Result<()> {
@@ -24,11 +34,7 @@ fn main() -> Result<()> {
     });
     
     info!("Configuration loaded");
-    info!("SpinnerWM initialized - this is a prototype");
-    info!("Press Ctrl+C to exit");
     
-    // Main event loop placeholder
-    loop {
-        std::thread::sleep(std::time::Duration::from_secs(1));
-    }
+    let mut compositor = SpinnerCompositor::new(config)?;
+    compositor.run()
 }
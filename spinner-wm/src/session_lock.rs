@@ -0,0 +1,485 @@
+//! ext-session-lock-v1 for SpinnerWM
+//!
+//! A lock client takes over the output: from its `lock` request on, only
+//! its lock surface is drawn and it gets all input. `locked` is sent once
+//! the output shows that surface. If the client goes away without
+//! `unlock_and_destroy`, e.g. because it crashed, the session stays locked
+//! and the output stays blank until a new lock client takes over.
+//!
+//! The shell's lock screen is a GTK overlay layer surface and locks
+//! through the IPC socket instead. While it holds the lock, a layer
+//! surface of the same process in the `spinner-lock` namespace stands in
+//! for a lock surface, with the same guarantees.
+//!
+//! Until `locked`, a client may give up: destroying the lock object, or
+//! `Unlock` over IPC, unlocks the session again. Giving up a lock that
+//! took over an abandoned one leaves the session locked.
+
+use crate::ipc::ClientId;
+use crate::layer_shell::LayerSurfaceId;
+use crate::window::Geometry;
+
+use std::sync::atomic::{AtomicU32, Ordering};
+use tracing::{info, warn};
+
+/// Layer surfaces of an IPC lock client in this namespace are its lock
+/// surfaces.
+pub const LOCK_NAMESPACE: &str = "spinner-lock";
+
+static LOCK_SURFACE_ID_COUNTER: AtomicU32 = AtomicU32::new(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LockSurfaceId(u32);
+
+impl LockSurfaceId {
+    fn new() -> Self {
+        Self(LOCK_SURFACE_ID_COUNTER.fetch_add(1, Ordering::SeqCst))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockClient {
+    /// An `ext_session_lock_v1` object, numbered as it is created.
+    Wayland(u32),
+    Ipc(ClientId),
+}
+
+/// What may be drawn on the output while locked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockSurface {
+    /// An `ext_session_lock_surface_v1`.
+    Protocol(LockSurfaceId),
+    /// A `spinner-lock` layer surface of an IPC lock client.
+    Layer(LayerSurfaceId),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockState {
+    Unlocked,
+    /// Normal content is hidden, waiting for the lock surface.
+    Locking,
+    Locked,
+    /// The lock client went away while locked.
+    Abandoned,
+}
+
+/// What the renderer puts on the output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputContent {
+    Normal,
+    Lock(LockSurface),
+    /// Locked, but no lock surface to show.
+    Blank,
+}
+
+/// A size the lock surface must be told about in a `configure` event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LockConfigure {
+    pub id: LockSurfaceId,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockError {
+    /// Another client holds the lock; the new one gets `finished`.
+    AlreadyLocked,
+    /// The request came from a client that doesn't hold the lock.
+    NotLockClient,
+    /// `unlock_and_destroy` before `locked`.
+    InvalidUnlock,
+    /// Giving up a lock after `locked`; only unlocking ends it then.
+    InvalidCancel,
+    /// A second lock surface for the output.
+    DuplicateOutput,
+    /// The committed buffer doesn't match the configured size.
+    DimensionsMismatch,
+    UnknownSurface,
+}
+
+pub struct SessionLock {
+    state: LockState,
+    client: Option<LockClient>,
+    surface: Option<LockSurface>,
+    /// A protocol lock surface created but not committed yet.
+    configured: Option<LockConfigure>,
+    /// The lock in progress took over an abandoned one, which giving it up
+    /// brings back.
+    took_over: bool,
+    output: Geometry,
+}
+
+impl SessionLock {
+    pub fn new(output: Geometry) -> Self {
+        Self {
+            state: LockState::Unlocked,
+            client: None,
+            surface: None,
+            configured: None,
+            took_over: false,
+            output,
+        }
+    }
+
+    pub fn state(&self) -> LockState {
+        self.state
+    }
+
+    /// Whether normal content must stay hidden and input withheld from it.
+    pub fn is_locked(&self) -> bool {
+        self.state != LockState::Unlocked
+    }
+
+    pub fn client(&self) -> Option<LockClient> {
+        self.client
+    }
+
+    pub fn lock(&mut self, client: LockClient) -> Result<(), LockError> {
+        match self.state {
+            LockState::Locking | LockState::Locked => return Err(LockError::AlreadyLocked),
+            LockState::Abandoned => info!("{:?} takes over the abandoned lock", client),
+            LockState::Unlocked => info!("Session locked by {:?}", client),
+        }
+        self.took_over = self.state == LockState::Abandoned;
+        self.state = LockState::Locking;
+        self.client = Some(client);
+        self.surface = None;
+        self.configured = None;
+        Ok(())
+    }
+
+    /// Creates the lock surface for the output; it covers the whole output.
+    pub fn get_lock_surface(&mut self, client: LockClient) -> Result<LockConfigure, LockError> {
+        self.check_client(client)?;
+        if self.surface.is_some() || self.configured.is_some() {
+            return Err(LockError::DuplicateOutput);
+        }
+        let configure = LockConfigure {
+            id: LockSurfaceId::new(),
+            width: self.output.width,
+            height: self.output.height,
+        };
+        self.configured = Some(configure);
+        Ok(configure)
+    }
+
+    /// Applies a lock surface commit. Returns `true` when this completes
+    /// the lock, so `locked` must be sent.
+    pub fn commit(
+        &mut self,
+        id: LockSurfaceId,
+        width: u32,
+        height: u32,
+    ) -> Result<bool, LockError> {
+        let configure = match self.configured {
+            Some(configure) if configure.id == id => configure,
+            _ if self.surface == Some(LockSurface::Protocol(id)) => return Ok(false),
+            _ => return Err(LockError::UnknownSurface),
+        };
+        if (width, height) != (configure.width, configure.height) {
+            return Err(LockError::DimensionsMismatch);
+        }
+        self.configured = None;
+        Ok(self.show(LockSurface::Protocol(id)))
+    }
+
+    /// Takes a mapped `spinner-lock` layer surface of the IPC lock client
+    /// as its lock surface. Returns `true` when this completes the lock.
+    pub fn attach_layer_surface(&mut self, id: LayerSurfaceId) -> bool {
+        if !matches!(self.client, Some(LockClient::Ipc(_))) || self.surface.is_some() {
+            return false;
+        }
+        self.show(LockSurface::Layer(id))
+    }
+
+    fn show(&mut self, surface: LockSurface) -> bool {
+        self.surface = Some(surface);
+        if self.state == LockState::Locking {
+            self.state = LockState::Locked;
+            return true;
+        }
+        false
+    }
+
+    pub fn unlock(&mut self, client: LockClient) -> Result<(), LockError> {
+        self.check_client(client)?;
+        if self.state != LockState::Locked {
+            return Err(LockError::InvalidUnlock);
+        }
+        info!("Session unlocked");
+        self.state = LockState::Unlocked;
+        self.client = None;
+        self.surface = None;
+        self.configured = None;
+        Ok(())
+    }
+
+    /// Gives up a lock that is not complete yet. The session is unlocked
+    /// again, unless the lock took over an abandoned one.
+    pub fn cancel(&mut self, client: LockClient) -> Result<(), LockError> {
+        self.check_client(client)?;
+        if self.state != LockState::Locking {
+            return Err(LockError::InvalidCancel);
+        }
+        info!("{:?} gave up the lock before it completed", client);
+        self.state = if self.took_over { LockState::Abandoned } else { LockState::Unlocked };
+        self.client = None;
+        self.surface = None;
+        self.configured = None;
+        Ok(())
+    }
+
+    /// The client disconnected or destroyed its lock object without
+    /// unlocking; the session stays locked.
+    pub fn client_gone(&mut self, client: LockClient) {
+        if self.client != Some(client) {
+            return;
+        }
+        warn!(
+            "{:?} went away without unlocking, keeping the session locked",
+            client
+        );
+        self.state = LockState::Abandoned;
+        self.client = None;
+        self.surface = None;
+        self.configured = None;
+    }
+
+    /// The output goes blank until the client provides a new surface.
+    pub fn surface_destroyed(&mut self, surface: LockSurface) {
+        if self.surface == Some(surface) {
+            self.surface = None;
+        }
+        if let (LockSurface::Protocol(id), Some(configure)) = (surface, self.configured) {
+            if configure.id == id {
+                self.configured = None;
+            }
+        }
+    }
+
+    pub fn content(&self) -> OutputContent {
+        match (self.state, self.surface) {
+            (LockState::Unlocked, _) => OutputContent::Normal,
+            (_, Some(surface)) => OutputContent::Lock(surface),
+            (_, None) => OutputContent::Blank,
+        }
+    }
+
+    fn check_client(&self, client: LockClient) -> Result<(), LockError> {
+        if self.client == Some(client) {
+            Ok(())
+        } else {
+            Err(LockError::NotLockClient)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layer_shell::{Layer, LayerShell};
+
+    const OWNER: LockClient = LockClient::Wayland(1);
+    const OTHER: LockClient = LockClient::Wayland(2);
+
+    fn session_lock() -> SessionLock {
+        SessionLock::new(Geometry::new(0, 0, 1280, 800))
+    }
+
+    /// A session locked by `OWNER` through its protocol lock surface.
+    fn locked() -> (SessionLock, LockSurfaceId) {
+        let mut lock = session_lock();
+        lock.lock(OWNER).unwrap();
+        let configure = lock.get_lock_surface(OWNER).unwrap();
+        assert!(lock.commit(configure.id, 1280, 800).unwrap());
+        (lock, configure.id)
+    }
+
+    fn layer_surface() -> LayerSurfaceId {
+        LayerShell::new().create_surface(LOCK_NAMESPACE.to_string(), Layer::Overlay, None)
+    }
+
+    #[test]
+    fn lock_completes_on_the_first_matching_commit() {
+        let mut lock = session_lock();
+        assert_eq!(lock.state(), LockState::Unlocked);
+        assert_eq!(lock.content(), OutputContent::Normal);
+
+        lock.lock(OWNER).unwrap();
+        assert_eq!(lock.state(), LockState::Locking);
+        assert!(lock.is_locked());
+        assert_eq!(lock.content(), OutputContent::Blank);
+
+        let configure = lock.get_lock_surface(OWNER).unwrap();
+        assert_eq!((configure.width, configure.height), (1280, 800));
+        assert_eq!(
+            lock.get_lock_surface(OWNER),
+            Err(LockError::DuplicateOutput)
+        );
+        assert_eq!(
+            lock.commit(configure.id, 640, 400),
+            Err(LockError::DimensionsMismatch)
+        );
+        assert_eq!(lock.state(), LockState::Locking);
+
+        assert_eq!(lock.commit(configure.id, 1280, 800), Ok(true));
+        assert_eq!(lock.state(), LockState::Locked);
+        let surface = LockSurface::Protocol(configure.id);
+        assert_eq!(lock.content(), OutputContent::Lock(surface));
+
+        // Later commits don't send `locked` again.
+        assert_eq!(lock.commit(configure.id, 1280, 800), Ok(false));
+        assert_eq!(
+            lock.commit(LockSurfaceId::new(), 1280, 800),
+            Err(LockError::UnknownSurface)
+        );
+    }
+
+    #[test]
+    fn a_second_client_cannot_lock() {
+        let mut lock = session_lock();
+        lock.lock(OWNER).unwrap();
+        assert_eq!(lock.lock(OTHER), Err(LockError::AlreadyLocked));
+
+        let (mut lock, _) = locked();
+        assert_eq!(lock.lock(OTHER), Err(LockError::AlreadyLocked));
+        assert_eq!(lock.client(), Some(OWNER));
+    }
+
+    #[test]
+    fn unlock_only_after_locked() {
+        let mut lock = session_lock();
+        lock.lock(OWNER).unwrap();
+        assert_eq!(lock.unlock(OWNER), Err(LockError::InvalidUnlock));
+
+        let (mut lock, _) = locked();
+        lock.unlock(OWNER).unwrap();
+        assert_eq!(lock.state(), LockState::Unlocked);
+        assert_eq!(lock.client(), None);
+        assert_eq!(lock.content(), OutputContent::Normal);
+    }
+
+    #[test]
+    fn cancel_only_before_locked() {
+        let mut lock = session_lock();
+        lock.lock(OWNER).unwrap();
+        lock.get_lock_surface(OWNER).unwrap();
+        lock.cancel(OWNER).unwrap();
+        assert_eq!(lock.state(), LockState::Unlocked);
+        assert_eq!(lock.content(), OutputContent::Normal);
+
+        let (mut lock, _) = locked();
+        assert_eq!(lock.cancel(OWNER), Err(LockError::InvalidCancel));
+        assert_eq!(lock.state(), LockState::Locked);
+    }
+
+    #[test]
+    fn only_the_lock_client_may_act() {
+        let (mut lock, _) = locked();
+        let ipc = LockClient::Ipc(7);
+        for client in [OTHER, ipc] {
+            assert_eq!(lock.unlock(client), Err(LockError::NotLockClient));
+            assert_eq!(lock.cancel(client), Err(LockError::NotLockClient));
+            assert_eq!(lock.get_lock_surface(client), Err(LockError::NotLockClient));
+            lock.client_gone(client);
+        }
+        assert_eq!(lock.state(), LockState::Locked);
+        assert_eq!(lock.client(), Some(OWNER));
+    }
+
+    #[test]
+    fn a_crashed_client_leaves_the_output_blank() {
+        let (mut lock, _) = locked();
+        lock.client_gone(OWNER);
+        assert_eq!(lock.state(), LockState::Abandoned);
+        assert!(lock.is_locked());
+        assert_eq!(lock.client(), None);
+        assert_eq!(lock.content(), OutputContent::Blank);
+        assert_eq!(lock.unlock(OWNER), Err(LockError::NotLockClient));
+
+        // A new client takes over and unlocks.
+        lock.lock(OTHER).unwrap();
+        let configure = lock.get_lock_surface(OTHER).unwrap();
+        assert_eq!(lock.commit(configure.id, 1280, 800), Ok(true));
+        lock.unlock(OTHER).unwrap();
+        assert_eq!(lock.content(), OutputContent::Normal);
+    }
+
+    #[test]
+    fn cancelling_a_takeover_keeps_the_session_locked() {
+        let (mut lock, _) = locked();
+        lock.client_gone(OWNER);
+
+        lock.lock(OTHER).unwrap();
+        lock.cancel(OTHER).unwrap();
+        assert_eq!(lock.state(), LockState::Abandoned);
+        assert_eq!(lock.content(), OutputContent::Blank);
+
+        // Once unlocked, a fresh lock that is given up unlocks again.
+        lock.lock(OTHER).unwrap();
+        let configure = lock.get_lock_surface(OTHER).unwrap();
+        lock.commit(configure.id, 1280, 800).unwrap();
+        lock.unlock(OTHER).unwrap();
+        lock.lock(OWNER).unwrap();
+        lock.cancel(OWNER).unwrap();
+        assert_eq!(lock.state(), LockState::Unlocked);
+    }
+
+    #[test]
+    fn destroyed_lock_surface_blanks_the_output() {
+        let (mut lock, id) = locked();
+        lock.surface_destroyed(LockSurface::Protocol(LockSurfaceId::new()));
+        assert_eq!(
+            lock.content(),
+            OutputContent::Lock(LockSurface::Protocol(id))
+        );
+
+        lock.surface_destroyed(LockSurface::Protocol(id));
+        assert_eq!(lock.state(), LockState::Locked);
+        assert_eq!(lock.content(), OutputContent::Blank);
+
+        // A configured surface destroyed before its commit is forgotten.
+        let configure = lock.get_lock_surface(OWNER).unwrap();
+        lock.surface_destroyed(LockSurface::Protocol(configure.id));
+        assert_eq!(
+            lock.commit(configure.id, 1280, 800),
+            Err(LockError::UnknownSurface)
+        );
+
+        // The replacement shows without sending `locked` again.
+        let configure = lock.get_lock_surface(OWNER).unwrap();
+        assert_eq!(lock.commit(configure.id, 1280, 800), Ok(false));
+        assert_eq!(
+            lock.content(),
+            OutputContent::Lock(LockSurface::Protocol(configure.id))
+        );
+    }
+
+    #[test]
+    fn ipc_client_locks_with_a_layer_surface() {
+        let mut lock = session_lock();
+        let client = LockClient::Ipc(3);
+        lock.lock(client).unwrap();
+
+        let surface = layer_surface();
+        assert!(lock.attach_layer_surface(surface));
+        assert_eq!(lock.state(), LockState::Locked);
+        assert_eq!(
+            lock.content(),
+            OutputContent::Lock(LockSurface::Layer(surface))
+        );
+        assert!(!lock.attach_layer_surface(layer_surface()));
+
+        lock.unlock(client).unwrap();
+        assert_eq!(lock.state(), LockState::Unlocked);
+    }
+
+    #[test]
+    fn wayland_client_cannot_use_a_layer_surface() {
+        let mut lock = session_lock();
+        lock.lock(OWNER).unwrap();
+        assert!(!lock.attach_layer_surface(layer_surface()));
+        assert_eq!(lock.state(), LockState::Locking);
+        assert_eq!(lock.content(), OutputContent::Blank);
+    }
+}
@@ -0,0 +1,112 @@
+//! xdg-activation-v1 requests, applied to the `Activation` model
+
+use super::Role;
+use crate::compositor::SpinnerCompositor;
+
+use std::sync::Mutex;
+use std::time::Instant;
+use tracing::debug;
+use wayland_protocols::xdg::activation::v1::server::xdg_activation_token_v1::{self, XdgActivationTokenV1};
+use wayland_protocols::xdg::activation::v1::server::xdg_activation_v1::{self, XdgActivationV1};
+use wayland_server::protocol::wl_surface::WlSurface;
+use wayland_server::{Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource};
+
+/// Handed out when a request may not pass focus on; it never redeems.
+const INVALID_TOKEN: &str = "spinner-invalid";
+
+/// What a token object collects until `commit`.
+#[derive(Default)]
+struct TokenRequest {
+    app_id: Option<String>,
+    surface: Option<WlSurface>,
+    committed: bool,
+}
+
+/// Whether a token requested for `surface` may bring another window to the
+/// front. Without input events there are no serials to check, so only the
+/// focused window and layer surfaces such as the panel may pass focus on.
+fn may_activate(state: &SpinnerCompositor, surface: Option<&WlSurface>) -> bool {
+    let Some(role) = surface.and_then(|surface| state.wayland().role(surface)) else {
+        return false;
+    };
+    match role {
+        Role::Toplevel(id) => state.window_manager().focused_window().is_some_and(|w| w.id == id),
+        Role::Layer(_) => true,
+        _ => false,
+    }
+}
+
+impl GlobalDispatch<XdgActivationV1, ()> for SpinnerCompositor {
+    fn bind(
+        _state: &mut Self,
+        _display: &DisplayHandle,
+        _client: &Client,
+        resource: New<XdgActivationV1>,
+        _data: &(),
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl Dispatch<XdgActivationV1, ()> for SpinnerCompositor {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        _activation: &XdgActivationV1,
+        request: xdg_activation_v1::Request,
+        _data: &(),
+        _display: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        match request {
+            xdg_activation_v1::Request::GetActivationToken { id } => {
+                data_init.init(id, Mutex::new(TokenRequest::default()));
+            }
+            xdg_activation_v1::Request::Activate { token, surface } => match state.wayland().role(&surface) {
+                Some(Role::Toplevel(id)) => {
+                    state.activate_window(id, &token);
+                }
+                _ => debug!("Ignoring activation of a surface that isn't a window"),
+            },
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<XdgActivationTokenV1, Mutex<TokenRequest>> for SpinnerCompositor {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        token: &XdgActivationTokenV1,
+        request: xdg_activation_token_v1::Request,
+        data: &Mutex<TokenRequest>,
+        _display: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        let mut data = data.lock().unwrap();
+        if data.committed {
+            if !matches!(request, xdg_activation_token_v1::Request::Destroy) {
+                token.post_error(xdg_activation_token_v1::Error::AlreadyUsed, "The token was already committed");
+            }
+            return;
+        }
+
+        match request {
+            xdg_activation_token_v1::Request::SetAppId { app_id } => data.app_id = Some(app_id),
+            xdg_activation_token_v1::Request::SetSurface { surface } => data.surface = Some(surface),
+            xdg_activation_token_v1::Request::Commit => {
+                data.committed = true;
+                let value = if may_activate(state, data.surface.as_ref()) {
+                    state.activation_mut().create_token(data.app_id.as_deref(), Instant::now())
+                } else {
+                    INVALID_TOKEN.to_string()
+                };
+                token.done(value);
+            }
+            // The serial would tie the token to an input event, and there
+            // are none yet.
+            _ => {}
+        }
+    }
+}
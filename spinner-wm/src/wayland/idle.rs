@@ -0,0 +1,145 @@
+//! ext-idle-notify-v1 and idle-inhibit requests, applied to the
+//! `IdleManager` model
+
+use super::{Role, Wayland};
+use crate::compositor::SpinnerCompositor;
+use crate::idle::{IdleEvent, IdleInhibitorId, IdleNotificationId, InhibitSurface};
+
+use wayland_protocols::ext::idle_notify::v1::server::ext_idle_notification_v1::{self, ExtIdleNotificationV1};
+use wayland_protocols::ext::idle_notify::v1::server::ext_idle_notifier_v1::{self, ExtIdleNotifierV1};
+use wayland_protocols::wp::idle_inhibit::zv1::server::zwp_idle_inhibit_manager_v1::{self, ZwpIdleInhibitManagerV1};
+use wayland_protocols::wp::idle_inhibit::zv1::server::zwp_idle_inhibitor_v1::{self, ZwpIdleInhibitorV1};
+use wayland_server::backend::ClientId;
+use wayland_server::{Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New};
+
+impl Wayland {
+    /// Sends the model's `idled` and `resumed` events to their objects.
+    pub fn send_idle_events(&self, events: Vec<IdleEvent>) {
+        for event in events {
+            match event {
+                IdleEvent::Idled(id) => {
+                    if let Some(notification) = self.idle_notifications.get(&id) {
+                        notification.idled();
+                    }
+                }
+                IdleEvent::Resumed(id) => {
+                    if let Some(notification) = self.idle_notifications.get(&id) {
+                        notification.resumed();
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl GlobalDispatch<ExtIdleNotifierV1, ()> for SpinnerCompositor {
+    fn bind(
+        _state: &mut Self,
+        _display: &DisplayHandle,
+        _client: &Client,
+        resource: New<ExtIdleNotifierV1>,
+        _data: &(),
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl Dispatch<ExtIdleNotifierV1, ()> for SpinnerCompositor {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        _notifier: &ExtIdleNotifierV1,
+        request: ext_idle_notifier_v1::Request,
+        _data: &(),
+        _display: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        // There is one seat, so the requested one doesn't matter.
+        let (id, timeout, respect_inhibitors) = match request {
+            ext_idle_notifier_v1::Request::GetIdleNotification { id, timeout, .. } => (id, timeout, true),
+            ext_idle_notifier_v1::Request::GetInputIdleNotification { id, timeout, .. } => (id, timeout, false),
+            _ => return,
+        };
+
+        let notification_id = state.idle_mut().get_idle_notification(timeout, respect_inhibitors);
+        let notification = data_init.init(id, notification_id);
+        state.wayland_mut().idle_notifications.insert(notification_id, notification);
+    }
+}
+
+impl Dispatch<ExtIdleNotificationV1, IdleNotificationId> for SpinnerCompositor {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        _notification: &ExtIdleNotificationV1,
+        _request: ext_idle_notification_v1::Request,
+        _data: &IdleNotificationId,
+        _display: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+    }
+
+    fn destroyed(state: &mut Self, _client: ClientId, _notification: &ExtIdleNotificationV1, id: &IdleNotificationId) {
+        state.idle_mut().destroy_notification(*id);
+        state.wayland_mut().idle_notifications.remove(id);
+    }
+}
+
+impl GlobalDispatch<ZwpIdleInhibitManagerV1, ()> for SpinnerCompositor {
+    fn bind(
+        _state: &mut Self,
+        _display: &DisplayHandle,
+        _client: &Client,
+        resource: New<ZwpIdleInhibitManagerV1>,
+        _data: &(),
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl Dispatch<ZwpIdleInhibitManagerV1, ()> for SpinnerCompositor {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        _manager: &ZwpIdleInhibitManagerV1,
+        request: zwp_idle_inhibit_manager_v1::Request,
+        _data: &(),
+        _display: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        let zwp_idle_inhibit_manager_v1::Request::CreateInhibitor { id, surface } = request else {
+            return;
+        };
+
+        // Only windows and layer surfaces can be visible; an inhibitor on
+        // anything else never counts.
+        let surface = match state.wayland_mut().role(&surface) {
+            Some(Role::Toplevel(id)) => Some(InhibitSurface::Window(id)),
+            Some(Role::Layer(id)) => Some(InhibitSurface::Layer(id)),
+            _ => None,
+        };
+        let inhibitor = surface.map(|surface| state.idle_mut().create_inhibitor(surface));
+        data_init.init(id, inhibitor);
+    }
+}
+
+impl Dispatch<ZwpIdleInhibitorV1, Option<IdleInhibitorId>> for SpinnerCompositor {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        _inhibitor: &ZwpIdleInhibitorV1,
+        _request: zwp_idle_inhibitor_v1::Request,
+        _data: &Option<IdleInhibitorId>,
+        _display: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+    }
+
+    fn destroyed(state: &mut Self, _client: ClientId, _inhibitor: &ZwpIdleInhibitorV1, id: &Option<IdleInhibitorId>) {
+        if let Some(id) = id {
+            state.idle_mut().destroy_inhibitor(*id);
+        }
+    }
+}
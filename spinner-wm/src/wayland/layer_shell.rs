@@ -0,0 +1,156 @@
+//! wlr-layer-shell requests, applied to the `LayerShell` model
+
+use super::{next_serial, Role, Wayland};
+use crate::compositor::SpinnerCompositor;
+use crate::layer_shell::{Anchor, Layer, LayerConfigure, LayerError, LayerSurfaceId, Margins};
+
+use wayland_protocols_wlr::layer_shell::v1::server::zwlr_layer_shell_v1::{self, ZwlrLayerShellV1};
+use wayland_protocols_wlr::layer_shell::v1::server::zwlr_layer_surface_v1::{self, ZwlrLayerSurfaceV1};
+use wayland_server::backend::ClientId;
+use wayland_server::{Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource, WEnum};
+
+impl Wayland {
+    fn send_layer_configures(&self, configures: Vec<LayerConfigure>) {
+        for configure in configures {
+            if let Some(surface) = self.layer_surfaces.get(&configure.id) {
+                surface.configure(next_serial(), configure.width, configure.height);
+            }
+        }
+    }
+}
+
+pub(super) fn commit(state: &mut SpinnerCompositor, id: LayerSurfaceId, mapped: bool) {
+    match state.commit_layer_surface(id, mapped) {
+        Ok(configures) => state.wayland_mut().send_layer_configures(configures),
+        Err(LayerError::InvalidSize) => {
+            if let Some(surface) = state.wayland_mut().layer_surfaces.get(&id) {
+                surface.post_error(
+                    zwlr_layer_surface_v1::Error::InvalidSize,
+                    "A zero width or height needs anchors on both opposite edges",
+                );
+            }
+        }
+        Err(LayerError::UnknownSurface) => {}
+    }
+}
+
+/// The layer surface or its wl_surface went away; the surfaces left are
+/// arranged without it.
+pub(super) fn destroy(state: &mut SpinnerCompositor, id: LayerSurfaceId) {
+    if state.wayland_mut().layer_surfaces.remove(&id).is_none() {
+        return;
+    }
+    let configures = state.destroy_layer_surface(id);
+    state.wayland_mut().send_layer_configures(configures);
+}
+
+impl From<zwlr_layer_shell_v1::Layer> for Layer {
+    fn from(layer: zwlr_layer_shell_v1::Layer) -> Self {
+        match layer {
+            zwlr_layer_shell_v1::Layer::Background => Self::Background,
+            zwlr_layer_shell_v1::Layer::Bottom => Self::Bottom,
+            zwlr_layer_shell_v1::Layer::Top => Self::Top,
+            _ => Self::Overlay,
+        }
+    }
+}
+
+impl GlobalDispatch<ZwlrLayerShellV1, ()> for SpinnerCompositor {
+    fn bind(
+        _state: &mut Self,
+        _display: &DisplayHandle,
+        _client: &Client,
+        resource: New<ZwlrLayerShellV1>,
+        _data: &(),
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl Dispatch<ZwlrLayerShellV1, ()> for SpinnerCompositor {
+    fn request(
+        state: &mut Self,
+        client: &Client,
+        shell: &ZwlrLayerShellV1,
+        request: zwlr_layer_shell_v1::Request,
+        _data: &(),
+        display: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        // There is one output, so the requested one doesn't matter.
+        let zwlr_layer_shell_v1::Request::GetLayerSurface { id, surface, layer, namespace, .. } = request else {
+            return;
+        };
+
+        let layer = match layer {
+            WEnum::Value(layer) => layer.into(),
+            WEnum::Unknown(value) => {
+                shell.post_error(zwlr_layer_shell_v1::Error::InvalidLayer, format!("Invalid layer {}", value));
+                Layer::Top
+            }
+        };
+        let pid = client.get_credentials(display).ok().map(|c| c.pid as u32);
+        let layer_id = state.layer_shell_mut().create_surface(namespace, layer, pid);
+        let resource = data_init.init(id, layer_id);
+
+        let wayland = state.wayland_mut();
+        wayland.layer_surfaces.insert(layer_id, resource);
+        if wayland.has_buffer(&surface) {
+            shell.post_error(zwlr_layer_shell_v1::Error::AlreadyConstructed, "Surface already has a buffer");
+        } else if !wayland.set_role(&surface, Role::Layer(layer_id)) {
+            shell.post_error(zwlr_layer_shell_v1::Error::Role, "Surface already has a role");
+        }
+    }
+}
+
+impl Dispatch<ZwlrLayerSurfaceV1, LayerSurfaceId> for SpinnerCompositor {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        resource: &ZwlrLayerSurfaceV1,
+        request: zwlr_layer_surface_v1::Request,
+        id: &LayerSurfaceId,
+        _display: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        let Some(surface) = state.layer_shell_mut().surface_mut(*id) else {
+            return;
+        };
+
+        match request {
+            zwlr_layer_surface_v1::Request::SetSize { width, height } => surface.set_size(width, height),
+            zwlr_layer_surface_v1::Request::SetAnchor { anchor } => match anchor {
+                WEnum::Value(anchor) => surface.set_anchor(Anchor::from_bits_truncate(anchor.bits())),
+                WEnum::Unknown(value) => resource.post_error(
+                    zwlr_layer_surface_v1::Error::InvalidAnchor,
+                    format!("Invalid anchor {}", value),
+                ),
+            },
+            zwlr_layer_surface_v1::Request::SetExclusiveZone { zone } => surface.set_exclusive_zone(zone),
+            zwlr_layer_surface_v1::Request::SetMargin { top, right, bottom, left } => {
+                surface.set_margin(Margins { top, right, bottom, left })
+            }
+            zwlr_layer_surface_v1::Request::SetLayer { layer } => match layer {
+                WEnum::Value(layer) => surface.set_layer(layer.into()),
+                WEnum::Unknown(value) => resource.post_error(
+                    zwlr_layer_shell_v1::Error::InvalidLayer,
+                    format!("Invalid layer {}", value),
+                ),
+            },
+            // Validated, but there is no seat yet to give the keyboard to.
+            zwlr_layer_surface_v1::Request::SetKeyboardInteractivity {
+                keyboard_interactivity: WEnum::Unknown(value),
+            } => resource.post_error(
+                zwlr_layer_surface_v1::Error::InvalidKeyboardInteractivity,
+                format!("Invalid keyboard interactivity {}", value),
+            ),
+            // Popups and configure acks need nothing from the model.
+            _ => {}
+        }
+    }
+
+    fn destroyed(state: &mut Self, _client: ClientId, _resource: &ZwlrLayerSurfaceV1, id: &LayerSurfaceId) {
+        destroy(state, *id);
+    }
+}
@@ -0,0 +1,554 @@
+//! Wayland protocol front-end for SpinnerWM
+//!
+//! Serves clients on `$WAYLAND_DISPLAY` and turns their requests into calls
+//! on the compositor's models: xdg toplevels become managed windows, layer
+//! surfaces go to `LayerShell`, lock surfaces to `SessionLock`, idle
+//! notifications and inhibitors to `IdleManager` and activation tokens to
+//! `Activation`. Nothing is rendered yet, so buffers are released as soon
+//! as they are committed and frame callbacks fire once per frame, for the
+//! surfaces the output would show; the models only need surface state and
+//! buffer sizes. The seat has no input devices yet, so it has no
+//! capabilities.
+
+mod activation;
+mod idle;
+mod layer_shell;
+mod session_lock;
+mod xdg_shell;
+
+use crate::compositor::SpinnerCompositor;
+use crate::idle::{IdleNotificationId, InhibitSurface, OutputPower};
+use crate::layer_shell::LayerSurfaceId;
+use crate::session_lock::{LockSurface, LockSurfaceId, OutputContent};
+use crate::window::{WindowId, WindowManager};
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Instant;
+use wayland_server::backend::{ClientId, ObjectId};
+use wayland_server::protocol::wl_buffer::{self, WlBuffer};
+use wayland_server::protocol::wl_callback::{self, WlCallback};
+use wayland_server::protocol::wl_compositor::{self, WlCompositor};
+use wayland_server::protocol::wl_keyboard::{self, WlKeyboard};
+use wayland_server::protocol::wl_output::{self, WlOutput};
+use wayland_server::protocol::wl_pointer::{self, WlPointer};
+use wayland_server::protocol::wl_region::{self, WlRegion};
+use wayland_server::protocol::wl_seat::{self, WlSeat};
+use wayland_server::protocol::wl_shm::{self, WlShm};
+use wayland_server::protocol::wl_shm_pool::{self, WlShmPool};
+use wayland_server::protocol::wl_surface::{self, WlSurface};
+use wayland_server::protocol::wl_touch::{self, WlTouch};
+use wayland_server::{Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource};
+use wayland_protocols::ext::idle_notify::v1::server::ext_idle_notification_v1::ExtIdleNotificationV1;
+use wayland_protocols::ext::idle_notify::v1::server::ext_idle_notifier_v1::ExtIdleNotifierV1;
+use wayland_protocols::ext::session_lock::v1::server::ext_session_lock_manager_v1::ExtSessionLockManagerV1;
+use wayland_protocols::ext::session_lock::v1::server::ext_session_lock_surface_v1::ExtSessionLockSurfaceV1;
+use wayland_protocols::ext::session_lock::v1::server::ext_session_lock_v1::ExtSessionLockV1;
+use wayland_protocols::wp::idle_inhibit::zv1::server::zwp_idle_inhibit_manager_v1::ZwpIdleInhibitManagerV1;
+use wayland_protocols::xdg::activation::v1::server::xdg_activation_v1::XdgActivationV1;
+use wayland_protocols::xdg::shell::server::xdg_wm_base::XdgWmBase;
+use wayland_protocols_wlr::layer_shell::v1::server::zwlr_layer_shell_v1::ZwlrLayerShellV1;
+use wayland_protocols_wlr::layer_shell::v1::server::zwlr_layer_surface_v1::ZwlrLayerSurfaceV1;
+
+/// The name clients see for the single output.
+pub const OUTPUT_NAME: &str = "SPINNER-1";
+
+const SEAT_NAME: &str = "seat0";
+
+static SERIAL_COUNTER: AtomicU32 = AtomicU32::new(1);
+
+fn next_serial() -> u32 {
+    SERIAL_COUNTER.fetch_add(1, Ordering::SeqCst)
+}
+
+/// What a surface is used for. A surface gets one role for its lifetime.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Role {
+    /// An xdg_surface without its toplevel or popup yet.
+    XdgSurface,
+    Toplevel(WindowId),
+    Popup,
+    Layer(LayerSurfaceId),
+    Lock(LockSurfaceId),
+}
+
+/// The size of a wl_shm buffer, which is all the compositor needs from it.
+struct ShmBuffer {
+    width: u32,
+    height: u32,
+}
+
+struct Surface {
+    role: Option<Role>,
+    /// Attached since the last commit; `Some(None)` removes the buffer.
+    pending_buffer: Option<Option<WlBuffer>>,
+    pending_scale: i32,
+    /// Size of the committed buffer in surface coordinates, if it has one.
+    size: Option<(u32, u32)>,
+    buffer_size: Option<(u32, u32)>,
+    pending_frames: Vec<WlCallback>,
+}
+
+impl Surface {
+    fn new() -> Self {
+        Self {
+            role: None,
+            pending_buffer: None,
+            pending_scale: 1,
+            size: None,
+            buffer_size: None,
+            pending_frames: Vec::new(),
+        }
+    }
+}
+
+pub struct Wayland {
+    surfaces: HashMap<ObjectId, Surface>,
+    /// Callbacks of committed surfaces, done on the next frame the surface
+    /// is shown in.
+    frames: Vec<(Option<Role>, WlCallback)>,
+    layer_surfaces: HashMap<LayerSurfaceId, ZwlrLayerSurfaceV1>,
+    /// The lock object of the session's lock client, told when it's locked.
+    session_lock: Option<ExtSessionLockV1>,
+    lock_surfaces: HashMap<LockSurfaceId, ExtSessionLockSurfaceV1>,
+    idle_notifications: HashMap<IdleNotificationId, ExtIdleNotificationV1>,
+    toplevels: HashMap<WindowId, xdg_shell::Toplevel>,
+    popups: HashMap<ObjectId, xdg_shell::Popup>,
+    started: Instant,
+}
+
+impl Wayland {
+    pub fn new() -> Self {
+        Self {
+            surfaces: HashMap::new(),
+            frames: Vec::new(),
+            layer_surfaces: HashMap::new(),
+            session_lock: None,
+            lock_surfaces: HashMap::new(),
+            idle_notifications: HashMap::new(),
+            toplevels: HashMap::new(),
+            popups: HashMap::new(),
+            started: Instant::now(),
+        }
+    }
+
+    /// Creates the globals clients bind to.
+    pub fn create_globals(display: &DisplayHandle) {
+        display.create_global::<SpinnerCompositor, WlCompositor, ()>(4, ());
+        display.create_global::<SpinnerCompositor, WlShm, ()>(1, ());
+        display.create_global::<SpinnerCompositor, WlOutput, ()>(4, ());
+        display.create_global::<SpinnerCompositor, WlSeat, ()>(7, ());
+        display.create_global::<SpinnerCompositor, XdgWmBase, ()>(3, ());
+        display.create_global::<SpinnerCompositor, ZwlrLayerShellV1, ()>(4, ());
+        display.create_global::<SpinnerCompositor, ExtSessionLockManagerV1, ()>(1, ());
+        display.create_global::<SpinnerCompositor, ExtIdleNotifierV1, ()>(2, ());
+        display.create_global::<SpinnerCompositor, ZwpIdleInhibitManagerV1, ()>(1, ());
+        display.create_global::<SpinnerCompositor, XdgActivationV1, ()>(1, ());
+    }
+
+    /// Runs once per frame, after the models have settled. While the
+    /// session is locked only the lock surface gets frame callbacks, and
+    /// none do while the output is off, so hidden clients stop drawing
+    /// until they are shown again.
+    pub fn frame(&mut self, wm: &WindowManager, content: OutputContent, power: OutputPower) {
+        self.sync_toplevels(wm);
+
+        let time = self.started.elapsed().as_millis() as u32;
+        self.frames.retain(|(role, callback)| {
+            let shown = match content {
+                _ if power == OutputPower::Off => false,
+                OutputContent::Normal => true,
+                OutputContent::Lock(LockSurface::Protocol(id)) => *role == Some(Role::Lock(id)),
+                OutputContent::Lock(LockSurface::Layer(id)) => *role == Some(Role::Layer(id)),
+                OutputContent::Blank => false,
+            };
+            if shown {
+                callback.done(time);
+            }
+            !shown && callback.is_alive()
+        });
+    }
+
+    fn role(&self, surface: &WlSurface) -> Option<Role> {
+        self.surfaces.get(&surface.id()).and_then(|s| s.role)
+    }
+
+    /// Gives `surface` its role. False if it already has a different one,
+    /// which is a protocol error for the caller to post.
+    fn set_role(&mut self, surface: &WlSurface, role: Role) -> bool {
+        let Some(surface) = self.surfaces.get_mut(&surface.id()) else {
+            return false;
+        };
+        match surface.role {
+            // An xdg_surface goes on to become a toplevel or a popup.
+            Some(Role::XdgSurface) if role != Role::XdgSurface => {}
+            Some(_) => return false,
+            None => {}
+        }
+        surface.role = Some(role);
+        true
+    }
+
+    fn has_buffer(&self, surface: &WlSurface) -> bool {
+        self.surfaces
+            .get(&surface.id())
+            .is_some_and(|s| s.size.is_some() || matches!(s.pending_buffer, Some(Some(_))))
+    }
+}
+
+impl Default for Wayland {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Applies the pending state of a surface and hands the commit to its
+/// role.
+fn commit(state: &mut SpinnerCompositor, surface: &WlSurface) {
+    let wayland = state.wayland_mut();
+    let Some(data) = wayland.surfaces.get_mut(&surface.id()) else {
+        return;
+    };
+
+    if let Some(buffer) = data.pending_buffer.take() {
+        data.buffer_size = buffer
+            .as_ref()
+            .and_then(|b| b.data::<ShmBuffer>())
+            .map(|b| (b.width, b.height));
+        // Nothing reads the contents, so the client may reuse it right away.
+        if let Some(buffer) = buffer {
+            buffer.release();
+        }
+    }
+    let scale = data.pending_scale.max(1) as u32;
+    data.size = data.buffer_size.map(|(w, h)| (w / scale, h / scale));
+    let (role, size) = (data.role, data.size);
+    wayland.frames.extend(data.pending_frames.drain(..).map(|callback| (role, callback)));
+
+    match role {
+        Some(Role::Toplevel(id)) => xdg_shell::commit_toplevel(state, id, size),
+        Some(Role::Popup) => xdg_shell::commit_popup(state, surface),
+        Some(Role::Layer(id)) => layer_shell::commit(state, id, size.is_some()),
+        Some(Role::Lock(id)) => session_lock::commit(state, id, size),
+        Some(Role::XdgSurface) | None => {}
+    }
+}
+
+impl GlobalDispatch<WlCompositor, ()> for SpinnerCompositor {
+    fn bind(
+        _state: &mut Self,
+        _display: &DisplayHandle,
+        _client: &Client,
+        resource: New<WlCompositor>,
+        _data: &(),
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl Dispatch<WlCompositor, ()> for SpinnerCompositor {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        _compositor: &WlCompositor,
+        request: wl_compositor::Request,
+        _data: &(),
+        _display: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        match request {
+            wl_compositor::Request::CreateSurface { id } => {
+                let surface = data_init.init(id, ());
+                state.wayland_mut().surfaces.insert(surface.id(), Surface::new());
+            }
+            wl_compositor::Request::CreateRegion { id } => {
+                data_init.init(id, ());
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<WlSurface, ()> for SpinnerCompositor {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        surface: &WlSurface,
+        request: wl_surface::Request,
+        _data: &(),
+        _display: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        match request {
+            wl_surface::Request::Attach { buffer, .. } => {
+                if let Some(data) = state.wayland_mut().surfaces.get_mut(&surface.id()) {
+                    data.pending_buffer = Some(buffer);
+                }
+            }
+            wl_surface::Request::Frame { callback } => {
+                let callback = data_init.init(callback, ());
+                if let Some(data) = state.wayland_mut().surfaces.get_mut(&surface.id()) {
+                    data.pending_frames.push(callback);
+                }
+            }
+            wl_surface::Request::SetBufferScale { scale } => {
+                if let Some(data) = state.wayland_mut().surfaces.get_mut(&surface.id()) {
+                    data.pending_scale = scale;
+                }
+            }
+            wl_surface::Request::Commit => commit(state, surface),
+            // Damage, regions and transforms only matter to a renderer.
+            _ => {}
+        }
+    }
+
+    fn destroyed(state: &mut Self, _client: ClientId, surface: &WlSurface, _data: &()) {
+        let Some(data) = state.wayland_mut().surfaces.remove(&surface.id()) else {
+            return;
+        };
+        match data.role {
+            Some(Role::Toplevel(id)) => {
+                state.idle_mut().surface_destroyed(InhibitSurface::Window(id));
+                xdg_shell::unmap(state, id);
+            }
+            Some(Role::Popup) => {
+                state.wayland_mut().popups.remove(&surface.id());
+            }
+            Some(Role::Layer(id)) => layer_shell::destroy(state, id),
+            Some(Role::Lock(id)) => session_lock::destroy(state, id),
+            Some(Role::XdgSurface) | None => {}
+        }
+    }
+}
+
+impl GlobalDispatch<WlSeat, ()> for SpinnerCompositor {
+    fn bind(
+        _state: &mut Self,
+        _display: &DisplayHandle,
+        _client: &Client,
+        resource: New<WlSeat>,
+        _data: &(),
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        let seat = data_init.init(resource, ());
+        seat.capabilities(wl_seat::Capability::empty());
+        if seat.version() >= 2 {
+            seat.name(SEAT_NAME.to_string());
+        }
+    }
+}
+
+impl Dispatch<WlSeat, ()> for SpinnerCompositor {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        _seat: &WlSeat,
+        request: wl_seat::Request,
+        _data: &(),
+        _display: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        // Without the capabilities these never get events.
+        match request {
+            wl_seat::Request::GetPointer { id } => {
+                data_init.init(id, ());
+            }
+            wl_seat::Request::GetKeyboard { id } => {
+                data_init.init(id, ());
+            }
+            wl_seat::Request::GetTouch { id } => {
+                data_init.init(id, ());
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<WlPointer, ()> for SpinnerCompositor {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        _pointer: &WlPointer,
+        _request: wl_pointer::Request,
+        _data: &(),
+        _display: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+    }
+}
+
+impl Dispatch<WlKeyboard, ()> for SpinnerCompositor {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        _keyboard: &WlKeyboard,
+        _request: wl_keyboard::Request,
+        _data: &(),
+        _display: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+    }
+}
+
+impl Dispatch<WlTouch, ()> for SpinnerCompositor {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        _touch: &WlTouch,
+        _request: wl_touch::Request,
+        _data: &(),
+        _display: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+    }
+}
+
+impl Dispatch<WlCallback, ()> for SpinnerCompositor {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        _callback: &WlCallback,
+        _request: wl_callback::Request,
+        _data: &(),
+        _display: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+    }
+}
+
+impl Dispatch<WlRegion, ()> for SpinnerCompositor {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        _region: &WlRegion,
+        _request: wl_region::Request,
+        _data: &(),
+        _display: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+    }
+}
+
+impl GlobalDispatch<WlShm, ()> for SpinnerCompositor {
+    fn bind(
+        _state: &mut Self,
+        _display: &DisplayHandle,
+        _client: &Client,
+        resource: New<WlShm>,
+        _data: &(),
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        let shm = data_init.init(resource, ());
+        shm.format(wl_shm::Format::Argb8888);
+        shm.format(wl_shm::Format::Xrgb8888);
+    }
+}
+
+impl Dispatch<WlShm, ()> for SpinnerCompositor {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        _shm: &WlShm,
+        request: wl_shm::Request,
+        _data: &(),
+        _display: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        // The pool's memory is never mapped, so its fd is closed right away.
+        if let wl_shm::Request::CreatePool { id, .. } = request {
+            data_init.init(id, ());
+        }
+    }
+}
+
+impl Dispatch<WlShmPool, ()> for SpinnerCompositor {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        pool: &WlShmPool,
+        request: wl_shm_pool::Request,
+        _data: &(),
+        _display: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let wl_shm_pool::Request::CreateBuffer { id, width, height, .. } = request {
+            data_init.init(
+                id,
+                ShmBuffer {
+                    width: width.max(0) as u32,
+                    height: height.max(0) as u32,
+                },
+            );
+            if width <= 0 || height <= 0 {
+                pool.post_error(wl_shm::Error::InvalidStride, "Invalid buffer size");
+            }
+        }
+    }
+}
+
+impl Dispatch<WlBuffer, ShmBuffer> for SpinnerCompositor {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        _buffer: &WlBuffer,
+        _request: wl_buffer::Request,
+        _data: &ShmBuffer,
+        _display: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+    }
+}
+
+impl GlobalDispatch<WlOutput, ()> for SpinnerCompositor {
+    fn bind(
+        state: &mut Self,
+        _display: &DisplayHandle,
+        _client: &Client,
+        resource: New<WlOutput>,
+        _data: &(),
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        let output = data_init.init(resource, ());
+        let (width, height) = state.window_manager().screen_size();
+
+        output.geometry(
+            0,
+            0,
+            0,
+            0,
+            wl_output::Subpixel::Unknown,
+            "SpinnerOS".to_string(),
+            OUTPUT_NAME.to_string(),
+            wl_output::Transform::Normal,
+        );
+        output.mode(
+            wl_output::Mode::Current | wl_output::Mode::Preferred,
+            width as i32,
+            height as i32,
+            60_000,
+        );
+        if output.version() >= 2 {
+            output.scale(1);
+        }
+        if output.version() >= 4 {
+            output.name(OUTPUT_NAME.to_string());
+        }
+        if output.version() >= 2 {
+            output.done();
+        }
+    }
+}
+
+impl Dispatch<WlOutput, ()> for SpinnerCompositor {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        _output: &WlOutput,
+        _request: wl_output::Request,
+        _data: &(),
+        _display: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+    }
+}
@@ -0,0 +1,175 @@
+//! ext-session-lock-v1 requests, applied to the `SessionLock` model
+
+use super::{next_serial, Role};
+use crate::compositor::SpinnerCompositor;
+use crate::session_lock::{LockClient, LockError, LockSurface, LockSurfaceId};
+
+use std::sync::atomic::{AtomicU32, Ordering};
+use wayland_protocols::ext::session_lock::v1::server::ext_session_lock_manager_v1::{self, ExtSessionLockManagerV1};
+use wayland_protocols::ext::session_lock::v1::server::ext_session_lock_surface_v1::{self, ExtSessionLockSurfaceV1};
+use wayland_protocols::ext::session_lock::v1::server::ext_session_lock_v1::{self, ExtSessionLockV1};
+use wayland_server::backend::ClientId;
+use wayland_server::{Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource};
+
+/// Numbers lock objects, which is how the model tells lock clients apart.
+static LOCK_COUNTER: AtomicU32 = AtomicU32::new(1);
+
+/// Shows the lock surface once its buffer has the configured size, and
+/// tells the client when that completes the lock.
+pub(super) fn commit(state: &mut SpinnerCompositor, id: LockSurfaceId, size: Option<(u32, u32)>) {
+    let Some((width, height)) = size else {
+        if let Some(surface) = state.wayland_mut().lock_surfaces.get(&id) {
+            surface.post_error(ext_session_lock_surface_v1::Error::NullBuffer, "A lock surface needs a buffer");
+        }
+        return;
+    };
+
+    match state.session_lock_mut().commit(id, width, height) {
+        Ok(true) => {
+            if let Some(lock) = &state.wayland_mut().session_lock {
+                lock.locked();
+            }
+        }
+        Err(LockError::DimensionsMismatch) => {
+            if let Some(surface) = state.wayland_mut().lock_surfaces.get(&id) {
+                surface.post_error(
+                    ext_session_lock_surface_v1::Error::DimensionsMismatch,
+                    "The buffer doesn't match the configured size",
+                );
+            }
+        }
+        // Surfaces of an earlier lock client are no longer shown.
+        Ok(false) | Err(_) => {}
+    }
+}
+
+/// The lock surface or its wl_surface went away; the output goes blank.
+pub(super) fn destroy(state: &mut SpinnerCompositor, id: LockSurfaceId) {
+    if state.wayland_mut().lock_surfaces.remove(&id).is_some() {
+        state.session_lock_mut().surface_destroyed(LockSurface::Protocol(id));
+    }
+}
+
+impl GlobalDispatch<ExtSessionLockManagerV1, ()> for SpinnerCompositor {
+    fn bind(
+        _state: &mut Self,
+        _display: &DisplayHandle,
+        _client: &Client,
+        resource: New<ExtSessionLockManagerV1>,
+        _data: &(),
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl Dispatch<ExtSessionLockManagerV1, ()> for SpinnerCompositor {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        _manager: &ExtSessionLockManagerV1,
+        request: ext_session_lock_manager_v1::Request,
+        _data: &(),
+        _display: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        let ext_session_lock_manager_v1::Request::Lock { id } = request else {
+            return;
+        };
+
+        let client = LockClient::Wayland(LOCK_COUNTER.fetch_add(1, Ordering::SeqCst));
+        let lock = data_init.init(id, client);
+        match state.session_lock_mut().lock(client) {
+            Ok(()) => state.wayland_mut().session_lock = Some(lock),
+            Err(_) => lock.finished(),
+        }
+    }
+}
+
+impl Dispatch<ExtSessionLockV1, LockClient> for SpinnerCompositor {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        lock: &ExtSessionLockV1,
+        request: ext_session_lock_v1::Request,
+        client: &LockClient,
+        _display: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        match request {
+            ext_session_lock_v1::Request::GetLockSurface { id, surface, .. } => {
+                let configure = match state.session_lock_mut().get_lock_surface(*client) {
+                    Ok(configure) => configure,
+                    Err(e) => {
+                        data_init.init(id, None);
+                        if e == LockError::DuplicateOutput {
+                            lock.post_error(
+                                ext_session_lock_v1::Error::DuplicateOutput,
+                                "The output already has a lock surface",
+                            );
+                        }
+                        // A lock that got `finished` has nothing to show.
+                        return;
+                    }
+                };
+
+                let resource = data_init.init(id, Some(configure.id));
+                let wayland = state.wayland_mut();
+                if wayland.has_buffer(&surface) {
+                    lock.post_error(ext_session_lock_v1::Error::AlreadyConstructed, "Surface already has a buffer");
+                } else if !wayland.set_role(&surface, Role::Lock(configure.id)) {
+                    lock.post_error(ext_session_lock_v1::Error::Role, "Surface already has a role");
+                }
+                resource.configure(next_serial(), configure.width, configure.height);
+                wayland.lock_surfaces.insert(configure.id, resource);
+            }
+            ext_session_lock_v1::Request::Destroy => {
+                // Fine until `locked`, which only unlocking undoes.
+                let result = state.session_lock_mut().cancel(*client);
+                if result == Err(LockError::InvalidCancel) {
+                    lock.post_error(
+                        ext_session_lock_v1::Error::InvalidDestroy,
+                        "The session is locked, use unlock_and_destroy",
+                    );
+                }
+            }
+            ext_session_lock_v1::Request::UnlockAndDestroy => {
+                let result = state.session_lock_mut().unlock(*client);
+                if result.is_err() {
+                    lock.post_error(ext_session_lock_v1::Error::InvalidUnlock, "The session is not locked");
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn destroyed(state: &mut Self, _client: ClientId, lock: &ExtSessionLockV1, client: &LockClient) {
+        // Gone without unlocking: the session stays locked.
+        state.session_lock_mut().client_gone(*client);
+
+        let wayland = state.wayland_mut();
+        if wayland.session_lock.as_ref().is_some_and(|l| l.id() == lock.id()) {
+            wayland.session_lock = None;
+        }
+    }
+}
+
+impl Dispatch<ExtSessionLockSurfaceV1, Option<LockSurfaceId>> for SpinnerCompositor {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        _surface: &ExtSessionLockSurfaceV1,
+        _request: ext_session_lock_surface_v1::Request,
+        _data: &Option<LockSurfaceId>,
+        _display: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        // The size never changes, so acks need no tracking.
+    }
+
+    fn destroyed(state: &mut Self, _client: ClientId, _surface: &ExtSessionLockSurfaceV1, id: &Option<LockSurfaceId>) {
+        if let Some(id) = id {
+            destroy(state, *id);
+        }
+    }
+}
@@ -0,0 +1,432 @@
+//! xdg-shell: toplevels become managed windows once they have a buffer,
+//! and compositor-side changes to them go back as configures. Popups open
+//! below their anchor rectangle; gravity and constraints aren't applied.
+
+use super::{next_serial, Role, Wayland};
+use crate::compositor::SpinnerCompositor;
+use crate::window::{Geometry, ManagedWindow, WindowId, WindowManager};
+
+use std::sync::Mutex;
+use tracing::debug;
+use wayland_protocols::xdg::shell::server::xdg_popup::{self, XdgPopup};
+use wayland_protocols::xdg::shell::server::xdg_positioner::{self, XdgPositioner};
+use wayland_protocols::xdg::shell::server::xdg_surface::{self, XdgSurface};
+use wayland_protocols::xdg::shell::server::xdg_toplevel::{self, XdgToplevel};
+use wayland_protocols::xdg::shell::server::xdg_wm_base::{self, XdgWmBase};
+use wayland_server::backend::ClientId;
+use wayland_server::protocol::wl_surface::WlSurface;
+use wayland_server::{Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource};
+
+pub(super) struct Toplevel {
+    toplevel: XdgToplevel,
+    xdg_surface: XdgSurface,
+    title: String,
+    app_id: String,
+    parent: Option<WindowId>,
+    /// The initial configure went out; the next buffer maps the window.
+    configured: bool,
+    mapped: bool,
+    synced: Option<SyncedState>,
+}
+
+/// What the client was last told about its window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct SyncedState {
+    width: u32,
+    height: u32,
+    focused: bool,
+    maximized: bool,
+    fullscreen: bool,
+}
+
+pub(super) struct Popup {
+    popup: XdgPopup,
+    xdg_surface: XdgSurface,
+    geometry: Geometry,
+    configured: bool,
+}
+
+/// Popup placement relative to the parent surface.
+#[derive(Debug, Clone, Copy, Default)]
+struct Positioner {
+    width: i32,
+    height: i32,
+    anchor_rect: (i32, i32, i32, i32),
+    offset: (i32, i32),
+}
+
+impl Positioner {
+    fn geometry(&self) -> Geometry {
+        let (x, y, _, height) = self.anchor_rect;
+        Geometry::new(
+            x + self.offset.0,
+            y + height + self.offset.1,
+            self.width.max(1) as u32,
+            self.height.max(1) as u32,
+        )
+    }
+}
+
+impl Wayland {
+    /// Asks the client to close the window. False if it isn't a Wayland
+    /// toplevel.
+    pub fn close(&self, id: WindowId) -> bool {
+        match self.toplevels.get(&id) {
+            Some(toplevel) => {
+                toplevel.toplevel.close();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Pushes compositor-side state of mapped toplevels to their clients.
+    pub(super) fn sync_toplevels(&mut self, wm: &WindowManager) {
+        for (id, toplevel) in &mut self.toplevels {
+            let Some(window) = wm.window(*id).filter(|_| toplevel.mapped) else {
+                continue;
+            };
+            let state = SyncedState {
+                width: window.geometry.width,
+                height: window.geometry.height,
+                focused: window.focused,
+                maximized: window.maximized,
+                fullscreen: window.fullscreen,
+            };
+            if toplevel.synced.replace(state) != Some(state) {
+                configure(toplevel, Some(state));
+            }
+        }
+    }
+}
+
+fn configure(toplevel: &Toplevel, state: Option<SyncedState>) {
+    let (width, height, states) = match state {
+        Some(state) => {
+            let mut states = Vec::new();
+            if state.focused {
+                states.push(xdg_toplevel::State::Activated);
+            }
+            if state.maximized {
+                states.push(xdg_toplevel::State::Maximized);
+            }
+            if state.fullscreen {
+                states.push(xdg_toplevel::State::Fullscreen);
+            }
+            (state.width as i32, state.height as i32, states)
+        }
+        // The client picks its initial size.
+        None => (0, 0, Vec::new()),
+    };
+    let states = states
+        .into_iter()
+        .flat_map(|s| u32::from(s).to_ne_bytes())
+        .collect();
+    toplevel.toplevel.configure(width, height, states);
+    toplevel.xdg_surface.configure(next_serial());
+}
+
+pub(super) fn commit_toplevel(state: &mut SpinnerCompositor, id: WindowId, size: Option<(u32, u32)>) {
+    let Some(toplevel) = state.wayland_mut().toplevels.get_mut(&id) else {
+        return;
+    };
+
+    match (size, toplevel.mapped) {
+        (None, false) => {
+            if !toplevel.configured {
+                toplevel.configured = true;
+                configure(toplevel, None);
+            }
+        }
+        (None, true) => unmap(state, id),
+        (Some((width, height)), false) if toplevel.configured => {
+            toplevel.mapped = true;
+            let mut window = ManagedWindow::new(
+                toplevel.title.clone(),
+                toplevel.app_id.clone(),
+                0,
+                0,
+                width,
+                height,
+            );
+            window.id = id;
+            window.transient_for = toplevel.parent;
+
+            let wm = state.window_manager_mut();
+            // Center new windows, over their parent when they are dialogs.
+            let area = window
+                .transient_for
+                .and_then(|parent| wm.window(parent))
+                .map(|parent| parent.geometry)
+                .unwrap_or_else(|| wm.usable_area());
+            window.set_position(
+                area.x + (area.width as i32 - width as i32) / 2,
+                area.y + (area.height as i32 - height as i32) / 2,
+            );
+            wm.add_window(window);
+            debug!("Managing Wayland window {:?}", id);
+        }
+        (Some(_), false) => {
+            toplevel
+                .xdg_surface
+                .post_error(xdg_surface::Error::UnconfiguredBuffer, "Buffer before configure");
+        }
+        (Some((width, height)), true) => {
+            if let Some(window) = state.window_manager_mut().window_mut(id) {
+                window.set_size(width, height);
+            }
+        }
+    }
+}
+
+/// The window goes away; the toplevel may map again with a new initial
+/// commit.
+pub(super) fn unmap(state: &mut SpinnerCompositor, id: WindowId) {
+    if let Some(toplevel) = state.wayland_mut().toplevels.get_mut(&id) {
+        if !toplevel.mapped {
+            return;
+        }
+        toplevel.mapped = false;
+        toplevel.configured = false;
+        toplevel.synced = None;
+    }
+    state.window_manager_mut().remove_window(id);
+}
+
+pub(super) fn commit_popup(state: &mut SpinnerCompositor, surface: &WlSurface) {
+    if let Some(popup) = state.wayland_mut().popups.get_mut(&surface.id()) {
+        if !popup.configured {
+            popup.configured = true;
+            configure_popup(popup);
+        }
+    }
+}
+
+fn configure_popup(popup: &Popup) {
+    let g = popup.geometry;
+    popup.popup.configure(g.x, g.y, g.width as i32, g.height as i32);
+    popup.xdg_surface.configure(next_serial());
+}
+
+impl GlobalDispatch<XdgWmBase, ()> for SpinnerCompositor {
+    fn bind(
+        _state: &mut Self,
+        _display: &DisplayHandle,
+        _client: &Client,
+        resource: New<XdgWmBase>,
+        _data: &(),
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl Dispatch<XdgWmBase, ()> for SpinnerCompositor {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        wm_base: &XdgWmBase,
+        request: xdg_wm_base::Request,
+        _data: &(),
+        _display: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        match request {
+            xdg_wm_base::Request::CreatePositioner { id } => {
+                data_init.init(id, Mutex::new(Positioner::default()));
+            }
+            xdg_wm_base::Request::GetXdgSurface { id, surface } => {
+                data_init.init(id, surface.clone());
+                if !state.wayland_mut().set_role(&surface, Role::XdgSurface) {
+                    wm_base.post_error(xdg_wm_base::Error::Role, "Surface already has a role");
+                }
+            }
+            // Clients are never pinged.
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<XdgPositioner, Mutex<Positioner>> for SpinnerCompositor {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        _positioner: &XdgPositioner,
+        request: xdg_positioner::Request,
+        data: &Mutex<Positioner>,
+        _display: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        let mut positioner = data.lock().unwrap();
+        match request {
+            xdg_positioner::Request::SetSize { width, height } => {
+                positioner.width = width;
+                positioner.height = height;
+            }
+            xdg_positioner::Request::SetAnchorRect { x, y, width, height } => {
+                positioner.anchor_rect = (x, y, width, height);
+            }
+            xdg_positioner::Request::SetOffset { x, y } => {
+                positioner.offset = (x, y);
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<XdgSurface, WlSurface> for SpinnerCompositor {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        xdg_surface: &XdgSurface,
+        request: xdg_surface::Request,
+        surface: &WlSurface,
+        _display: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        match request {
+            xdg_surface::Request::GetToplevel { id } => {
+                let window = WindowId::new();
+                let toplevel = data_init.init(id, window);
+                let wayland = state.wayland_mut();
+                if wayland.role(surface) != Some(Role::XdgSurface) {
+                    xdg_surface.post_error(xdg_surface::Error::AlreadyConstructed, "Surface already has a role object");
+                    return;
+                }
+                wayland.set_role(surface, Role::Toplevel(window));
+                wayland.toplevels.insert(
+                    window,
+                    Toplevel {
+                        toplevel,
+                        xdg_surface: xdg_surface.clone(),
+                        title: String::new(),
+                        app_id: String::new(),
+                        parent: None,
+                        configured: false,
+                        mapped: false,
+                        synced: None,
+                    },
+                );
+            }
+            xdg_surface::Request::GetPopup { id, positioner, .. } => {
+                let popup = data_init.init(id, ());
+                let wayland = state.wayland_mut();
+                if wayland.role(surface) != Some(Role::XdgSurface) {
+                    xdg_surface.post_error(xdg_surface::Error::AlreadyConstructed, "Surface already has a role object");
+                    return;
+                }
+                let geometry = positioner
+                    .data::<Mutex<Positioner>>()
+                    .map(|p| p.lock().unwrap().geometry())
+                    .unwrap_or(Geometry::new(0, 0, 1, 1));
+                wayland.set_role(surface, Role::Popup);
+                wayland.popups.insert(
+                    surface.id(),
+                    Popup {
+                        popup,
+                        xdg_surface: xdg_surface.clone(),
+                        geometry,
+                        configured: false,
+                    },
+                );
+            }
+            // Window geometry only trims client-side shadows when drawing.
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<XdgToplevel, WindowId> for SpinnerCompositor {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        _toplevel: &XdgToplevel,
+        request: xdg_toplevel::Request,
+        id: &WindowId,
+        _display: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        let id = *id;
+        let Some(toplevel) = state.wayland_mut().toplevels.get_mut(&id) else {
+            return;
+        };
+
+        match request {
+            xdg_toplevel::Request::SetTitle { title } => {
+                toplevel.title = title.clone();
+                if let Some(window) = state.window_manager_mut().window_mut(id) {
+                    window.title = title;
+                }
+            }
+            xdg_toplevel::Request::SetAppId { app_id } => {
+                toplevel.app_id = app_id.clone();
+                if let Some(window) = state.window_manager_mut().window_mut(id) {
+                    window.app_id = app_id;
+                }
+            }
+            xdg_toplevel::Request::SetParent { parent } => {
+                let parent = parent.and_then(|p| p.data::<WindowId>().copied());
+                toplevel.parent = parent;
+                if let Some(window) = state.window_manager_mut().window_mut(id) {
+                    window.transient_for = parent;
+                }
+            }
+            xdg_toplevel::Request::SetMaximized | xdg_toplevel::Request::UnsetMaximized => {
+                let maximize = matches!(request, xdg_toplevel::Request::SetMaximized);
+                let wm = state.window_manager_mut();
+                let area = wm.usable_area();
+                if let Some(window) = wm.window_mut(id).filter(|w| w.maximized != maximize) {
+                    window.toggle_maximize(area);
+                }
+            }
+            xdg_toplevel::Request::SetFullscreen { .. } | xdg_toplevel::Request::UnsetFullscreen => {
+                let fullscreen = matches!(request, xdg_toplevel::Request::SetFullscreen { .. });
+                let wm = state.window_manager_mut();
+                let (width, height) = wm.screen_size();
+                if let Some(window) = wm.window_mut(id) {
+                    window.set_fullscreen(fullscreen, width, height);
+                }
+            }
+            xdg_toplevel::Request::SetMinimized => {
+                if let Some(window) = state.window_manager_mut().window_mut(id) {
+                    window.minimize();
+                }
+            }
+            // Interactive move, resize and the window menu need a seat.
+            _ => {}
+        }
+    }
+
+    fn destroyed(state: &mut Self, _client: ClientId, _toplevel: &XdgToplevel, id: &WindowId) {
+        unmap(state, *id);
+        state.wayland_mut().toplevels.remove(id);
+    }
+}
+
+impl Dispatch<XdgPopup, ()> for SpinnerCompositor {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        popup: &XdgPopup,
+        request: xdg_popup::Request,
+        _data: &(),
+        _display: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let xdg_popup::Request::Reposition { positioner, token } = request {
+            let wayland = state.wayland_mut();
+            let Some(entry) = wayland.popups.values_mut().find(|p| p.popup == *popup) else {
+                return;
+            };
+            if let Some(positioner) = positioner.data::<Mutex<Positioner>>() {
+                entry.geometry = positioner.lock().unwrap().geometry();
+            }
+            popup.repositioned(token);
+            configure_popup(entry);
+        }
+    }
+
+    fn destroyed(state: &mut Self, _client: ClientId, popup: &XdgPopup, _data: &()) {
+        state.wayland_mut().popups.retain(|_, p| p.popup != *popup);
+    }
+}
@@ -1,16 +1,25 @@
 //! Window management for SpinnerWM
 
+use serde::Serialize;
 use std::sync::atomic::{AtomicU32, Ordering};
 
 static WINDOW_ID_COUNTER: AtomicU32 = AtomicU32::new(1);
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
 pub struct WindowId(u32);
 
 impl WindowId {
     pub fn new() -> Self {
         Self(WINDOW_ID_COUNTER.fetch_add(1, Ordering::SeqCst))
     }
+
+    pub fn as_u32(self) -> u32 {
+        self.0
+    }
+
+    pub fn from_u32(id: u32) -> Self {
+        Self(id)
+    }
 }
 
 impl Default for WindowId {
@@ -19,62 +28,316 @@ impl Default for WindowId {
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct Window {
-    pub id: WindowId,
-    pub title: String,
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Geometry {
     pub x: i32,
     pub y: i32,
     pub width: u32,
     pub height: u32,
+}
+
+impl Geometry {
+    pub fn new(x: i32, y: i32, width: u32, height: u32) -> Self {
+        Self { x, y, width, height }
+    }
+
+    pub fn contains(&self, px: i32, py: i32) -> bool {
+        px >= self.x
+            && py >= self.y
+            && px < self.x + self.width as i32
+            && py < self.y + self.height as i32
+    }
+}
+
+/// The client protocol a window belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowSurface {
+    Wayland,
+    X11 {
+        xid: u32,
+        /// Override-redirect windows (menus, tooltips, DnD icons) are
+        /// positioned by the client and never decorated, focused or listed.
+        override_redirect: bool,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct ManagedWindow {
+    pub id: WindowId,
+    pub title: String,
+    pub app_id: String,
+    pub geometry: Geometry,
+    pub surface: WindowSurface,
+    pub transient_for: Option<WindowId>,
+    pub workspace: u32,
     pub focused: bool,
+    pub floating: bool,
+    pub maximized: bool,
+    pub fullscreen: bool,
+    pub minimized: bool,
+    saved_geometry: Option<Geometry>,
 }
 
-impl Window {
-    pub fn new(title: String, x: i32, y: i32, width: u32, height: u32) -> Self {
+impl ManagedWindow {
+    pub fn new(title: String, app_id: String, x: i32, y: i32, width: u32, height: u32) -> Self {
         Self {
             id: WindowId::new(),
             title,
-            x,
-            y,
-            width,
-            height,
+            app_id,
+            geometry: Geometry::new(x, y, width, height),
+            surface: WindowSurface::Wayland,
+            transient_for: None,
+            workspace: 1,
             focused: false,
+            floating: true,
+            maximized: false,
+            fullscreen: false,
+            minimized: false,
+            saved_geometry: None,
+        }
+    }
+
+    pub fn is_x11(&self) -> bool {
+        matches!(self.surface, WindowSurface::X11 { .. })
+    }
+
+    pub fn is_override_redirect(&self) -> bool {
+        matches!(self.surface, WindowSurface::X11 { override_redirect: true, .. })
+    }
+
+    /// Whether the window belongs in the taskbar.
+    pub fn is_listed(&self) -> bool {
+        !self.is_override_redirect() && self.transient_for.is_none()
+    }
+
+    pub fn set_position(&mut self, x: i32, y: i32) {
+        self.geometry.x = x;
+        self.geometry.y = y;
+    }
+
+    pub fn set_size(&mut self, width: u32, height: u32) {
+        self.geometry.width = width;
+        self.geometry.height = height;
+    }
+
+    pub fn set_fullscreen(&mut self, fullscreen: bool, screen_width: u32, screen_height: u32) {
+        if fullscreen == self.fullscreen {
+            return;
         }
+
+        if fullscreen {
+            if !self.maximized {
+                self.saved_geometry = Some(self.geometry);
+            }
+            self.geometry = Geometry::new(0, 0, screen_width, screen_height);
+        } else if let Some(saved) = self.saved_geometry.take() {
+            self.geometry = saved;
+            self.maximized = false;
+        }
+        self.fullscreen = fullscreen;
+    }
+
+    pub fn toggle_fullscreen(&mut self, screen_width: u32, screen_height: u32) {
+        self.set_fullscreen(!self.fullscreen, screen_width, screen_height);
+    }
+
+    pub fn toggle_floating(&mut self) {
+        self.floating = !self.floating;
+    }
+
+    /// Maximizes into `usable_area`, the output minus layer-shell
+    /// exclusive zones.
+    pub fn toggle_maximize(&mut self, usable_area: Geometry) {
+        if self.fullscreen {
+            return;
+        }
+
+        if self.maximized {
+            if let Some(saved) = self.saved_geometry.take() {
+                self.geometry = saved;
+            }
+        } else {
+            self.saved_geometry = Some(self.geometry);
+            self.geometry = usable_area;
+        }
+        self.maximized = !self.maximized;
+    }
+
+    pub fn minimize(&mut self) {
+        self.minimized = true;
+        self.focused = false;
     }
 }
 
 pub struct WindowManager {
-    windows: Vec<Window>,
+    windows: Vec<ManagedWindow>,
     focused: Option<WindowId>,
+    active_workspace: u32,
+    screen_width: u32,
+    screen_height: u32,
+    usable_area: Geometry,
 }
 
 impl WindowManager {
-    pub fn new() -> Self {
+    pub fn new(screen_width: u32, screen_height: u32) -> Self {
         Self {
             windows: Vec::new(),
             focused: None,
+            active_workspace: 1,
+            screen_width,
+            screen_height,
+            usable_area: Geometry::new(0, 0, screen_width, screen_height),
         }
     }
-    
-    pub fn add_window(&mut self, window: Window) {
+
+    pub fn add_window(&mut self, mut window: ManagedWindow) -> WindowId {
+        let id = window.id;
+        window.workspace = self.active_workspace;
+
+        // Transient dialogs follow their parent's workspace.
+        if let Some(parent) = window.transient_for.and_then(|p| self.window(p)) {
+            window.workspace = parent.workspace;
+        }
+
+        let takes_focus = !window.is_override_redirect();
         self.windows.push(window);
+
+        if takes_focus {
+            self.focus_window(id);
+        }
+        id
     }
-    
+
     pub fn remove_window(&mut self, id: WindowId) {
         self.windows.retain(|w| w.id != id);
+
+        for window in &mut self.windows {
+            if window.transient_for == Some(id) {
+                window.transient_for = None;
+            }
+        }
+
+        if self.focused == Some(id) {
+            self.focused = None;
+            let next = self
+                .windows
+                .iter()
+                .rev()
+                .find(|w| self.is_visible(w) && !w.is_override_redirect())
+                .map(|w| w.id);
+            if let Some(next) = next {
+                self.focus_window(next);
+            }
+        }
     }
-    
+
     pub fn focus_window(&mut self, id: WindowId) {
         self.focused = Some(id);
         for window in &mut self.windows {
             window.focused = window.id == id;
+            if window.focused {
+                window.minimized = false;
+            }
+        }
+
+        // Raise to the top of the stacking order.
+        if let Some(index) = self.windows.iter().position(|w| w.id == id) {
+            let window = self.windows.remove(index);
+            self.windows.push(window);
         }
     }
-}
 
-impl Default for WindowManager {
-    fn default() -> Self {
-        Self::new()
+    pub fn window(&self, id: WindowId) -> Option<&ManagedWindow> {
+        self.windows.iter().find(|w| w.id == id)
+    }
+
+    pub fn window_mut(&mut self, id: WindowId) -> Option<&mut ManagedWindow> {
+        self.windows.iter_mut().find(|w| w.id == id)
+    }
+
+    /// All windows, bottom to top.
+    pub fn windows(&self) -> impl Iterator<Item = &ManagedWindow> {
+        self.windows.iter()
+    }
+
+    pub fn focused_window(&self) -> Option<&ManagedWindow> {
+        self.focused.and_then(|id| self.window(id))
+    }
+
+    pub fn focused_window_mut(&mut self) -> Option<&mut ManagedWindow> {
+        let id = self.focused?;
+        self.window_mut(id)
+    }
+
+    fn is_visible(&self, window: &ManagedWindow) -> bool {
+        window.workspace == self.active_workspace && !window.minimized
+    }
+
+    pub fn window_at_point(&self, x: i32, y: i32) -> Option<&ManagedWindow> {
+        self.windows
+            .iter()
+            .rev()
+            .find(|w| self.is_visible(w) && w.geometry.contains(x, y))
+    }
+
+    pub fn active_workspace(&self) -> u32 {
+        self.active_workspace
+    }
+
+    pub fn switch_workspace(&mut self, workspace: u32) {
+        self.active_workspace = workspace;
+
+        let focus = self
+            .windows
+            .iter()
+            .rev()
+            .find(|w| self.is_visible(w) && w.is_listed())
+            .map(|w| w.id);
+
+        match focus {
+            Some(id) => self.focus_window(id),
+            None => {
+                self.focused = None;
+                for window in &mut self.windows {
+                    window.focused = false;
+                }
+            }
+        }
+    }
+
+    pub fn move_window_to_workspace(&mut self, id: WindowId, workspace: u32) {
+        let mut moved = vec![id];
+        for window in &mut self.windows {
+            if window.id == id || window.transient_for == Some(id) {
+                window.workspace = workspace;
+                moved.push(window.id);
+            }
+        }
+
+        if self.focused.is_some_and(|f| moved.contains(&f)) && workspace != self.active_workspace {
+            self.switch_workspace(self.active_workspace);
+        }
+    }
+
+    pub fn screen_size(&self) -> (u32, u32) {
+        (self.screen_width, self.screen_height)
+    }
+
+    /// The part of the screen not reserved by layer-shell exclusive zones.
+    pub fn usable_area(&self) -> Geometry {
+        self.usable_area
+    }
+
+    pub fn set_usable_area(&mut self, area: Geometry) {
+        if area == self.usable_area {
+            return;
+        }
+        self.usable_area = area;
+
+        for window in &mut self.windows {
+            if window.maximized {
+                window.geometry = area;
+            }
+        }
     }
 }
@@ -0,0 +1,212 @@
+//! On-demand XWayland server for SpinnerWM
+//!
+//! The X11 display sockets are bound up front and `DISPLAY` is exported to
+//! children, but Xwayland itself is only spawned when the first X11 client
+//! connects. The listening sockets are handed to Xwayland with `-listenfd`
+//! so that first connection is not lost, and `-terminate` lets the server
+//! exit again once the last client is gone.
+
+use anyhow::{bail, Context, Result};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::net::{SocketAddr, UnixListener, UnixStream};
+use std::os::unix::process::CommandExt;
+use std::path::PathBuf;
+use std::process::{Child, Command};
+use tracing::{debug, info, warn};
+
+const X11_SOCKET_DIR: &str = "/tmp/.X11-unix";
+const MAX_DISPLAY: u32 = 32;
+
+/// Handles for a freshly spawned server.
+pub struct XWaylandStartup {
+    /// Compositor end of the `-wm` connection.
+    pub wm_stream: UnixStream,
+    /// Becomes readable once the server has written its display number
+    /// and is ready to accept the WM connection.
+    pub ready: OwnedFd,
+}
+
+pub struct XWayland {
+    display: u32,
+    listeners: Vec<UnixListener>,
+    child: Option<Child>,
+}
+
+impl XWayland {
+    /// Reserves a free display number and binds its sockets.
+    pub fn prepare() -> Result<Self> {
+        fs::create_dir_all(X11_SOCKET_DIR)
+            .with_context(|| format!("Failed to create {}", X11_SOCKET_DIR))?;
+
+        for number in 0..=MAX_DISPLAY {
+            if !lock_display(number) {
+                continue;
+            }
+
+            match bind_sockets(number) {
+                Ok(listeners) => {
+                    info!("Reserved X11 display :{}", number);
+                    return Ok(Self {
+                        display: number,
+                        listeners,
+                        child: None,
+                    });
+                }
+                Err(e) => {
+                    debug!("Display :{} unavailable: {}", number, e);
+                    let _ = fs::remove_file(lock_path(number));
+                }
+            }
+        }
+
+        bail!("No free X11 display number")
+    }
+
+    pub fn display_name(&self) -> String {
+        format!(":{}", self.display)
+    }
+
+    pub fn listeners(&self) -> impl Iterator<Item = UnixListener> + '_ {
+        self.listeners.iter().filter_map(|l| l.try_clone().ok())
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.child.is_some()
+    }
+
+    /// Spawns Xwayland. The WM must not connect until `ready` is readable,
+    /// since the server blocks on the compositor while it initializes.
+    pub fn start(&mut self) -> Result<XWaylandStartup> {
+        let (wm_ours, wm_theirs) = UnixStream::pair().context("Failed to create WM socket")?;
+        let (ready, ready_theirs) = display_pipe()?;
+
+        let mut inherited: Vec<RawFd> = self.listeners.iter().map(|l| l.as_raw_fd()).collect();
+        inherited.push(wm_theirs.as_raw_fd());
+        inherited.push(ready_theirs.as_raw_fd());
+
+        let mut cmd = Command::new("Xwayland");
+        cmd.arg(self.display_name())
+            .args(["-rootless", "-terminate", "-core"]);
+        for listener in &self.listeners {
+            cmd.arg("-listenfd").arg(listener.as_raw_fd().to_string());
+        }
+        cmd.arg("-wm").arg(wm_theirs.as_raw_fd().to_string());
+        cmd.arg("-displayfd").arg(ready_theirs.as_raw_fd().to_string());
+
+        // SAFETY: only async-signal-safe calls between fork and exec.
+        unsafe {
+            cmd.pre_exec(move || {
+                for &fd in &inherited {
+                    let flags = libc::fcntl(fd, libc::F_GETFD);
+                    if flags == -1 || libc::fcntl(fd, libc::F_SETFD, flags & !libc::FD_CLOEXEC) == -1 {
+                        return Err(std::io::Error::last_os_error());
+                    }
+                }
+                Ok(())
+            });
+        }
+
+        let child = cmd.spawn().context("Failed to spawn Xwayland")?;
+        info!("Started Xwayland on {} (pid {})", self.display_name(), child.id());
+        self.child = Some(child);
+
+        Ok(XWaylandStartup {
+            wm_stream: wm_ours,
+            ready,
+        })
+    }
+
+    /// Returns true once if the server has exited since the last call.
+    pub fn reap(&mut self) -> bool {
+        let Some(child) = &mut self.child else {
+            return false;
+        };
+
+        match child.try_wait() {
+            Ok(Some(status)) => {
+                info!("Xwayland exited ({}), will restart on demand", status);
+                self.child = None;
+                true
+            }
+            Ok(None) => false,
+            Err(e) => {
+                warn!("Failed to poll Xwayland: {}", e);
+                false
+            }
+        }
+    }
+}
+
+impl Drop for XWayland {
+    fn drop(&mut self) {
+        if let Some(child) = &mut self.child {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+        let _ = fs::remove_file(socket_path(self.display));
+        let _ = fs::remove_file(lock_path(self.display));
+    }
+}
+
+fn lock_path(display: u32) -> PathBuf {
+    PathBuf::from(format!("/tmp/.X{}-lock", display))
+}
+
+fn socket_path(display: u32) -> PathBuf {
+    PathBuf::from(format!("{}/X{}", X11_SOCKET_DIR, display))
+}
+
+/// Takes the X lock file for `display`, reclaiming it from dead servers.
+fn lock_display(display: u32) -> bool {
+    let path = lock_path(display);
+
+    for _ in 0..2 {
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(mut file) => {
+                // The X lock file format is the pid right-aligned in 10 columns.
+                return writeln!(file, "{:>10}", std::process::id()).is_ok();
+            }
+            Err(_) => {
+                let owner = fs::read_to_string(&path)
+                    .ok()
+                    .and_then(|s| s.trim().parse::<libc::pid_t>().ok());
+
+                // SAFETY: signal 0 only checks whether the process exists.
+                let alive = owner.is_some_and(|pid| unsafe { libc::kill(pid, 0) } == 0);
+                if alive || fs::remove_file(&path).is_err() {
+                    return false;
+                }
+            }
+        }
+    }
+
+    false
+}
+
+/// Returns the (read, write) ends of a close-on-exec pipe.
+fn display_pipe() -> Result<(OwnedFd, OwnedFd)> {
+    let mut fds = [0; 2];
+    // SAFETY: pipe2 fills `fds` with two new descriptors on success.
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } == -1 {
+        return Err(std::io::Error::last_os_error()).context("Failed to create displayfd pipe");
+    }
+    // SAFETY: both descriptors are freshly created and owned by us.
+    unsafe { Ok((OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1]))) }
+}
+
+fn bind_sockets(display: u32) -> Result<Vec<UnixListener>> {
+    let path = socket_path(display);
+    let _ = fs::remove_file(&path);
+
+    let abstract_addr = SocketAddr::from_abstract_name(path.to_string_lossy().as_bytes())?;
+    let abstract_listener = UnixListener::bind_addr(&abstract_addr)?;
+    let path_listener = UnixListener::bind(&path)?;
+
+    abstract_listener.set_nonblocking(true)?;
+    path_listener.set_nonblocking(true)?;
+
+    Ok(vec![abstract_listener, path_listener])
+}
@@ -0,0 +1,598 @@
+//! X11 window manager for XWayland clients
+//!
+//! X11 windows are tracked in the same `WindowManager` as Wayland windows.
+//! Changes coming from clients (titles, WM_CLASS, transient-for, fullscreen
+//! requests) are applied to the model in `dispatch`, and decisions made by
+//! the compositor (focus, geometry, fullscreen, minimize) are pushed back to
+//! the X server in `sync`.
+
+use crate::window::{Geometry, ManagedWindow, WindowId, WindowManager, WindowSurface};
+
+use anyhow::{Context, Result};
+use std::collections::{HashMap, HashSet};
+use std::os::fd::{AsFd, OwnedFd};
+use std::os::unix::net::UnixStream;
+use tracing::{debug, info, warn};
+use x11rb::connection::Connection;
+use x11rb::errors::{ConnectionError, ReplyError};
+use x11rb::protocol::xproto::{
+    AtomEnum, ChangeWindowAttributesAux, ClientMessageEvent, ConfigWindow, ConfigureRequestEvent,
+    ConfigureWindowAux, ConnectionExt as _, CreateWindowAux, EventMask, InputFocus, PropMode,
+    StackMode, Window as XWindow, WindowClass,
+};
+use x11rb::protocol::Event;
+use x11rb::rust_connection::{DefaultStream, RustConnection};
+use x11rb::wrapper::ConnectionExt as _;
+use x11rb::CURRENT_TIME;
+
+x11rb::atom_manager! {
+    pub Atoms: AtomsCookie {
+        WM_PROTOCOLS,
+        WM_DELETE_WINDOW,
+        WM_STATE,
+        UTF8_STRING,
+        _NET_WM_NAME,
+        _NET_WM_STATE,
+        _NET_WM_STATE_FULLSCREEN,
+        _NET_ACTIVE_WINDOW,
+        _NET_CLIENT_LIST,
+        _NET_SUPPORTED,
+        _NET_SUPPORTING_WM_CHECK,
+    }
+}
+
+const WM_STATE_NORMAL: u32 = 1;
+const WM_STATE_ICONIC: u32 = 3;
+
+const NET_WM_STATE_REMOVE: u32 = 0;
+const NET_WM_STATE_ADD: u32 = 1;
+const NET_WM_STATE_TOGGLE: u32 = 2;
+
+/// What was last sent to the X server for a managed window.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct SyncedState {
+    geometry: Geometry,
+    fullscreen: bool,
+    minimized: bool,
+}
+
+pub struct Xwm {
+    conn: RustConnection,
+    root: XWindow,
+    atoms: Atoms,
+    windows: HashMap<XWindow, WindowId>,
+    synced: HashMap<XWindow, SyncedState>,
+    /// Windows we unmapped ourselves to minimize them.
+    hidden: HashSet<XWindow>,
+    focused: Option<XWindow>,
+}
+
+impl Xwm {
+    pub fn new(stream: UnixStream) -> Result<Self> {
+        let (stream, _) = DefaultStream::from_unix_stream(stream)?;
+        let conn = RustConnection::connect_to_stream(stream, 0)
+            .context("Failed to connect to Xwayland")?;
+
+        let root = conn.setup().roots[0].root;
+        let atoms = Atoms::new(&conn)?.reply()?;
+
+        conn.change_window_attributes(
+            root,
+            &ChangeWindowAttributesAux::new().event_mask(
+                EventMask::SUBSTRUCTURE_REDIRECT
+                    | EventMask::SUBSTRUCTURE_NOTIFY
+                    | EventMask::PROPERTY_CHANGE,
+            ),
+        )?
+        .check()
+        .context("Another X11 window manager is running")?;
+
+        // EWMH requires a child window that names the running WM.
+        let check = conn.generate_id()?;
+        conn.create_window(
+            0,
+            check,
+            root,
+            -1,
+            -1,
+            1,
+            1,
+            0,
+            WindowClass::INPUT_OUTPUT,
+            0,
+            &CreateWindowAux::new(),
+        )?;
+        for window in [root, check] {
+            conn.change_property32(
+                PropMode::REPLACE,
+                window,
+                atoms._NET_SUPPORTING_WM_CHECK,
+                AtomEnum::WINDOW,
+                &[check],
+            )?;
+        }
+        conn.change_property8(
+            PropMode::REPLACE,
+            check,
+            atoms._NET_WM_NAME,
+            atoms.UTF8_STRING,
+            b"SpinnerWM",
+        )?;
+        conn.change_property32(
+            PropMode::REPLACE,
+            root,
+            atoms._NET_SUPPORTED,
+            AtomEnum::ATOM,
+            &[
+                atoms._NET_WM_NAME,
+                atoms._NET_WM_STATE,
+                atoms._NET_WM_STATE_FULLSCREEN,
+                atoms._NET_ACTIVE_WINDOW,
+                atoms._NET_CLIENT_LIST,
+            ],
+        )?;
+        conn.flush()?;
+
+        info!("X11 window manager attached to Xwayland");
+
+        Ok(Self {
+            conn,
+            root,
+            atoms,
+            windows: HashMap::new(),
+            synced: HashMap::new(),
+            hidden: HashSet::new(),
+            focused: None,
+        })
+    }
+
+    /// A duplicate of the connection fd for registering with the event loop.
+    pub fn poll_fd(&self) -> Result<OwnedFd> {
+        Ok(self.conn.stream().as_fd().try_clone_to_owned()?)
+    }
+
+    /// Ids of every window this WM has added to the model.
+    pub fn window_ids(&self) -> impl Iterator<Item = WindowId> + '_ {
+        self.windows.values().copied()
+    }
+
+    /// Handles all pending X11 events. Only a broken connection is an
+    /// error; a request failing for one event, e.g. because its window was
+    /// destroyed in the meantime, doesn't hold up the events behind it.
+    pub fn dispatch(&mut self, wm: &mut WindowManager) -> Result<()> {
+        while let Some(event) = self.conn.poll_for_event()? {
+            if let Err(e) = self.handle_event(event, wm) {
+                if is_connection_error(&e) {
+                    return Err(e);
+                }
+                warn!("Failed to handle X11 event: {:#}", e);
+            }
+        }
+        self.conn.flush()?;
+        Ok(())
+    }
+
+    fn handle_event(&mut self, event: Event, wm: &mut WindowManager) -> Result<()> {
+        match event {
+            Event::MapRequest(e) => self.manage(e.window, wm)?,
+            Event::MapNotify(e) if !self.windows.contains_key(&e.window) => {
+                self.track_override_redirect(e.window, wm)?;
+            }
+            Event::UnmapNotify(e) if !self.hidden.contains(&e.window) => {
+                self.unmanage(e.window, wm)?;
+            }
+            Event::DestroyNotify(e) => self.unmanage(e.window, wm)?,
+            Event::ConfigureRequest(e) => self.configure_request(e, wm)?,
+            Event::ConfigureNotify(e) => {
+                // Only override-redirect windows move themselves.
+                if let Some(window) = self.window_mut(e.window, wm) {
+                    if window.is_override_redirect() {
+                        window.geometry =
+                            Geometry::new(e.x.into(), e.y.into(), e.width.into(), e.height.into());
+                    }
+                }
+            }
+            Event::PropertyNotify(e) => self.property_changed(e.window, e.atom, wm)?,
+            Event::ClientMessage(e) => self.client_message(e, wm)?,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn window_mut<'a>(&self, xid: XWindow, wm: &'a mut WindowManager) -> Option<&'a mut ManagedWindow> {
+        self.windows.get(&xid).and_then(|id| wm.window_mut(*id))
+    }
+
+    fn manage(&mut self, xid: XWindow, wm: &mut WindowManager) -> Result<()> {
+        if self.windows.contains_key(&xid) {
+            self.hidden.remove(&xid);
+            self.conn.map_window(xid)?;
+            return Ok(());
+        }
+
+        let attrs = self.conn.get_window_attributes(xid)?.reply()?;
+        if attrs.override_redirect {
+            self.conn.map_window(xid)?;
+            return Ok(());
+        }
+
+        let geom = self.conn.get_geometry(xid)?.reply()?;
+        let title = self.read_title(xid)?;
+        let app_id = self.read_class(xid)?;
+        let transient_for = self.read_transient_for(xid)?;
+        let fullscreen = self.read_fullscreen(xid)?;
+
+        let mut window = ManagedWindow::new(
+            title,
+            app_id,
+            geom.x.into(),
+            geom.y.into(),
+            geom.width.into(),
+            geom.height.into(),
+        );
+        window.surface = WindowSurface::X11 {
+            xid,
+            override_redirect: false,
+        };
+        window.transient_for = transient_for;
+
+        let (screen_width, screen_height) = wm.screen_size();
+
+        // Center new windows, over their parent when they are dialogs.
+        if geom.x == 0 && geom.y == 0 {
+            let area = transient_for
+                .and_then(|parent| wm.window(parent))
+                .map(|parent| parent.geometry)
+                .unwrap_or_else(|| wm.usable_area());
+            window.set_position(
+                area.x + (area.width as i32 - window.geometry.width as i32) / 2,
+                area.y + (area.height as i32 - window.geometry.height as i32) / 2,
+            );
+        }
+
+        self.conn.change_window_attributes(
+            xid,
+            &ChangeWindowAttributesAux::new()
+                .event_mask(EventMask::PROPERTY_CHANGE | EventMask::FOCUS_CHANGE),
+        )?;
+
+        let id = wm.add_window(window);
+        if fullscreen {
+            if let Some(window) = wm.window_mut(id) {
+                window.set_fullscreen(true, screen_width, screen_height);
+            }
+        }
+        self.windows.insert(xid, id);
+
+        self.set_wm_state(xid, WM_STATE_NORMAL)?;
+        self.conn.map_window(xid)?;
+        self.update_client_list()?;
+
+        debug!("Managing X11 window {:#x} as {:?}", xid, id);
+        Ok(())
+    }
+
+    fn track_override_redirect(&mut self, xid: XWindow, wm: &mut WindowManager) -> Result<()> {
+        let attrs = self.conn.get_window_attributes(xid)?.reply()?;
+        if !attrs.override_redirect {
+            return Ok(());
+        }
+
+        let geom = self.conn.get_geometry(xid)?.reply()?;
+        let mut window = ManagedWindow::new(
+            String::new(),
+            String::new(),
+            geom.x.into(),
+            geom.y.into(),
+            geom.width.into(),
+            geom.height.into(),
+        );
+        window.surface = WindowSurface::X11 {
+            xid,
+            override_redirect: true,
+        };
+
+        let id = wm.add_window(window);
+        self.windows.insert(xid, id);
+        Ok(())
+    }
+
+    fn unmanage(&mut self, xid: XWindow, wm: &mut WindowManager) -> Result<()> {
+        let Some(id) = self.windows.remove(&xid) else {
+            return Ok(());
+        };
+
+        self.synced.remove(&xid);
+        self.hidden.remove(&xid);
+        if self.focused == Some(xid) {
+            self.focused = None;
+        }
+
+        wm.remove_window(id);
+        self.update_client_list()?;
+
+        debug!("Stopped managing X11 window {:#x}", xid);
+        Ok(())
+    }
+
+    fn configure_request(&mut self, e: ConfigureRequestEvent, wm: &mut WindowManager) -> Result<()> {
+        let Some(window) = self.window_mut(e.window, wm) else {
+            // Not ours to manage; pass the request through unchanged.
+            let aux = ConfigureWindowAux::from_configure_request(&e);
+            self.conn.configure_window(e.window, &aux)?;
+            return Ok(());
+        };
+
+        // Fullscreen and maximized windows keep the geometry we gave them.
+        if !window.fullscreen && !window.maximized {
+            if e.value_mask.contains(ConfigWindow::X) {
+                window.geometry.x = e.x.into();
+            }
+            if e.value_mask.contains(ConfigWindow::Y) {
+                window.geometry.y = e.y.into();
+            }
+            if e.value_mask.contains(ConfigWindow::WIDTH) {
+                window.geometry.width = e.width.into();
+            }
+            if e.value_mask.contains(ConfigWindow::HEIGHT) {
+                window.geometry.height = e.height.into();
+            }
+        }
+
+        let geometry = window.geometry;
+        self.configure(e.window, geometry)?;
+        if let Some(synced) = self.synced.get_mut(&e.window) {
+            synced.geometry = geometry;
+        }
+        Ok(())
+    }
+
+    fn property_changed(&mut self, xid: XWindow, atom: u32, wm: &mut WindowManager) -> Result<()> {
+        if !self.windows.contains_key(&xid) {
+            return Ok(());
+        }
+
+        if atom == self.atoms._NET_WM_NAME || atom == u32::from(AtomEnum::WM_NAME) {
+            let title = self.read_title(xid)?;
+            if let Some(window) = self.window_mut(xid, wm) {
+                window.title = title;
+            }
+        } else if atom == u32::from(AtomEnum::WM_CLASS) {
+            let app_id = self.read_class(xid)?;
+            if let Some(window) = self.window_mut(xid, wm) {
+                window.app_id = app_id;
+            }
+        } else if atom == u32::from(AtomEnum::WM_TRANSIENT_FOR) {
+            let transient_for = self.read_transient_for(xid)?;
+            if let Some(window) = self.window_mut(xid, wm) {
+                window.transient_for = transient_for;
+            }
+        }
+        Ok(())
+    }
+
+    fn client_message(&mut self, e: ClientMessageEvent, wm: &mut WindowManager) -> Result<()> {
+        let Some(&id) = self.windows.get(&e.window) else {
+            return Ok(());
+        };
+        let data = e.data.as_data32();
+
+        if e.type_ == self.atoms._NET_WM_STATE {
+            let fullscreen_atom = self.atoms._NET_WM_STATE_FULLSCREEN;
+            if data[1] != fullscreen_atom && data[2] != fullscreen_atom {
+                return Ok(());
+            }
+
+            let (screen_width, screen_height) = wm.screen_size();
+            if let Some(window) = wm.window_mut(id) {
+                let fullscreen = match data[0] {
+                    NET_WM_STATE_REMOVE => false,
+                    NET_WM_STATE_ADD => true,
+                    NET_WM_STATE_TOGGLE => !window.fullscreen,
+                    _ => return Ok(()),
+                };
+                window.set_fullscreen(fullscreen, screen_width, screen_height);
+            }
+        } else if e.type_ == self.atoms._NET_ACTIVE_WINDOW {
+            wm.focus_window(id);
+        }
+        Ok(())
+    }
+
+    /// Pushes compositor-side state of X11 windows to the X server.
+    pub fn sync(&mut self, wm: &WindowManager) -> Result<()> {
+        for window in wm.windows() {
+            let WindowSurface::X11 { xid, override_redirect: false } = window.surface else {
+                continue;
+            };
+
+            let state = SyncedState {
+                geometry: window.geometry,
+                fullscreen: window.fullscreen,
+                minimized: window.minimized || window.workspace != wm.active_workspace(),
+            };
+            let previous = self.synced.insert(xid, state);
+            if previous == Some(state) {
+                continue;
+            }
+
+            if previous.map(|p| p.geometry) != Some(state.geometry) {
+                self.configure(xid, state.geometry)?;
+            }
+
+            if previous.map(|p| p.fullscreen) != Some(state.fullscreen) {
+                let atoms: &[u32] = if state.fullscreen {
+                    &[self.atoms._NET_WM_STATE_FULLSCREEN]
+                } else {
+                    &[]
+                };
+                self.conn.change_property32(
+                    PropMode::REPLACE,
+                    xid,
+                    self.atoms._NET_WM_STATE,
+                    AtomEnum::ATOM,
+                    atoms,
+                )?;
+            }
+
+            if previous.map(|p| p.minimized) != Some(state.minimized) {
+                if state.minimized {
+                    self.hidden.insert(xid);
+                    self.set_wm_state(xid, WM_STATE_ICONIC)?;
+                    self.conn.unmap_window(xid)?;
+                } else if previous.is_some() {
+                    self.hidden.remove(&xid);
+                    self.set_wm_state(xid, WM_STATE_NORMAL)?;
+                    self.conn.map_window(xid)?;
+                }
+            }
+        }
+
+        let focused = wm.focused_window().and_then(|w| match w.surface {
+            WindowSurface::X11 { xid, .. } => Some(xid),
+            WindowSurface::Wayland => None,
+        });
+        if focused != self.focused {
+            self.focused = focused;
+            let (target, active) = match focused {
+                Some(xid) => (xid, xid),
+                None => (self.root, 0),
+            };
+            self.conn.set_input_focus(InputFocus::POINTER_ROOT, target, CURRENT_TIME)?;
+            if let Some(xid) = focused {
+                self.conn
+                    .configure_window(xid, &ConfigureWindowAux::new().stack_mode(StackMode::ABOVE))?;
+            }
+            self.conn.change_property32(
+                PropMode::REPLACE,
+                self.root,
+                self.atoms._NET_ACTIVE_WINDOW,
+                AtomEnum::WINDOW,
+                &[active],
+            )?;
+        }
+
+        self.conn.flush()?;
+        Ok(())
+    }
+
+    /// Asks a client to close politely, killing it if it doesn't support that.
+    pub fn close(&mut self, xid: XWindow) -> Result<()> {
+        let protocols = self
+            .conn
+            .get_property(false, xid, self.atoms.WM_PROTOCOLS, AtomEnum::ATOM, 0, 32)?
+            .reply()?;
+        let supports_delete = protocols
+            .value32()
+            .is_some_and(|mut atoms| atoms.any(|a| a == self.atoms.WM_DELETE_WINDOW));
+
+        if supports_delete {
+            let event = ClientMessageEvent::new(
+                32,
+                xid,
+                self.atoms.WM_PROTOCOLS,
+                [self.atoms.WM_DELETE_WINDOW, CURRENT_TIME, 0, 0, 0],
+            );
+            self.conn.send_event(false, xid, EventMask::NO_EVENT, event)?;
+        } else {
+            self.conn.kill_client(xid)?;
+        }
+
+        self.conn.flush()?;
+        Ok(())
+    }
+
+    fn configure(&self, xid: XWindow, geometry: Geometry) -> Result<()> {
+        self.conn.configure_window(
+            xid,
+            &ConfigureWindowAux::new()
+                .x(geometry.x)
+                .y(geometry.y)
+                .width(geometry.width)
+                .height(geometry.height)
+                .border_width(0),
+        )?;
+        Ok(())
+    }
+
+    fn set_wm_state(&self, xid: XWindow, state: u32) -> Result<()> {
+        self.conn.change_property32(
+            PropMode::REPLACE,
+            xid,
+            self.atoms.WM_STATE,
+            self.atoms.WM_STATE,
+            &[state, 0],
+        )?;
+        Ok(())
+    }
+
+    fn update_client_list(&self) -> Result<()> {
+        let clients: Vec<u32> = self.windows.keys().copied().collect();
+        self.conn.change_property32(
+            PropMode::REPLACE,
+            self.root,
+            self.atoms._NET_CLIENT_LIST,
+            AtomEnum::WINDOW,
+            &clients,
+        )?;
+        Ok(())
+    }
+
+    fn read_title(&self, xid: XWindow) -> Result<String> {
+        let reply = self
+            .conn
+            .get_property(false, xid, self.atoms._NET_WM_NAME, self.atoms.UTF8_STRING, 0, 1024)?
+            .reply()?;
+        if !reply.value.is_empty() {
+            return Ok(String::from_utf8_lossy(&reply.value).into_owned());
+        }
+
+        let reply = self
+            .conn
+            .get_property(false, xid, AtomEnum::WM_NAME, AtomEnum::STRING, 0, 1024)?
+            .reply()?;
+        // WM_NAME is Latin-1.
+        Ok(reply.value.iter().map(|&b| char::from(b)).collect())
+    }
+
+    /// WM_CLASS is `instance\0class\0`; the class is used as the app id.
+    fn read_class(&self, xid: XWindow) -> Result<String> {
+        let reply = self
+            .conn
+            .get_property(false, xid, AtomEnum::WM_CLASS, AtomEnum::STRING, 0, 256)?
+            .reply()?;
+
+        let mut parts = reply.value.split(|&b| b == 0).filter(|p| !p.is_empty());
+        let instance = parts.next();
+        let class = parts.next().or(instance).unwrap_or_default();
+        Ok(String::from_utf8_lossy(class).into_owned())
+    }
+
+    fn read_transient_for(&self, xid: XWindow) -> Result<Option<WindowId>> {
+        let reply = self
+            .conn
+            .get_property(false, xid, AtomEnum::WM_TRANSIENT_FOR, AtomEnum::WINDOW, 0, 1)?
+            .reply()?;
+
+        Ok(reply
+            .value32()
+            .and_then(|mut v| v.next())
+            .and_then(|parent| self.windows.get(&parent).copied()))
+    }
+
+    fn read_fullscreen(&self, xid: XWindow) -> Result<bool> {
+        let reply = self
+            .conn
+            .get_property(false, xid, self.atoms._NET_WM_STATE, AtomEnum::ATOM, 0, 32)?
+            .reply()?;
+
+        Ok(reply
+            .value32()
+            .is_some_and(|mut atoms| atoms.any(|a| a == self.atoms._NET_WM_STATE_FULLSCREEN)))
+    }
+}
+
+fn is_connection_error(error: &anyhow::Error) -> bool {
+    error.downcast_ref::<ConnectionError>().is_some()
+        || matches!(error.downcast_ref::<ReplyError>(), Some(ReplyError::ConnectionError(_)))
+}